@@ -0,0 +1,257 @@
+//! Adds the purchasing data kept in the managed library to a BOM exported by
+//! KiCad, so the schematic does not need to carry it.
+
+use crate::dry_run;
+use crate::fields::FieldNames;
+use crate::lifecycle::symbol_lifecycle;
+use crate::symbols::property::{KiCadPropertyType, KiCadSymbol};
+use anyhow::{anyhow, bail};
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Properties whose name contains one of these hold distributor order numbers.
+const SUPPLIER_NAMES: [&str; 8] = ["mouser", "digikey", "digi-key", "lcsc", "farnell", "newark", "arrow", "tme"];
+
+#[derive(Debug, Default)]
+pub(crate) struct BomReport {
+    pub(crate) lines: usize,
+    pub(crate) enriched: usize,
+    /// References or values of the lines no library part was found for
+    pub(crate) unmatched: Vec<String>,
+}
+
+/// The purchasing fields of a symbol as name and value, in output order.
+fn purchasing_fields(symbol: &KiCadSymbol, names: &FieldNames) -> Vec<(String, String)> {
+    let mut fields = vec![];
+    if let Some(manufacturer) = names.symbol_manufacturer(symbol) {
+        fields.push((names.manufacturer().to_string(), manufacturer.to_string()));
+    }
+    if let Some(mpn) = names.symbol_mpn(symbol) {
+        fields.push((names.mpn().to_string(), mpn.to_string()));
+    }
+    if let Some(lifecycle) = symbol_lifecycle(symbol, names) {
+        fields.push((names.lifecycle().to_string(), lifecycle.to_string()));
+    }
+    for property in symbol.properties() {
+        let name = property.property_type().to_string();
+        let lowercase = name.to_lowercase();
+        if SUPPLIER_NAMES.iter().any(|supplier| lowercase.contains(supplier)) && !property.value().is_empty() {
+            fields.push((name, property.value().to_string()));
+        }
+    }
+    fields
+}
+
+/// Finds the library symbol of a BOM line by the symbol name of its lib_id,
+/// or by its value when there is no lib_id. Values shared by several symbols
+/// are not matched.
+fn find_symbol<'a>(symbols: &'a [KiCadSymbol], lib_id: Option<&str>, value: Option<&str>) -> Option<&'a KiCadSymbol> {
+    if let Some(lib_id) = lib_id.filter(|lib_id| !lib_id.is_empty()) {
+        let name = lib_id.split_once(':').map_or(lib_id, |(_, name)| name);
+        return symbols.iter().find(|symbol| symbol.name() == name);
+    }
+    let value = value.filter(|value| !value.is_empty())?;
+    let mut matches = symbols
+        .iter()
+        .filter(|symbol| symbol.property_value(&KiCadPropertyType::Value) == Some(value) || symbol.name() == value);
+    let symbol = matches.next()?;
+    matches.next().is_none().then_some(symbol)
+}
+
+/// Writes the enriched copy of `input` to `output`. KiCad's intermediate
+/// `.xml` netlist gets the fields added to every component, any other file
+/// is read as a CSV BOM and gets a column per field.
+pub(crate) fn enrich_bom(input: &Path, output: &Path, symbols: &[KiCadSymbol], names: &FieldNames) -> Result<BomReport, anyhow::Error> {
+    let is_xml = input.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("xml"));
+    let result = if is_xml { enrich_xml(input, output, symbols, names) } else { enrich_csv(input, output, symbols, names) };
+    result.map_err(|error| anyhow!("{}: {error:#}", input.display()))
+}
+
+/// Normalises a column title, so "Lib ID", "LibId" and "lib_id" are the same.
+fn column_key(title: &str) -> String {
+    title.chars().filter(char::is_ascii_alphanumeric).collect::<String>().to_lowercase()
+}
+
+/// Picks the most frequent of the usual delimiters in the header line.
+fn sniff_delimiter(content: &str) -> u8 {
+    let header = content.lines().next().unwrap_or_default();
+    [b',', b';', b'\t'].into_iter().max_by_key(|delimiter| header.matches(char::from(*delimiter)).count()).unwrap_or(b',')
+}
+
+/// The file the enriched BOM is written to, or nothing in a dry run.
+fn create_output(output: &Path) -> Result<Box<dyn Write>, anyhow::Error> {
+    if dry_run::skip_write(output) {
+        return Ok(Box::new(std::io::sink()));
+    }
+    Ok(Box::new(BufWriter::new(File::create(output)?)))
+}
+
+fn enrich_csv(input: &Path, output: &Path, symbols: &[KiCadSymbol], names: &FieldNames) -> Result<BomReport, anyhow::Error> {
+    let content = std::fs::read_to_string(input)?;
+    let delimiter = sniff_delimiter(&content);
+    let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).flexible(true).from_reader(content.as_bytes());
+    let mut headers: Vec<String> = reader.headers()?.iter().map(str::to_string).collect();
+
+    let column = |keys: &[&str]| headers.iter().position(|title| keys.contains(&column_key(title).as_str()));
+    let lib_id_column = column(&["libid", "librarylink", "libraryid", "symbol", "libsource"]);
+    let value_column = column(&["value"]);
+    let reference_column = column(&["reference", "references", "refs", "ref", "designator"]);
+    if lib_id_column.is_none() && value_column.is_none() {
+        bail!("BOM has neither a Lib ID nor a Value column");
+    }
+
+    let mut report = BomReport::default();
+    let mut rows = vec![];
+    for record in reader.records() {
+        let mut row: Vec<String> = record?.iter().map(str::to_string).collect();
+        let cell = |column: Option<usize>| column.and_then(|column| row.get(column)).map(String::as_str);
+        let symbol = find_symbol(symbols, cell(lib_id_column), cell(value_column));
+        report.lines += 1;
+
+        let Some(symbol) = symbol else {
+            let line = cell(reference_column).or(cell(value_column)).unwrap_or_default();
+            report.unmatched.push(line.to_string());
+            rows.push(row);
+            continue;
+        };
+        report.enriched += 1;
+        for (name, value) in purchasing_fields(symbol, names) {
+            let index = match headers.iter().position(|title| title.eq_ignore_ascii_case(&name)) {
+                Some(index) => index,
+                None => {
+                    headers.push(name);
+                    headers.len() - 1
+                }
+            };
+            if row.len() <= index {
+                row.resize(index + 1, String::new());
+            }
+            // Values already in the BOM are left as they are
+            if row[index].is_empty() {
+                row[index] = value;
+            }
+        }
+        rows.push(row);
+    }
+
+    let mut writer = csv::WriterBuilder::new().delimiter(delimiter).flexible(true).from_writer(create_output(output)?);
+    writer.write_record(&headers)?;
+    for mut row in rows {
+        row.resize(headers.len(), String::new());
+        writer.write_record(&row)?;
+    }
+    writer.flush()?;
+    Ok(report)
+}
+
+/// What identifies a component of the XML netlist.
+#[derive(Default)]
+struct XmlComponent {
+    reference: String,
+    value: String,
+    lib_id: Option<String>,
+    /// Names of the fields the component already has
+    fields: Vec<String>,
+}
+
+fn attribute(start: &BytesStart, name: &str) -> Result<Option<String>, anyhow::Error> {
+    match start.try_get_attribute(name)? {
+        Some(attribute) => Ok(Some(attribute.unescape_value()?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+/// Reads the identifying parts of a buffered `<comp>` element.
+fn read_component(events: &[Event<'static>]) -> Result<XmlComponent, anyhow::Error> {
+    let mut component = XmlComponent::default();
+    let mut in_value = false;
+    for event in events {
+        match event {
+            Event::Start(start) if start.name().as_ref() == b"comp" => {
+                component.reference = attribute(start, "ref")?.unwrap_or_default();
+            }
+            Event::Start(start) if start.name().as_ref() == b"value" => in_value = true,
+            Event::End(end) if end.name().as_ref() == b"value" => in_value = false,
+            Event::Text(text) if in_value => component.value.push_str(&text.decode()?),
+            Event::GeneralRef(reference) if in_value => match reference.resolve_char_ref()? {
+                Some(c) => component.value.push(c),
+                None => component.value.push_str(resolve_predefined_entity(&reference.decode()?).unwrap_or_default()),
+            },
+            Event::Start(start) | Event::Empty(start) if start.name().as_ref() == b"libsource" => {
+                if let (Some(lib), Some(part)) = (attribute(start, "lib")?, attribute(start, "part")?) {
+                    component.lib_id = Some(format!("{lib}:{part}"));
+                }
+            }
+            Event::Start(start) if start.name().as_ref() == b"field" => {
+                component.fields.extend(attribute(start, "name")?);
+            }
+            _ => {}
+        }
+    }
+    Ok(component)
+}
+
+fn write_fields<W: Write>(writer: &mut Writer<W>, fields: &[(String, String)]) -> Result<(), anyhow::Error> {
+    for (name, value) in fields {
+        writer.write_event(Event::Start(BytesStart::new("field").with_attributes([("name", name.as_str())])))?;
+        writer.write_event(Event::Text(BytesText::new(value)))?;
+        writer.write_event(Event::End(BytesEnd::new("field")))?;
+    }
+    Ok(())
+}
+
+fn enrich_xml(input: &Path, output: &Path, symbols: &[KiCadSymbol], names: &FieldNames) -> Result<BomReport, anyhow::Error> {
+    let mut reader = Reader::from_reader(BufReader::new(File::open(input)?));
+    let mut writer = Writer::new(create_output(output)?);
+    let mut report = BomReport::default();
+    let mut buffer = vec![];
+    // Components are buffered, as the libsource comes after the fields
+    let mut component: Option<Vec<Event<'static>>> = None;
+
+    loop {
+        let event = reader.read_event_into(&mut buffer)?.into_owned();
+        buffer.clear();
+        match (&mut component, event) {
+            (None, Event::Eof) => break,
+            (Some(_), Event::Eof) => bail!("Unexpected end of file inside a component"),
+            (None, Event::Start(start)) if start.name().as_ref() == b"comp" => component = Some(vec![Event::Start(start)]),
+            (None, event) => writer.write_event(event)?,
+            (Some(events), Event::End(end)) if end.name().as_ref() == b"comp" => {
+                events.push(Event::End(end));
+                let info = read_component(events)?;
+                report.lines += 1;
+                let fields = match find_symbol(symbols, info.lib_id.as_deref(), Some(&info.value)) {
+                    Some(symbol) => {
+                        report.enriched += 1;
+                        purchasing_fields(symbol, names).into_iter().filter(|(name, _)| !info.fields.contains(name)).collect()
+                    }
+                    None => {
+                        report.unmatched.push(info.reference.clone());
+                        vec![]
+                    }
+                };
+
+                let has_fields = events.iter().any(|event| matches!(event, Event::Start(start) if start.name().as_ref() == b"fields"));
+                for event in component.take().unwrap_or_default() {
+                    match &event {
+                        Event::End(end) if end.name().as_ref() == b"fields" => write_fields(&mut writer, &fields)?,
+                        Event::End(end) if end.name().as_ref() == b"comp" && !has_fields && !fields.is_empty() => {
+                            writer.write_event(Event::Start(BytesStart::new("fields")))?;
+                            write_fields(&mut writer, &fields)?;
+                            writer.write_event(Event::End(BytesEnd::new("fields")))?;
+                        }
+                        _ => {}
+                    }
+                    writer.write_event(event)?;
+                }
+            }
+            (Some(events), event) => events.push(event),
+        }
+    }
+    writer.into_inner().flush()?;
+    Ok(report)
+}
@@ -0,0 +1,83 @@
+use crate::dry_run;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ArtifactKind {
+    Footprint,
+    Model,
+    Ibis,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct ManifestEntry {
+    pub(crate) kind: ArtifactKind,
+    pub(crate) path: PathBuf,
+}
+
+/// Everything that was imported for a single symbol, so that footprints,
+/// models and simulation files can be found again from the part name.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct CatalogPart {
+    pub(crate) symbol: String,
+    pub(crate) source: String,
+    pub(crate) manifest: Vec<ManifestEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) manufacturer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) mpn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) dimensions: Option<PartDimensions>,
+}
+
+/// Package envelope in mm: `x`/`y` from the footprint courtyard and `z` the
+/// maximum height above the board.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct PartDimensions {
+    pub(crate) x: Option<f32>,
+    pub(crate) y: Option<f32>,
+    pub(crate) z: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub(crate) struct Catalog {
+    pub(crate) parts: Vec<CatalogPart>,
+}
+
+impl Catalog {
+    /// The catalog lives next to the symbol library it describes, e.g.
+    /// `MyLib.kicad_sym` is catalogued in `MyLib.catalog.json`.
+    pub(crate) fn default_path(symbol_lib: &Path) -> PathBuf {
+        symbol_lib.with_extension("catalog.json")
+    }
+
+    pub(crate) fn from_path(path: &Path) -> Result<Self, anyhow::Error> {
+        if !path.exists() {
+            return Ok(Catalog::default());
+        }
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub(crate) fn write_to_path(&self, path: &Path) -> Result<(), anyhow::Error> {
+        if dry_run::skip_write(path) {
+            return Ok(());
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Adds the part, replacing an existing entry for the same symbol.
+    pub(crate) fn upsert(&mut self, part: CatalogPart) {
+        match self.parts.iter_mut().find(|existing| existing.symbol == part.symbol) {
+            Some(existing) => *existing = part,
+            None => self.parts.push(part),
+        }
+    }
+}
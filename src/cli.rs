@@ -0,0 +1,2675 @@
+use crate::bom::enrich_bom;
+use crate::catalog::{ArtifactKind, Catalog, CatalogPart, ManifestEntry};
+use crate::dbl::{part_columns, sync_parts, write_database_library, KEY_COLUMNS};
+use crate::dedupe::{find_duplicates, merge_duplicates, DuplicateGroup};
+use crate::config::{
+    Config, ConflictPolicy, LibTableScope, DEFAULT_CONFIG_FILE, LibraryRoute, ModelAdjustment, ModelFormat, ModelPathStyle, NicknameCollision,
+    WatchTarget,
+};
+use crate::dimensions::part_dimensions;
+use crate::dry_run;
+use crate::footprints::layers::fix_layer_names;
+use crate::footprints::upgrade::{upgrade_footprint, FOOTPRINT_VERSION};
+use crate::footprints::rename::{rename_in_library, renamed, RenamePattern};
+use crate::footprints::models::{
+    adjust_models, convert_to_wrl, find_orphaned_models, import_models, model_format, names_after_footprints,
+    rewrite_model_paths, ImportedModel, ModelReferences,
+};
+use crate::footprints::{copy_footprint, lint, prepare_footprint_library, KiCadFootprint};
+use crate::installation::KiCadInstallation;
+use crate::layout::ArchiveLayout;
+use crate::lib_table::{library_uri, register_library, LibTable, LibTableEntry, Registration};
+use crate::lifecycle::{fetch_lifecycle, lifecycle_agent, set_symbol_lifecycle, symbol_lifecycle, Lifecycle};
+use crate::overlay::{is_writable, OverlayLibrary};
+use crate::routing::route;
+use crate::policy::{load_policy, ApplyFieldTemplates, Policy, PolicyChecks};
+use crate::project::{find_files_with_extension, find_footprint_usages, find_symbol_usages};
+use crate::snapshot::{diff, Change, LibraryState, SnapshotHistory};
+use crate::pipeline::{DirectoryExtractor, EasyEdaExtractor, FixReferencePrefixes, ImportPipeline, LinkIbisModels, NormalizeFields, RewriteFootprintNicknames, StripProperties};
+use crate::pipeline::{matches_wildcards, ClassifiedFiles, ConflictMerger, MergeReport, Merger, Validator};
+use crate::fields::property_type;
+use crate::symbols::property::{KiCadProperty, KiCadPropertyType, KiCadSymbol};
+use crate::symbols::{fidelity, KicadSymbolLib, ParseLimits};
+use crate::validation::footprint::{FootprintReferences, FootprintResolver, PinPadConsistency};
+use crate::validation::datasheet::{check_datasheets, DatasheetCheckOptions};
+use crate::validation::reference::reference_prefix;
+use crate::validation::{check_symbol_rule_names, Finding, ReferencePrefixes, RequiredFields, Severity, UnitPinBalance, SYMBOL_RULES};
+use crate::update::self_update;
+use crate::variables::{expand, expand_path};
+use crate::watch::{watch, WatchOptions};
+use anyhow::{anyhow, bail};
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use mktemp::Temp;
+use regex::{Regex, RegexBuilder};
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::fs;
+use strum::EnumString;
+
+/// Without a subcommand the vendor archive given by `--zip` is imported.
+#[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    import: Option<ImportArgs>,
+
+    /// Config file, defaults to `klm.toml` in the working directory
+    #[arg(long = "config", value_name = "PATH TO CONFIG", global = true)]
+    config: Option<PathBuf>,
+
+    /// Read, check and plan as usual, but only print which files would be
+    /// created, modified or deleted
+    #[arg(long = "dry-run", global = true)]
+    dry_run: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check that the Datasheet links of a symbol library are valid
+    CheckDatasheets(CheckDatasheetsArgs),
+    /// Track the lifecycle status (active/NRND/obsolete) of parts
+    Lifecycle {
+        #[command(subcommand)]
+        command: LifecycleCommand,
+    },
+    /// Import vendor archives as they are downloaded into watched directories
+    Watch(WatchArgs),
+    /// Show the package envelope (x/y/z in mm) of catalogued parts
+    Dimensions(DimensionsArgs),
+    /// Replace this program with the latest release for this platform
+    SelfUpdate(SelfUpdateArgs),
+    /// Convert a KiCad 5 `.lib`/`.dcm` library into a `.kicad_sym` library
+    Convert(ConvertArgs),
+    /// Add manufacturer, MPN, lifecycle and distributor numbers from the library to a BOM
+    Bom(BomArgs),
+    /// Record the state of a library and list what changed since
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommand,
+    },
+    /// Create a library set: a symbol and footprint library per category, a 3D model
+    /// directory, library tables and a config file routing imports by category
+    Init(InitArgs),
+    /// Show the format, generator, size, units and field coverage of symbol libraries
+    Info(InfoArgs),
+    /// Show the symbols added, removed and changed between two libraries, down to fields and pins
+    Diff(DiffArgs),
+    /// Check symbol and footprint libraries, failing on any warning, e.g. in CI
+    Check(CheckArgs),
+    /// Rename a symbol of a library, along with its units and the symbols derived from it
+    Rename(RenameArgs),
+    /// Rename footprints of a library, by name or by patterns
+    RenameFootprint(RenameFootprintArgs),
+    /// Render footprints as SVG images to preview them
+    Preview(PreviewArgs),
+    /// List the symbols of a library with their value, footprint, datasheet and keywords
+    List(ListArgs),
+    /// Find symbols by name, field values and keywords in one or more libraries
+    Search(SearchArgs),
+    /// Copy symbols, with the symbols they are derived from, into a new library
+    Extract(ExtractArgs),
+    /// Combine symbol libraries into one, handling symbols of the same name like imports do
+    Merge(MergeArgs),
+    /// Split a symbol library into one library per reference prefix, keyword or regex match
+    Split(SplitArgs),
+    /// Find symbols of a library that are the same part, by body or MPN, and merge them
+    Dedupe(DedupeArgs),
+    /// List the footprints of a library with their pads, description, tags and 3D models
+    ListFootprints(ListFootprintsArgs),
+    /// Delete symbols from a library, optionally with the footprints and 3D models only they used
+    Remove(RemoveArgs),
+    /// Delete a footprint from a library unless symbols or projects still use it
+    RemoveFootprint(RemoveFootprintArgs),
+    /// List 3D models no footprint of the libraries refers to
+    OrphanedModels(OrphanedModelsArgs),
+    /// Show or change the offset, scale and rotation of the 3D models of footprints
+    AdjustModel(AdjustModelArgs),
+    /// Write a `.kicad_dbl` database library definition for the parts of a symbol library
+    ExportDbl(ExportDblArgs),
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotCommand {
+    /// Record the symbols and footprints of the library as they are now
+    Create {
+        #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+        symbol_lib: PathBuf,
+
+        /// Record the footprints of this directory as well
+        #[arg(short = 'f', long = "footprint-dir", value_name = "PATH TO FOOTPRINT DIR")]
+        footprint_dir: Option<PathBuf>,
+
+        /// Name to refer to the snapshot by instead of its number
+        #[arg(long = "label")]
+        label: Option<String>,
+    },
+    /// List the snapshots of the library
+    List {
+        #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+        symbol_lib: PathBuf,
+    },
+    /// List the symbols and footprints added, removed or changed between two snapshots
+    Diff {
+        #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+        symbol_lib: PathBuf,
+
+        /// Footprint directory compared with the snapshot, defaults to the one it was created with
+        #[arg(short = 'f', long = "footprint-dir", value_name = "PATH TO FOOTPRINT DIR")]
+        footprint_dir: Option<PathBuf>,
+
+        /// Number or label of the older snapshot
+        from: String,
+
+        /// Number or label of the newer snapshot, defaults to the library as it is now
+        to: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum LifecycleCommand {
+    /// Set the lifecycle status of symbols by hand
+    Set {
+        #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+        symbol_lib: PathBuf,
+
+        /// Active, NRND or Obsolete
+        #[arg(long = "status")]
+        status: Lifecycle,
+
+        #[arg(required = true, value_name = "SYMBOL")]
+        symbols: Vec<String>,
+    },
+    /// Refresh the lifecycle status of all symbols with a part number from a JSON API
+    Refresh {
+        #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+        symbol_lib: PathBuf,
+
+        /// URL queried for every part, `{mpn}` is replaced by the part number
+        #[arg(long = "api-url", value_name = "URL TEMPLATE")]
+        api_url: String,
+
+        /// Field of the JSON response holding the lifecycle status
+        #[arg(long = "field", default_value = "lifecycle")]
+        field: String,
+
+        /// Request timeout in seconds
+        #[arg(long = "timeout", default_value_t = 10)]
+        timeout: u64,
+    },
+    /// List NRND and obsolete parts and the projects using them
+    Report {
+        #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+        symbol_lib: PathBuf,
+
+        /// Project directory to scan for schematics using the parts
+        #[arg(short = 'p', long = "project", value_name = "PROJECT DIR")]
+        projects: Vec<PathBuf>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct CheckDatasheetsArgs {
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_lib: PathBuf,
+
+    /// Send HEAD requests to check that the links are reachable
+    #[arg(long = "online")]
+    online: bool,
+
+    /// Maximum number of concurrent requests
+    #[arg(short = 'j', long = "jobs", default_value_t = 8)]
+    jobs: usize,
+
+    /// Request timeout in seconds
+    #[arg(long = "timeout", default_value_t = 10)]
+    timeout: u64,
+
+    /// Rewrite http datasheet and text links that are also served over https
+    #[arg(long = "upgrade-https", requires = "online")]
+    upgrade_https: bool,
+}
+
+#[derive(Args, Debug)]
+struct InitArgs {
+    /// Directory of the library set, created if needed
+    #[arg(value_name = "DIR", default_value = ".")]
+    dir: PathBuf,
+
+    /// Categories to create libraries for instead of the built-in ones, can
+    /// be given more than once
+    #[arg(long = "category", value_name = "CATEGORY")]
+    categories: Vec<String>,
+
+    /// Prefix of the library nicknames, like `ACME` for `ACME_Connectors`
+    #[arg(long = "prefix", value_name = "PREFIX")]
+    prefix: Option<String>,
+
+    /// Path variable the library tables and the config refer to the directory with
+    #[arg(long = "var", value_name = "VARIABLE", default_value = "KLM_LIBRARY_DIR")]
+    variable: String,
+}
+
+#[derive(Args, Debug)]
+struct InfoArgs {
+    /// Symbol libraries, or directories to find them in
+    #[arg(value_name = "SYMBOL LIBS", required = true)]
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
+    #[arg(value_name = "OLD SYMBOL LIB")]
+    old: PathBuf,
+
+    #[arg(value_name = "NEW SYMBOL LIB")]
+    new: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct CheckArgs {
+    /// Symbol library to check, can be given more than once
+    #[arg(
+        short = 's',
+        long = "symbol-lib",
+        value_name = "PATH TO SYMBOL LIB",
+        required_unless_present_any = ["footprint_lib", "list_rules"]
+    )]
+    symbol_lib: Vec<PathBuf>,
+
+    /// Footprint library to check, can be given more than once. The first one
+    /// is also where footprints of its nickname are looked up
+    #[arg(short = 'f', long = "footprint-lib", value_name = "PATH TO FOOTPRINT LIB")]
+    footprint_lib: Vec<PathBuf>,
+
+    /// Rule to skip in addition to `disabled_footprint_rules` and
+    /// `disabled_symbol_rules` of the config
+    #[arg(long = "disable", value_name = "RULE")]
+    disable: Vec<String>,
+
+    /// Run rules disabled in the config anyway
+    #[arg(long = "enable", value_name = "RULE")]
+    enable: Vec<String>,
+
+    /// List the rules and what they check
+    #[arg(long = "list-rules")]
+    list_rules: bool,
+
+    /// Replace layer names with an obvious correction, like `F.Silkscreen` with `F.SilkS`
+    #[arg(long = "fix-layers")]
+    fix_layers: bool,
+}
+
+#[derive(Args, Debug)]
+struct RenameArgs {
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_lib: PathBuf,
+
+    #[arg(value_name = "OLD NAME")]
+    old: String,
+
+    #[arg(value_name = "NEW NAME")]
+    new: String,
+}
+
+#[derive(Args, Debug)]
+struct RenameFootprintArgs {
+    #[arg(short = 'f', long = "footprint-lib", value_name = "PATH TO FOOTPRINT LIB")]
+    footprint_lib: PathBuf,
+
+    /// Symbol library whose Footprint properties are pointed at the new names
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_lib: Option<PathBuf>,
+
+    /// Pattern like `PCBLIB_*=ACME_*` to rename every matching footprint with
+    #[arg(long = "pattern", value_name = "FROM=TO", conflicts_with_all = ["old", "new"])]
+    patterns: Vec<RenamePattern>,
+
+    #[arg(value_name = "OLD NAME", requires = "new", required_unless_present = "patterns")]
+    old: Option<String>,
+
+    #[arg(value_name = "NEW NAME")]
+    new: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct PreviewArgs {
+    /// Footprint files or `.pretty` libraries
+    #[arg(required = true, value_name = "FOOTPRINT")]
+    footprints: Vec<PathBuf>,
+
+    /// Directory to write the images to, defaults to next to the footprints
+    #[arg(short = 'o', long = "output-dir", value_name = "PATH TO OUTPUT DIR")]
+    output_dir: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct ListArgs {
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_lib: PathBuf,
+
+    /// List the symbols of this overlay library as well, which shadow the
+    /// symbols of the same name in `--symbol-lib`
+    #[arg(long = "overlay-lib", value_name = "PATH TO OVERLAY LIB")]
+    overlay_lib: Option<PathBuf>,
+
+    /// Only list the symbols with this text in their name or one of the
+    /// listed fields, regardless of case
+    #[arg(value_name = "FILTER")]
+    filter: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct SearchArgs {
+    /// Libraries to search, by default `symbol_lib` and the libraries of the
+    /// routes of the config
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_libs: Vec<PathBuf>,
+
+    /// Search this overlay library as well, which shadows the symbols of the
+    /// same name in the library searched
+    #[arg(long = "overlay-lib", value_name = "PATH TO OVERLAY LIB")]
+    overlay_lib: Option<PathBuf>,
+
+    /// Regular expression the symbol name must match, regardless of case
+    #[arg(value_name = "REGEX")]
+    pattern: Option<String>,
+
+    /// Field value the symbol must have, with `*` and `?` wildcards and
+    /// regardless of case, like `MPN=LM358*`
+    #[arg(long = "field", value_name = "NAME=VALUE", value_parser = parse_field_filter)]
+    fields: Vec<(String, String)>,
+
+    /// Keyword the symbol must have in its `ki_keywords`
+    #[arg(long = "keyword", value_name = "KEYWORD")]
+    keywords: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct ExtractArgs {
+    #[arg(long = "from", value_name = "PATH TO SYMBOL LIB")]
+    from: PathBuf,
+
+    /// The new library, which must not exist yet
+    #[arg(long = "to", value_name = "PATH TO NEW SYMBOL LIB")]
+    to: PathBuf,
+
+    #[arg(value_name = "NAME", required = true)]
+    names: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct MergeArgs {
+    #[arg(value_name = "PATH TO SYMBOL LIB", required = true)]
+    symbol_libs: Vec<PathBuf>,
+
+    /// Library the symbols are merged into, created if it does not exist
+    #[arg(long = "into", value_name = "PATH TO SYMBOL LIB")]
+    into: PathBuf,
+
+    /// What to do with symbols whose name is taken by a different symbol:
+    /// skip, overwrite, rename or ask. Defaults to `symbol_conflicts` of the config
+    #[arg(long = "on-conflict", value_name = "POLICY")]
+    on_conflict: Option<ConflictPolicy>,
+}
+
+/// What [`split_command`] groups symbols by.
+#[derive(EnumString, Copy, Clone, Debug, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+enum SplitBy {
+    /// The prefix of the reference designator, like `U` or `R`
+    Prefix,
+    /// The first of the keywords
+    Keyword,
+    /// The first group of `--regex` in the name, or the whole match
+    Regex,
+}
+
+#[derive(Args, Debug)]
+struct SplitArgs {
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_lib: PathBuf,
+
+    #[arg(long = "by", value_name = "GROUPING")]
+    by: SplitBy,
+
+    /// Regular expression for `--by regex`, like `^(LM|TL)`
+    #[arg(long = "regex", value_name = "REGEX", required_if_eq("by", "regex"))]
+    regex: Option<String>,
+
+    /// Directory of the new libraries, `<library>_<group>.kicad_sym`, by
+    /// default the one of the library
+    #[arg(short = 'o', long = "output-dir", value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+
+    /// Overwrite libraries that already exist
+    #[arg(long = "force")]
+    force: bool,
+
+    /// Add the new libraries to the project or global sym-lib-table, defaults
+    /// to `register_libraries` of the config
+    #[arg(long = "register", value_name = "TABLE")]
+    register: Option<LibTableScope>,
+}
+
+#[derive(Args, Debug)]
+struct DedupeArgs {
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_lib: PathBuf,
+
+    /// Merge every group into its first symbol without asking
+    #[arg(short = 'y', long = "yes")]
+    yes: bool,
+
+    /// Keep the names of merged symbols as symbols derived from the one kept
+    #[arg(long = "aliases")]
+    aliases: bool,
+}
+
+fn parse_field_filter(value: &str) -> Result<(String, String), String> {
+    let (name, value) = value.split_once('=').ok_or("expected NAME=VALUE")?;
+    Ok((name.to_string(), value.to_lowercase()))
+}
+
+#[derive(Args, Debug)]
+struct ListFootprintsArgs {
+    #[arg(short = 'f', long = "footprint-lib", value_name = "PATH TO FOOTPRINT LIB")]
+    footprint_lib: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct RemoveArgs {
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_lib: PathBuf,
+
+    /// Footprint library the symbols refer to
+    #[arg(short = 'f', long = "footprint-lib", value_name = "PATH TO FOOTPRINT LIB")]
+    footprint_lib: Option<PathBuf>,
+
+    /// Also delete the footprints of the library no remaining symbol refers
+    /// to, and their 3D models no other footprint uses
+    #[arg(long = "with-footprints", requires = "footprint_lib")]
+    with_footprints: bool,
+
+    /// Directory of the 3D models, by default the `.3dshapes` of the footprint
+    /// library in `model_library_dir` of the config or next to the library
+    #[arg(long = "model-dir", value_name = "MODEL DIR")]
+    model_dir: Option<PathBuf>,
+
+    #[arg(value_name = "NAME", required = true)]
+    names: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct RemoveFootprintArgs {
+    #[arg(short = 'f', long = "footprint-lib", value_name = "PATH TO FOOTPRINT LIB")]
+    footprint_lib: PathBuf,
+
+    /// Symbol library to look for Footprint properties using the footprint in
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_lib: Option<PathBuf>,
+
+    /// Project directory to scan for schematics and boards using the footprint
+    #[arg(short = 'p', long = "project", value_name = "PROJECT DIR")]
+    projects: Vec<PathBuf>,
+
+    /// Remove the footprint even if it is still in use
+    #[arg(long = "force")]
+    force: bool,
+
+    #[arg(value_name = "NAME")]
+    name: String,
+}
+
+#[derive(Args, Debug)]
+struct AdjustModelArgs {
+    #[arg(short = 'f', long = "footprint-lib", value_name = "PATH TO FOOTPRINT LIB")]
+    footprint_lib: PathBuf,
+
+    /// New offset in mm
+    #[arg(long = "offset", value_name = "X,Y,Z", value_parser = parse_xyz, allow_hyphen_values = true)]
+    offset: Option<[f32; 3]>,
+
+    /// New scale
+    #[arg(long = "scale", value_name = "X,Y,Z", value_parser = parse_xyz, allow_hyphen_values = true)]
+    scale: Option<[f32; 3]>,
+
+    /// New rotation in degrees
+    #[arg(long = "rotate", value_name = "X,Y,Z", value_parser = parse_xyz, allow_hyphen_values = true)]
+    rotate: Option<[f32; 3]>,
+
+    /// Footprints whose models are shown or changed
+    #[arg(required = true, value_name = "FOOTPRINT")]
+    footprints: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct ExportDblArgs {
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_lib: PathBuf,
+
+    /// Database library definition, defaults to `<symbol lib>.kicad_dbl`
+    #[arg(short = 'o', long = "output", value_name = "PATH TO DBL")]
+    output: Option<PathBuf>,
+
+    /// Table of the parts, defaults to the nickname of the symbol library
+    #[arg(long = "table", value_name = "TABLE")]
+    table: Option<String>,
+
+    /// ODBC connection string of the database, defaults to an SQLite database
+    /// `<symbol lib>.sqlite` next to the definition
+    #[arg(long = "connection-string", value_name = "CONNECTION STRING")]
+    connection_string: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct OrphanedModelsArgs {
+    /// Footprint library whose model references count, can be given several times
+    #[arg(short = 'f', long = "footprint-lib", value_name = "PATH TO FOOTPRINT LIB", required = true)]
+    footprint_libs: Vec<PathBuf>,
+
+    /// Directory of the models, defaults to the `<footprint lib>.3dshapes` of the first
+    /// library, in `model_library_dir` of the config or next to the library
+    #[arg(long = "model-dir", value_name = "PATH TO MODEL DIR")]
+    model_dir: Option<PathBuf>,
+
+    /// Project directory `${KIPRJMOD}` stands for, defaults to the directory of each library
+    #[arg(short = 'p', long = "project", value_name = "PROJECT DIR")]
+    project: Option<PathBuf>,
+
+    /// Delete the orphaned models
+    #[arg(long = "delete")]
+    delete: bool,
+}
+
+#[derive(Args, Debug)]
+struct DimensionsArgs {
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_lib: PathBuf,
+
+    /// Part catalog, defaults to `<symbol lib>.catalog.json`
+    #[arg(long = "catalog", value_name = "PATH TO CATALOG")]
+    catalog: Option<PathBuf>,
+
+    /// Only list parts taller than this
+    #[arg(long = "taller-than", value_name = "MM")]
+    taller_than: Option<f32>,
+}
+
+#[derive(Args, Debug)]
+struct ConvertArgs {
+    /// Legacy library, the `.dcm` file next to it is read as well
+    #[arg(value_name = "PATH TO LEGACY LIB")]
+    legacy_lib: PathBuf,
+
+    /// Converted library, defaults to the legacy library with a `.kicad_sym` extension
+    #[arg(short = 'o', long = "output", value_name = "PATH TO SYMBOL LIB")]
+    output: Option<PathBuf>,
+
+    /// Overwrite the output library if it exists
+    #[arg(long = "force")]
+    force: bool,
+
+    /// Add the library to the project or global sym-lib-table, defaults to
+    /// `register_libraries` of the config
+    #[arg(long = "register", value_name = "TABLE")]
+    register: Option<LibTableScope>,
+}
+
+#[derive(Args, Debug)]
+struct BomArgs {
+    /// BOM exported by KiCad, a CSV file or the intermediate `.xml` netlist
+    #[arg(value_name = "PATH TO BOM")]
+    bom: PathBuf,
+
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_lib: PathBuf,
+
+    /// Enriched BOM, defaults to `<bom>-enriched` with the extension of the BOM
+    #[arg(short = 'o', long = "output", value_name = "PATH TO BOM")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct SelfUpdateArgs {
+    /// Only report whether a newer release is available
+    #[arg(long = "check")]
+    check: bool,
+
+    /// Accept releases signed with the `public_key` of `update.toml` in the
+    /// user config directory instead of the embedded release key, for
+    /// in-house builds
+    #[arg(long = "user-key")]
+    user_key: bool,
+}
+
+#[derive(Args, Debug)]
+struct WatchArgs {
+    /// Directory to watch in addition to the `[[watch]]` entries of the config
+    #[arg(long = "dir", value_name = "DOWNLOAD DIR", requires_all = ["footprint_dir", "symbol_lib"])]
+    dir: Option<PathBuf>,
+
+    #[arg(
+        short = 'f',
+        long = "footprint-dir",
+        value_name = "PATH TO FOOTPRINT DIR",
+        requires = "dir"
+    )]
+    footprint_dir: Option<PathBuf>,
+
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB", requires = "dir")]
+    symbol_lib: Option<PathBuf>,
+
+    /// Seconds between scans of the watched directories
+    #[arg(long = "poll-interval", default_value_t = 2)]
+    poll_interval: u64,
+
+    /// Seconds an archive has to stay unchanged before it is imported
+    #[arg(long = "settle-time", default_value_t = 3)]
+    settle_time: u64,
+
+    /// Import attempts per archive while it is still locked by the browser
+    #[arg(long = "retries", default_value_t = 5)]
+    retries: u32,
+}
+
+#[derive(Args, Debug, Clone)]
+struct ImportArgs {
+    /// Vendor archives (`.zip`, `.7z`, `.tar.gz` or `.tgz`), or the EasyEDA
+    /// JSON of LCSC parts (`.json`), imported one after the other. A name
+    /// with `*` or `?`, like `Downloads/*.zip`, imports all that match
+    #[arg(short = 'z', long = "zip", value_name = "INPUT ZIP FILE", num_args = 1.., required_unless_present = "input_dir")]
+    input_zip: Vec<PathBuf>,
+
+    /// Folder with the files of an already extracted archive, or a staging
+    /// folder of parts, instead of `--zip`
+    #[arg(long = "dir", value_name = "INPUT DIR", conflicts_with = "input_zip")]
+    input_dir: Option<PathBuf>,
+
+    /// `.pretty` footprint library, created if it does not exist. Defaults to
+    /// the one of `--project`, then to `footprint_lib` of the config, then to
+    /// `Imported.pretty` in the user directory of KiCad
+    #[arg(
+        short = 'f',
+        long = "footprint-lib",
+        visible_alias = "footprint-dir",
+        value_name = "PATH TO FOOTPRINT LIB"
+    )]
+    footprint_dir: Option<PathBuf>,
+
+    /// Import into the libraries of a KiCad project, `libs/<project>.kicad_sym`
+    /// and `libs/<project>.pretty`, referred to by `${KIPRJMOD}` and added to
+    /// the library tables of the project
+    #[arg(long = "project", value_name = "PATH TO .kicad_pro")]
+    project: Option<PathBuf>,
+
+    /// Copy footprints into `--footprint-lib` even if it is not a `.pretty` library
+    #[arg(long = "force")]
+    force: bool,
+
+    /// Directory 3D models are copied to, defaults to `<footprint lib>.3dshapes`
+    /// in `model_library_dir` of the config or next to the footprint library
+    #[arg(long = "model-dir", value_name = "PATH TO MODEL DIR")]
+    model_dir: Option<PathBuf>,
+
+    /// Write model references relative to this variable, like `KICAD_3RD_PARTY`
+    /// or `KIPRJMOD`, instead of `model_dir`/`model_path_variable` of the config
+    #[arg(long = "model-path-var", value_name = "VARIABLE")]
+    model_path_var: Option<String>,
+
+    /// How model references are written: project (`${KIPRJMOD}/...`), library
+    /// (relative to the footprint library), absolute or variable
+    /// (`--model-path-var` or `model_dir` of the config)
+    #[arg(long = "model-path-style", value_name = "STYLE")]
+    model_path_style: Option<ModelPathStyle>,
+
+    /// Add the symbol and footprint libraries to the project or global library
+    /// tables, defaults to `register_libraries` of the config
+    #[arg(long = "register", value_name = "TABLE")]
+    register: Option<LibTableScope>,
+
+    /// Model footprints refer to when an archive has it as `.step` and `.wrl`:
+    /// step or wrl. Defaults to `preferred_model_format` of the config
+    #[arg(long = "prefer-model", value_name = "FORMAT")]
+    prefer_model: Option<ModelFormat>,
+
+    /// Store STEP files gzipped as `.stpZ`, as with `compress_models` of the config
+    #[arg(long = "compress-models")]
+    compress_models: bool,
+
+    /// Name the copied models after their footprint, as with `rename_models` of the config
+    #[arg(long = "rename-models")]
+    rename_models: bool,
+
+    /// Leave out the 3D models of the archive
+    #[arg(long = "no-3d")]
+    no_3d: bool,
+
+    /// Import the symbols only, keeping the footprints of the library as they are
+    #[arg(long = "only-symbols", conflicts_with = "only_footprints")]
+    only_symbols: bool,
+
+    /// Import the footprints and 3D models only, leaving the symbol library untouched
+    #[arg(long = "only-footprints")]
+    only_footprints: bool,
+
+    /// What to do when a library table has a different library under the
+    /// nickname of an imported library: rename, merge or abort. Defaults to
+    /// `nickname_collisions` of the config, which asks
+    #[arg(long = "on-nickname-collision", value_name = "POLICY")]
+    on_nickname_collision: Option<NicknameCollision>,
+
+    /// What to do with footprints and symbols whose name is taken by a
+    /// different one: skip, overwrite, rename or ask. Defaults to
+    /// `footprint_conflicts` and `symbol_conflicts` of the config
+    #[arg(long = "on-conflict", value_name = "POLICY")]
+    on_conflict: Option<ConflictPolicy>,
+
+    /// Defaults to the one of `--project`, then to `symbol_lib` of the config,
+    /// then, once confirmed, to `Imported.kicad_sym` in the user directory of KiCad
+    #[arg(short = 's', long = "symbol-lib", value_name = "PATH TO SYMBOL LIB")]
+    symbol_lib: Option<PathBuf>,
+
+    /// Create the symbol library if it does not exist, which is asked on a
+    /// terminal otherwise
+    #[arg(long = "create-lib")]
+    create_lib: bool,
+
+    /// Add symbols to this library instead, leaving a read-only `--symbol-lib` untouched
+    #[arg(long = "overlay-lib", value_name = "PATH TO OVERLAY LIB")]
+    overlay_lib: Option<PathBuf>,
+
+    /// Directory IBIS models are archived in, defaults to `ibis` next to the symbol library
+    #[arg(long = "ibis-dir", value_name = "PATH TO IBIS DIR")]
+    ibis_dir: Option<PathBuf>,
+
+    /// Add an `IBIS_Model` property pointing at the archived IBIS file to imported symbols
+    #[arg(long = "link-ibis")]
+    link_ibis: bool,
+
+    /// SQLite database of a database library to insert or update the imported
+    /// parts in, defaults to `part_database` of the config
+    #[arg(long = "part-database", value_name = "PATH TO SQLITE DB")]
+    part_database: Option<PathBuf>,
+
+    /// Part catalog to record imports in, defaults to `<symbol lib>.catalog.json`
+    #[arg(long = "catalog", value_name = "PATH TO CATALOG")]
+    catalog: Option<PathBuf>,
+
+    /// Rewrite reference prefixes that do not match the part category where unambiguous
+    #[arg(long = "fix-references")]
+    fix_references: bool,
+
+    /// Import into the libraries of this category of the `routes` of the
+    /// config, instead of the route the parts match
+    #[arg(long = "category", value_name = "CATEGORY")]
+    category: Option<String>,
+
+    /// Point the Footprint property of imported symbols at the imported
+    /// footprints, using the nickname of `--footprint-lib`. Always done for
+    /// Ultra Librarian and SamacSys archives
+    #[arg(long = "rewrite-footprint-lib")]
+    rewrite_footprint_lib: bool,
+
+    /// Replace layer names of imported footprints with an obvious correction,
+    /// like `F.Silkscreen` with `F.SilkS`
+    #[arg(long = "fix-layers")]
+    fix_layers: bool,
+
+    /// Rename imported footprints with a pattern like `*=ACME_*`, before the
+    /// `footprint_rename_patterns` of the config
+    #[arg(long = "fp-rename-pattern", value_name = "FROM=TO")]
+    fp_rename_patterns: Vec<RenamePattern>,
+
+    /// Also list the extracted files, and the ones that were not imported
+    #[arg(short = 'v', long = "verbose")]
+    verbose: bool,
+}
+
+fn copy_files(files: &[PathBuf], dest_dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut copied = Vec::new();
+    for file in files {
+        let dest_file = dest_dir.join(
+            file.file_name()
+                .ok_or(anyhow!("File {file:?} has no filename"))?,
+        );
+        println!("{file:?} -> {dest_file:?}");
+        dry_run::copy(file, &dest_file)?;
+        copied.push(dest_file);
+    }
+    Ok(copied)
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// The footprint library tables of the config and the `fp-lib-table` of the
+/// project, if there is one.
+fn footprint_lib_tables(config: &Config, project_dir: &Path) -> Result<Vec<LibTable>, anyhow::Error> {
+    let project_table = project_dir.join("fp-lib-table");
+    let mut tables = config.fp_lib_tables.iter().map(|path| LibTable::from_path(path)).collect::<Result<Vec<_>, _>>()?;
+    if let Some(global_table) = config.fp_lib_table.as_ref().filter(|table| table.exists()) {
+        tables.push(LibTable::from_path(global_table)?);
+    }
+    if project_table.exists() {
+        tables.push(LibTable::from_path(&project_table)?);
+    }
+    Ok(tables)
+}
+
+/// The library table of `scope`, a `sym-lib-table` or an `fp-lib-table` by
+/// `table_name`, the project one being in `project_dir`.
+fn lib_table_path(table_name: &str, project_dir: &Path, scope: LibTableScope, config: &Config) -> Result<PathBuf, anyhow::Error> {
+    let global_table = if table_name == "sym-lib-table" { &config.sym_lib_table } else { &config.fp_lib_table };
+    match scope {
+        LibTableScope::Project => Ok(project_dir.join(table_name)),
+        // The key of the global table in the config
+        LibTableScope::Global => global_table
+            .clone()
+            .ok_or(anyhow!("There is no global {table_name}, set `{}` in the config", table_name.replace('-', "_"))),
+    }
+}
+
+/// The library to import into and the nickname to add it to the library
+/// table of `scope` with. If the table has a different library under the
+/// nickname of `library`, `policy` decides whether the nickname gets a
+/// numbered suffix, the library of the table is imported into instead, or
+/// the import is aborted.
+fn resolve_nickname_collision(
+    library: &Path,
+    table_name: &str,
+    project_dir: &Path,
+    scope: Option<LibTableScope>,
+    policy: NicknameCollision,
+    config: &Config,
+) -> Result<(PathBuf, String), anyhow::Error> {
+    let nickname = file_stem(library);
+    let Some(scope) = scope.or(config.register_libraries) else { return Ok((library.to_path_buf(), nickname)) };
+    let table_path = lib_table_path(table_name, project_dir, scope, config)?;
+    if !table_path.exists() {
+        return Ok((library.to_path_buf(), nickname));
+    }
+    let table = LibTable::from_path(&table_path)?;
+    let Some(existing) = table.find(&nickname) else { return Ok((library.to_path_buf(), nickname)) };
+    let existing_path = table.library_path(existing);
+    let same_library = |path: &Path| match (fs::canonicalize(path), fs::canonicalize(library)) {
+        (Ok(path), Ok(library)) => path == library,
+        _ => std::path::absolute(path).ok() == std::path::absolute(library).ok(),
+    };
+    if existing_path.as_deref().is_some_and(same_library) {
+        return Ok((library.to_path_buf(), nickname));
+    }
+
+    let collision = format!("{} has a different library {nickname}: {}", table_path.display(), existing.uri);
+    let policy = match policy {
+        NicknameCollision::Ask => ask_nickname_collision(&collision)?,
+        policy => policy,
+    };
+    match policy {
+        NicknameCollision::Rename => {
+            let renamed = (1..).map(|number| format!("{nickname}_{number}")).find(|name| table.find(name).is_none()).unwrap_or_default();
+            println!("{collision}, adding {} as {renamed}", library.display());
+            Ok((library.to_path_buf(), renamed))
+        }
+        NicknameCollision::Merge => {
+            let Some(existing_path) = existing_path else { bail!("{collision}, whose path uses a variable that is not set") };
+            println!("{collision}, importing into it");
+            Ok((existing_path, nickname))
+        }
+        NicknameCollision::Abort | NicknameCollision::Ask => bail!("{collision}"),
+    }
+}
+
+fn ask_nickname_collision(collision: &str) -> Result<NicknameCollision, anyhow::Error> {
+    if !io::stdin().is_terminal() {
+        return Ok(NicknameCollision::Abort);
+    }
+    loop {
+        print!("{collision}, [r]ename, [m]erge into it or [a]bort? ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(NicknameCollision::Abort);
+        }
+        match answer.trim() {
+            "r" | "rename" => return Ok(NicknameCollision::Rename),
+            "m" | "merge" => return Ok(NicknameCollision::Merge),
+            "a" | "abort" => return Ok(NicknameCollision::Abort),
+            _ => {}
+        }
+    }
+}
+
+/// Adds the library to the library table of `scope` under `nickname`, see
+/// [`lib_table_path`]. Without a scope, points out how if no table has the
+/// library yet.
+fn register_in_lib_table(
+    library: &Path,
+    nickname: &str,
+    table_name: &str,
+    project_dir: &Path,
+    scope: Option<LibTableScope>,
+    config: &Config,
+) -> Result<(), anyhow::Error> {
+    let Some(scope) = scope.or(config.register_libraries) else {
+        let global_table = if table_name == "sym-lib-table" { &config.sym_lib_table } else { &config.fp_lib_table };
+        let tables = [Some(project_dir.join(table_name)), global_table.clone()];
+        let registered = tables
+            .iter()
+            .flatten()
+            .filter(|table| table.exists())
+            .any(|table| LibTable::from_path(table).is_ok_and(|table| table.find(nickname).is_some()));
+        if !registered {
+            println!("{nickname} is in no {table_name} yet, pass --register project or --register global to add it");
+        }
+        return Ok(());
+    };
+
+    let table = lib_table_path(table_name, project_dir, scope, config)?;
+    let project_dir = (scope == LibTableScope::Project).then_some(project_dir);
+    let uri = library_uri(library, project_dir, &config.model_path_variables)?;
+    let entry = LibTableEntry::new(nickname, &uri, "Imported by kicad-library-manager");
+    match register_library(&table, &table_name.replace('-', "_"), &entry)? {
+        Registration::Added => println!("Added {nickname} ({uri}) to {}", table.display()),
+        Registration::Updated => println!("Updated {nickname} in {} to {uri}", table.display()),
+        Registration::Unchanged => {}
+    }
+    Ok(())
+}
+
+/// The imported footprint the Footprint property of the symbol points at, or
+/// the only footprint of the archive.
+fn symbol_footprint_file<'a>(symbol: &KiCadSymbol, footprints: &'a [PathBuf]) -> Option<&'a Path> {
+    let name = symbol
+        .property_value(&KiCadPropertyType::Footprint)
+        .map(|footprint| footprint.rsplit(':').next().unwrap_or(footprint));
+    footprints
+        .iter()
+        .find(|path| name.is_some() && path.file_stem().and_then(|stem| stem.to_str()) == name)
+        .or(if footprints.len() == 1 { footprints.first() } else { None })
+        .map(PathBuf::as_path)
+}
+
+/// Parses the command line and runs the requested command.
+pub fn run() -> Result<(), anyhow::Error> {
+    let cli = Cli::parse();
+    let config_path = cli.config.as_deref().map(|path| expand_path(path, &BTreeMap::new())).transpose()?;
+    let config = Config::load(config_path.as_deref())?;
+    // Paths like `${KICAD8_SYMBOL_DIR}/MyLib.kicad_sym` are expanded as KiCad does, the connection string of a
+    // database library keeps its variables for KiCad
+    let mut arguments = vec![];
+    let mut previous = None;
+    for argument in std::env::args_os() {
+        let verbatim = previous.as_deref() == Some("--connection-string");
+        previous = argument.to_str().map(str::to_string);
+        arguments.push(match argument.to_str() {
+            Some(value) if value.contains("${") && !verbatim && !value.starts_with("--connection-string=") => {
+                expand(value, &config.model_path_variables)?.into()
+            }
+            _ => argument,
+        });
+    }
+    let cli = Cli::parse_from(arguments);
+    if cli.dry_run {
+        dry_run::enable();
+    }
+
+    let (result, fidelity) = fidelity::collect(|| match (cli.command, cli.import) {
+        (Some(Command::CheckDatasheets(args)), _) => check_datasheets_command(args),
+        (Some(Command::Lifecycle { command }), _) => lifecycle_command(command, &config),
+        (Some(Command::Watch(args)), _) => watch_command(args, &config),
+        (Some(Command::Dimensions(args)), _) => dimensions_command(args),
+        (Some(Command::SelfUpdate(args)), _) => self_update(&config.update, args.check, args.user_key),
+        (Some(Command::Convert(args)), _) => convert_command(args, &config),
+        (Some(Command::Bom(args)), _) => bom_command(args, &config),
+        (Some(Command::Snapshot { command }), _) => snapshot_command(command),
+        (Some(Command::Init(args)), _) => init_command(args),
+        (Some(Command::Info(args)), _) => info_command(args),
+        (Some(Command::Diff(args)), _) => diff_command(args),
+        (Some(Command::Check(args)), _) => check_command(args, &config),
+        (Some(Command::Rename(args)), _) => rename_command(args),
+        (Some(Command::RenameFootprint(args)), _) => rename_footprint_command(args),
+        (Some(Command::Preview(args)), _) => preview_command(args),
+        (Some(Command::List(args)), _) => list_command(args),
+        (Some(Command::Search(args)), _) => search_command(args, &config),
+        (Some(Command::Extract(args)), _) => extract_command(args),
+        (Some(Command::Merge(args)), _) => merge_command(args, &config),
+        (Some(Command::Split(args)), _) => split_command(args, &config),
+        (Some(Command::Dedupe(args)), _) => dedupe_command(args, &config),
+        (Some(Command::ListFootprints(args)), _) => list_footprints_command(args),
+        (Some(Command::Remove(args)), _) => remove_command(args, &config),
+        (Some(Command::RemoveFootprint(args)), _) => remove_footprint_command(args),
+        (Some(Command::OrphanedModels(args)), _) => orphaned_models_command(args, &config),
+        (Some(Command::AdjustModel(args)), _) => adjust_model_command(args),
+        (Some(Command::ExportDbl(args)), _) => export_dbl_command(args, &config),
+        (None, Some(args)) => import_all(args, &config),
+        (None, None) => unreachable!("clap requires the import arguments without a subcommand"),
+    });
+    if !fidelity.is_empty() {
+        println!("Fidelity report: {fidelity}");
+    }
+    if dry_run::is_enabled() {
+        println!("Dry run, no files were written");
+    }
+    result
+}
+
+fn check_datasheets_command(args: CheckDatasheetsArgs) -> Result<(), anyhow::Error> {
+    let mut symbol_lib = KicadSymbolLib::from_path(&args.symbol_lib)?;
+    let options = DatasheetCheckOptions {
+        online: args.online,
+        jobs: args.jobs,
+        timeout: Duration::from_secs(args.timeout),
+    };
+
+    let report = check_datasheets(&symbol_lib.symbols, &options);
+    for finding in &report.findings {
+        println!("{finding}");
+    }
+    println!("Checked datasheets of {} symbols, {} finding(s)", symbol_lib.symbols.len(), report.findings.len());
+
+    if args.upgrade_https && !report.https_upgrades.is_empty() {
+        for symbol in symbol_lib.symbols.iter_mut() {
+            for (http_url, https_url) in &report.https_upgrades {
+                symbol.replace_link(http_url, https_url);
+            }
+        }
+        symbol_lib.write_to_path(&args.symbol_lib)?;
+        println!("Upgraded {} link(s) to https", report.https_upgrades.len());
+    }
+
+    Ok(())
+}
+
+/// The config file `init` writes.
+#[derive(Serialize)]
+struct InitConfig {
+    symbol_lib: String,
+    footprint_lib: String,
+    model_library_dir: String,
+    model_path_variable: String,
+    routes: Vec<InitRoute>,
+    model_path_variables: BTreeMap<String, PathBuf>,
+}
+
+#[derive(Serialize)]
+struct InitRoute {
+    category: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    reference_prefixes: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    keywords: Vec<String>,
+    symbol_lib: String,
+    footprint_lib: String,
+}
+
+/// The categories `init` creates libraries for, with the reference prefixes
+/// and keywords imports are routed by. Parts of no category go to the last.
+const DEFAULT_CATEGORIES: [(&str, &[&str], &[&str]); 7] = [
+    ("Passives", &["R", "C", "L", "FB"], &["resistor", "capacitor", "inductor", "ferrite"]),
+    ("Connectors", &["J", "P"], &["connector", "header", "socket"]),
+    ("Diodes", &["D", "LED"], &["diode", "led"]),
+    ("Transistors", &["Q"], &["transistor", "mosfet"]),
+    ("Electromechanical", &["SW", "K", "F", "BT"], &["switch", "relay", "fuse", "battery"]),
+    ("ICs", &["U", "IC"], &[]),
+    ("Misc", &[], &[]),
+];
+
+fn init_command(args: InitArgs) -> Result<(), anyhow::Error> {
+    let dir = std::path::absolute(&args.dir)?;
+    let variable = &args.variable;
+    if variable.is_empty() || !variable.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        bail!("{variable:?} is not a path variable name, which has only letters, digits and underscores");
+    }
+    let categories: Vec<(&str, &[&str], &[&str])> = match args.categories.is_empty() {
+        true => DEFAULT_CATEGORIES.to_vec(),
+        false => args.categories.iter().map(|category| (category.as_str(), &[][..], &[][..])).collect(),
+    };
+    for subdir in ["symbols", "footprints", "3dmodels"] {
+        dry_run::create_dir_all(&dir.join(subdir))?;
+    }
+
+    let variables = BTreeMap::from([(variable.clone(), dir.clone())]);
+    let mut routes = vec![];
+    // Imports of no category go to the first catch-all category, or else the first one
+    let (mut catch_all_libraries, mut first_libraries) = (None, None);
+    for (category, reference_prefixes, keywords) in &categories {
+        let nickname = args.prefix.as_ref().map_or(category.to_string(), |prefix| format!("{prefix}_{category}"));
+        let symbol_lib = dir.join("symbols").join(format!("{nickname}.kicad_sym"));
+        let footprint_lib = dir.join("footprints").join(format!("{nickname}.pretty"));
+        if symbol_lib.exists() {
+            println!("Kept {}", symbol_lib.display());
+        } else {
+            create_symbol_library(&symbol_lib)?;
+        }
+        dry_run::create_dir_all(&footprint_lib)?;
+        let description = format!("{category} parts");
+        let symbol_uri = library_uri(&symbol_lib, None, &variables)?;
+        let footprint_uri = library_uri(&footprint_lib, None, &variables)?;
+        register_library(&dir.join("sym-lib-table"), "sym_lib_table", &LibTableEntry::new(&nickname, &symbol_uri, &description))?;
+        register_library(&dir.join("fp-lib-table"), "fp_lib_table", &LibTableEntry::new(&nickname, &footprint_uri, &description))?;
+
+        first_libraries.get_or_insert((symbol_uri.clone(), footprint_uri.clone()));
+        if reference_prefixes.is_empty() && keywords.is_empty() {
+            catch_all_libraries.get_or_insert((symbol_uri.clone(), footprint_uri.clone()));
+        }
+        routes.push(InitRoute {
+            category: category.to_string(),
+            reference_prefixes: reference_prefixes.iter().map(|prefix| prefix.to_string()).collect(),
+            keywords: keywords.iter().map(|keyword| keyword.to_string()).collect(),
+            symbol_lib: symbol_uri,
+            footprint_lib: footprint_uri,
+        });
+    }
+
+    let config_path = dir.join(DEFAULT_CONFIG_FILE);
+    if config_path.exists() {
+        println!("Kept {}", config_path.display());
+    } else {
+        let (symbol_lib, footprint_lib) = catch_all_libraries.or(first_libraries).unwrap_or_default();
+        let config = InitConfig {
+            symbol_lib,
+            footprint_lib,
+            model_library_dir: format!("${{{variable}}}/3dmodels"),
+            model_path_variable: variable.clone(),
+            routes,
+            model_path_variables: variables,
+        };
+        let config = format!(
+            "# KiCad finds the libraries through {variable}, set it in Configure Paths\n{}",
+            toml::to_string(&config).map_err(|error| anyhow!("{}: {error}", config_path.display()))?
+        );
+        if !dry_run::skip_write(&config_path) {
+            fs::write(&config_path, config)?;
+            println!("Created {}", config_path.display());
+        }
+    }
+    println!("Set {variable} to {} in Configure Paths of KiCad to use the libraries", dir.display());
+    Ok(())
+}
+
+fn info_command(args: InfoArgs) -> Result<(), anyhow::Error> {
+    let mut symbol_libs = vec![];
+    for path in &args.paths {
+        if path.is_dir() {
+            let mut found = find_files_with_extension(path, "kicad_sym")?;
+            found.sort();
+            symbol_libs.extend(found);
+        } else {
+            symbol_libs.push(path.clone());
+        }
+    }
+    let (mut total_symbols, mut total_size) = (0, 0);
+    for path in &symbol_libs {
+        let symbol_lib = KicadSymbolLib::from_path(path)?;
+        let size = fs::metadata(path)?.len();
+        let symbols = &symbol_lib.symbols;
+        let derived = symbols.iter().filter(|symbol| symbol.extends().is_some()).count();
+        let mut units = BTreeMap::<u32, usize>::new();
+        for symbol in symbols {
+            let root = symbol.extends().and_then(|parent| symbols.iter().find(|symbol| symbol.name() == parent)).unwrap_or(symbol);
+            *units.entry(root.unit_count()).or_default() += 1;
+        }
+        let units: Vec<String> = units.iter().map(|(units, symbols)| format!("{units} unit(s): {symbols}")).collect();
+
+        println!("{}", path.display());
+        println!("  Format:     {}", symbol_lib.version().map_or("unknown".to_string(), |version| version.to_string()));
+        println!("  Generator:  {}", symbol_lib.generator().unwrap_or("unknown".to_string()));
+        println!("  File size:  {}", format_size(size));
+        println!("  Symbols:    {} ({derived} derived)", symbols.len());
+        if !units.is_empty() {
+            println!("  Units:      {}", units.join(", "));
+        }
+        if !symbols.is_empty() {
+            let coverage = field_coverage(symbols);
+            let width = coverage.iter().map(|(field, _)| field.chars().count()).max().unwrap_or(0);
+            println!("  Fields:");
+            for (field, count) in coverage {
+                println!("    {field:<width$}  {count}/{} ({}%)", symbols.len(), count * 100 / symbols.len());
+            }
+        }
+        total_symbols += symbols.len();
+        total_size += size;
+    }
+    if symbol_libs.len() > 1 {
+        println!("{} libraries, {total_symbols} symbol(s), {}", symbol_libs.len(), format_size(total_size));
+    }
+    Ok(())
+}
+
+/// How many of the symbols set each field, Reference, Value, Footprint and
+/// Datasheet first and the other fields by name.
+fn field_coverage(symbols: &[KiCadSymbol]) -> Vec<(String, usize)> {
+    let standard = ["Reference", "Value", "Footprint", "Datasheet"];
+    let mut coverage = BTreeMap::<String, usize>::new();
+    for symbol in symbols {
+        for property in symbol.properties().iter().filter(|property| !property.value().trim().is_empty() && property.value() != "~") {
+            *coverage.entry(property.property_type().to_string()).or_default() += 1;
+        }
+    }
+    let mut fields: Vec<(String, usize)> =
+        standard.iter().map(|field| (field.to_string(), coverage.remove(*field).unwrap_or_default())).collect();
+    fields.extend(coverage);
+    fields
+}
+
+/// A file size in B, KiB or MiB.
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1048576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1048576.0),
+    }
+}
+
+fn diff_command(args: DiffArgs) -> Result<(), anyhow::Error> {
+    let old_lib = KicadSymbolLib::from_path(&args.old)?;
+    let new_lib = KicadSymbolLib::from_path(&args.new)?;
+    let (old_state, new_state) = (LibraryState::capture(&old_lib, None)?, LibraryState::capture(&new_lib, None)?);
+    let changes = diff(&old_state, &new_state);
+    for change in &changes {
+        println!("{change}");
+        let Change::SymbolChanged(name, _) = change else { continue };
+        let old = old_lib.symbols.iter().find(|symbol| symbol.name() == *name);
+        let new = new_lib.symbols.iter().find(|symbol| symbol.name() == *name);
+        let (Some(old), Some(new)) = (old, new) else { continue };
+        if old.extends() != new.extends() {
+            println!("    extends {} -> {}", old.extends().unwrap_or("nothing"), new.extends().unwrap_or("nothing"));
+        }
+        for pin_change in new.pin_changes_from(old) {
+            println!("    {pin_change}");
+        }
+    }
+    println!("{} change(s)", changes.len());
+    Ok(())
+}
+
+fn check_command(args: CheckArgs, config: &Config) -> Result<(), anyhow::Error> {
+    if args.list_rules {
+        for (rule, description) in SYMBOL_RULES.iter().chain(&lint::RULES) {
+            println!("{rule}: {description}");
+        }
+        return Ok(());
+    }
+    lint::check_rule_names(&config.disabled_footprint_rules)?;
+    check_symbol_rule_names(&config.disabled_symbol_rules)?;
+    let all_rules = SYMBOL_RULES.iter().chain(&lint::RULES).map(|(rule, _)| *rule).collect::<Vec<_>>();
+    if let Some(rule) = args.disable.iter().chain(&args.enable).find(|rule| !all_rules.contains(&rule.as_str())) {
+        bail!("Unknown rule {rule}, the rules are {}", all_rules.join(", "));
+    }
+    let mut disabled: Vec<String> = [config.disabled_footprint_rules.as_slice(), &config.disabled_symbol_rules].concat();
+    disabled.retain(|rule| !args.enable.contains(rule));
+    disabled.extend(args.disable);
+    let policy = config.policy.as_ref().map(load_policy).transpose()?;
+    if let Some(policy) = &policy {
+        for rule in policy.mandatory_rules.iter().filter(|rule| disabled.contains(rule)) {
+            println!("note: {rule} is mandatory by policy, it is checked anyway");
+        }
+        disabled.retain(|rule| !policy.mandatory_rules.contains(rule));
+    }
+
+    let mut findings = 0;
+    for symbol_lib in &args.symbol_lib {
+        findings += check_symbol_library(symbol_lib, args.footprint_lib.first(), &disabled, policy.as_ref(), config)?;
+    }
+    for footprint_lib in &args.footprint_lib {
+        let mut footprints = find_files_with_extension(footprint_lib, "kicad_mod")?;
+        footprints.sort();
+        let mut library_findings = 0;
+        for path in &footprints {
+            if args.fix_layers {
+                report_layer_fixes(path, true)?;
+            }
+            let footprint = KiCadFootprint::from_path(path)?;
+            for finding in lint::lint_footprint(&footprint, path, &disabled, config.courtyard_clearance) {
+                println!("{finding}");
+                library_findings += 1;
+            }
+        }
+        println!("Checked {} footprint(s) of {}, {library_findings} finding(s)", footprints.len(), footprint_lib.display());
+        findings += library_findings;
+    }
+    if findings > 0 {
+        bail!("The check found {findings} problem(s)");
+    }
+    Ok(())
+}
+
+/// Runs the symbol rules not in `disabled` on a symbol library and prints
+/// the warnings and errors, returning how many there were. Footprints are
+/// looked up in `footprint_lib` and the footprint library tables.
+fn check_symbol_library(
+    path: &Path,
+    footprint_lib: Option<&PathBuf>,
+    disabled: &[String],
+    policy: Option<&Policy>,
+    config: &Config,
+) -> Result<usize, anyhow::Error> {
+    let (symbol_lib, skipped) = KicadSymbolLib::from_path_recovering(path, &config.parse_limits)?;
+    let project_dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let resolver = FootprintResolver::new(
+        footprint_lib.map_or(Path::new(""), PathBuf::as_path),
+        &footprint_lib.map(|library| file_stem(library)).unwrap_or_default(),
+        footprint_lib_tables(config, project_dir)?,
+    );
+    let has_footprint_libraries = resolver.has_libraries();
+    let mut rules: Vec<(&str, Box<dyn Validator>)> = vec![
+        ("unit-balance", Box::new(UnitPinBalance)),
+        ("reference-prefix", Box::new(ReferencePrefixes::new(config))),
+        ("footprint-reference", Box::new(FootprintReferences::new(resolver.clone()))),
+        ("pin-pad", Box::new(PinPadConsistency::new(resolver))),
+        ("required-fields", Box::new(RequiredFields::new(config))),
+    ];
+    if let Some(policy) = policy {
+        rules.push(("policy", Box::new(PolicyChecks::new(policy))));
+    }
+    rules.retain(|(rule, _)| !disabled.iter().any(|name| name == rule));
+    // Every footprint would be unknown, which says nothing about the library
+    let footprint_rules = ["footprint-reference", "pin-pad"];
+    if !has_footprint_libraries && rules.iter().any(|(rule, _)| footprint_rules.contains(rule)) {
+        println!("note: no footprint libraries configured, pass --footprint-lib or add an fp-lib-table to check footprints");
+        rules.retain(|(rule, _)| !footprint_rules.contains(rule));
+    }
+
+    let mut findings = vec![];
+    if !disabled.iter().any(|name| name == "parse") {
+        findings.extend(skipped.into_iter().map(|skipped| Finding::new(Severity::Error, &skipped.name, skipped.error).with_rule("parse")));
+    }
+    for symbol in &symbol_lib.symbols {
+        for (rule, validator) in &rules {
+            findings.extend(validator.validate(symbol).into_iter().map(|finding| finding.with_rule(rule)));
+        }
+    }
+    // Info findings like the pins per unit describe the symbols, they are no problems
+    findings.retain(|finding| finding.severity != Severity::Info);
+    for finding in &findings {
+        println!("{finding}");
+    }
+    println!("Checked {} symbol(s) of {}, {} finding(s)", symbol_lib.symbols.len(), path.display(), findings.len());
+    Ok(findings.len())
+}
+
+fn rename_footprint_command(args: RenameFootprintArgs) -> Result<(), anyhow::Error> {
+    let mut renames = vec![];
+    if let (Some(old), Some(new)) = (args.old, args.new) {
+        renames.push((old, new));
+    } else {
+        let mut footprints = find_files_with_extension(&args.footprint_lib, "kicad_mod")?;
+        footprints.sort();
+        for footprint in &footprints {
+            let old = file_stem(footprint);
+            let new = renamed(&args.patterns, &old);
+            if new != old {
+                renames.push((old, new));
+            }
+        }
+    }
+    for (old, new) in &renames {
+        rename_in_library(&args.footprint_lib, old, new)?;
+        println!("{old} -> {new}");
+    }
+
+    if let Some(symbol_lib_path) = args.symbol_lib {
+        let nickname = file_stem(&args.footprint_lib);
+        let mut symbol_lib = KicadSymbolLib::from_path(&symbol_lib_path)?;
+        let mut updated = 0;
+        for symbol in symbol_lib.symbols.iter_mut() {
+            let Some(footprint) = symbol.property_value(&KiCadPropertyType::Footprint) else { continue };
+            let Some((_, new)) = renames.iter().find(|(old, _)| footprint == format!("{nickname}:{old}")) else { continue };
+            symbol.set_property(KiCadProperty::new_hidden(KiCadPropertyType::Footprint, format!("{nickname}:{new}")));
+            updated += 1;
+        }
+        if updated > 0 {
+            symbol_lib.write_to_path(&symbol_lib_path)?;
+        }
+        println!("Pointed {updated} symbol(s) of {} at the new names", symbol_lib_path.display());
+    }
+    Ok(())
+}
+
+fn preview_command(args: PreviewArgs) -> Result<(), anyhow::Error> {
+    if let Some(output_dir) = &args.output_dir {
+        dry_run::create_dir_all(output_dir)?;
+    }
+    for path in &args.footprints {
+        let mut footprints =
+            if path.is_dir() { find_files_with_extension(path, "kicad_mod")? } else { vec![path.clone()] };
+        footprints.sort();
+        for footprint in footprints {
+            let svg = KiCadFootprint::from_path(&footprint)?.to_svg();
+            let image = match &args.output_dir {
+                Some(output_dir) => output_dir.join(footprint.with_extension("svg").file_name().unwrap_or_default()),
+                None => footprint.with_extension("svg"),
+            };
+            dry_run::write(&image, svg)?;
+            println!("{}", image.display());
+        }
+    }
+    Ok(())
+}
+
+/// Prints the names the overlay library shadows in the base library.
+fn report_overlay_conflicts(library: &OverlayLibrary, base_lib: &Path) {
+    for name in library.conflicts() {
+        println!("warning: {name} in the overlay shadows the symbol in {}", base_lib.display());
+    }
+}
+
+fn list_command(args: ListArgs) -> Result<(), anyhow::Error> {
+    let library = match &args.overlay_lib {
+        Some(overlay_lib) => OverlayLibrary::open(&args.symbol_lib, overlay_lib)?,
+        None => OverlayLibrary { base: KicadSymbolLib::from_path(&args.symbol_lib)?, overlay: KicadSymbolLib::new() },
+    };
+    report_overlay_conflicts(&library, &args.symbol_lib);
+    let symbols = library.symbols();
+    let fields = [KiCadPropertyType::Value, KiCadPropertyType::Footprint, KiCadPropertyType::Datasheet, KiCadPropertyType::KiKeywords];
+    let filter = args.filter.map(|filter| filter.to_lowercase());
+    let mut rows = vec![["Name", "Value", "Footprint", "Datasheet", "Keywords"].map(String::from).to_vec()];
+    for symbol in &symbols {
+        let values = fields.iter().map(|field| symbol.property_value(field).unwrap_or_default().to_string());
+        let row: Vec<String> = std::iter::once(symbol.name().to_string()).chain(values).collect();
+        if filter.as_ref().is_some_and(|filter| !row.iter().any(|cell| cell.to_lowercase().contains(filter))) {
+            continue;
+        }
+        rows.push(row);
+    }
+    let widths: Vec<usize> = (0..rows[0].len()).map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0)).collect();
+    for row in &rows {
+        let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, &width)| format!("{cell:<width$}")).collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+    println!("{} of {} symbol(s)", rows.len() - 1, symbols.len());
+    Ok(())
+}
+
+fn search_command(args: SearchArgs, config: &Config) -> Result<(), anyhow::Error> {
+    let mut symbol_libs = args.symbol_libs;
+    if symbol_libs.is_empty() {
+        symbol_libs.extend(config.symbol_lib.iter().cloned());
+        symbol_libs.extend(config.routes.iter().map(|route| route.symbol_lib.clone()));
+        symbol_libs.sort();
+        symbol_libs.dedup();
+    }
+    if symbol_libs.is_empty() {
+        bail!("Pass --symbol-lib, or set symbol_lib or routes in the config");
+    }
+    let pattern = args.pattern.map(|pattern| RegexBuilder::new(&pattern).case_insensitive(true).build()).transpose()?;
+    let fields: Vec<(KiCadPropertyType, String)> = args
+        .fields
+        .iter()
+        .map(|(name, value)| (property_type(config.fields.normalized_name(name).unwrap_or(name)), value.clone()))
+        .collect();
+    let keywords: Vec<String> = args.keywords.iter().map(|keyword| keyword.to_lowercase()).collect();
+
+    let mut matches = 0;
+    let mut report = |nickname: &str, symbol: &KiCadSymbol| {
+        let symbol_keywords = symbol.property_value(&KiCadPropertyType::KiKeywords).unwrap_or_default().to_lowercase();
+        let symbol_keywords: Vec<&str> = symbol_keywords.split([' ', ',']).collect();
+        let matched = pattern.as_ref().is_none_or(|pattern| pattern.is_match(symbol.name()))
+            && fields.iter().all(|(field, value)| {
+                symbol.property_value(field).is_some_and(|actual| matches_wildcards(value, &actual.to_lowercase()))
+            })
+            && keywords.iter().all(|keyword| symbol_keywords.contains(&keyword.as_str()));
+        if !matched {
+            return;
+        }
+        matches += 1;
+        let value = symbol.property_value(&KiCadPropertyType::Value).unwrap_or_default();
+        let description = symbol.property_value(&KiCadPropertyType::Description).unwrap_or_default();
+        println!("{nickname}:{}  {value}  {description}", symbol.name());
+    };
+    let mut library_count = symbol_libs.len();
+    match &args.overlay_lib {
+        Some(overlay_lib) => {
+            let [base_lib] = symbol_libs.as_slice() else {
+                bail!("--overlay-lib needs exactly one library to search, the base library of the overlay");
+            };
+            let library = OverlayLibrary::open(base_lib, overlay_lib)?;
+            report_overlay_conflicts(&library, base_lib);
+            for symbol in library.base_symbols() {
+                report(&file_stem(base_lib), symbol);
+            }
+            for symbol in &library.overlay.symbols {
+                report(&file_stem(overlay_lib), symbol);
+            }
+            library_count += 1;
+        }
+        None => {
+            for path in &symbol_libs {
+                let symbol_lib = KicadSymbolLib::from_path(path)?;
+                for symbol in &symbol_lib.symbols {
+                    report(&file_stem(path), symbol);
+                }
+            }
+        }
+    }
+    println!("{matches} symbol(s) in {library_count} librar{}", if library_count == 1 { "y" } else { "ies" });
+    Ok(())
+}
+
+fn extract_command(args: ExtractArgs) -> Result<(), anyhow::Error> {
+    if args.to.exists() {
+        bail!("{} already exists", args.to.display());
+    }
+    let source = KicadSymbolLib::from_path(&args.from)?;
+    let find = |name: &str| source.symbols.iter().find(|symbol| symbol.name() == name);
+    let mut names: Vec<&str> = vec![];
+    for name in &args.names {
+        let mut symbol = find(name).ok_or(anyhow!("{} has no symbol {name}", args.from.display()))?;
+        // The symbols it is derived from, which KiCad needs in the same library
+        loop {
+            if !names.contains(&symbol.name()) {
+                names.push(symbol.name());
+            }
+            let Some(parent) = symbol.extends() else { break };
+            symbol = find(parent).ok_or(anyhow!("{} extends {parent}, which is not in the library", symbol.name()))?;
+        }
+    }
+    let mut extracted = KicadSymbolLib::new();
+    extracted.symbols = source.symbols.iter().filter(|symbol| names.contains(&symbol.name())).cloned().collect();
+    extracted.write_to_path(&args.to)?;
+    let parents = names.iter().filter(|name| !args.names.iter().any(|requested| requested == *name)).count();
+    println!("Extracted {} symbol(s) to {}, {parents} of them as parents", extracted.symbols.len(), args.to.display());
+    Ok(())
+}
+
+fn merge_command(args: MergeArgs, config: &Config) -> Result<(), anyhow::Error> {
+    let mut library = if args.into.exists() { KicadSymbolLib::from_path(&args.into)? } else { KicadSymbolLib::new() };
+    let merger = ConflictMerger::new(args.on_conflict.unwrap_or(config.symbol_conflicts));
+    let mut total = MergeReport::default();
+    for path in &args.symbol_libs {
+        let source = KicadSymbolLib::from_path(path)?;
+        let report = merger.merge(&mut library, &source.symbols)?;
+        println!("{}: {report}", path.display());
+        total.add(report);
+    }
+    library.write_to_path(&args.into)?;
+    println!("Merged into {}: {total}", args.into.display());
+    Ok(())
+}
+
+fn split_command(args: SplitArgs, config: &Config) -> Result<(), anyhow::Error> {
+    let library = KicadSymbolLib::from_path(&args.symbol_lib)?;
+    let regex = args.regex.as_deref().map(Regex::new).transpose()?;
+    let group = |symbol: &KiCadSymbol| -> Option<String> {
+        match args.by {
+            SplitBy::Prefix => Some(reference_prefix(symbol.property_value(&KiCadPropertyType::Reference)?).to_string()),
+            SplitBy::Keyword => Some(symbol.property_value(&KiCadPropertyType::KiKeywords)?.split([' ', ',']).next()?.to_lowercase()),
+            SplitBy::Regex => {
+                let captures = regex.as_ref()?.captures(symbol.name())?;
+                Some(captures.get(1).or(captures.get(0))?.as_str().to_string())
+            }
+        }
+    };
+    let mut groups: BTreeMap<String, Vec<KiCadSymbol>> = BTreeMap::new();
+    for symbol in &library.symbols {
+        // Derived symbols stay in the library of the symbol they extend
+        let mut root = symbol;
+        let mut visited = HashSet::from([symbol.name()]);
+        while let Some(parent) = root.extends().and_then(|parent| library.symbols.iter().find(|symbol| symbol.name() == parent)) {
+            if !visited.insert(parent.name()) {
+                bail!("{} is derived from itself through {}", parent.name(), root.name());
+            }
+            root = parent;
+        }
+        let name: String = group(root)
+            .filter(|group| !group.is_empty())
+            .unwrap_or("Other".to_string())
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+        groups.entry(name).or_default().push(symbol.clone());
+    }
+
+    let output_dir = args.output_dir.unwrap_or_else(|| {
+        args.symbol_lib.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new(".")).to_path_buf()
+    });
+    let stem = file_stem(&args.symbol_lib);
+    let outputs: Vec<PathBuf> = groups.keys().map(|group| output_dir.join(format!("{stem}_{group}.kicad_sym"))).collect();
+    if let Some(existing) = outputs.iter().find(|output| output.exists()).filter(|_| !args.force) {
+        bail!("{} already exists, pass --force to overwrite it", existing.display());
+    }
+    dry_run::create_dir_all(&output_dir)?;
+    for ((group, symbols), output) in groups.into_iter().zip(&outputs) {
+        let mut split = KicadSymbolLib::new();
+        split.symbols = symbols;
+        split.write_to_path(output)?;
+        println!("{group}: {} symbol(s) to {}", split.symbols.len(), output.display());
+        register_in_lib_table(output, &file_stem(output), "sym-lib-table", &output_dir, args.register, config)?;
+    }
+    let libraries = if outputs.len() == 1 { "library" } else { "libraries" };
+    println!("Split {} into {} {libraries}, it was left as it is", args.symbol_lib.display(), outputs.len());
+    Ok(())
+}
+
+fn list_footprints_command(args: ListFootprintsArgs) -> Result<(), anyhow::Error> {
+    let mut footprints = find_files_with_extension(&args.footprint_lib, "kicad_mod")?;
+    footprints.sort();
+    for path in &footprints {
+        let footprint = KiCadFootprint::from_path(path)?;
+        let models: Vec<String> = footprint
+            .models()
+            .iter()
+            .map(|model| model.path().rsplit(['/', '\\']).next().unwrap_or(model.path()).to_string())
+            .collect();
+        let models = if models.is_empty() { "no 3D model".to_string() } else { models.join(", ") };
+        println!("{}: {} pad(s), {models}", footprint.name(), footprint.pads().len());
+        if let Some(description) = footprint.description().filter(|description| !description.is_empty()) {
+            println!("    {description}");
+        }
+        if let Some(tags) = footprint.tags().filter(|tags| !tags.is_empty()) {
+            println!("    Tags: {tags}");
+        }
+    }
+    println!("{} footprint(s)", footprints.len());
+    Ok(())
+}
+
+fn rename_command(args: RenameArgs) -> Result<(), anyhow::Error> {
+    let mut symbol_lib = KicadSymbolLib::from_path(&args.symbol_lib)?;
+    symbol_lib.rename_symbol(&args.old, &args.new)?;
+    symbol_lib.write_to_path(&args.symbol_lib)?;
+    let catalog_path = Catalog::default_path(&args.symbol_lib);
+    if catalog_path.exists() {
+        let mut catalog = Catalog::from_path(&catalog_path)?;
+        for part in catalog.parts.iter_mut().filter(|part| part.symbol == args.old) {
+            part.symbol = args.new.clone();
+        }
+        catalog.write_to_path(&catalog_path)?;
+    }
+    let nickname = file_stem(&args.symbol_lib);
+    println!("Renamed {nickname}:{} to {nickname}:{}, schematics using it have to be updated", args.old, args.new);
+    Ok(())
+}
+
+fn dedupe_command(args: DedupeArgs, config: &Config) -> Result<(), anyhow::Error> {
+    let mut symbol_lib = KicadSymbolLib::from_path(&args.symbol_lib)?;
+    let groups = find_duplicates(&symbol_lib.symbols, &config.fields);
+    if groups.is_empty() {
+        println!("No duplicate symbols in {}", args.symbol_lib.display());
+        return Ok(());
+    }
+    let mut merged = vec![];
+    for group in &groups {
+        println!("Duplicates ({}): {}", group.reason, group.names.join(", "));
+        let keep = match args.yes {
+            true => Some(group.default_keep(&symbol_lib.symbols).to_string()),
+            false => ask_symbol_to_keep(group, &symbol_lib.symbols)?,
+        };
+        let Some(keep) = keep else { continue };
+        let removed: Vec<String> = group.names.iter().filter(|name| **name != keep).cloned().collect();
+        let symbol = symbol_lib.symbols.iter().find(|symbol| symbol.name() == keep);
+        if let Some(parent) = symbol.and_then(KiCadSymbol::extends).filter(|parent| removed.iter().any(|name| name == parent)) {
+            println!("Skipped, {keep} is derived from {parent}");
+            continue;
+        }
+        merge_duplicates(&mut symbol_lib.symbols, &keep, &removed, args.aliases);
+        println!("Merged {} into {keep}", removed.join(", "));
+        merged.extend(removed);
+    }
+    if merged.is_empty() {
+        println!("No duplicates merged");
+        return Ok(());
+    }
+    symbol_lib.write_to_path(&args.symbol_lib)?;
+
+    let catalog_path = Catalog::default_path(&args.symbol_lib);
+    if !args.aliases && catalog_path.exists() {
+        let mut catalog = Catalog::from_path(&catalog_path)?;
+        catalog.parts.retain(|part| !merged.contains(&part.symbol));
+        catalog.write_to_path(&catalog_path)?;
+    }
+    let outcome = if args.aliases { "kept as aliases" } else { "removed" };
+    println!("Merged {} duplicate symbol(s), {outcome}", merged.len());
+    Ok(())
+}
+
+/// Asks which symbol of a group of duplicates to keep, `None` to leave the
+/// group as it is. Groups are left alone when there is nobody to ask.
+fn ask_symbol_to_keep(group: &DuplicateGroup, symbols: &[KiCadSymbol]) -> Result<Option<String>, anyhow::Error> {
+    if !io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    let default = group.default_keep(symbols);
+    for (number, name) in group.names.iter().enumerate() {
+        println!("  {}) {name}", number + 1);
+    }
+    loop {
+        print!("Keep which symbol? [number, Enter for {default}, s to skip] ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        match answer.trim() {
+            "" => return Ok(Some(default.to_string())),
+            "s" | "S" => return Ok(None),
+            answer => match answer.parse::<usize>().ok().and_then(|number| group.names.get(number.wrapping_sub(1))) {
+                Some(name) => return Ok(Some(name.clone())),
+                None => println!("There is no symbol {answer}"),
+            },
+        }
+    }
+}
+
+fn remove_command(args: RemoveArgs, config: &Config) -> Result<(), anyhow::Error> {
+    let mut symbol_lib = KicadSymbolLib::from_path(&args.symbol_lib)?;
+    let missing: Vec<&str> =
+        args.names.iter().map(String::as_str).filter(|name| !symbol_lib.symbols.iter().any(|symbol| symbol.name() == *name)).collect();
+    if !missing.is_empty() {
+        bail!("{} has no symbol {}", args.symbol_lib.display(), missing.join(", "));
+    }
+    let removed_names = |name: &str| args.names.iter().any(|removed| removed == name);
+    if let Some(derived) = symbol_lib.symbols.iter().find(|symbol| !removed_names(symbol.name()) && symbol.extends().is_some_and(removed_names)) {
+        bail!("{} is derived from {}, remove it as well", derived.name(), derived.extends().unwrap_or_default());
+    }
+    let (removed, kept): (Vec<KiCadSymbol>, Vec<KiCadSymbol>) =
+        symbol_lib.symbols.drain(..).partition(|symbol| removed_names(symbol.name()));
+    symbol_lib.symbols = kept;
+    symbol_lib.write_to_path(&args.symbol_lib)?;
+    for symbol in &removed {
+        println!("Removed symbol {}", symbol.name());
+    }
+
+    let catalog_path = Catalog::default_path(&args.symbol_lib);
+    if catalog_path.exists() {
+        let mut catalog = Catalog::from_path(&catalog_path)?;
+        catalog.parts.retain(|part| !args.names.contains(&part.symbol));
+        catalog.write_to_path(&catalog_path)?;
+    }
+
+    let Some(footprint_lib) = args.footprint_lib.filter(|_| args.with_footprints) else { return Ok(()) };
+    let nickname = file_stem(&footprint_lib);
+    let footprint_name = |symbol: &KiCadSymbol| {
+        let footprint = symbol.property_value(&KiCadPropertyType::Footprint)?;
+        let (library, name) = footprint.split_once(':')?;
+        (library == nickname).then(|| name.to_string())
+    };
+    let still_used: Vec<String> = symbol_lib.symbols.iter().filter_map(footprint_name).collect();
+    let mut unused: Vec<String> = removed.iter().filter_map(footprint_name).filter(|name| !still_used.contains(name)).collect();
+    unused.sort();
+    unused.dedup();
+
+    let mut models = vec![];
+    for name in &unused {
+        let path = footprint_lib.join(format!("{name}.kicad_mod"));
+        if !path.is_file() {
+            continue;
+        }
+        let footprint = KiCadFootprint::from_path(&path)?;
+        models.extend(footprint.models().iter().map(|model| model.path().rsplit(['/', '\\']).next().unwrap_or(model.path()).to_string()));
+        dry_run::remove_file(&path)?;
+        println!("Removed {}", path.display());
+    }
+    let model_dir = args.model_dir.unwrap_or_else(|| default_model_dir(&footprint_lib, config));
+    if models.is_empty() || !model_dir.is_dir() {
+        return Ok(());
+    }
+    for model in find_orphaned_models(&model_dir, &[footprint_lib], &config.model_path_variables, None)? {
+        if model.file_name().is_some_and(|name| models.iter().any(|removed| removed.as_str() == name)) {
+            dry_run::remove_file(&model)?;
+            println!("Removed {}", model.display());
+        }
+    }
+    Ok(())
+}
+
+fn remove_footprint_command(args: RemoveFootprintArgs) -> Result<(), anyhow::Error> {
+    let path = args.footprint_lib.join(format!("{}.kicad_mod", args.name));
+    if !path.is_file() {
+        bail!("{} does not exist", path.display());
+    }
+    let footprint = format!("{}:{}", file_stem(&args.footprint_lib), args.name);
+
+    let mut users = vec![];
+    if let Some(symbol_lib_path) = &args.symbol_lib {
+        let symbol_lib = KicadSymbolLib::from_path(symbol_lib_path)?;
+        for symbol in &symbol_lib.symbols {
+            if symbol.property_value(&KiCadPropertyType::Footprint).is_some_and(|value| value == footprint) {
+                users.push(format!("symbol {} of {}", symbol.name(), symbol_lib_path.display()));
+            }
+        }
+    }
+    for file in find_footprint_usages(&args.projects, &footprint)? {
+        users.push(file.display().to_string());
+    }
+    for user in &users {
+        println!("{footprint} is used by {user}");
+    }
+    if !users.is_empty() && !args.force {
+        bail!("{footprint} is still in use, remove it anyway with --force");
+    }
+
+    dry_run::remove_file(&path)?;
+    println!("Removed {}", path.display());
+    Ok(())
+}
+
+fn orphaned_models_command(args: OrphanedModelsArgs, config: &Config) -> Result<(), anyhow::Error> {
+    let model_dir = args.model_dir.unwrap_or_else(|| default_model_dir(&args.footprint_libs[0], config));
+    let orphaned =
+        find_orphaned_models(&model_dir, &args.footprint_libs, &config.model_path_variables, args.project.as_deref())?;
+    for model in &orphaned {
+        if args.delete {
+            dry_run::remove_file(model)?;
+            println!("Deleted {}", model.display());
+        } else {
+            println!("{}", model.display());
+        }
+    }
+    println!("{} orphaned model(s) in {}", orphaned.len(), model_dir.display());
+    Ok(())
+}
+
+fn export_dbl_command(args: ExportDblArgs, config: &Config) -> Result<(), anyhow::Error> {
+    let library = KicadSymbolLib::from_path(&args.symbol_lib)?;
+    let nickname = file_stem(&args.symbol_lib);
+    let output = args.output.unwrap_or_else(|| args.symbol_lib.with_extension("kicad_dbl"));
+    let table = args.table.unwrap_or_else(|| nickname.clone());
+    // `${CWD}` is the directory of the definition for KiCad
+    let connection_string =
+        args.connection_string.unwrap_or_else(|| format!("Driver={{SQLite3}};Database=${{CWD}}/{nickname}.sqlite"));
+    write_database_library(&output, &library, &config.fields, &nickname, &table, &connection_string)?;
+
+    let columns = KEY_COLUMNS.map(str::to_string).into_iter().chain(part_columns(&library.symbols, &config.fields));
+    println!("Wrote {}", output.display());
+    println!("Columns of table {table}: {}", columns.collect::<Vec<_>>().join(", "));
+    println!("Symbols are referred to as {nickname}:<name>, add {nickname} to the sym-lib-table");
+    Ok(())
+}
+
+fn parse_xyz(value: &str) -> Result<[f32; 3], String> {
+    let values: Vec<f32> = value
+        .split(',')
+        .map(|value| value.trim().parse().map_err(|_| format!("{value} is not a number")))
+        .collect::<Result<_, _>>()?;
+    values.try_into().map_err(|_| "expected x,y,z".to_string())
+}
+
+fn adjust_model_command(args: AdjustModelArgs) -> Result<(), anyhow::Error> {
+    let adjustment = ModelAdjustment { offset: args.offset, scale: args.scale, rotate: args.rotate };
+    for name in &args.footprints {
+        let path = args.footprint_lib.join(format!("{name}.kicad_mod"));
+        if !path.is_file() {
+            bail!("{} has no footprint {name}", args.footprint_lib.display());
+        }
+        if adjustment != ModelAdjustment::default() {
+            let adjusted = adjust_models(&path, &adjustment, true)?;
+            println!("{name}: adjusted {adjusted} model(s)");
+            continue;
+        }
+        let footprint = KiCadFootprint::from_path(&path)?;
+        if footprint.models().is_empty() {
+            println!("{name}: no 3D model");
+        }
+        let xyz = |values: [f32; 3]| values.map(|value| value.to_string()).join(",");
+        for model in footprint.models() {
+            println!("{name}: {}", model.path());
+            println!("    offset {}  scale {}  rotate {}", xyz(model.offset()), xyz(model.scale()), xyz(model.rotate()));
+        }
+    }
+    Ok(())
+}
+
+/// The findings of the policy and of the rules it makes mandatory on the
+/// symbols of an import, as they would be imported, and on its footprints.
+fn policy_violations(pipeline: &ImportPipeline, files: &ClassifiedFiles, policy: &Policy, config: &Config) -> Result<Vec<Finding>, anyhow::Error> {
+    let mut rules: Vec<(&str, Box<dyn Validator>)> = vec![("policy", Box::new(PolicyChecks::new(policy)))];
+    for rule in &policy.mandatory_rules {
+        match rule.as_str() {
+            "unit-balance" => rules.push(("unit-balance", Box::new(UnitPinBalance))),
+            "reference-prefix" => rules.push(("reference-prefix", Box::new(ReferencePrefixes::new(config)))),
+            "required-fields" => rules.push(("required-fields", Box::new(RequiredFields::new(config)))),
+            _ => {}
+        }
+    }
+    let mut findings = vec![];
+    // Parsed again by the import, so what parsing leaves out is counted once
+    for symbol in &fidelity::uncounted(|| pipeline.transformed_symbols(files))? {
+        for (rule, validator) in &rules {
+            let violations = validator.validate(symbol).into_iter().filter(|finding| finding.severity != Severity::Info);
+            findings.extend(violations.map(|finding| finding.with_rule(rule)));
+        }
+    }
+    let disabled: Vec<String> =
+        lint::RULES.iter().map(|(rule, _)| rule.to_string()).filter(|rule| !policy.mandatory_rules.contains(rule)).collect();
+    if disabled.len() < lint::RULES.len() {
+        for path in &files.footprints {
+            findings.extend(lint::lint_footprint(&KiCadFootprint::from_path(path)?, path, &disabled, config.courtyard_clearance));
+        }
+    }
+    Ok(findings)
+}
+
+/// Fixes the layer names of a footprint and prints the corrections. A dry
+/// run leaves footprints in a library alone and fixes a copy instead.
+fn report_layer_fixes(footprint: &Path, in_library: bool) -> Result<(), anyhow::Error> {
+    let name = footprint.file_name().unwrap_or_default().to_string_lossy();
+    let corrections = if in_library && dry_run::is_enabled() {
+        let copy = Temp::new_file()?;
+        fs::copy(footprint, &copy)?;
+        let corrections = fix_layer_names(&copy)?;
+        if !corrections.is_empty() {
+            dry_run::skip_write(footprint);
+        }
+        corrections
+    } else {
+        fix_layer_names(footprint)?
+    };
+    for (old, new) in corrections {
+        println!("{name}: layer {old} -> {new}");
+    }
+    Ok(())
+}
+
+fn convert_command(args: ConvertArgs, config: &Config) -> Result<(), anyhow::Error> {
+    let output = args.output.unwrap_or_else(|| args.legacy_lib.with_extension("kicad_sym"));
+    let project_dir = output.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let (library, nickname) =
+        resolve_nickname_collision(&output, "sym-lib-table", project_dir, args.register, config.nickname_collisions, config)?;
+    if library != output {
+        bail!("A converted library cannot be merged into {}, rename it or choose another --output", library.display());
+    }
+    if output.exists() && !args.force {
+        bail!("{} already exists, pass --force to overwrite it", output.display());
+    }
+    let (symbol_lib, skipped) = KicadSymbolLib::from_legacy_path(&args.legacy_lib)?;
+    symbol_lib.write_to_path(&output)?;
+
+    println!("Converted {} symbols to {}", symbol_lib.symbols.len(), output.display());
+    if !skipped.is_empty() {
+        println!("Skipped {} symbol(s) that could not be converted:", skipped.len());
+        for skipped in &skipped {
+            println!("{}: {}", skipped.name, skipped.error);
+        }
+    }
+    register_in_lib_table(&output, &nickname, "sym-lib-table", project_dir, args.register, config)
+}
+
+fn bom_command(args: BomArgs, config: &Config) -> Result<(), anyhow::Error> {
+    let output = args.output.unwrap_or_else(|| {
+        let stem = args.bom.file_stem().unwrap_or_default().to_string_lossy();
+        match args.bom.extension() {
+            Some(extension) => args.bom.with_file_name(format!("{stem}-enriched.{}", extension.to_string_lossy())),
+            None => args.bom.with_file_name(format!("{stem}-enriched")),
+        }
+    });
+    if output == args.bom {
+        bail!("The enriched BOM would overwrite {}", args.bom.display());
+    }
+    let symbol_lib = KicadSymbolLib::from_path(&args.symbol_lib)?;
+    let report = enrich_bom(&args.bom, &output, &symbol_lib.symbols, &config.fields)?;
+
+    for line in &report.unmatched {
+        println!("{line}: no part in {}", args.symbol_lib.display());
+    }
+    println!("Enriched {} of {} BOM line(s), written to {}", report.enriched, report.lines, output.display());
+    Ok(())
+}
+
+fn snapshot_command(command: SnapshotCommand) -> Result<(), anyhow::Error> {
+    match command {
+        SnapshotCommand::Create { symbol_lib, footprint_dir, label } => {
+            let history_path = SnapshotHistory::default_path(&symbol_lib);
+            let mut history = SnapshotHistory::from_path(&history_path)?;
+            let state = LibraryState::capture(&KicadSymbolLib::from_path(&symbol_lib)?, footprint_dir.as_deref())?;
+            println!("Created snapshot {}", history.add(label, footprint_dir, state));
+            history.write_to_path(&history_path)?;
+        }
+        SnapshotCommand::List { symbol_lib } => {
+            let history = SnapshotHistory::from_path(&SnapshotHistory::default_path(&symbol_lib))?;
+            for snapshot in &history.snapshots {
+                println!("{snapshot}");
+            }
+        }
+        SnapshotCommand::Diff { symbol_lib, footprint_dir, from, to } => {
+            let history = SnapshotHistory::from_path(&SnapshotHistory::default_path(&symbol_lib))?;
+            let from = history.find(&from)?;
+            let current;
+            let to = match to {
+                Some(to) => &history.find(&to)?.state,
+                None => {
+                    let footprint_dir = footprint_dir.or(from.footprint_dir.clone());
+                    current = LibraryState::capture(&KicadSymbolLib::from_path(&symbol_lib)?, footprint_dir.as_deref())?;
+                    &current
+                }
+            };
+            let changes = diff(&from.state, to);
+            for change in &changes {
+                println!("{change}");
+            }
+            println!("{} change(s)", changes.len());
+        }
+    }
+    Ok(())
+}
+
+fn lifecycle_command(command: LifecycleCommand, config: &Config) -> Result<(), anyhow::Error> {
+    match command {
+        LifecycleCommand::Set { symbol_lib: symbol_lib_path, status, symbols } => {
+            let mut symbol_lib = KicadSymbolLib::from_path(&symbol_lib_path)?;
+            for name in &symbols {
+                let Some(symbol) = symbol_lib.symbols.iter_mut().find(|symbol| symbol.name() == name) else {
+                    bail!("Symbol {name} not found in {}", symbol_lib_path.display());
+                };
+                set_symbol_lifecycle(symbol, status, &config.fields);
+                println!("{name}: {status}");
+            }
+            symbol_lib.write_to_path(&symbol_lib_path)?;
+        }
+        LifecycleCommand::Refresh { symbol_lib: symbol_lib_path, api_url, field, timeout } => {
+            let mut symbol_lib = KicadSymbolLib::from_path(&symbol_lib_path)?;
+            let agent = lifecycle_agent(Duration::from_secs(timeout));
+            let mut updated = 0;
+            for symbol in symbol_lib.symbols.iter_mut() {
+                let Some(mpn) = config.fields.symbol_mpn(symbol).map(str::to_string) else { continue };
+                match fetch_lifecycle(&agent, &api_url, &field, &mpn) {
+                    Ok(status) => {
+                        println!("{} ({mpn}): {status}", symbol.name());
+                        set_symbol_lifecycle(symbol, status, &config.fields);
+                        updated += 1;
+                    }
+                    Err(error) => println!("{} ({mpn}): could not refresh lifecycle: {error}", symbol.name()),
+                }
+            }
+            symbol_lib.write_to_path(&symbol_lib_path)?;
+            println!("Refreshed lifecycle of {updated} symbols");
+        }
+        LifecycleCommand::Report { symbol_lib, projects } => {
+            let symbol_lib = KicadSymbolLib::from_path(&symbol_lib)?;
+            let usages = find_symbol_usages(&projects)?;
+            for symbol in &symbol_lib.symbols {
+                let Some(status @ (Lifecycle::Nrnd | Lifecycle::Obsolete)) = symbol_lifecycle(symbol, &config.fields) else { continue };
+                println!("{}: {status}", symbol.name());
+                for schematic in usages.get(symbol.name()).into_iter().flatten() {
+                    println!("    used in {}", schematic.display());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn dimensions_command(args: DimensionsArgs) -> Result<(), anyhow::Error> {
+    let catalog_path = args.catalog.unwrap_or_else(|| Catalog::default_path(&args.symbol_lib));
+    let catalog = Catalog::from_path(&catalog_path)?;
+    let format = |value: Option<f32>| value.map_or("?".to_string(), |value| format!("{value:.2}"));
+
+    for part in &catalog.parts {
+        let Some(dimensions) = &part.dimensions else { continue };
+        if let Some(taller_than) = args.taller_than {
+            if dimensions.z.is_none_or(|z| z <= taller_than) {
+                continue;
+            }
+        }
+        println!(
+            "{}: {} x {} x {} mm",
+            part.symbol,
+            format(dimensions.x),
+            format(dimensions.y),
+            format(dimensions.z)
+        );
+    }
+    Ok(())
+}
+
+fn watch_command(args: WatchArgs, config: &Config) -> Result<(), anyhow::Error> {
+    let mut targets = config.watch.clone();
+    if let (Some(dir), Some(footprint_dir), Some(symbol_lib)) = (args.dir, args.footprint_dir, args.symbol_lib) {
+        targets.push(WatchTarget { dir, symbol_lib, footprint_dir });
+    }
+    let options = WatchOptions {
+        poll_interval: Duration::from_secs(args.poll_interval),
+        settle_time: Duration::from_secs(args.settle_time),
+        retries: args.retries,
+    };
+
+    for target in &targets {
+        println!("Watching {} for {}", target.dir.display(), target.symbol_lib.display());
+    }
+    watch(&targets, &options, |archive, target| {
+        let args = ImportArgs {
+            input_zip: vec![archive.to_path_buf()],
+            input_dir: None,
+            verbose: false,
+            footprint_dir: Some(target.footprint_dir.clone()),
+            project: None,
+            create_lib: false,
+            force: false,
+            model_dir: None,
+            model_path_var: None,
+            model_path_style: None,
+            register: None,
+            prefer_model: None,
+            compress_models: false,
+            rename_models: false,
+            no_3d: false,
+            only_symbols: false,
+            only_footprints: false,
+            on_conflict: None,
+            on_nickname_collision: None,
+            symbol_lib: Some(target.symbol_lib.clone()),
+            overlay_lib: None,
+            ibis_dir: None,
+            link_ibis: false,
+            part_database: None,
+            catalog: None,
+            fix_references: false,
+            category: None,
+            rewrite_footprint_lib: false,
+            fix_layers: false,
+            fp_rename_patterns: vec![],
+        };
+        import(args, archive, config).map(|_| ())
+    })
+}
+
+/// Asks a yes/no question on the terminal, no if there is none.
+fn confirm(question: &str) -> Result<bool, anyhow::Error> {
+    if !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    print!("{question} [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Where the 3D models of a footprint library go: `<library>.3dshapes` in
+/// `model_library_dir` of the config for the libraries the config lays out,
+/// or else next to the library.
+fn default_model_dir(footprint_lib: &Path, config: &Config) -> PathBuf {
+    let model_dir = footprint_lib.with_extension("3dshapes");
+    let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut layout = config.footprint_lib.iter().chain(config.routes.iter().filter_map(|route| route.footprint_lib.as_ref()));
+    match &config.model_library_dir {
+        Some(dir) if layout.any(|library| absolute(library) == absolute(footprint_lib)) => {
+            dir.join(model_dir.file_name().unwrap_or_default())
+        }
+        _ => model_dir,
+    }
+}
+
+/// Writes an empty symbol library, and the directories it is in.
+fn create_symbol_library(path: &Path) -> Result<(), anyhow::Error> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        dry_run::create_dir_all(dir)?;
+    }
+    KicadSymbolLib::new().write_to_path(path)?;
+    if !dry_run::is_enabled() {
+        println!("Created symbol library {}", path.display());
+    }
+    Ok(())
+}
+
+/// Where an import goes to.
+struct ImportDestination {
+    symbol_lib: PathBuf,
+    footprint_lib: PathBuf,
+    /// Without a project, the directory of the symbol library
+    project_dir: PathBuf,
+    /// The library table the libraries are added to unless one is given
+    register: Option<LibTableScope>,
+    /// Whether a missing symbol library is created without asking
+    create_lib: bool,
+}
+
+/// The destination of an import. Libraries not given are the ones of the
+/// route of the parts, of the project, or those of the config, the user's
+/// libraries, or else, once confirmed, those proposed in the directory of the
+/// detected KiCad.
+fn import_destination(
+    args: &ImportArgs,
+    route: Option<&LibraryRoute>,
+    config: &Config,
+) -> Result<ImportDestination, anyhow::Error> {
+    let project = match &args.project {
+        Some(project) if project.extension().is_none_or(|extension| extension != "kicad_pro") => {
+            bail!("{} is not a KiCad project file (.kicad_pro)", project.display())
+        }
+        Some(project) if !project.is_file() => bail!("{} does not exist", project.display()),
+        Some(project) => project.parent().filter(|dir| !dir.as_os_str().is_empty()).or(Some(Path::new("."))),
+        None => None,
+    };
+    let project_libs = args.project.as_ref().zip(project).map(|(file, dir)| dir.join("libs").join(file_stem(file)));
+    let symbol_lib = args
+        .symbol_lib
+        .clone()
+        .or(route.map(|route| route.symbol_lib.clone()))
+        .or(project_libs.as_ref().map(|libs| libs.with_extension("kicad_sym")))
+        .or(config.symbol_lib.clone());
+    let footprint_lib = args
+        .footprint_dir
+        .clone()
+        .or(route.and_then(|route| route.footprint_lib.clone()))
+        .or(project_libs.as_ref().map(|libs| libs.with_extension("pretty")))
+        .or(config.footprint_lib.clone());
+    let missing = "Give --symbol-lib and --footprint-lib, --project, or `symbol_lib` and `footprint_lib` in the config";
+    let (symbol_lib, footprint_lib, proposed) = match (symbol_lib, footprint_lib) {
+        (Some(symbol_lib), Some(footprint_lib)) => (symbol_lib, footprint_lib, false),
+        (symbol_lib, footprint_lib) => {
+            let installation = KiCadInstallation::detect().ok_or(anyhow!("{missing}, no KiCad installation was found"))?;
+            let (default_symbol_lib, default_footprint_lib) =
+                installation.default_libraries().ok_or(anyhow!("{missing}, KiCad has no user directory"))?;
+            let symbol_lib = symbol_lib.unwrap_or(default_symbol_lib);
+            let footprint_lib = footprint_lib.unwrap_or(default_footprint_lib);
+            let flatpak = if installation.is_flatpak() { " (flatpak)" } else { "" };
+            println!("Found KiCad {}{flatpak} in {}", installation.version(), installation.config_dir().display());
+            let question = format!("Import into {} and {}?", symbol_lib.display(), footprint_lib.display());
+            if !confirm(&question)? {
+                bail!("{missing}, or confirm importing into {} and {}", symbol_lib.display(), footprint_lib.display());
+            }
+            (symbol_lib, footprint_lib, true)
+        }
+    };
+    let project_dir = project.or(symbol_lib.parent().filter(|dir| !dir.as_os_str().is_empty())).unwrap_or(Path::new("."));
+    // The proposed libraries are made available to every project
+    let global_tables = config.sym_lib_table.is_some() && config.fp_lib_table.is_some();
+    let register = match (project, proposed) {
+        (Some(_), _) => Some(LibTableScope::Project),
+        (None, true) if global_tables => Some(LibTableScope::Global),
+        _ => None,
+    };
+    Ok(ImportDestination {
+        project_dir: project_dir.to_path_buf(),
+        symbol_lib,
+        footprint_lib,
+        register,
+        create_lib: project.is_some() || proposed,
+    })
+}
+
+/// The files a name with wildcards in its last component stands for, like
+/// `Downloads/*.zip`, for shells that do not expand them.
+fn expand_wildcards(input: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let name = input.file_name().unwrap_or_default().to_string_lossy();
+    if !name.contains(['*', '?']) {
+        return Ok(vec![input.to_path_buf()]);
+    }
+    let dir = input.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut matches = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = input.with_file_name(entry?.file_name());
+        if path.is_file() && matches_wildcards(&name, &path.file_name().unwrap_or_default().to_string_lossy()) {
+            matches.push(path);
+        }
+    }
+    if matches.is_empty() {
+        bail!("No file matches {}", input.display());
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+/// What an import added to the libraries.
+#[derive(Default)]
+struct ImportSummary {
+    symbols: usize,
+    footprints: usize,
+}
+
+/// Imports the archives of `--zip`, or the folder of `--dir`, one after the
+/// other into the same libraries. An archive that fails does not stop the
+/// others, they are listed in the summary.
+fn import_all(args: ImportArgs, config: &Config) -> Result<(), anyhow::Error> {
+    let inputs = match &args.input_dir {
+        Some(dir) => vec![dir.clone()],
+        None => args.input_zip.iter().map(|input| expand_wildcards(input)).collect::<Result<Vec<_>, _>>()?.concat(),
+    };
+    if let [input] = inputs.as_slice() {
+        return import(args.clone(), input, config).map(|_| ());
+    }
+    let mut total = ImportSummary::default();
+    let mut failed = vec![];
+    for input in &inputs {
+        println!("=== {}", input.display());
+        match import(args.clone(), input, config) {
+            Ok(summary) => {
+                total.symbols += summary.symbols;
+                total.footprints += summary.footprints;
+            }
+            Err(error) => {
+                println!("error: {}: {error:#}", input.display());
+                failed.push(input.display().to_string());
+            }
+        }
+    }
+    println!(
+        "Imported {} of {} archives: {} symbol(s), {} footprint(s)",
+        inputs.len() - failed.len(),
+        inputs.len(),
+        total.symbols,
+        total.footprints
+    );
+    if !failed.is_empty() {
+        bail!("{} archive(s) could not be imported: {}", failed.len(), failed.join(", "));
+    }
+    Ok(())
+}
+
+fn import(args: ImportArgs, input: &Path, config: &Config) -> Result<ImportSummary, anyhow::Error> {
+    let mut pipeline = ImportPipeline::new(config);
+    let easyeda = args.input_dir.is_none() && input.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    if easyeda {
+        pipeline.extractor(EasyEdaExtractor);
+    } else if args.input_dir.is_some() {
+        pipeline.extractor(DirectoryExtractor);
+    }
+    let temp_extraction_dir = Temp::new_dir()?;
+    println!("Temp extraction dir: {:?}", temp_extraction_dir);
+
+    let mut files = pipeline.extract(input, temp_extraction_dir.as_path())?;
+    if args.no_3d || args.only_symbols {
+        files.models.clear();
+    }
+    if args.only_symbols {
+        files.footprints.clear();
+    }
+    if args.only_footprints {
+        files.symbol_libs.clear();
+        files.legacy_symbol_libs.clear();
+        files.ibis_models.clear();
+    }
+
+    if easyeda {
+        files.layout = ArchiveLayout::EasyEda;
+    }
+    if args.verbose {
+        println!("files: {files:?}");
+        for file in &files.ignored {
+            println!("debug: ignored {}", file.strip_prefix(&temp_extraction_dir).unwrap_or(file).display());
+        }
+    }
+    if files.layout != ArchiveLayout::Generic {
+        println!("{} archive", files.layout);
+    }
+
+    let route = match (&config.routes[..], &args.category) {
+        ([], None) => None,
+        ([], Some(_)) => bail!("--category needs `routes` in the config"),
+        (routes, category) => {
+            // Parsed again by the import, so what parsing leaves out is counted once
+            let parsed = fidelity::uncounted(|| pipeline.parse(&files))?;
+            route(routes, category.as_deref(), &parsed.symbols)?
+        }
+    };
+    if let Some(route) = route {
+        println!("Importing {} parts", route.category);
+    }
+    let destination = import_destination(&args, route, config)?;
+    let (symbol_lib, footprint_dir, project_dir) = (destination.symbol_lib, destination.footprint_lib, destination.project_dir);
+    let register = args.register.or(destination.register);
+    let collisions = args.on_nickname_collision.unwrap_or(config.nickname_collisions);
+    let (footprint_dir, footprint_nickname) =
+        resolve_nickname_collision(&footprint_dir, "fp-lib-table", &project_dir, register, collisions, config)?;
+    let target_lib = args.overlay_lib.clone().unwrap_or_else(|| symbol_lib.clone());
+    let (target_lib, symbol_nickname) = match args.only_footprints {
+        true => (target_lib.clone(), file_stem(&target_lib)),
+        false => resolve_nickname_collision(&target_lib, "sym-lib-table", &project_dir, register, collisions, config)?,
+    };
+    let (symbol_lib, overlay_lib) = match args.overlay_lib {
+        Some(_) => (symbol_lib, Some(target_lib.clone())),
+        None => (target_lib.clone(), None),
+    };
+    println!("Input: {}", input.display());
+    println!("Footprint library: {}", footprint_dir.display());
+    println!("Symbol library: {}", symbol_lib.display());
+
+    pipeline.add_transform(StripProperties::new(config));
+    pipeline.add_transform(NormalizeFields::new(config));
+    if args.fix_references {
+        pipeline.add_transform(FixReferencePrefixes::new(config));
+    }
+    if let Some(policy_config) = &config.policy {
+        let policy = load_policy(policy_config)?;
+        pipeline.add_transform(ApplyFieldTemplates::new(&policy));
+        // Before anything is created or copied, so a violation leaves the libraries as they were
+        let violations = policy_violations(&pipeline, &files, &policy, config)?;
+        for finding in &violations {
+            println!("{finding}");
+        }
+        if !violations.is_empty() {
+            bail!("{} policy violation(s), nothing was imported", violations.len());
+        }
+    }
+
+    if let Some(overlay_lib) = &overlay_lib {
+        println!("Overlay library: {}", overlay_lib.display());
+    } else if !args.only_footprints && !symbol_lib.exists() {
+        // The libraries of a project and the proposed ones are created by this tool
+        let question = format!("{} does not exist, create it?", symbol_lib.display());
+        if !args.create_lib && !destination.create_lib && !confirm(&question)? {
+            bail!("Symbol library {} does not exist, pass --create-lib to create it", symbol_lib.display());
+        }
+        create_symbol_library(&symbol_lib)?;
+    } else if !args.only_footprints && !is_writable(&symbol_lib) {
+        bail!(
+            "Symbol library {} is read-only, use --overlay-lib to add symbols to a separate library",
+            symbol_lib.display()
+        );
+    }
+    let global_tables = [&config.sym_lib_table, &config.fp_lib_table];
+    if register.or(config.register_libraries) == Some(LibTableScope::Global) && global_tables.contains(&&None) {
+        bail!("--register global needs `sym_lib_table` and `fp_lib_table` in the config or a KiCad installation");
+    }
+
+    if !args.only_symbols {
+        prepare_footprint_library(&footprint_dir, args.force)?;
+    }
+
+    let fp_lib_tables = footprint_lib_tables(config, &project_dir)?;
+    let resolver = FootprintResolver::new(&footprint_dir, &footprint_nickname, fp_lib_tables);
+    pipeline.add_validator(FootprintReferences::new(resolver.clone()));
+    pipeline.add_validator(PinPadConsistency::new(resolver));
+
+    let source = input
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let model_dir = args.model_dir.clone().unwrap_or_else(|| default_model_dir(&footprint_dir, config));
+    let rename_patterns = [args.fp_rename_patterns.as_slice(), &config.footprint_rename_patterns].concat();
+    let mut copied_models = vec![];
+    if !files.models.is_empty() {
+        let preferred_model = args.prefer_model.unwrap_or(config.preferred_model_format);
+        let variable = args.model_path_var.as_ref().or(config.model_path_variable.as_ref());
+        let style = args.model_path_style.or(args.project.is_some().then_some(ModelPathStyle::Project));
+        let model_references = match (style.or(config.model_path_style), variable) {
+            (Some(ModelPathStyle::Project), _) => {
+                ModelReferences::variable("KIPRJMOD", &config.model_path_variables, &project_dir)?
+            }
+            (Some(ModelPathStyle::Library), _) => ModelReferences::Relative(footprint_dir.clone()),
+            (Some(ModelPathStyle::Absolute), _) => ModelReferences::Absolute,
+            (_, Some(name)) => ModelReferences::variable(name, &config.model_path_variables, &project_dir)?,
+            (Some(ModelPathStyle::Variable), None) => match &config.model_dir {
+                Some(dir) => ModelReferences::Directory(dir.clone()),
+                None => bail!("The variable model path style needs --model-path-var, or `model_dir` in the config"),
+            },
+            (None, None) => config.model_dir.clone().map_or(ModelReferences::Absolute, ModelReferences::Directory),
+        };
+        println!("Copying {} 3D model file(s) to {}", files.models.len(), model_dir.display());
+        dry_run::create_dir_all(&model_dir)?;
+        let mut model_names = BTreeMap::new();
+        if args.rename_models || config.rename_models {
+            let footprints: Vec<(PathBuf, String)> =
+                files.footprints.iter().map(|footprint| (footprint.clone(), renamed(&rename_patterns, &file_stem(footprint)))).collect();
+            model_names = names_after_footprints(&footprints, &files.models)?;
+        }
+        let compress = args.compress_models || config.compress_models;
+        let mut imported_models = import_models(&files.models, &model_dir, compress, &model_names)?;
+        if let Some(command) = &config.step_to_wrl {
+            let step_models: Vec<ImportedModel> =
+                imported_models.iter().filter(|model| model_format(&model.path) == Some(ModelFormat::Step)).cloned().collect();
+            for model in &step_models {
+                match convert_to_wrl(model, command) {
+                    Ok(wrl) if !imported_models.contains(&wrl) => imported_models.push(wrl),
+                    Ok(_) => {}
+                    Err(error) => println!("warning: {}: no WRL model: {error}", model.path.display()),
+                }
+            }
+        }
+        copied_models = imported_models.iter().map(|model| model.path.clone()).collect();
+        copied_models.sort();
+        copied_models.dedup();
+        // Before copying, so a footprint imported before compares equal
+        for footprint in &files.footprints {
+            let rewritten = rewrite_model_paths(footprint, &imported_models, &model_references, preferred_model)?;
+            if rewritten > 0 {
+                let name = footprint.file_name().unwrap_or_default().to_string_lossy();
+                println!("{name}: pointed {rewritten} model reference(s) at the imported models");
+            }
+        }
+    }
+
+    for footprint in &files.footprints {
+        if let Some(version) = upgrade_footprint(footprint)? {
+            let name = footprint.file_name().unwrap_or_default().to_string_lossy();
+            println!("{name}: upgraded from format version {version} to {FOOTPRINT_VERSION}");
+        }
+        if args.fix_layers {
+            report_layer_fixes(footprint, false)?;
+        }
+        if let Some(adjustment) = config.model_adjustments.get(&file_stem(footprint)) {
+            let adjusted = adjust_models(footprint, adjustment, false)?;
+            if adjusted > 0 {
+                let name = footprint.file_name().unwrap_or_default().to_string_lossy();
+                println!("{name}: adjusted the placement of {adjusted} model(s)");
+            }
+        }
+        if KiCadFootprint::from_path(footprint).is_ok_and(|parsed| parsed.courtyard().is_none()) {
+            let name = footprint.file_name().unwrap_or_default().to_string_lossy();
+            println!("warning: {name}: no courtyard on F.CrtYd or B.CrtYd");
+        }
+    }
+
+    println!(
+        "Copying {} footprint file(s) to {}",
+        files.footprints.len(),
+        footprint_dir.display()
+    );
+
+    let conflict_policy = args.on_conflict.unwrap_or(config.footprint_conflicts);
+    let mut copied_footprints = Vec::new();
+    let mut footprint_names = BTreeMap::new();
+    for footprint in &files.footprints {
+        let name = renamed(&rename_patterns, &file_stem(footprint));
+        let Some(copied) = copy_footprint(footprint, &name, &footprint_dir, conflict_policy)? else { continue };
+        footprint_names.insert(file_stem(footprint), file_stem(&copied));
+        copied_footprints.push(copied);
+    }
+    if footprint_dir.is_dir() {
+        register_in_lib_table(&footprint_dir, &footprint_nickname, "fp-lib-table", &project_dir, register, config)?;
+    }
+    if args.only_footprints {
+        println!("Imported {} footprint(s), the symbol library was left as it is", copied_footprints.len());
+        return Ok(ImportSummary { symbols: 0, footprints: copied_footprints.len() });
+    }
+    if args.rewrite_footprint_lib || files.layout.rewrites_footprint_nicknames() {
+        pipeline.add_transform(RewriteFootprintNicknames::new(&footprint_nickname, footprint_names));
+    }
+
+    let ibis_dir = args.ibis_dir.clone().unwrap_or_else(|| {
+        target_lib
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join("ibis")
+    });
+    let mut copied_ibis_models = Vec::new();
+    if !files.ibis_models.is_empty() {
+        println!(
+            "Archiving {} IBIS model(s) in {}",
+            files.ibis_models.len(),
+            ibis_dir.display()
+        );
+        dry_run::create_dir_all(&ibis_dir)?;
+        copied_ibis_models = copy_files(&files.ibis_models, &ibis_dir)?;
+    }
+    if args.link_ibis && !copied_ibis_models.is_empty() {
+        pipeline.add_transform(LinkIbisModels::new(config, copied_ibis_models.clone()));
+    }
+
+    let (mut main_lib, base_lib) = match &overlay_lib {
+        Some(overlay_lib) => {
+            let OverlayLibrary { base, overlay } = OverlayLibrary::open(&symbol_lib, overlay_lib)?;
+            (overlay, Some(base))
+        }
+        // A dry run does not create the library
+        None if !symbol_lib.exists() => (KicadSymbolLib::new(), None),
+        // The symbols already in the library are only copied, not parsed
+        None => (KicadSymbolLib::from_path_lazy(&symbol_lib, &ParseLimits::trusted())?, None),
+    };
+
+    pipeline.merger(ConflictMerger::new(args.on_conflict.unwrap_or(config.symbol_conflicts)));
+    let outcome = pipeline.import_symbols(&files, &mut main_lib)?;
+    for finding in &outcome.findings {
+        println!("{finding}");
+    }
+
+    let catalog_path = args
+        .catalog
+        .clone()
+        .unwrap_or_else(|| Catalog::default_path(&target_lib));
+    let mut catalog = Catalog::from_path(&catalog_path)?;
+
+    let mut manifest = Vec::new();
+    for (kind, paths) in [
+        (ArtifactKind::Footprint, &copied_footprints),
+        (ArtifactKind::Model, &copied_models),
+        (ArtifactKind::Ibis, &copied_ibis_models),
+    ] {
+        manifest.extend(paths.iter().map(|path| ManifestEntry { kind, path: path.clone() }));
+    }
+
+    let merged = &outcome.merged;
+    for symbol in &merged.symbols {
+        let footprint = symbol_footprint_file(symbol, &copied_footprints);
+        let dimensions = part_dimensions(symbol, footprint).unwrap_or_else(|error| {
+            println!("{}: could not determine dimensions: {error}", symbol.name());
+            None
+        });
+        catalog.upsert(CatalogPart {
+            symbol: symbol.name().to_string(),
+            source: source.clone(),
+            manifest: manifest.clone(),
+            manufacturer: config.fields.symbol_manufacturer(symbol).map(str::to_string),
+            mpn: config.fields.symbol_mpn(symbol).map(str::to_string),
+            dimensions,
+        });
+    }
+
+    pipeline.write(&main_lib, &target_lib)?;
+    if let Some(base) = base_lib {
+        let library = OverlayLibrary { base, overlay: main_lib };
+        report_overlay_conflicts(&library, &symbol_lib);
+        println!("{} symbols available from base and overlay library", library.symbols().len());
+    }
+    catalog.write_to_path(&catalog_path)?;
+    if !dry_run::is_enabled() {
+        println!("Updated part catalog: {}", catalog_path.display());
+    }
+    if let Some(database) = args.part_database.as_ref().or(config.part_database.as_ref()) {
+        let table = config.part_table.clone().unwrap_or_else(|| symbol_nickname.clone());
+        let added = sync_parts(database, &table, &merged.symbols, &symbol_nickname, &config.fields)?;
+        println!("Updated {} part(s) in table {table} of {}", merged.symbols.len(), database.display());
+        if !added.is_empty() {
+            println!("Added column(s) {}, run export-dbl to show them in KiCad", added.join(", "));
+        }
+    }
+
+    println!("Imported symbols into {}: {merged}", target_lib.display());
+    if !outcome.skipped.is_empty() {
+        println!("Skipped {} symbol(s) that could not be parsed:", outcome.skipped.len());
+        for skipped in &outcome.skipped {
+            println!("{}: {}", skipped.name, skipped.error);
+        }
+    }
+    register_in_lib_table(&target_lib, &symbol_nickname, "sym-lib-table", &project_dir, register, config)?;
+    let symbols = merged.added + merged.overwritten + merged.renamed;
+    Ok(ImportSummary { symbols, footprints: copied_footprints.len() })
+}
@@ -0,0 +1,378 @@
+use crate::fields::FieldNames;
+use crate::footprints::rename::RenamePattern;
+use crate::symbols::ParseLimits;
+use crate::installation::KiCadInstallation;
+use crate::variables::expand_path;
+use serde::Deserialize;
+use strum::{Display, EnumString};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Config file picked up from the working directory when `--config` is not given.
+pub(crate) const DEFAULT_CONFIG_FILE: &str = "klm.toml";
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// Which reference designator prefix parts of a category must use
+    pub(crate) reference_prefixes: Vec<ReferencePrefixRule>,
+    /// Libraries parts of a category are imported into, the first matching
+    /// route is taken
+    pub(crate) routes: Vec<LibraryRoute>,
+    /// Download directories watched by the `watch` command
+    pub(crate) watch: Vec<WatchTarget>,
+    /// Limits for parsing the files of vendor archives
+    pub(crate) parse_limits: ParseLimits,
+    /// Where `self-update` looks for new releases
+    pub(crate) update: UpdateConfig,
+    /// Organisation policy enforced on imports
+    pub(crate) policy: Option<PolicyConfig>,
+    /// Names of the manufacturer, MPN, lifecycle and IBIS model fields
+    pub(crate) fields: FieldNames,
+    /// Directory written into the model references of imported footprints
+    /// instead of the directory the models were copied to, e.g.
+    /// `${KICAD_USER_3DMODEL_DIR}/Vendor`. KiCad expands the variables.
+    pub(crate) model_dir: Option<String>,
+    /// Directory the 3D models of `footprint_lib` and the footprint libraries
+    /// of `routes` are copied to, into `<footprint lib>.3dshapes`, like the
+    /// `3dmodels` directory of a library set made by `init`. The models of
+    /// other footprint libraries stay next to them
+    pub(crate) model_library_dir: Option<PathBuf>,
+    /// Variable model references are written with instead, relative to the
+    /// directory it stands for, e.g. `KICAD_3RD_PARTY` or `KIPRJMOD`
+    pub(crate) model_path_variable: Option<String>,
+    /// Directories of variables, for variables not set in the environment
+    pub(crate) model_path_variables: BTreeMap<String, PathBuf>,
+    /// How model references are written, defaults to `model_path_variable`,
+    /// then `model_dir`, then absolute paths
+    pub(crate) model_path_style: Option<ModelPathStyle>,
+    /// Which model footprints refer to when there is a `.step` and a `.wrl` one
+    pub(crate) preferred_model_format: ModelFormat,
+    /// Store imported STEP files gzipped as `.stpZ`
+    pub(crate) compress_models: bool,
+    /// Name imported models after their footprint instead of the vendor's name
+    pub(crate) rename_models: bool,
+    /// Command run for each imported STEP model to write a `.wrl` next to it,
+    /// for the ray tracer of KiCad. `{input}` and `{output}` are replaced by the
+    /// paths, e.g. `["freecadcmd", "step2wrl.py", "{input}", "{output}"]`
+    pub(crate) step_to_wrl: Option<Vec<String>>,
+    /// What to do with imported footprints whose name is already taken in
+    /// the footprint library by a different footprint
+    pub(crate) footprint_conflicts: ConflictPolicy,
+    /// What to do with imported or merged symbols whose name is already
+    /// taken in the symbol library by a different symbol
+    pub(crate) symbol_conflicts: ConflictPolicy,
+    /// Footprint library tables, like the global `fp-lib-table` of KiCad, in
+    /// which the libraries imported symbols refer to are looked up
+    pub(crate) fp_lib_tables: Vec<PathBuf>,
+    /// Placement of the 3D models of imported footprints, by the name the
+    /// vendor gave the footprint, for models that are misaligned, e.g.
+    /// `[model_adjustments.SOIC-8] rotate = [0, 0, 90]`
+    pub(crate) model_adjustments: BTreeMap<String, ModelAdjustment>,
+    /// What to do when a library table has a different library under the
+    /// nickname of a library being added to it
+    pub(crate) nickname_collisions: NicknameCollision,
+    /// SQLite database of a database library imported parts are inserted into
+    /// or updated in, see the `export-dbl` command
+    pub(crate) part_database: Option<PathBuf>,
+    /// Table of the parts in `part_database`, defaults to the nickname of the
+    /// symbol library
+    pub(crate) part_table: Option<String>,
+    /// Symbol library imports go to without `--symbol-lib` or `--project`, like
+    /// one in the user library directory of KiCad
+    pub(crate) symbol_lib: Option<PathBuf>,
+    /// Footprint library imports go to without `--footprint-lib` or `--project`
+    pub(crate) footprint_lib: Option<PathBuf>,
+    /// Library tables imported and converted libraries are added to
+    pub(crate) register_libraries: Option<LibTableScope>,
+    /// The global `sym-lib-table` of KiCad, e.g. `~/.config/kicad/8.0/sym-lib-table`,
+    /// detected if not set
+    pub(crate) sym_lib_table: Option<PathBuf>,
+    /// The global `fp-lib-table` of KiCad footprint libraries are added to,
+    /// also looked up like `fp_lib_tables`, detected if not set
+    pub(crate) fp_lib_table: Option<PathBuf>,
+    /// Footprint rules the `check` command skips, e.g. `["model"]`
+    pub(crate) disabled_footprint_rules: Vec<String>,
+    /// Symbol rules the `check` command skips, e.g. `["pin-pad"]`
+    pub(crate) disabled_symbol_rules: Vec<String>,
+    /// Fields the `check` command requires on every symbol besides Reference
+    /// and Value, e.g. `["Footprint", "Datasheet"]`
+    pub(crate) required_fields: Vec<String>,
+    /// Distance in mm the courtyard must keep around every pad, 0.25 by the KLC
+    pub(crate) courtyard_clearance: f32,
+    /// Patterns imported footprints are renamed with, e.g. `["*=ACME_*"]`
+    pub(crate) footprint_rename_patterns: Vec<RenamePattern>,
+    /// Properties removed from imported symbols, by name with `*` and `?`
+    /// wildcards, regardless of case
+    pub(crate) strip_properties: Vec<String>,
+}
+
+/// Parts whose keywords, description, name, value or footprint contain one of
+/// `keywords` (as a whole word) should use `prefix` as reference designator.
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct ReferencePrefixRule {
+    pub(crate) prefix: String,
+    pub(crate) keywords: Vec<String>,
+}
+
+/// Parts of `category`, given with `--category` or recognised by a keyword or
+/// reference prefix, are imported into `symbol_lib` and `footprint_lib`
+/// instead of the default libraries, e.g. connectors into
+/// `Connectors.kicad_sym`.
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct LibraryRoute {
+    pub(crate) category: String,
+    /// Words of the keywords, description, name, value or footprint
+    #[serde(default)]
+    pub(crate) keywords: Vec<String>,
+    /// Reference designator prefixes, like `J`
+    #[serde(default)]
+    pub(crate) reference_prefixes: Vec<String>,
+    pub(crate) symbol_lib: PathBuf,
+    /// Defaults to the footprint library of the import
+    pub(crate) footprint_lib: Option<PathBuf>,
+}
+
+/// How an imported item is handled when the library already has a different
+/// item of the same name. Identical items are always left alone.
+#[derive(Deserialize, EnumString, Display, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum ConflictPolicy {
+    /// Keep the item of the library
+    #[default]
+    Skip,
+    Overwrite,
+    /// Import under the name with a numbered suffix, e.g. `SOIC-8_1`
+    Rename,
+    /// Ask on the terminal for each conflict
+    Ask,
+}
+
+/// 3D model file formats, of which footprints refer to the preferred one
+/// when an archive has the model in both.
+#[derive(Deserialize, EnumString, Display, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum ModelFormat {
+    /// `.step` or `.stp`, exact geometry for mechanical CAD
+    #[default]
+    Step,
+    /// `.wrl` (VRML), with the colours KiCad renders
+    Wrl,
+}
+
+/// Which library table libraries are added to.
+#[derive(Deserialize, EnumString, Display, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum LibTableScope {
+    /// The table next to the library, of the project it is in
+    Project,
+    /// The table of the KiCad installation, configured in the config
+    Global,
+}
+
+/// How a library is added to a library table that has a different library
+/// under its nickname.
+#[derive(Deserialize, EnumString, Display, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum NicknameCollision {
+    /// Add it under the nickname with a numbered suffix, e.g. `Vendor_1`
+    Rename,
+    /// Import into the library of the table instead
+    Merge,
+    /// Stop before anything is imported
+    Abort,
+    /// Ask on the terminal, abort without one
+    #[default]
+    Ask,
+}
+
+/// How the model references of imported footprints point at the models.
+#[derive(Deserialize, EnumString, Display, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum ModelPathStyle {
+    /// `${KIPRJMOD}/...`, relative to the project of the symbol library
+    Project,
+    /// A relative path like `../Vendor.3dshapes/SOIC8.step`, which KiCad 7
+    /// and later resolve from the footprint library
+    Library,
+    /// The absolute path of the model
+    Absolute,
+    /// Relative to `model_path_variable`, or in `model_dir`
+    Variable,
+}
+
+/// New offset (mm), scale and rotation (degrees) for the 3D models of a
+/// footprint, as x, y and z. What is not given is kept.
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub(crate) struct ModelAdjustment {
+    pub(crate) offset: Option<[f32; 3]>,
+    pub(crate) scale: Option<[f32; 3]>,
+    pub(crate) rotate: Option<[f32; 3]>,
+}
+
+/// Archives appearing in `dir` are imported into `symbol_lib` and `footprint_dir`.
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct WatchTarget {
+    pub(crate) dir: PathBuf,
+    pub(crate) symbol_lib: PathBuf,
+    pub(crate) footprint_dir: PathBuf,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub(crate) struct UpdateConfig {
+    /// JSON release feed in the format of the GitHub latest release API
+    pub(crate) feed: String,
+    /// Request timeout in seconds
+    pub(crate) timeout: u64,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            feed: "https://api.github.com/repos/jbcolle/kicad-library-manager/releases/latest".to_string(),
+            timeout: 60,
+        }
+    }
+}
+
+/// A policy file served over HTTP(S), or kept in a git repository when
+/// `source` starts with `git+`, e.g. `git+https://git.example.com/library/policy.git`.
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct PolicyConfig {
+    pub(crate) source: String,
+    /// Path of the policy file within a git repository
+    pub(crate) file: Option<String>,
+    /// Age in hours after which a cached policy that could not be refreshed is reported as stale
+    #[serde(default = "default_policy_max_age")]
+    pub(crate) max_age_hours: u64,
+    /// Request timeout in seconds
+    #[serde(default = "default_policy_timeout")]
+    pub(crate) timeout: u64,
+}
+
+fn default_policy_max_age() -> u64 {
+    24
+}
+
+fn default_policy_timeout() -> u64 {
+    10
+}
+
+impl ReferencePrefixRule {
+    fn new(prefix: &str, keywords: &[&str]) -> Self {
+        Self { prefix: prefix.to_string(), keywords: keywords.iter().map(|keyword| keyword.to_string()).collect() }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            reference_prefixes: vec![
+                ReferencePrefixRule::new("R", &["resistor", "res", "potentiometer", "thermistor"]),
+                ReferencePrefixRule::new("C", &["capacitor", "cap"]),
+                ReferencePrefixRule::new("L", &["inductor", "choke"]),
+                ReferencePrefixRule::new("FB", &["ferrite"]),
+                ReferencePrefixRule::new("D", &["diode", "led", "zener", "schottky", "tvs"]),
+                ReferencePrefixRule::new("Q", &["transistor", "mosfet", "bjt", "jfet", "igbt"]),
+                ReferencePrefixRule::new("U", &["ic", "opamp", "microcontroller", "mcu", "regulator", "amplifier"]),
+                ReferencePrefixRule::new("J", &["connector", "header", "socket", "receptacle"]),
+                ReferencePrefixRule::new("SW", &["switch", "button", "pushbutton"]),
+                ReferencePrefixRule::new("Y", &["crystal", "resonator"]),
+                ReferencePrefixRule::new("F", &["fuse", "polyfuse"]),
+                ReferencePrefixRule::new("K", &["relay"]),
+                ReferencePrefixRule::new("BT", &["battery"]),
+                ReferencePrefixRule::new("TP", &["testpoint"]),
+            ],
+            routes: vec![],
+            watch: vec![],
+            parse_limits: ParseLimits::default(),
+            update: UpdateConfig::default(),
+            policy: None,
+            fields: FieldNames::default(),
+            model_dir: None,
+            model_library_dir: None,
+            model_path_variable: None,
+            model_path_variables: BTreeMap::new(),
+            model_path_style: None,
+            preferred_model_format: ModelFormat::default(),
+            compress_models: false,
+            rename_models: false,
+            step_to_wrl: None,
+            footprint_conflicts: ConflictPolicy::default(),
+            symbol_conflicts: ConflictPolicy::default(),
+            fp_lib_tables: vec![],
+            model_adjustments: BTreeMap::new(),
+            nickname_collisions: NicknameCollision::default(),
+            part_database: None,
+            part_table: None,
+            symbol_lib: None,
+            footprint_lib: None,
+            register_libraries: None,
+            sym_lib_table: None,
+            fp_lib_table: None,
+            disabled_footprint_rules: vec![],
+            disabled_symbol_rules: vec![],
+            required_fields: vec![],
+            courtyard_clearance: 0.25,
+            footprint_rename_patterns: vec![],
+            strip_properties: ["Check_prices", "SnapEDA_Link", "*Price/Stock", "Mouser Testing*"].map(String::from).to_vec(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the given config file, or `klm.toml` from the working directory
+    /// if it exists, falling back to the built-in defaults. The global library
+    /// tables default to those of the detected KiCad installation.
+    pub fn load(path: Option<&Path>) -> Result<Self, anyhow::Error> {
+        let path = path.map(Path::to_path_buf).or(Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|path| path.exists()));
+        let mut config = match path {
+            Some(path) => toml::from_str(&fs::read_to_string(path)?)?,
+            None => Config::default(),
+        };
+        config.expand_variables()?;
+        if let Some(installation) = KiCadInstallation::detect() {
+            let existing = |path: PathBuf| Some(path).filter(|path| path.exists());
+            config.sym_lib_table = config.sym_lib_table.or_else(|| existing(installation.sym_lib_table()));
+            config.fp_lib_table = config.fp_lib_table.or_else(|| existing(installation.fp_lib_table()));
+        }
+        Ok(config)
+    }
+
+    /// Expands the variables of KiCad and of `model_path_variables` in the
+    /// paths of the config. `model_dir` is written into footprints as it is.
+    fn expand_variables(&mut self) -> Result<(), anyhow::Error> {
+        let mut variables = BTreeMap::new();
+        for (name, dir) in &self.model_path_variables {
+            variables.insert(name.clone(), expand_path(dir, &variables)?);
+        }
+        self.model_path_variables = variables;
+        let variables = &self.model_path_variables;
+        for path in [&mut self.sym_lib_table, &mut self.fp_lib_table, &mut self.part_database, &mut self.symbol_lib, &mut self.footprint_lib, &mut self.model_library_dir]
+            .into_iter()
+            .flatten()
+            .chain(&mut self.fp_lib_tables)
+        {
+            *path = expand_path(path, variables)?;
+        }
+        for route in &mut self.routes {
+            for path in [Some(&mut route.symbol_lib), route.footprint_lib.as_mut()].into_iter().flatten() {
+                *path = expand_path(path, variables)?;
+            }
+        }
+        for target in &mut self.watch {
+            for path in [&mut target.dir, &mut target.symbol_lib, &mut target.footprint_dir] {
+                *path = expand_path(path, variables)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+//! KiCad database libraries, `.kicad_dbl`: a JSON definition mapping the
+//! columns of a database table of parts onto symbol fields, with the symbols
+//! and footprints taken from the managed libraries. Imported parts can be
+//! kept in an SQLite database of that layout.
+
+use crate::dry_run;
+use crate::fields::FieldNames;
+use crate::symbols::property::{KiCadPropertyType, KiCadSymbol};
+use crate::symbols::KicadSymbolLib;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// The column identifying a part, holding the symbol name.
+const KEY_COLUMN: &str = "Part ID";
+/// The column with the `nickname:name` of the symbol.
+const SYMBOL_COLUMN: &str = "Symbol";
+/// The column with the `nickname:name` of the footprint.
+const FOOTPRINT_COLUMN: &str = "Footprint";
+/// The columns every part has before those of [`part_columns`].
+pub(crate) const KEY_COLUMNS: [&str; 3] = [KEY_COLUMN, SYMBOL_COLUMN, FOOTPRINT_COLUMN];
+const DESCRIPTION_COLUMN: &str = "Description";
+const KEYWORDS_COLUMN: &str = "Keywords";
+
+#[derive(Serialize, Debug)]
+struct DatabaseLibrary {
+    meta: Meta,
+    name: String,
+    description: String,
+    source: Source,
+    libraries: Vec<PartTable>,
+}
+
+#[derive(Serialize, Debug)]
+struct Meta {
+    version: u32,
+}
+
+#[derive(Serialize, Debug)]
+struct Source {
+    #[serde(rename = "type")]
+    kind: String,
+    dsn: String,
+    username: String,
+    password: String,
+    timeout_seconds: u32,
+    connection_string: String,
+}
+
+#[derive(Serialize, Debug)]
+struct PartTable {
+    name: String,
+    table: String,
+    key: String,
+    symbols: String,
+    footprints: String,
+    fields: Vec<Field>,
+    properties: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Debug)]
+struct Field {
+    column: String,
+    name: String,
+    visible_on_add: bool,
+    visible_in_chooser: bool,
+    show_name: bool,
+    inherit_properties: bool,
+}
+
+/// The columns of the parts of the library besides the key, symbol and
+/// footprint: Value, Datasheet, Description, Keywords, the manufacturer and
+/// MPN fields and every other field a symbol has, in the order they are first
+/// seen.
+pub(crate) fn part_columns(symbols: &[KiCadSymbol], names: &FieldNames) -> Vec<String> {
+    let mut columns: Vec<String> =
+        ["Value", "Datasheet", DESCRIPTION_COLUMN, KEYWORDS_COLUMN, names.manufacturer(), names.mpn()]
+            .map(str::to_string)
+            .to_vec();
+    for symbol in symbols {
+        for property in symbol.properties() {
+            let name = property.property_type().to_string();
+            let builtin = matches!(
+                property.property_type(),
+                KiCadPropertyType::Reference
+                    | KiCadPropertyType::Value
+                    | KiCadPropertyType::Footprint
+                    | KiCadPropertyType::Datasheet
+                    | KiCadPropertyType::Description
+            );
+            if !builtin && !columns.contains(&name) && !name.starts_with("ki_") {
+                columns.push(name);
+            }
+        }
+    }
+    columns
+}
+
+/// Writes the `.kicad_dbl` of a database with the parts of `library` in
+/// `table`, with a column per field.
+pub(crate) fn write_database_library(
+    path: &Path,
+    library: &KicadSymbolLib,
+    names: &FieldNames,
+    name: &str,
+    table: &str,
+    connection_string: &str,
+) -> Result<(), anyhow::Error> {
+    let fields = part_columns(&library.symbols, names)
+        .into_iter()
+        .filter(|column| column != DESCRIPTION_COLUMN && column != KEYWORDS_COLUMN)
+        .map(|column| Field {
+            name: column.clone(),
+            // Value is shown on the schematic, the other fields only in the chooser
+            visible_on_add: column == "Value",
+            visible_in_chooser: true,
+            show_name: column != "Value",
+            inherit_properties: false,
+            column,
+        })
+        .collect();
+    let properties = [("description", DESCRIPTION_COLUMN), ("keywords", KEYWORDS_COLUMN)]
+        .map(|(property, column)| (property.to_string(), column.to_string()))
+        .into();
+    let database = DatabaseLibrary {
+        meta: Meta { version: 0 },
+        name: name.to_string(),
+        description: format!("Parts of {name}, managed by kicad-library-manager"),
+        source: Source {
+            kind: "odbc".to_string(),
+            dsn: String::new(),
+            username: String::new(),
+            password: String::new(),
+            timeout_seconds: 2,
+            connection_string: connection_string.to_string(),
+        },
+        libraries: vec![PartTable {
+            name: name.to_string(),
+            table: table.to_string(),
+            key: KEY_COLUMN.to_string(),
+            symbols: SYMBOL_COLUMN.to_string(),
+            footprints: FOOTPRINT_COLUMN.to_string(),
+            fields,
+            properties,
+        }],
+    };
+    if dry_run::skip_write(path) {
+        return Ok(());
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, &database)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// The row of the part database for the symbol of the library `nickname`, by
+/// column. The manufacturer and MPN are also taken from the fields vendors
+/// use for them.
+fn part_row(symbol: &KiCadSymbol, nickname: &str, columns: &[String], names: &FieldNames) -> BTreeMap<String, String> {
+    let mut row = BTreeMap::new();
+    row.insert(KEY_COLUMN.to_string(), symbol.name().to_string());
+    row.insert(SYMBOL_COLUMN.to_string(), format!("{nickname}:{}", symbol.name()));
+    let footprint = symbol.property_value(&KiCadPropertyType::Footprint).unwrap_or_default();
+    row.insert(FOOTPRINT_COLUMN.to_string(), footprint.to_string());
+    for column in columns {
+        let value = match column.as_str() {
+            KEYWORDS_COLUMN => symbol.property_value(&KiCadPropertyType::KiKeywords),
+            column if column == names.manufacturer() => names.symbol_manufacturer(symbol),
+            column if column == names.mpn() => names.symbol_mpn(symbol),
+            column => symbol.property_value(&column.parse().unwrap_or(KiCadPropertyType::Custom(column.to_string()))),
+        };
+        row.insert(column.clone(), value.unwrap_or_default().to_string());
+    }
+    row
+}
+
+/// Inserts the symbols of the library `nickname` into `table` of the SQLite
+/// database, or updates their rows, creating the database, the table and
+/// missing columns. Returns the columns that were added to an existing table.
+pub(crate) fn sync_parts(
+    database: &Path,
+    table: &str,
+    symbols: &[KiCadSymbol],
+    nickname: &str,
+    names: &FieldNames,
+) -> Result<Vec<String>, anyhow::Error> {
+    // A dry run updates the table in a transaction it rolls back, in memory if the database does not exist yet
+    let mut connection =
+        if dry_run::skip_write(database) && !database.exists() { Connection::open_in_memory()? } else { Connection::open(database)? };
+    let transaction = connection.transaction()?;
+    let identifier = |name: &str| format!("\"{}\"", name.replace('"', "\"\""));
+    let columns = part_columns(symbols, names);
+
+    let mut existing: Vec<String> = {
+        let mut statement = transaction.prepare(&format!("PRAGMA table_info({})", identifier(table)))?;
+        let names = statement.query_map([], |row| row.get::<_, String>(1))?;
+        names.collect::<Result<_, _>>()?
+    };
+    let mut added = vec![];
+    if existing.is_empty() {
+        let definitions: Vec<String> = KEY_COLUMNS
+            .iter()
+            .map(|column| column.to_string())
+            .chain(columns.iter().cloned())
+            .map(|column| match column.as_str() {
+                KEY_COLUMN => format!("{} TEXT PRIMARY KEY", identifier(&column)),
+                _ => format!("{} TEXT", identifier(&column)),
+            })
+            .collect();
+        transaction.execute(&format!("CREATE TABLE {} ({})", identifier(table), definitions.join(", ")), [])?;
+        existing = KEY_COLUMNS.iter().map(|column| column.to_string()).chain(columns.iter().cloned()).collect();
+    }
+    for column in KEY_COLUMNS.iter().map(|column| column.to_string()).chain(columns.iter().cloned()) {
+        if !existing.contains(&column) {
+            transaction.execute(&format!("ALTER TABLE {} ADD COLUMN {} TEXT", identifier(table), identifier(&column)), [])?;
+            existing.push(column.clone());
+            added.push(column);
+        }
+    }
+
+    for symbol in symbols {
+        let row = part_row(symbol, nickname, &columns, names);
+        let names: Vec<String> = row.keys().map(|column| identifier(column)).collect();
+        let placeholders: Vec<String> = (1..=row.len()).map(|index| format!("?{index}")).collect();
+        let updates: Vec<String> = names.iter().map(|name| format!("{name} = excluded.{name}")).collect();
+        let statement = format!(
+            "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT({}) DO UPDATE SET {}",
+            identifier(table),
+            names.join(", "),
+            placeholders.join(", "),
+            identifier(KEY_COLUMN),
+            updates.join(", ")
+        );
+        transaction.execute(&statement, rusqlite::params_from_iter(row.values()))?;
+    }
+    if !dry_run::is_enabled() {
+        transaction.commit()?;
+    }
+    Ok(added)
+}
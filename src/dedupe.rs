@@ -0,0 +1,93 @@
+//! Symbols of a library that are the same part under different names: the
+//! same drawing, or the same manufacturer part number. Duplicates are merged
+//! into one of them, optionally leaving the others behind as derived symbols
+//! so schematics using their names keep working.
+
+use crate::fields::FieldNames;
+use crate::symbols::property::KiCadSymbol;
+
+/// Symbols found to be duplicates of each other, in library order.
+pub(crate) struct DuplicateGroup {
+    pub(crate) names: Vec<String>,
+    /// What makes them duplicates, like `identical body` or `same MPN LM358`
+    pub(crate) reason: String,
+}
+
+impl DuplicateGroup {
+    /// The symbol to keep unless told otherwise: the first one that is not
+    /// derived from another symbol.
+    pub(crate) fn default_keep<'a>(&'a self, symbols: &[KiCadSymbol]) -> &'a str {
+        let is_root = |name: &&String| symbols.iter().any(|symbol| symbol.name() == name.as_str() && symbol.extends().is_none());
+        self.names.iter().find(is_root).unwrap_or(&self.names[0])
+    }
+}
+
+/// Groups the symbols with identical bodies or the same MPN. Derived symbols
+/// have no body of their own, so they only match on their MPN.
+pub(crate) fn find_duplicates(symbols: &[KiCadSymbol], fields: &FieldNames) -> Vec<DuplicateGroup> {
+    let mpn = |symbol: &KiCadSymbol| fields.symbol_mpn(symbol).map(str::trim).filter(|mpn| !mpn.is_empty()).map(str::to_lowercase);
+    let has_body = |symbol: &KiCadSymbol| symbol.extends().is_none() && !symbol.sub_symbols().is_empty();
+    let mut grouped = vec![false; symbols.len()];
+    let mut groups = vec![];
+    for (i, symbol) in symbols.iter().enumerate() {
+        if grouped[i] {
+            continue;
+        }
+        let mut names = vec![symbol.name().to_string()];
+        let mut same_bodies = true;
+        for (j, other) in symbols.iter().enumerate().skip(i + 1) {
+            if grouped[j] {
+                continue;
+            }
+            let same_body = has_body(symbol) && has_body(other) && symbol.has_same_body(other);
+            if same_body || (mpn(symbol).is_some() && mpn(symbol) == mpn(other)) {
+                grouped[j] = true;
+                same_bodies &= same_body;
+                names.push(other.name().to_string());
+            }
+        }
+        if names.len() > 1 {
+            let reason = match fields.symbol_mpn(symbol).filter(|_| !same_bodies) {
+                Some(mpn) => format!("same MPN {}", mpn.trim()),
+                None => "identical body".to_string(),
+            };
+            groups.push(DuplicateGroup { names, reason });
+        }
+    }
+    groups
+}
+
+/// Merges the `removed` symbols into `keep`: they are dropped, or turned into
+/// symbols derived from it with `aliases`, and symbols derived from them are
+/// derived from `keep` instead.
+pub(crate) fn merge_duplicates(symbols: &mut Vec<KiCadSymbol>, keep: &str, removed: &[String], aliases: bool) {
+    // Symbols cannot be derived from derived symbols, so aliases of one go to its parent
+    let parent =
+        symbols.iter().find(|symbol| symbol.name() == keep).and_then(KiCadSymbol::extends).unwrap_or(keep).to_string();
+    let is_removed = |symbol: &KiCadSymbol| removed.iter().any(|name| name == symbol.name());
+    if !aliases {
+        symbols.retain(|symbol| !is_removed(symbol));
+    }
+    for symbol in symbols.iter_mut() {
+        if is_removed(symbol) {
+            symbol.derive_from(&parent);
+        }
+        for name in removed {
+            symbol.rename_extended(name, &parent);
+        }
+    }
+    order_after_parents(symbols);
+}
+
+/// Moves derived symbols that come before the symbol they extend right after
+/// it, as KiCad reads the parent of a symbol first.
+fn order_after_parents(symbols: &mut Vec<KiCadSymbol>) {
+    let position = |symbols: &[KiCadSymbol], name: &str| symbols.iter().position(|symbol| symbol.name() == name);
+    while let Some((index, parent)) = symbols.iter().enumerate().find_map(|(index, symbol)| {
+        let parent = position(symbols, symbol.extends()?)?;
+        (parent > index).then_some((index, parent))
+    }) {
+        let symbol = symbols.remove(index);
+        symbols.insert(parent, symbol);
+    }
+}
@@ -0,0 +1,54 @@
+use crate::catalog::PartDimensions;
+use crate::footprints::courtyard_bounds;
+use crate::symbols::property::{KiCadPropertyType, KiCadSymbol};
+use std::path::Path;
+
+/// Other properties vendors use for the maximum height of the package above
+/// the board, in order of preference.
+const HEIGHT_PROPERTIES: [&str; 5] = ["Maximum_Package_Height", "Max_Height", "Height", "HEIGHT", "Component_Height"];
+
+pub(crate) fn symbol_height(symbol: &KiCadSymbol) -> Option<f32> {
+    symbol
+        .property_value(&KiCadPropertyType::MaximumPackageHeight)
+        .or_else(|| {
+            HEIGHT_PROPERTIES
+                .iter()
+                .find_map(|name| symbol.property_value(&KiCadPropertyType::Custom(name.to_string())))
+        })
+        .and_then(parse_length)
+}
+
+/// Parses lengths like `1.75`, `1.75mm`, `69 mil` or `0.07in` into mm. Values
+/// without a unit are taken to be mm.
+fn parse_length(value: &str) -> Option<f32> {
+    let value = value.trim().to_lowercase();
+    let number_end = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.' && c != '-')
+        .unwrap_or(value.len());
+    let number: f32 = value[..number_end].parse().ok()?;
+    let scale = match value[number_end..].trim() {
+        "" | "mm" => 1.0,
+        "mil" | "mils" => 0.0254,
+        "in" | "inch" | "\"" => 25.4,
+        _ => return None,
+    };
+    Some(number * scale)
+}
+
+/// The x/y envelope is taken from the courtyard of the footprint, the z
+/// envelope from the height property of the symbol.
+pub(crate) fn part_dimensions(symbol: &KiCadSymbol, footprint: Option<&Path>) -> Result<Option<PartDimensions>, anyhow::Error> {
+    let courtyard = match footprint {
+        Some(footprint) => courtyard_bounds(footprint)?,
+        None => None,
+    };
+    let dimensions = PartDimensions {
+        x: courtyard.map(|bounds| bounds.width()),
+        y: courtyard.map(|bounds| bounds.height()),
+        z: symbol_height(symbol),
+    };
+    if dimensions.x.is_none() && dimensions.z.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(dimensions))
+}
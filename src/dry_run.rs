@@ -0,0 +1,64 @@
+//! The `--dry-run` mode, in which commands read, check and plan as usual but
+//! only print which files they would create, modify or delete.
+//!
+//! Files are written deep inside the commands, so the mode is a switch for
+//! the whole process and the functions here stand in for those of `std::fs`
+//! wherever libraries, tables and other results are written. Scratch files,
+//! like extracted archives, are written in a dry run as well.
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn enable() {
+    DRY_RUN.store(true, Ordering::Relaxed);
+}
+
+pub(crate) fn is_enabled() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// In a dry run, prints that `path` would be created or modified and returns
+/// true, so the caller leaves it alone.
+pub(crate) fn skip_write(path: &Path) -> bool {
+    if !is_enabled() {
+        return false;
+    }
+    let action = if path.exists() { "modify" } else { "create" };
+    println!("Would {action} {}", path.display());
+    true
+}
+
+pub(crate) fn write(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    if skip_write(path) {
+        return Ok(());
+    }
+    fs::write(path, contents)
+}
+
+pub(crate) fn copy(from: &Path, to: &Path) -> std::io::Result<()> {
+    if skip_write(to) {
+        return Ok(());
+    }
+    fs::copy(from, to).map(|_| ())
+}
+
+pub(crate) fn create_dir_all(path: &Path) -> std::io::Result<()> {
+    if is_enabled() {
+        if !path.is_dir() {
+            println!("Would create {}", path.display());
+        }
+        return Ok(());
+    }
+    fs::create_dir_all(path)
+}
+
+pub(crate) fn remove_file(path: &Path) -> std::io::Result<()> {
+    if is_enabled() {
+        println!("Would delete {}", path.display());
+        return Ok(());
+    }
+    fs::remove_file(path)
+}
@@ -0,0 +1,531 @@
+//! Converter for EasyEDA parts, the JSON LCSC part pages load their symbol
+//! and footprint from (`https://easyeda.com/api/products/<LCSC number>/components`).
+//!
+//! Like a legacy symbol, the symbol is translated into the S-expression KiCad
+//! would write for it and read with the regular parser. The footprint is
+//! written as a `.kicad_mod`. Arcs, texts and the 3D model are left out.
+
+use crate::footprints::upgrade::FOOTPRINT_VERSION;
+use crate::footprints::KiCadFootprint;
+use crate::symbols::fidelity;
+use crate::symbols::legacy::{effects, hidden_property, mils_to_mm, parse_symbol, point, property, stroke};
+use crate::symbols::writer::{bool_to_kicad_string, SExpr};
+use crate::symbols::KicadSymbolLib;
+use anyhow::{anyhow, bail};
+use serde_json::Value;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// EasyEDA coordinates are in units of 10 mil
+const MILS_PER_UNIT: f32 = 10.0;
+
+fn length(units: f32) -> f32 {
+    mils_to_mm(units * MILS_PER_UNIT)
+}
+
+/// One `~` separated shape of EasyEDA, like `R~390~270~~~40~60~#880000~1~0~none~gge7~0`.
+struct Shape<'a> {
+    kind: &'a str,
+    fields: Vec<&'a str>,
+}
+
+impl<'a> Shape<'a> {
+    fn new(text: &'a str) -> Self {
+        let fields: Vec<&str> = text.split('~').collect();
+        Self { kind: fields[0], fields }
+    }
+
+    /// The field at `index`, empty if the shape has fewer fields
+    fn text(&self, index: usize) -> &'a str {
+        self.fields.get(index).copied().unwrap_or_default()
+    }
+
+    fn number(&self, index: usize) -> Result<f32, anyhow::Error> {
+        let text = self.text(index).trim();
+        text.parse().map_err(|_| anyhow!("Not a number in field {index} of {} shape: {text:?}", self.kind))
+    }
+
+    fn number_or(&self, index: usize, default: f32) -> f32 {
+        self.number(index).unwrap_or(default)
+    }
+
+    /// The points of a `x1 y1 x2 y2 ...` field
+    fn points(&self, index: usize) -> Result<Vec<(f32, f32)>, anyhow::Error> {
+        let numbers = self
+            .text(index)
+            .split_whitespace()
+            .map(|number| number.parse::<f32>().map_err(|_| anyhow!("Not a number in the points of {} shape: {number}", self.kind)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(numbers.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect())
+    }
+}
+
+/// A number of the JSON, which EasyEDA writes as a number or as a string.
+fn json_number(value: Option<&Value>) -> f32 {
+    value.and_then(|value| value.as_f64().map(|number| number as f32).or_else(|| value.as_str()?.parse().ok())).unwrap_or(0.0)
+}
+
+fn json_text<'a>(value: &'a Value, pointer: &str) -> Option<&'a str> {
+    value.pointer(pointer).and_then(Value::as_str).map(str::trim).filter(|text| !text.is_empty())
+}
+
+/// The `dataStr` of a symbol, sub part or package, which older responses
+/// have as a JSON string.
+fn data(value: &Value) -> Result<Value, anyhow::Error> {
+    match value.get("dataStr") {
+        Some(Value::String(text)) => Ok(serde_json::from_str(text)?),
+        Some(data) => Ok(data.clone()),
+        None => bail!("No dataStr in the EasyEDA part"),
+    }
+}
+
+/// The shapes of a `dataStr`.
+fn shapes(data: &Value) -> Vec<&str> {
+    data.get("shape").and_then(Value::as_array).map(|shapes| shapes.iter().filter_map(Value::as_str).collect()).unwrap_or_default()
+}
+
+/// The origin of the coordinates of a `dataStr`.
+fn origin(data: &Value) -> (f32, f32) {
+    (json_number(data.pointer("/head/x")), json_number(data.pointer("/head/y")))
+}
+
+/// A name usable as a KiCad symbol or footprint name and file name.
+fn sanitized(name: &str) -> String {
+    name.trim().chars().map(|c| if matches!(c, ':' | '/' | '\\' | '"') { '_' } else { c }).collect()
+}
+
+fn symbol_fill(color: &str) -> SExpr {
+    let fill_type = if color.is_empty() || color == "none" { "none" } else { "background" };
+    SExpr::list("fill", vec![SExpr::list("type", vec![SExpr::atom(fill_type)])])
+}
+
+/// The KiCad symbol coordinates of an EasyEDA point, whose y axis points down.
+struct SymbolOrigin {
+    x: f32,
+    y: f32,
+}
+
+impl SymbolOrigin {
+    fn at(&self, x: f32, y: f32) -> (f32, f32) {
+        (length(x - self.x), length(self.y - y))
+    }
+}
+
+/// `P~show~electric~spice number~x~y~rotation~id~locked^^dot^^path^^name^^number^^...`
+fn convert_pin(text: &str, origin: &SymbolOrigin) -> Result<SExpr, anyhow::Error> {
+    let segments: Vec<Shape> = text.split("^^").map(Shape::new).collect();
+    let settings = &segments[0];
+    let pin_type = match settings.text(2) {
+        "1" => "input",
+        "2" => "output",
+        "3" => "bidirectional",
+        "4" => "power_in",
+        _ => "unspecified",
+    };
+    let (x, y) = origin.at(settings.number(4)?, settings.number(5)?);
+    // The pin of rotation 0 points from its end on the right towards the body
+    let angle = (180.0 + settings.number_or(6, 0.0)).rem_euclid(360.0);
+    // The path is like `M 360 290 h 10` or `M 400 300 v -20`
+    let path = segments.get(2).map(|segment| segment.text(0)).unwrap_or_default();
+    let pin_length = path.split(['h', 'v']).next_back().and_then(|last| last.trim().parse::<f32>().ok()).unwrap_or(0.0).abs();
+    let name = segments.get(3).map(|segment| segment.text(4)).unwrap_or_default();
+    let number = segments.get(4).map(|segment| segment.text(4)).unwrap_or_default();
+
+    let mut children = vec![
+        SExpr::atom(pin_type),
+        SExpr::atom("line"),
+        SExpr::list("at", vec![SExpr::atom(x), SExpr::atom(y), SExpr::atom(angle)]),
+        SExpr::list("length", vec![SExpr::atom(length(pin_length))]),
+    ];
+    if settings.text(1) != "show" {
+        children.push(SExpr::list("hide", vec![SExpr::atom(bool_to_kicad_string(true))]));
+    }
+    children.push(SExpr::list("name", vec![SExpr::quoted(name), effects(1.27, false, false, vec![], false)]));
+    children.push(SExpr::list("number", vec![SExpr::quoted(number), effects(1.27, false, false, vec![], false)]));
+    Ok(SExpr::list("pin", children))
+}
+
+/// The drawing item of a symbol shape, `None` for shapes KiCad symbols
+/// cannot have.
+fn convert_symbol_shape(text: &str, origin: &SymbolOrigin) -> Result<Option<SExpr>, anyhow::Error> {
+    let shape = Shape::new(text);
+    let polyline = |closed: bool| -> Result<SExpr, anyhow::Error> {
+        let mut points = shape.points(1)?;
+        if closed && points.first() != points.last() {
+            points.extend(points.first().copied());
+        }
+        let points = points.into_iter().map(|(x, y)| origin.at(x, y)).map(|(x, y)| point("xy", x, y)).collect();
+        let fill = if closed { symbol_fill(shape.text(5)) } else { symbol_fill("none") };
+        Ok(SExpr::list("polyline", vec![SExpr::list("pts", points), stroke(length(shape.number_or(3, 1.0))), fill]))
+    };
+    let item = match shape.kind {
+        "P" => convert_pin(text, origin)?,
+        "R" => {
+            let (x, y) = origin.at(shape.number(1)?, shape.number(2)?);
+            let (end_x, end_y) = origin.at(shape.number(1)? + shape.number(5)?, shape.number(2)? + shape.number(6)?);
+            SExpr::list("rectangle", vec![
+                point("start", x, y),
+                point("end", end_x, end_y),
+                stroke(length(shape.number_or(8, 1.0))),
+                symbol_fill(shape.text(10)),
+            ])
+        }
+        "PL" => polyline(false)?,
+        "PG" => polyline(true)?,
+        "C" => {
+            let (x, y) = origin.at(shape.number(1)?, shape.number(2)?);
+            SExpr::list("circle", vec![
+                point("center", x, y),
+                SExpr::list("radius", vec![SExpr::atom(length(shape.number(3)?))]),
+                stroke(length(shape.number_or(5, 1.0))),
+                symbol_fill(shape.text(7)),
+            ])
+        }
+        "E" if shape.number(3)? == shape.number(4)? => {
+            let (x, y) = origin.at(shape.number(1)?, shape.number(2)?);
+            SExpr::list("circle", vec![
+                point("center", x, y),
+                SExpr::list("radius", vec![SExpr::atom(length(shape.number(3)?))]),
+                stroke(length(shape.number_or(6, 1.0))),
+                symbol_fill(shape.text(8)),
+            ])
+        }
+        kind => {
+            fidelity::record(format!("EasyEDA symbol shape {kind}"));
+            return Ok(None);
+        }
+    };
+    Ok(Some(item))
+}
+
+/// The top and bottom of a symbol shape, in EasyEDA coordinates.
+fn vertical_extent(text: &str) -> Vec<f32> {
+    let shape = Shape::new(text);
+    let number = |index: usize| shape.number(index).ok();
+    let radius = |index: usize| number(2).zip(number(index));
+    let extent = match shape.kind {
+        "P" => vec![number(5)],
+        "R" => vec![number(2), number(2).zip(number(6)).map(|(y, height)| y + height)],
+        "C" => vec![radius(3).map(|(y, radius)| y - radius), radius(3).map(|(y, radius)| y + radius)],
+        "E" => vec![radius(4).map(|(y, radius)| y - radius), radius(4).map(|(y, radius)| y + radius)],
+        "PL" | "PG" => return shape.points(1).unwrap_or_default().into_iter().map(|(_, y)| y).collect(),
+        _ => vec![],
+    };
+    extent.into_iter().flatten().collect()
+}
+
+/// The symbol of the part, with one unit per sub part of a multi unit part.
+fn convert_symbol(part: &Value, name: &str, footprint: Option<&str>) -> Result<KicadSymbolLib, anyhow::Error> {
+    let head = data(part)?;
+    let parameters = head.pointer("/head/c_para").cloned().unwrap_or_default();
+    let mut units = vec![];
+    match part.get("subparts").and_then(Value::as_array).filter(|subparts| !subparts.is_empty()) {
+        Some(subparts) => {
+            for subpart in subparts {
+                units.push(data(subpart)?);
+            }
+        }
+        None => units.push(head),
+    }
+
+    let mut children = vec![
+        SExpr::quoted(name),
+        SExpr::list("pin_names", vec![SExpr::list("offset", vec![SExpr::atom(0.254)])]),
+    ];
+    for flag in ["exclude_from_sim", "in_bom", "on_board"] {
+        let value = flag != "exclude_from_sim";
+        children.push(SExpr::list(flag, vec![SExpr::atom(bool_to_kicad_string(value))]));
+    }
+    let mut unit_symbols = vec![];
+    let (mut top, mut bottom) = (0.0f32, 0.0f32);
+    for (index, unit) in units.iter().enumerate() {
+        let (x, y) = origin(unit);
+        let origin = SymbolOrigin { x, y };
+        let mut items = vec![SExpr::quoted(format!("{name}_{}_1", index + 1))];
+        for shape in shapes(unit) {
+            items.extend(convert_symbol_shape(shape, &origin)?);
+        }
+        // The reference and value go above and below the unit
+        for y in shapes(unit).into_iter().flat_map(vertical_extent) {
+            let (_, y) = origin.at(0.0, y);
+            (top, bottom) = (top.max(y), bottom.min(y));
+        }
+        unit_symbols.push(SExpr::list("symbol", items));
+    }
+
+    let reference = json_text(&parameters, "/pre").unwrap_or("U").trim_end_matches('?');
+    let text_effects = || effects(1.27, false, false, vec![], false);
+    children.push(property("Reference", reference, (0.0, top + 2.54, 0.0), text_effects()));
+    children.push(property("Value", name, (0.0, bottom - 2.54, 0.0), text_effects()));
+    children.push(hidden_property("Footprint", footprint.unwrap_or_default()));
+    children.push(hidden_property("Datasheet", json_text(&parameters, "/link").unwrap_or_default()));
+    if let Some(description) = json_text(part, "/description") {
+        children.push(hidden_property("Description", description));
+    }
+    for (parameter, field) in [("/BOM_Manufacturer", "Manufacturer"), ("/BOM_Manufacturer Part", "MPN")] {
+        if let Some(value) = json_text(&parameters, parameter) {
+            children.push(hidden_property(field, value));
+        }
+    }
+    if let Some(lcsc) = json_text(&parameters, "/BOM_Supplier Part").or(json_text(part, "/lcsc/number")) {
+        children.push(hidden_property("LCSC", lcsc));
+    }
+    children.extend(unit_symbols);
+
+    let mut library = KicadSymbolLib::new();
+    library.symbols.push(parse_symbol(SExpr::list("symbol", children))?);
+    Ok(library)
+}
+
+/// The KiCad layer of an EasyEDA PCB layer id.
+fn footprint_layer(id: &str) -> Option<&'static str> {
+    Some(match id {
+        "1" => "F.Cu",
+        "2" => "B.Cu",
+        "3" => "F.SilkS",
+        "4" => "B.SilkS",
+        "5" => "F.Paste",
+        "6" => "B.Paste",
+        "7" => "F.Mask",
+        "8" => "B.Mask",
+        "10" => "Edge.Cuts",
+        "12" => "Cmts.User",
+        "13" => "F.Fab",
+        "14" => "B.Fab",
+        "15" => "Dwgs.User",
+        // The component and lead shapes
+        "99" => "F.CrtYd",
+        "100" | "101" => "F.Fab",
+        _ => return None,
+    })
+}
+
+fn graphic_stroke(width: f32) -> SExpr {
+    SExpr::list("stroke", vec![SExpr::list("width", vec![SExpr::atom(width)]), SExpr::list("type", vec![SExpr::atom("solid")])])
+}
+
+fn layer(name: &str) -> SExpr {
+    SExpr::list("layer", vec![SExpr::quoted(name)])
+}
+
+/// `PAD~shape~x~y~width~height~layer~net~number~hole radius~points~rotation~id~hole length~hole points~plated~locked`
+fn convert_pad(shape: &Shape, origin: (f32, f32)) -> Result<(SExpr, bool), anyhow::Error> {
+    let at = |x: f32, y: f32| (length(x - origin.0), length(y - origin.1));
+    let (x, y) = at(shape.number(2)?, shape.number(3)?);
+    let (width, height) = (length(shape.number(4)?), length(shape.number(5)?));
+    let hole_diameter = length(2.0 * shape.number_or(9, 0.0));
+    let through_hole = shape.text(6) == "11";
+    let kind = match (through_hole, shape.text(15)) {
+        (false, _) => "smd",
+        (true, "N") => "np_thru_hole",
+        (true, _) => "thru_hole",
+    };
+    let layers = match shape.text(6) {
+        "2" => ["B.Cu", "B.Paste", "B.Mask"].as_slice(),
+        "11" => ["*.Cu", "*.Mask"].as_slice(),
+        _ => ["F.Cu", "F.Paste", "F.Mask"].as_slice(),
+    };
+    let pad_shape = match shape.text(1) {
+        "RECT" => "rect",
+        "ELLIPSE" if width == height => "circle",
+        "POLYGON" => "custom",
+        _ => "oval",
+    };
+    let mut position = vec![SExpr::atom(x), SExpr::atom(y)];
+    let rotation = shape.number_or(11, 0.0).rem_euclid(360.0);
+    // The points of polygon pads are rotated already
+    if rotation != 0.0 && pad_shape != "custom" {
+        position.push(SExpr::atom(rotation));
+    }
+    let size = match pad_shape {
+        "custom" => [hole_diameter.max(0.1); 2],
+        _ => [width, height],
+    };
+    let mut children = vec![
+        SExpr::quoted(shape.text(8)),
+        SExpr::atom(kind),
+        SExpr::atom(pad_shape),
+        SExpr::List([vec![SExpr::atom("at")], position].concat()),
+        SExpr::list("size", size.map(SExpr::atom).to_vec()),
+    ];
+    if through_hole {
+        let hole_length = length(shape.number_or(13, 0.0));
+        match hole_length > 0.0 {
+            true => children.push(SExpr::list("drill", vec![
+                SExpr::atom("oval"),
+                SExpr::atom(hole_diameter),
+                SExpr::atom(hole_length.max(hole_diameter)),
+            ])),
+            false => children.push(SExpr::list("drill", vec![SExpr::atom(hole_diameter)])),
+        }
+    }
+    children.push(SExpr::list("layers", layers.iter().map(SExpr::quoted).collect()));
+    if pad_shape == "custom" {
+        let (pad_x, pad_y) = (shape.number(2)?, shape.number(3)?);
+        let points = shape.points(10)?.into_iter().map(|(px, py)| point("xy", length(px - pad_x), length(py - pad_y)));
+        children.push(SExpr::list("options", vec![
+            SExpr::list("clearance", vec![SExpr::atom("outline")]),
+            SExpr::list("anchor", vec![SExpr::atom("circle")]),
+        ]));
+        children.push(SExpr::list("primitives", vec![SExpr::list("gr_poly", vec![
+            SExpr::list("pts", points.collect()),
+            SExpr::list("width", vec![SExpr::atom(0)]),
+            SExpr::list("fill", vec![SExpr::atom(bool_to_kicad_string(true))]),
+        ])]));
+    }
+    Ok((SExpr::list("pad", children), through_hole))
+}
+
+/// The footprint items of a package shape, `None` for shapes that are left out.
+fn convert_footprint_shape(text: &str, origin: (f32, f32)) -> Result<Option<Vec<SExpr>>, anyhow::Error> {
+    let shape = Shape::new(text);
+    let at = |x: f32, y: f32| (length(x - origin.0), length(y - origin.1));
+    let graphic_layer = |index: usize| {
+        let layer = footprint_layer(shape.text(index));
+        if layer.is_none() {
+            fidelity::record(format!("EasyEDA footprint layer {}", shape.text(index)));
+        }
+        layer
+    };
+    let items = match shape.kind {
+        "TRACK" => {
+            let Some(layer_name) = graphic_layer(2) else { return Ok(None) };
+            let width = length(shape.number(1)?);
+            let points: Vec<(f32, f32)> = shape.points(4)?.into_iter().map(|(x, y)| at(x, y)).collect();
+            points
+                .windows(2)
+                .map(|line| {
+                    SExpr::list("fp_line", vec![
+                        point("start", line[0].0, line[0].1),
+                        point("end", line[1].0, line[1].1),
+                        graphic_stroke(width),
+                        layer(layer_name),
+                    ])
+                })
+                .collect()
+        }
+        "CIRCLE" => {
+            let Some(layer_name) = graphic_layer(5) else { return Ok(None) };
+            let (x, y) = at(shape.number(1)?, shape.number(2)?);
+            let (end_x, end_y) = at(shape.number(1)? + shape.number(3)?, shape.number(2)?);
+            vec![SExpr::list("fp_circle", vec![
+                point("center", x, y),
+                point("end", end_x, end_y),
+                graphic_stroke(length(shape.number(4)?)),
+                SExpr::list("fill", vec![SExpr::atom("none")]),
+                layer(layer_name),
+            ])]
+        }
+        "RECT" => {
+            let Some(layer_name) = graphic_layer(5) else { return Ok(None) };
+            let (x, y) = at(shape.number(1)?, shape.number(2)?);
+            let (end_x, end_y) = at(shape.number(1)? + shape.number(3)?, shape.number(2)? + shape.number(4)?);
+            vec![SExpr::list("fp_rect", vec![
+                point("start", x, y),
+                point("end", end_x, end_y),
+                graphic_stroke(length(shape.number_or(8, 0.5))),
+                SExpr::list("fill", vec![SExpr::atom("none")]),
+                layer(layer_name),
+            ])]
+        }
+        // `HOLE~x~y~radius~id~locked`
+        "HOLE" => {
+            let (x, y) = at(shape.number(1)?, shape.number(2)?);
+            let diameter = length(2.0 * shape.number(3)?);
+            vec![SExpr::list("pad", vec![
+                SExpr::quoted(""),
+                SExpr::atom("np_thru_hole"),
+                SExpr::atom("circle"),
+                point("at", x, y),
+                point("size", diameter, diameter),
+                SExpr::list("drill", vec![SExpr::atom(diameter)]),
+                SExpr::list("layers", vec![SExpr::quoted("*.Cu"), SExpr::quoted("*.Mask")]),
+            ])]
+        }
+        // The 3D model is on the servers of EasyEDA
+        "SVGNODE" => {
+            fidelity::record("EasyEDA 3D model");
+            return Ok(None);
+        }
+        kind => {
+            fidelity::record(format!("EasyEDA footprint shape {kind}"));
+            return Ok(None);
+        }
+    };
+    Ok(Some(items))
+}
+
+/// The `.kicad_mod` of the package of the part.
+fn convert_footprint(package: &Value, name: &str) -> Result<String, anyhow::Error> {
+    let data = data(package)?;
+    let origin = origin(&data);
+    let mut items = vec![];
+    let mut through_hole = false;
+    for text in shapes(&data) {
+        match Shape::new(text) {
+            shape if shape.kind == "PAD" => {
+                let (pad, pad_through_hole) = convert_pad(&shape, origin)?;
+                through_hole |= pad_through_hole;
+                items.push(pad);
+            }
+            _ => items.extend(convert_footprint_shape(text, origin)?.into_iter().flatten()),
+        }
+    }
+    let text = |kind: &str, value: &str, y: f32, layer_name: &str| {
+        let font = SExpr::list("font", vec![point("size", 1.0, 1.0), SExpr::list("thickness", vec![SExpr::atom(0.15)])]);
+        SExpr::list("fp_text", vec![
+            SExpr::atom(kind),
+            SExpr::quoted(value),
+            point("at", 0.0, y),
+            layer(layer_name),
+            SExpr::list("effects", vec![font]),
+        ])
+    };
+    let mut children = vec![
+        SExpr::quoted(name),
+        SExpr::list("version", vec![SExpr::atom(FOOTPRINT_VERSION)]),
+        SExpr::list("generator", vec![SExpr::quoted("kicad_library_manager")]),
+        layer("F.Cu"),
+        SExpr::list("attr", vec![SExpr::atom(if through_hole { "through_hole" } else { "smd" })]),
+        text("reference", "REF**", -2.0, "F.SilkS"),
+        text("value", name, 2.0, "F.Fab"),
+    ];
+    children.extend(items);
+    Ok(format!("{}\n", SExpr::list("footprint", children)))
+}
+
+/// Converts the EasyEDA JSON of a part at `path` into a symbol library and a
+/// footprint in `dest`, returning their paths.
+pub(crate) fn convert(path: &Path, dest: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let json: Value = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|error| anyhow!("{} is not EasyEDA JSON: {error}", path.display()))?;
+    let part = json.get("result").unwrap_or(&json);
+    let head = data(part)?;
+    let name = json_text(&head, "/head/c_para/name").or(json_text(part, "/title")).ok_or(anyhow!("The EasyEDA part has no name"))?;
+    let name = sanitized(name);
+    let mut files = vec![];
+
+    let package = part.get("packageDetail").filter(|package| !package.is_null());
+    let footprint = match package {
+        Some(package) => {
+            let package_data = data(package)?;
+            let footprint = json_text(&package_data, "/head/c_para/package")
+                .or(json_text(package, "/title"))
+                .ok_or(anyhow!("The package of {name} has no name"))?;
+            let footprint = sanitized(footprint);
+            let footprint_path = dest.join(format!("{footprint}.kicad_mod"));
+            fs::write(&footprint_path, convert_footprint(package, &footprint)?)?;
+            KiCadFootprint::from_path(&footprint_path)?;
+            files.push(footprint_path);
+            Some(footprint)
+        }
+        None => None,
+    };
+
+    let library = convert_symbol(part, &name, footprint.as_deref())?;
+    let symbol_path = dest.join(format!("{name}.kicad_sym"));
+    library.write_to_file(File::create(&symbol_path)?)?;
+    files.push(symbol_path);
+    Ok(files)
+}
@@ -0,0 +1,142 @@
+//! The names of the fields the library keeps purchasing and tracking data in.
+//! Organisations name them differently, so they come from a profile in the
+//! config, e.g.
+//!
+//! ```toml
+//! [fields]
+//! profile = "altium"
+//! mpn = "PartNumber"
+//! ```
+
+use crate::symbols::property::{KiCadPropertyType, KiCadSymbol};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Other properties vendors use for the manufacturer name, in order of preference.
+const MANUFACTURER_PROPERTIES: [&str; 4] = ["MANUFACTURER", "Manufacturer", "Manufacturer_Name", "MFR"];
+
+/// Other properties vendors use for the manufacturer part number, in order of preference.
+const MPN_PROPERTIES: [&str; 4] = ["MPN", "Manufacturer_Part_Number", "MANUFACTURER_PART_NUMBER", "MFR_PN"];
+
+/// The spellings of each field in the archives of the vendors, as compared by
+/// [`field_key`], e.g. `MANUFACTURER_PART_NUMBER` of SnapEDA and `Mouser Part
+/// Number` of SamacSys.
+const MANUFACTURER_KEYS: [&str; 5] = ["manufacturer", "manufacturername", "manufacturer1", "mfr", "mf"];
+const MPN_KEYS: [&str; 6] = ["mpn", "manufacturerpartnumber", "manufacturerpartnumber1", "mfrpn", "mfrpartnumber", "mp"];
+const MOUSER_KEYS: [&str; 3] = ["mouserpartnumber", "mouserpn", "mousernumber"];
+const DIGIKEY_KEYS: [&str; 3] = ["digikeypartnumber", "digikeypn", "digikeynumber"];
+
+/// A set of field names in use by a tool or vendor.
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FieldProfile {
+    /// `Manufacturer`, `MPN`, `Lifecycle` and `IBIS_Model`
+    #[default]
+    Default,
+    /// The supplier link fields of Altium, `Manufacturer 1` and `Manufacturer Part Number 1`
+    Altium,
+    /// The fields of SamacSys (Component Search Engine) libraries
+    Samacsys,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub(crate) struct FieldNames {
+    pub(crate) profile: FieldProfile,
+    /// Overrides of the field names of the profile
+    pub(crate) manufacturer: Option<String>,
+    pub(crate) mpn: Option<String>,
+    pub(crate) lifecycle: Option<String>,
+    pub(crate) ibis_model: Option<String>,
+    pub(crate) mouser: Option<String>,
+    pub(crate) digikey: Option<String>,
+}
+
+pub(crate) fn property_type(name: &str) -> KiCadPropertyType {
+    KiCadPropertyType::from_str(name).unwrap_or(KiCadPropertyType::Custom(name.to_string()))
+}
+
+impl FieldNames {
+    pub(crate) fn manufacturer(&self) -> &str {
+        self.manufacturer.as_deref().unwrap_or(match self.profile {
+            FieldProfile::Default => "Manufacturer",
+            FieldProfile::Altium => "Manufacturer 1",
+            FieldProfile::Samacsys => "Manufacturer_Name",
+        })
+    }
+
+    pub(crate) fn mpn(&self) -> &str {
+        self.mpn.as_deref().unwrap_or(match self.profile {
+            FieldProfile::Default => "MPN",
+            FieldProfile::Altium => "Manufacturer Part Number 1",
+            FieldProfile::Samacsys => "Manufacturer_Part_Number",
+        })
+    }
+
+    pub(crate) fn lifecycle(&self) -> &str {
+        self.lifecycle.as_deref().unwrap_or(match self.profile {
+            FieldProfile::Default | FieldProfile::Samacsys => "Lifecycle",
+            FieldProfile::Altium => "Lifecycle Status",
+        })
+    }
+
+    pub(crate) fn ibis_model(&self) -> &str {
+        self.ibis_model.as_deref().unwrap_or("IBIS_Model")
+    }
+
+    pub(crate) fn mouser(&self) -> &str {
+        self.mouser.as_deref().unwrap_or("Mouser Part Number")
+    }
+
+    pub(crate) fn digikey(&self) -> &str {
+        self.digikey.as_deref().unwrap_or("DigiKey Part Number")
+    }
+
+    /// The name the library uses for a field a vendor spells its own way,
+    /// like `Manufacturer` for `MANUFACTURER_NAME`.
+    pub(crate) fn normalized_name(&self, name: &str) -> Option<&str> {
+        let key = field_key(name);
+        [
+            (self.manufacturer(), &MANUFACTURER_KEYS[..]),
+            (self.mpn(), &MPN_KEYS[..]),
+            (self.mouser(), &MOUSER_KEYS[..]),
+            (self.digikey(), &DIGIKEY_KEYS[..]),
+        ]
+        .into_iter()
+        .find(|(field, keys)| field_key(field) == key || keys.contains(&key.as_str()))
+        .map(|(field, _)| field)
+    }
+
+    pub(crate) fn lifecycle_property(&self) -> KiCadPropertyType {
+        property_type(self.lifecycle())
+    }
+
+    pub(crate) fn ibis_model_property(&self) -> KiCadPropertyType {
+        property_type(self.ibis_model())
+    }
+
+    /// The manufacturer of the symbol, from the configured field or else from
+    /// one of the fields vendors use.
+    pub(crate) fn symbol_manufacturer<'a>(&self, symbol: &'a KiCadSymbol) -> Option<&'a str> {
+        first_value(symbol, self.manufacturer(), &MANUFACTURER_PROPERTIES)
+    }
+
+    /// The manufacturer part number of the symbol, from the configured field
+    /// or else from one of the fields vendors use.
+    pub(crate) fn symbol_mpn<'a>(&self, symbol: &'a KiCadSymbol) -> Option<&'a str> {
+        first_value(symbol, self.mpn(), &MPN_PROPERTIES)
+    }
+}
+
+/// A field name without case and separators, so `Manufacturer_Part_Number`
+/// and `MANUFACTURER PART NUMBER` are the same field.
+fn field_key(name: &str) -> String {
+    name.chars().filter(char::is_ascii_alphanumeric).map(|c| c.to_ascii_lowercase()).collect()
+}
+
+fn first_value<'a>(symbol: &'a KiCadSymbol, name: &str, fallbacks: &[&str]) -> Option<&'a str> {
+    std::iter::once(name)
+        .chain(fallbacks.iter().copied())
+        .find_map(|name| symbol.property_value(&property_type(name)))
+        .filter(|value| !value.is_empty())
+}
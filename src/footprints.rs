@@ -0,0 +1,561 @@
+//! Reads `.kicad_mod` footprints, as far as the checks of the import need them.
+
+use crate::config::ConflictPolicy;
+use crate::dry_run;
+use crate::footprints::graphics::{KiCadFootprintGraphic, GRAPHIC_ITEMS};
+use crate::footprints::models::KiCadModel;
+use crate::footprints::pad::KiCadPad;
+use crate::footprints::writer::write_library_footprint;
+use crate::symbols::{describe_error, subdivide_expression, tokenise, top_level_words, Token, TryFromExpression};
+use anyhow::{anyhow, bail};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub mod graphics;
+pub(crate) mod layers;
+pub(crate) mod lint;
+pub(crate) mod models;
+pub mod pad;
+pub(crate) mod rename;
+mod svg;
+pub(crate) mod upgrade;
+pub(crate) mod writer;
+
+const COURTYARD_LAYERS: [&str; 2] = ["F.CrtYd", "B.CrtYd"];
+
+/// Axis aligned bounding box in mm.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct BoundingBox {
+    min_x: f32,
+    min_y: f32,
+    max_x: f32,
+    max_y: f32,
+}
+
+impl BoundingBox {
+    pub(crate) fn around(x: f32, y: f32) -> Self {
+        BoundingBox { min_x: x, min_y: y, max_x: x, max_y: y }
+    }
+
+    pub(crate) fn include(&mut self, x: f32, y: f32) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+
+    pub(crate) fn width(&self) -> f32 {
+        self.max_x - self.min_x
+    }
+
+    pub(crate) fn height(&self) -> f32 {
+        self.max_y - self.min_y
+    }
+
+    /// How far `other` is inside this box at its closest side, negative if
+    /// it sticks out.
+    pub(crate) fn inset_of(&self, other: &BoundingBox) -> f32 {
+        (other.min_x - self.min_x)
+            .min(other.min_y - self.min_y)
+            .min(self.max_x - other.max_x)
+            .min(self.max_y - other.max_y)
+    }
+}
+
+/// A text of a footprint, `(fp_text ...)` or in KiCad 8 and later
+/// `(property ...)` for the reference and value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KiCadFootprintText {
+    /// `reference`, `value` or `user`
+    kind: String,
+    text: String,
+    layer: String,
+}
+
+impl KiCadFootprintText {
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn layer(&self) -> &str {
+        &self.layer
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KiCadFootprint {
+    name: String,
+    description: Option<String>,
+    /// Keywords separated by spaces
+    tags: Option<String>,
+    /// Flags like `smd` or `through_hole`
+    attributes: Vec<String>,
+    texts: Vec<KiCadFootprintText>,
+    graphics: Vec<KiCadFootprintGraphic>,
+    pads: Vec<KiCadPad>,
+    models: Vec<KiCadModel>,
+    courtyard: Option<BoundingBox>,
+    /// Every layer name used by the footprint, its pads and graphics
+    layers: Vec<String>,
+}
+
+impl KiCadFootprint {
+    pub fn from_path(path: &Path) -> Result<Self, anyhow::Error> {
+        let content = fs::read_to_string(path).map_err(|error| anyhow!("{}: {error}", path.display()))?;
+        Self::parse(&content, Some(path))
+    }
+
+    fn parse(content: &str, path: Option<&Path>) -> Result<Self, anyhow::Error> {
+        let tokens = footprint_tokens(content, path)?;
+        let Some(Token::Word(name)) = tokens.get(2) else { bail!("Footprint has no name") };
+
+        let mut description = None;
+        let mut tags = None;
+        let mut attributes = vec![];
+        let mut texts = vec![];
+        let mut graphics = vec![];
+        let mut pads = vec![];
+        let mut models = vec![];
+        for item in subdivide_expression(&tokens[3..]) {
+            let item = &item[item.iter().position(|token| matches!(token, Token::OpenParen(_))).unwrap_or(0)..];
+            let Some(Token::Word(keyword)) = item.get(1) else { continue };
+            match keyword.as_ref() {
+                "pad" => pads.push(KiCadPad::parse_expression(item).map_err(|error| describe_error(error, content, path))?),
+                keyword if GRAPHIC_ITEMS.contains(&keyword) => {
+                    graphics.push(KiCadFootprintGraphic::parse_expression(item).map_err(|error| describe_error(error, content, path))?);
+                }
+                "descr" | "tags" => {
+                    let Some(Token::Word(text)) = item.get(2) else { continue };
+                    let field = if keyword == "descr" { &mut description } else { &mut tags };
+                    *field = Some(text.to_string());
+                }
+                "attr" => attributes.extend(top_level_words(item).into_iter().skip(1).map(str::to_string)),
+                "model" => models.extend(KiCadModel::parse_expression(item)),
+                "fp_text" | "property" => {
+                    let (Some(Token::Word(kind)), Some(Token::Word(text))) = (item.get(2), item.get(3)) else { continue };
+                    let kind = kind.to_lowercase();
+                    if keyword == "property" && kind != "reference" && kind != "value" {
+                        continue;
+                    }
+                    let layer = subdivide_expression(&item[4..])
+                        .into_iter()
+                        .find(|child| child.get(1) == Some(&Token::Word("layer".into())))
+                        .and_then(|child| match child.get(2) {
+                            Some(Token::Word(layer)) => Some(layer.to_string()),
+                            _ => None,
+                        })
+                        .unwrap_or_default();
+                    texts.push(KiCadFootprintText { kind, text: text.to_string(), layer });
+                }
+                _ => {}
+            }
+        }
+        let courtyard = courtyard_of(&tokens);
+        let mut layers: Vec<String> = layers::layer_lists(&tokens).into_iter().flat_map(|(_, names)| names).collect();
+        layers.sort();
+        layers.dedup();
+        Ok(KiCadFootprint {
+            name: name.to_string(),
+            description,
+            tags,
+            attributes, texts, graphics, pads, models, courtyard, layers })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn tags(&self) -> Option<&str> {
+        self.tags.as_deref()
+    }
+
+    pub fn attributes(&self) -> &[String] {
+        &self.attributes
+    }
+
+    pub fn texts(&self) -> &[KiCadFootprintText] {
+        &self.texts
+    }
+
+    pub fn graphics(&self) -> &[KiCadFootprintGraphic] {
+        &self.graphics
+    }
+
+    pub fn pads(&self) -> &[KiCadPad] {
+        &self.pads
+    }
+
+    pub fn models(&self) -> &[KiCadModel] {
+        &self.models
+    }
+
+    pub fn layers(&self) -> &[String] {
+        &self.layers
+    }
+
+    /// The extent of the courtyard, `None` if the footprint has none.
+    pub(crate) fn courtyard(&self) -> Option<BoundingBox> {
+        self.courtyard
+    }
+}
+
+impl FromStr for KiCadFootprint {
+    type Err = anyhow::Error;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        Self::parse(content, None)
+    }
+}
+
+/// The tokens of a footprint file, KiCad 6 and later call the top level list
+/// `footprint` and older versions `module`.
+pub(crate) fn footprint_tokens<'a>(content: &'a str, path: Option<&Path>) -> Result<Vec<Token<'a>>, anyhow::Error> {
+    let tokens = tokenise(content).map_err(|error| describe_error(error, content, path))?;
+    let file = path.map_or("File".to_string(), |path| path.display().to_string());
+    let Some(Token::Word(kind)) = tokens.get(1) else { bail!("{file} is not a KiCad footprint") };
+    if kind != "footprint" && kind != "module" {
+        bail!("{file} is not a KiCad footprint");
+    }
+    Ok(tokens)
+}
+
+/// Characters KiCad does not allow in library nicknames, which are taken
+/// from the name of the `.pretty` directory.
+const INVALID_NICKNAME_CHARACTERS: [char; 6] = [':', '/', '\\', '"', '\t', '\n'];
+
+/// Makes sure `path` is a `.pretty` footprint library, creating it if it does
+/// not exist yet. Footprints are only copied into other directories with
+/// `force`, as KiCad would not find them there.
+pub(crate) fn prepare_footprint_library(path: &Path, force: bool) -> Result<(), anyhow::Error> {
+    if path.extension().is_some_and(|extension| extension == "pretty") {
+        let nickname = path.file_stem().unwrap_or_default().to_string_lossy();
+        if nickname.is_empty() || nickname.contains(INVALID_NICKNAME_CHARACTERS) {
+            bail!("{} is not a valid footprint library name", path.display());
+        }
+        if path.exists() && !path.is_dir() {
+            bail!("Footprint library {} is not a directory", path.display());
+        }
+    } else if !force {
+        bail!(
+            "{} is not a .pretty footprint library, pass --force to copy footprints into it anyway",
+            path.display()
+        );
+    }
+    if !path.exists() {
+        if !dry_run::is_enabled() {
+            println!("Creating footprint library {}", path.display());
+        }
+        dry_run::create_dir_all(path).map_err(|error| anyhow!("{}: {error}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Copies the footprint file into the footprint library. A footprint that is
+/// equivalent to one in the library, e.g. the same package from another
+/// vendor archive, is not copied and the one in the library is used instead.
+/// The footprint is renamed to `name` if that is not the name of the file.
+/// If the library has a different footprint of the same name, `policy` decides
+/// whether it is kept, overwritten or the footprint is imported under a new
+/// name. Returns the path of the footprint in the library, or `None` if it was
+/// skipped.
+pub(crate) fn copy_footprint(
+    file: &Path,
+    name: &str,
+    library: &Path,
+    policy: ConflictPolicy,
+) -> Result<Option<PathBuf>, anyhow::Error> {
+    let mut content = fs::read_to_string(file).map_err(|error| anyhow!("{}: {error}", file.display()))?;
+    if file.file_stem().is_none_or(|stem| stem != name) {
+        content = rename_footprint(&content, name, Some(file))?;
+    }
+    let dest_file = library.join(format!("{name}.kicad_mod"));
+    if let Some(existing) = find_equivalent_footprint(library, &dest_file, &normalized_hash(&content, Some(file))?)? {
+        if existing == dest_file {
+            println!("{}: unchanged", dest_file.display());
+        } else {
+            println!("{name}: the same footprint is in the library as {}, using it", existing.display());
+        }
+        return Ok(Some(existing));
+    }
+    if !dest_file.exists() {
+        write_library_footprint(&dest_file, &content)?;
+        return Ok(Some(dest_file));
+    }
+
+    let policy = match policy {
+        ConflictPolicy::Ask => ask_conflict(&dest_file.display().to_string())?,
+        policy => policy,
+    };
+    match policy {
+        ConflictPolicy::Skip => {
+            println!("{}: a different footprint of that name exists, skipped", dest_file.display());
+            Ok(None)
+        }
+        ConflictPolicy::Overwrite => {
+            println!("{}: overwritten", dest_file.display());
+            write_library_footprint(&dest_file, &content)?;
+            Ok(Some(dest_file))
+        }
+        ConflictPolicy::Ask => unreachable!("the answer is one of the other policies"),
+        ConflictPolicy::Rename => {
+            let (new_name, dest_file) = (1..)
+                .map(|suffix| format!("{name}_{suffix}"))
+                .map(|new_name| (new_name.clone(), library.join(format!("{new_name}.kicad_mod"))))
+                .find(|(_, dest_file)| !dest_file.exists())
+                .expect("the suffixes are unbounded");
+            println!("{}: a different footprint of that name exists, imported as {new_name}", library.join(format!("{name}.kicad_mod")).display());
+            write_library_footprint(&dest_file, &rename_footprint(&content, &new_name, Some(file))?)?;
+            Ok(Some(dest_file))
+        }
+    }
+}
+
+/// Asks on the terminal what to do with an item that exists and differs.
+pub(crate) fn ask_conflict(item: &str) -> Result<ConflictPolicy, anyhow::Error> {
+    if !io::stdin().is_terminal() {
+        bail!("{item} already exists and there is no terminal to ask what to do with it");
+    }
+    loop {
+        print!("{item} already exists and differs, [s]kip, [o]verwrite or [r]ename? ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(ConflictPolicy::Skip);
+        }
+        match answer.trim() {
+            "s" | "skip" => return Ok(ConflictPolicy::Skip),
+            "o" | "overwrite" => return Ok(ConflictPolicy::Overwrite),
+            "r" | "rename" => return Ok(ConflictPolicy::Rename),
+            _ => {}
+        }
+    }
+}
+
+/// Lists whose content changes whenever a footprint is saved, without changing
+/// the footprint.
+const VOLATILE_LISTS: [&str; 6] = ["tstamp", "uuid", "tedit", "version", "generator", "generator_version"];
+
+/// A hash of the footprint that ignores formatting, timestamps, uuids and the
+/// name of the footprint, so copies of the same footprint under other names
+/// or saved by another program hash the same.
+fn normalized_hash(content: &str, path: Option<&Path>) -> Result<String, anyhow::Error> {
+    let tokens = footprint_tokens(content, path)?;
+    let Some(Token::Word(name)) = tokens.get(2) else { bail!("Footprint has no name") };
+
+    let mut hasher = Sha256::new();
+    let mut skip_depth = None;
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenParen(_) => {
+                depth += 1;
+                let keyword = match tokens.get(index + 1) {
+                    Some(Token::Word(keyword)) => keyword.as_ref(),
+                    _ => "",
+                };
+                if skip_depth.is_none() && VOLATILE_LISTS.contains(&keyword) {
+                    skip_depth = Some(depth);
+                }
+                if skip_depth.is_none() {
+                    hasher.update(b"(");
+                }
+            }
+            Token::CloseParen => {
+                if skip_depth.is_none() {
+                    hasher.update(b")");
+                }
+                if skip_depth == Some(depth) {
+                    skip_depth = None;
+                }
+                depth -= 1;
+            }
+            Token::Word(_) if skip_depth.is_some() => {}
+            // The value text of the footprint repeats its name
+            Token::Word(word) if word == name => hasher.update(b"\0name "),
+            Token::Word(word) => {
+                hasher.update(word.as_bytes());
+                hasher.update(b" ");
+            }
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// A footprint of the library with the given normalized hash, `preferred` if
+/// it is one of them. Files that cannot be read as footprints are passed over.
+fn find_equivalent_footprint(library: &Path, preferred: &Path, hash: &str) -> Result<Option<PathBuf>, anyhow::Error> {
+    let mut candidates = vec![preferred.to_path_buf()];
+    // A dry run does not create the library
+    if library.is_dir() {
+        for entry in fs::read_dir(library)? {
+            let path = entry?.path();
+            if path != preferred && path.extension().is_some_and(|extension| extension == "kicad_mod") {
+                candidates.push(path);
+            }
+        }
+    }
+    let equivalent = candidates.into_iter().find(|candidate| {
+        fs::read_to_string(candidate)
+            .ok()
+            .and_then(|content| normalized_hash(&content, Some(candidate)).ok())
+            .is_some_and(|candidate_hash| candidate_hash == hash)
+    });
+    Ok(equivalent)
+}
+
+/// The footprint file with the name in its header, and the value text if it
+/// repeats the name, replaced by `name`.
+pub(crate) fn rename_footprint(content: &str, name: &str, path: Option<&Path>) -> Result<String, anyhow::Error> {
+    let tokens = footprint_tokens(content, path)?;
+    let (Some(&Token::OpenParen(offset)), Some(Token::Word(old))) = (tokens.first(), tokens.get(2)) else {
+        bail!("Footprint has no name")
+    };
+    let Some(header) = first_argument_span(content, offset) else { bail!("Footprint has no name") };
+    let mut spans = vec![header];
+    for window in tokens.windows(4) {
+        let [Token::OpenParen(offset), Token::Word(kind), Token::Word(key), Token::Word(value)] = window else { continue };
+        let is_value = (kind == "fp_text" && key == "value") || (kind == "property" && key == "Value");
+        if is_value && value == old {
+            spans.extend(argument_spans(content, *offset).into_iter().nth(1));
+        }
+    }
+    let mut renamed = content.to_string();
+    // From the end, so the spans of the earlier names stay valid
+    for span in spans.into_iter().rev() {
+        renamed.replace_range(span, &quote(name));
+    }
+    Ok(renamed)
+}
+
+/// Byte range of the first argument of the list starting at `offset`, e.g. of
+/// the quoted path in `(model "path" ...)`.
+pub(crate) fn first_argument_span(content: &str, offset: usize) -> Option<Range<usize>> {
+    argument_spans(content, offset).into_iter().next()
+}
+
+/// Byte ranges of the words after the keyword of the list starting at
+/// `offset`, up to its first nested list or its end.
+pub(crate) fn argument_spans(content: &str, offset: usize) -> Vec<Range<usize>> {
+    let bytes = content.as_bytes();
+    let is_space = |byte: u8| byte.is_ascii_whitespace();
+    let mut spans = vec![];
+    let mut index = offset + 1;
+    // The keyword
+    while bytes.get(index).is_some_and(|&byte| !is_space(byte) && byte != b'(' && byte != b')') {
+        index += 1;
+    }
+    loop {
+        while bytes.get(index).copied().is_some_and(is_space) {
+            index += 1;
+        }
+        let start = index;
+        match bytes.get(index) {
+            None | Some(b'(') | Some(b')') => return spans,
+            Some(b'"') => {
+                index += 1;
+                loop {
+                    match bytes.get(index) {
+                        None => return spans,
+                        Some(b'\\') => index += 2,
+                        Some(b'"') => break,
+                        Some(_) => index += 1,
+                    }
+                }
+                index += 1;
+            }
+            Some(_) => {
+                while bytes.get(index).is_some_and(|&byte| !is_space(byte) && byte != b'(' && byte != b')') {
+                    index += 1;
+                }
+            }
+        }
+        spans.push(start..index);
+    }
+}
+
+pub(crate) fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The extent of everything drawn on the courtyard layers of a `.kicad_mod`
+/// file, or `None` if the footprint has no courtyard.
+pub(crate) fn courtyard_bounds(path: &Path) -> Result<Option<BoundingBox>, anyhow::Error> {
+    let content = fs::read_to_string(path)?;
+    let tokens = footprint_tokens(&content, Some(path))?;
+    Ok(courtyard_of(&tokens))
+}
+
+fn courtyard_of(tokens: &[Token]) -> Option<BoundingBox> {
+    let mut bounds: Option<BoundingBox> = None;
+    let mut include = |x: f32, y: f32| match &mut bounds {
+        Some(bounds) => bounds.include(x, y),
+        None => bounds = Some(BoundingBox::around(x, y)),
+    };
+
+    for item in subdivide_expression(&tokens[2..]) {
+        // Footprint name and flags like `locked` in front of the first list
+        let item = &item[item.iter().position(|token| matches!(token, Token::OpenParen(_))).unwrap_or(0)..];
+        let Some(Token::Word(name)) = item.get(1) else { continue };
+        if !matches!(name.as_ref(), "fp_line" | "fp_rect" | "fp_poly" | "fp_circle" | "fp_arc") {
+            continue;
+        }
+        let children = subdivide_expression(&item[2..]);
+        let on_courtyard = children.iter().any(|child| {
+            child.get(1) == Some(&Token::Word("layer".into()))
+                && matches!(child.get(2), Some(Token::Word(layer)) if COURTYARD_LAYERS.contains(&layer.as_ref()))
+        });
+        if !on_courtyard {
+            continue;
+        }
+
+        let mut center = None;
+        let mut end = None;
+        for child in &children {
+            match child.get(1) {
+                Some(Token::Word(point)) if matches!(point.as_ref(), "start" | "mid" | "end" | "center") => {
+                    let Some((x, y)) = parse_point(child) else { continue };
+                    match point.as_ref() {
+                        "center" => center = Some((x, y)),
+                        "end" => end = Some((x, y)),
+                        _ => {}
+                    }
+                    include(x, y);
+                }
+                Some(Token::Word(pts)) if pts == "pts" => {
+                    for xy in subdivide_expression(&child[2..]) {
+                        if let Some((x, y)) = parse_point(xy) {
+                            include(x, y);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        // A circle is given by its center and a point on the circumference
+        if let (true, Some((cx, cy)), Some((ex, ey))) = (name == "fp_circle", center, end) {
+            let radius = (ex - cx).hypot(ey - cy);
+            include(cx - radius, cy - radius);
+            include(cx + radius, cy + radius);
+        }
+    }
+
+    bounds
+}
+
+pub(crate) fn parse_point(expression: &[Token]) -> Option<(f32, f32)> {
+    let (Some(Token::Word(x)), Some(Token::Word(y))) = (expression.get(2), expression.get(3)) else { return None };
+    Some((x.parse().ok()?, y.parse().ok()?))
+}
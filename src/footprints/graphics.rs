@@ -0,0 +1,119 @@
+use crate::footprints::parse_point;
+use crate::symbols::Token::Word;
+use crate::symbols::{subdivide_expression, Expression, TryFromExpression};
+use anyhow::{bail, Error};
+
+/// Graphic item keywords, `fp_curve` (Bézier curves) is not read.
+pub(crate) const GRAPHIC_ITEMS: [&str; 5] = ["fp_line", "fp_rect", "fp_circle", "fp_arc", "fp_poly"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum KiCadShape {
+    Line { start: (f32, f32), end: (f32, f32) },
+    Rect { start: (f32, f32), end: (f32, f32) },
+    Circle { center: (f32, f32), radius: f32 },
+    /// An arc through three points, from `start` over `mid` to `end`
+    Arc { start: (f32, f32), mid: (f32, f32), end: (f32, f32) },
+    Polygon(Vec<(f32, f32)>),
+}
+
+/// A line, rectangle, circle, arc or polygon drawn on a layer of the footprint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KiCadFootprintGraphic {
+    shape: KiCadShape,
+    layer: String,
+    /// Line width in mm, 0 for filled shapes without outline
+    width: f32,
+    filled: bool,
+}
+
+impl KiCadFootprintGraphic {
+    pub fn shape(&self) -> &KiCadShape {
+        &self.shape
+    }
+
+    pub fn layer(&self) -> &str {
+        &self.layer
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn is_filled(&self) -> bool {
+        self.filled
+    }
+}
+
+impl TryFromExpression<KiCadFootprintGraphic> for KiCadFootprintGraphic {
+    fn try_from_expression(expression: &Expression) -> Result<KiCadFootprintGraphic, Error> {
+        let Some(Word(kind)) = expression.get(1) else { bail!("Not a KiCad graphic item: {expression:?}") };
+
+        let mut points = vec![];
+        let mut named = |name: &str, point: (f32, f32)| points.push((name.to_string(), point));
+        let mut polygon = vec![];
+        let mut angle = None;
+        let mut layer = String::new();
+        let mut width = 0.0;
+        let mut filled = false;
+        for child in subdivide_expression(&expression[2..]) {
+            let Some(Word(name)) = child.get(1) else { continue };
+            match name.as_ref() {
+                "start" | "mid" | "end" | "center" => {
+                    let Some(point) = parse_point(child) else { bail!("{kind} has a malformed {name}") };
+                    named(name, point);
+                }
+                "pts" => polygon.extend(subdivide_expression(&child[2..]).into_iter().filter_map(|xy| parse_point(xy))),
+                "angle" => angle = parse_number(child),
+                "layer" => {
+                    if let Some(Word(name)) = child.get(2) {
+                        layer = name.to_string();
+                    }
+                }
+                "width" => width = parse_number(child).unwrap_or_default(),
+                "stroke" => {
+                    let stroke_width = subdivide_expression(&child[2..])
+                        .into_iter()
+                        .find(|property| property.get(1) == Some(&Word("width".into())))
+                        .and_then(|property| parse_number(property));
+                    width = stroke_width.unwrap_or_default();
+                }
+                // `(fill solid)` since KiCad 6 and `(fill yes)` since KiCad 7
+                "fill" => filled = matches!(child.get(2), Some(Word(fill)) if fill == "solid" || fill == "yes"),
+                _ => {}
+            }
+        }
+        let point = |name: &str| points.iter().find(|(point, _)| point == name).map(|(_, point)| *point);
+
+        let shape = match (kind.as_ref(), point("start"), point("end")) {
+            ("fp_line", Some(start), Some(end)) => KiCadShape::Line { start, end },
+            ("fp_rect", Some(start), Some(end)) => KiCadShape::Rect { start, end },
+            ("fp_circle", _, Some(end)) => {
+                let Some(center) = point("center").or(point("start")) else { bail!("fp_circle has no center") };
+                KiCadShape::Circle { center, radius: (end.0 - center.0).hypot(end.1 - center.1) }
+            }
+            ("fp_arc", Some(start), Some(end)) => match (point("mid"), angle) {
+                (Some(mid), _) => KiCadShape::Arc { start, mid, end },
+                // KiCad 5 arcs: the center, the start point and the angle clockwise in degrees
+                (None, Some(angle)) => {
+                    let rotate = |degrees: f32| {
+                        let (sin, cos) = degrees.to_radians().sin_cos();
+                        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+                        (start.0 + dx * cos - dy * sin, start.1 + dx * sin + dy * cos)
+                    };
+                    KiCadShape::Arc { start: end, mid: rotate(angle / 2.0), end: rotate(angle) }
+                }
+                (None, None) => bail!("fp_arc has neither a mid point nor an angle"),
+            },
+            ("fp_poly", _, _) if !polygon.is_empty() => KiCadShape::Polygon(polygon),
+            _ => bail!("{kind} is missing points"),
+        };
+        Ok(KiCadFootprintGraphic { shape, layer, width, filled })
+    }
+}
+
+fn parse_number(expression: &Expression) -> Option<f32> {
+    match expression.get(2) {
+        Some(Word(value)) => value.parse().ok(),
+        _ => None,
+    }
+}
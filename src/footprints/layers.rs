@@ -0,0 +1,106 @@
+//! The layer names KiCad writes into footprint files, and the names vendor
+//! exporters write instead.
+
+use crate::footprints::writer::write_footprint;
+use crate::footprints::{argument_spans, footprint_tokens, quote};
+use crate::symbols::Token;
+use std::fs;
+use std::path::Path;
+
+const LAYERS: [&str; 20] = [
+    "F.Cu", "B.Cu", "F.Adhes", "B.Adhes", "F.Paste", "B.Paste", "F.SilkS", "B.SilkS", "F.Mask", "B.Mask",
+    "Dwgs.User", "Cmts.User", "Eco1.User", "Eco2.User", "Edge.Cuts", "Margin", "F.CrtYd", "B.CrtYd", "F.Fab", "B.Fab",
+];
+
+/// Layer sets pads use, like `*.Cu` for every copper layer.
+const WILDCARDS: [&str; 9] = ["*.Cu", "F&B.Cu", "*.Adhes", "*.Paste", "*.SilkS", "*.Mask", "*.CrtYd", "*.Fab", "*In.Cu"];
+
+/// The names KiCad shows in its user interface, which some exporters write
+/// into the file instead of the layer name.
+const DISPLAY_NAMES: [(&str, &str); 12] = [
+    ("F.Silkscreen", "F.SilkS"),
+    ("B.Silkscreen", "B.SilkS"),
+    ("F.Adhesive", "F.Adhes"),
+    ("B.Adhesive", "B.Adhes"),
+    ("F.Courtyard", "F.CrtYd"),
+    ("B.Courtyard", "B.CrtYd"),
+    ("F.Fabrication", "F.Fab"),
+    ("B.Fabrication", "B.Fab"),
+    ("User.Drawings", "Dwgs.User"),
+    ("User.Comments", "Cmts.User"),
+    ("User.Eco1", "Eco1.User"),
+    ("User.Eco2", "Eco2.User"),
+];
+
+pub(crate) fn is_known_layer(name: &str) -> bool {
+    let numbered = |prefix: &str, suffix: &str, max: u32| {
+        name.strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix(suffix))
+            .and_then(|number| number.parse::<u32>().ok())
+            .is_some_and(|number| (1..=max).contains(&number))
+    };
+    LAYERS.contains(&name) || WILDCARDS.contains(&name) || numbered("In", ".Cu", 30) || numbered("User.", "", 45)
+}
+
+/// The layer an unknown layer name is obviously meant to be, for display
+/// names and names in the wrong case like `f.silks`.
+pub(crate) fn corrected_layer(name: &str) -> Option<&'static str> {
+    DISPLAY_NAMES
+        .iter()
+        .find(|(display_name, _)| display_name.eq_ignore_ascii_case(name))
+        .map(|(_, layer)| *layer)
+        .or_else(|| LAYERS.iter().chain(&WILDCARDS).find(|layer| layer.eq_ignore_ascii_case(name)).copied())
+}
+
+pub(crate) fn is_copper_layer(name: &str) -> bool {
+    name.ends_with(".Cu")
+}
+
+/// The names of `(layer ...)` and `(layers ...)` lists, with the offset of
+/// the list they are in.
+pub(crate) fn layer_lists(tokens: &[Token]) -> Vec<(usize, Vec<String>)> {
+    let mut lists = vec![];
+    for (index, window) in tokens.windows(2).enumerate() {
+        let [Token::OpenParen(offset), Token::Word(keyword)] = window else { continue };
+        if keyword != "layer" && keyword != "layers" {
+            continue;
+        }
+        let names = tokens[index + 2..]
+            .iter()
+            .map_while(|token| match token {
+                Token::Word(word) => Some(word.to_string()),
+                _ => None,
+            })
+            .collect();
+        lists.push((*offset, names));
+    }
+    lists
+}
+
+/// Replaces layer names that have an obvious correction in the footprint
+/// file and writes it back if anything changed. Returns the corrections as
+/// old and new name.
+pub(crate) fn fix_layer_names(path: &Path) -> Result<Vec<(String, &'static str)>, anyhow::Error> {
+    let content = fs::read_to_string(path)?;
+    let tokens = footprint_tokens(&content, Some(path))?;
+    let mut fixed = content.clone();
+    let mut corrections = vec![];
+    // From the end, so the spans of the earlier names stay valid
+    let names = layer_lists(&tokens)
+        .into_iter()
+        .flat_map(|(offset, names)| argument_spans(&content, offset).into_iter().zip(names));
+    for (span, name) in names.collect::<Vec<_>>().into_iter().rev() {
+        if is_known_layer(&name) {
+            continue;
+        }
+        if let Some(layer) = corrected_layer(&name) {
+            fixed.replace_range(span, &quote(layer));
+            corrections.push((name, layer));
+        }
+    }
+    if !corrections.is_empty() {
+        write_footprint(path, &fixed)?;
+    }
+    corrections.reverse();
+    Ok(corrections)
+}
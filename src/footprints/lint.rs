@@ -0,0 +1,171 @@
+//! Checks of footprints against the main rules of the KiCad Library
+//! Conventions (KLC), which libraries shared with KiCad users should follow.
+
+use crate::footprints::layers::{corrected_layer, is_copper_layer, is_known_layer};
+use crate::footprints::pad::KiCadPadType;
+use crate::footprints::pad::KiCadPad;
+use crate::footprints::{BoundingBox, KiCadFootprint, KiCadFootprintText};
+use crate::validation::{Finding, Severity};
+use anyhow::bail;
+use std::path::Path;
+
+/// Pads this far from where the origin should be still count as placed there, in mm.
+const ORIGIN_TOLERANCE: f32 = 0.01;
+
+/// The rules by name, with what they check.
+pub(crate) const RULES: [(&str, &str); 9] = [
+    ("name", "the footprint is named like its file and only uses A-Z, a-z, 0-9 and _-.,+"),
+    ("reference-silkscreen", "the reference is on the silkscreen"),
+    ("reference-fab", "a ${REFERENCE} text is on the fabrication layer"),
+    ("value-fab", "the value is on the fabrication layer"),
+    ("courtyard", "the footprint has a courtyard"),
+    ("courtyard-clearance", "the courtyard encloses every pad with the clearance of `courtyard_clearance`"),
+    ("model", "a 3D model is assigned"),
+    ("origin", "SMD footprints have their origin in the center of the pads, THT footprints at pad 1"),
+    ("layers", "only KiCad layer names are used and every pad but mounting holes has copper"),
+];
+
+/// Fails for names of rules that do not exist, e.g. misspelled in the config.
+pub(crate) fn check_rule_names(names: &[String]) -> Result<(), anyhow::Error> {
+    for name in names {
+        if !RULES.iter().any(|(rule, _)| rule == name) {
+            bail!("Unknown footprint rule {name}, the rules are {}", RULES.map(|(rule, _)| rule).join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Runs the rules not in `disabled` on the footprint read from `path`.
+/// `clearance` is the distance in mm the courtyard must keep around the pads.
+pub(crate) fn lint_footprint(footprint: &KiCadFootprint, path: &Path, disabled: &[String], clearance: f32) -> Vec<Finding> {
+    let mut findings = vec![];
+    for (rule, _) in RULES {
+        if disabled.iter().any(|name| name == rule) {
+            continue;
+        }
+        let message = match rule {
+            "name" => check_name(footprint, path),
+            "reference-silkscreen" => check_text(footprint, "reference", |text| text.kind() == "reference", "SilkS"),
+            "reference-fab" => check_text(
+                footprint,
+                "${REFERENCE} text",
+                |text| text.kind() == "user" && matches!(text.text(), "${REFERENCE}" | "%R"),
+                "Fab",
+            ),
+            "value-fab" => check_text(footprint, "value", |text| text.kind() == "value", "Fab"),
+            "courtyard" => footprint.courtyard().is_none().then(|| "no courtyard on F.CrtYd or B.CrtYd".to_string()),
+            "courtyard-clearance" => check_courtyard_clearance(footprint, clearance),
+            "model" => footprint.models().is_empty().then(|| "no 3D model assigned".to_string()),
+            "origin" => check_origin(footprint),
+            "layers" => check_layers(footprint),
+            _ => unreachable!("every rule is checked"),
+        };
+        if let Some(message) = message {
+            findings.push(Finding::new(Severity::Warning, footprint.name(), format!("[{rule}] {message}")));
+        }
+    }
+    findings
+}
+
+fn check_name(footprint: &KiCadFootprint, path: &Path) -> Option<String> {
+    let name = footprint.name();
+    if let Some(c) = name.chars().find(|c| !(c.is_ascii_alphanumeric() || "_-.,+".contains(*c))) {
+        return Some(format!("'{c}' is not allowed in footprint names"));
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    (stem != name).then(|| format!("the file is named {stem}"))
+}
+
+/// Checks that a text matching `is_text` is on the front or back `layer`.
+fn check_text(
+    footprint: &KiCadFootprint,
+    description: &str,
+    is_text: impl Fn(&KiCadFootprintText) -> bool,
+    layer: &str,
+) -> Option<String> {
+    let layers = [format!("F.{layer}"), format!("B.{layer}")];
+    let texts: Vec<_> = footprint.texts().iter().filter(|text| is_text(text)).collect();
+    if texts.iter().any(|text| layers.iter().any(|layer| layer == text.layer())) {
+        return None;
+    }
+    match texts.first() {
+        Some(text) => Some(format!("the {description} is on {} instead of {}", text.layer(), layers[0])),
+        None => Some(format!("no {description} on {}", layers[0])),
+    }
+}
+
+fn check_origin(footprint: &KiCadFootprint) -> Option<String> {
+    let pads: Vec<_> = footprint.pads().iter().filter(|pad| pad.pad_type() != KiCadPadType::NpThruHole).collect();
+    if pads.is_empty() {
+        return None;
+    }
+    let near_origin = |x: f32, y: f32| x.abs() <= ORIGIN_TOLERANCE && y.abs() <= ORIGIN_TOLERANCE;
+
+    let is_smd = footprint.attributes().iter().any(|attribute| attribute == "smd")
+        || pads.iter().all(|pad| pad.pad_type() == KiCadPadType::Smd);
+    if is_smd {
+        let (xs, ys): (Vec<f32>, Vec<f32>) = pads.iter().map(|pad| (pad.location().0, pad.location().1)).unzip();
+        let center = |values: &[f32]| (values.iter().copied().fold(f32::MAX, f32::min) + values.iter().copied().fold(f32::MIN, f32::max)) / 2.0;
+        let (x, y) = (center(&xs), center(&ys));
+        return (!near_origin(x, y)).then(|| format!("the center of the pads is at ({x}, {y}) instead of the origin"));
+    }
+    let first = pads.iter().find(|pad| pad.number() == "1")?;
+    let (x, y, _) = first.location();
+    (!near_origin(x, y)).then(|| format!("pad 1 is at ({x}, {y}) instead of the origin"))
+}
+
+fn check_layers(footprint: &KiCadFootprint) -> Option<String> {
+    let mut problems = vec![];
+    for name in footprint.layers().iter().filter(|name| !is_known_layer(name)) {
+        match corrected_layer(name) {
+            Some(layer) => problems.push(format!("layer {name} should be {layer}")),
+            None => problems.push(format!("unknown layer {name}")),
+        }
+    }
+    let without_copper: Vec<&str> = footprint
+        .pads()
+        .iter()
+        .filter(|pad| pad.pad_type() != KiCadPadType::NpThruHole && !pad.layers().iter().any(|layer| is_copper_layer(layer)))
+        .map(|pad| pad.number())
+        .collect();
+    if !without_copper.is_empty() {
+        problems.push(format!("pad(s) {} on no copper layer", without_copper.join(", ")));
+    }
+    (!problems.is_empty()).then(|| problems.join(", "))
+}
+
+/// The courtyard is compared as a box around all courtyard lines, which is
+/// exact for the rectangular courtyards nearly all footprints have.
+fn check_courtyard_clearance(footprint: &KiCadFootprint, clearance: f32) -> Option<String> {
+    let courtyard = footprint.courtyard()?;
+    let mut too_close: Vec<(&str, f32)> = footprint
+        .pads()
+        .iter()
+        .map(|pad| (pad.number(), courtyard.inset_of(&pad_bounds(pad))))
+        .filter(|(_, inset)| *inset < clearance - ORIGIN_TOLERANCE)
+        .collect();
+    too_close.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+    let (closest, inset) = too_close.first()?;
+    let pads: Vec<&str> = too_close.iter().map(|(number, _)| *number).collect();
+    let position = if *inset < 0.0 { "outside the courtyard".to_string() } else { format!("{inset:.2} mm from the courtyard") };
+    Some(format!(
+        "pad(s) {} are closer to the courtyard than {clearance} mm, pad {} is {position}",
+        pads.join(", "),
+        if closest.is_empty() { "without number" } else { closest }
+    ))
+}
+
+/// The box around the pad, turned by its rotation.
+fn pad_bounds(pad: &KiCadPad) -> BoundingBox {
+    let (x, y, rotation) = pad.location();
+    let (half_width, half_height) = (pad.size().0 / 2.0, pad.size().1 / 2.0);
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let corner = |dx: f32, dy: f32| (x + dx * cos + dy * sin, y - dx * sin + dy * cos);
+    let (cx, cy) = corner(-half_width, -half_height);
+    let mut bounds = BoundingBox::around(cx, cy);
+    for (dx, dy) in [(half_width, -half_height), (half_width, half_height), (-half_width, half_height)] {
+        let (cx, cy) = corner(dx, dy);
+        bounds.include(cx, cy);
+    }
+    bounds
+}
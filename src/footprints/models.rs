@@ -0,0 +1,533 @@
+//! The 3D model references of footprints, `(model "path" ...)`.
+
+use crate::config::{ModelAdjustment, ModelFormat};
+use crate::dry_run;
+use crate::footprints::writer::{parse_footprint, write_footprint, write_library_footprint};
+use crate::footprints::{first_argument_span, footprint_tokens, quote, KiCadFootprint};
+use crate::project::find_files_with_extension;
+use crate::symbols::writer::SExpr;
+use crate::symbols::{subdivide_expression, Token};
+use crate::variables::variable;
+use anyhow::{anyhow, bail};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use mktemp::Temp;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process::Command;
+use std::path::{Path, PathBuf};
+
+/// The extension of gzipped STEP files.
+const COMPRESSED_STEP_EXTENSION: &str = "stpZ";
+
+/// A `(model ...)` of a footprint: the path as written and where the model
+/// is placed, as x, y and z.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KiCadModel {
+    path: String,
+    /// In mm
+    offset: [f32; 3],
+    scale: [f32; 3],
+    /// In degrees
+    rotate: [f32; 3],
+}
+
+impl KiCadModel {
+    pub(crate) fn parse_expression(expression: &[Token]) -> Option<Self> {
+        let Some(Token::Word(path)) = expression.get(2) else { return None };
+        let mut model = KiCadModel { path: path.to_string(), offset: [0.0; 3], scale: [1.0; 3], rotate: [0.0; 3] };
+        for child in subdivide_expression(&expression[3..]) {
+            let child = &child[child.iter().position(|token| matches!(token, Token::OpenParen(_))).unwrap_or(0)..];
+            let Some(Token::Word(keyword)) = child.get(1) else { continue };
+            let Some(xyz) = parse_xyz(child) else { continue };
+            match keyword.as_ref() {
+                "offset" => model.offset = xyz,
+                // KiCad 5 placed models in inches
+                "at" => model.offset = xyz.map(|value| value * 25.4),
+                "scale" => model.scale = xyz,
+                "rotate" => model.rotate = xyz,
+                _ => {}
+            }
+        }
+        Some(model)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn offset(&self) -> [f32; 3] {
+        self.offset
+    }
+
+    pub fn scale(&self) -> [f32; 3] {
+        self.scale
+    }
+
+    pub fn rotate(&self) -> [f32; 3] {
+        self.rotate
+    }
+}
+
+/// The values of the `(xyz x y z)` in e.g. `(offset (xyz 0 0 0))`.
+fn parse_xyz(expression: &[Token]) -> Option<[f32; 3]> {
+    let start = expression.iter().position(|token| token == &Token::Word("xyz".into()))?;
+    let mut values = [0.0; 3];
+    for (value, token) in values.iter_mut().zip(&expression[start + 1..]) {
+        let Token::Word(word) = token else { return None };
+        *value = word.parse().ok()?;
+    }
+    Some(values)
+}
+
+/// Changes the offset, scale or rotation of every model of the footprint file
+/// and writes it back, which a dry run only reports for a footprint in a
+/// library. Returns the number of models changed.
+pub(crate) fn adjust_models(path: &Path, adjustment: &ModelAdjustment, in_library: bool) -> Result<usize, anyhow::Error> {
+    let content = fs::read_to_string(path)?;
+    let mut footprint = parse_footprint(&content, Some(path))?;
+    let SExpr::List(items) = &mut footprint else { bail!("{}: malformed footprint", path.display()) };
+    let named = |expression: &SExpr, name: &str| {
+        matches!(expression, SExpr::List(children) if matches!(children.first(), Some(SExpr::Atom(keyword)) if keyword == name))
+    };
+
+    let mut count = 0;
+    for model in items.iter_mut().filter(|item| named(item, "model")) {
+        let SExpr::List(children) = model else { continue };
+        let mut changed = false;
+        for (keyword, values) in [("offset", adjustment.offset), ("scale", adjustment.scale), ("rotate", adjustment.rotate)] {
+            let Some(values) = values else { continue };
+            // Adding 0 turns -0 into 0
+            let xyz = SExpr::list("xyz", values.iter().map(|value| SExpr::atom(value + 0.0)).collect());
+            let expression = SExpr::list(keyword, vec![xyz]);
+            // The `at` of KiCad 5 is replaced, its offset was in inches
+            match children.iter().position(|child| named(child, keyword) || (keyword == "offset" && named(child, "at"))) {
+                Some(index) if children[index] == expression => continue,
+                Some(index) => children[index] = expression,
+                None => children.push(expression),
+            }
+            changed = true;
+        }
+        count += usize::from(changed);
+    }
+    if count > 0 && in_library {
+        write_library_footprint(path, &footprint.to_string())?;
+    } else if count > 0 {
+        write_footprint(path, &footprint.to_string())?;
+    }
+    Ok(count)
+}
+
+/// How the model references of imported footprints are written.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ModelReferences {
+    /// The absolute path the model was copied to
+    Absolute,
+    /// A directory like `${KICAD_USER_3DMODEL_DIR}/Vendor` joined with the
+    /// file name of the model
+    Directory(String),
+    /// The path relative to the directory a variable stands for, like
+    /// `${KICAD_3RD_PARTY}/Vendor.3dshapes/SOIC8.step`
+    Variable { name: String, dir: PathBuf },
+    /// The path relative to a directory, like `../Vendor.3dshapes/SOIC8.step`
+    /// from the footprint library
+    Relative(PathBuf),
+}
+
+impl ModelReferences {
+    /// A reference to `name`, whose directory is taken from `configured`, or
+    /// the environment and KiCad. `${KIPRJMOD}` stands for `project_dir`.
+    pub(crate) fn variable(
+        name: &str,
+        configured: &BTreeMap<String, PathBuf>,
+        project_dir: &Path,
+    ) -> Result<Self, anyhow::Error> {
+        let name = name.trim_start_matches("${").trim_end_matches('}');
+        let dir = match configured.get(name) {
+            Some(dir) => dir.clone(),
+            None if name == "KIPRJMOD" => project_dir.to_path_buf(),
+            None => variable(name)
+                .map(PathBuf::from)
+                .ok_or(anyhow!("{name} is neither set, a variable of KiCad nor in `model_path_variables` of the config"))?,
+        };
+        Ok(ModelReferences::Variable { name: name.to_string(), dir })
+    }
+
+    /// How the footprint refers to `model`, a copied model file.
+    pub(crate) fn reference(&self, model: &Path) -> Result<String, anyhow::Error> {
+        let file_name = model.file_name().unwrap_or_default().to_string_lossy();
+        Ok(match self {
+            ModelReferences::Absolute => std::path::absolute(model)?.display().to_string(),
+            ModelReferences::Directory(dir) => format!("{}/{file_name}", dir.trim_end_matches('/')),
+            ModelReferences::Variable { name, dir } => {
+                let model = resolved(model)?;
+                let Ok(relative) = model.strip_prefix(resolved(dir)?) else {
+                    bail!("{} is not inside {} (${{{name}}}), copy the models there with --model-dir", model.display(), dir.display())
+                };
+                let parts: Vec<String> = relative.iter().map(|part| part.to_string_lossy().to_string()).collect();
+                format!("${{{name}}}/{}", parts.join("/"))
+            }
+            ModelReferences::Relative(dir) => {
+                let (model, dir) = (resolved(model)?, resolved(dir)?);
+                let common = model.components().zip(dir.components()).take_while(|(a, b)| a == b).count();
+                let mut parts: Vec<String> = dir.components().skip(common).map(|_| "..".to_string()).collect();
+                parts.extend(model.components().skip(common).map(|part| part.as_os_str().to_string_lossy().to_string()));
+                parts.join("/")
+            }
+        })
+    }
+}
+
+/// The absolute path without symbolic links where it exists.
+fn resolved(path: &Path) -> Result<PathBuf, anyhow::Error> {
+    Ok(fs::canonicalize(path).or_else(|_| std::path::absolute(path))?)
+}
+
+/// A model of an archive and where it is in the model directory, which is a
+/// model already there if it was the same.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ImportedModel {
+    pub(crate) original: PathBuf,
+    pub(crate) path: PathBuf,
+}
+
+/// Copies the models into `model_dir`, unless an identical model of the
+/// same format is in there already, which is used instead. Nothing is copied
+/// if a STEP file is not valid. With `compress`, STEP files are stored
+/// gzipped as `.stpZ`, which KiCad reads as well. Models in `names` are
+/// copied under the file stem given there.
+pub(crate) fn import_models(
+    models: &[PathBuf],
+    model_dir: &Path,
+    compress: bool,
+    names: &BTreeMap<PathBuf, String>,
+) -> Result<Vec<ImportedModel>, anyhow::Error> {
+    for model in models.iter().filter(|model| model_format(model) == Some(ModelFormat::Step)) {
+        check_step_file(model)?;
+    }
+    let mut imported = vec![];
+    for model in models {
+        let mut file_name = model.file_name().ok_or(anyhow!("File {} has no filename", model.display()))?.to_os_string();
+        if let Some(name) = names.get(model) {
+            let extension = model.extension().unwrap_or_default().to_string_lossy();
+            file_name = format!("{name}.{extension}").into();
+        }
+        let compress = compress && model_format(model) == Some(ModelFormat::Step) && !is_compressed(model);
+        if compress {
+            file_name = Path::new(&file_name).with_extension(COMPRESSED_STEP_EXTENSION).into_os_string();
+        }
+        let dest_file = model_dir.join(&file_name);
+        let hash = model_hash(model)?;
+        if let Some(existing) = find_identical_model(model_dir, &dest_file, &hash)? {
+            if existing == dest_file {
+                println!("{}: unchanged", dest_file.display());
+            } else {
+                println!("{}: the same model is in the library as {}, using it", file_name.to_string_lossy(), existing.display());
+            }
+            imported.push(ImportedModel { original: model.clone(), path: existing });
+            continue;
+        }
+        println!("{} -> {}", model.display(), dest_file.display());
+        if !compress {
+            dry_run::copy(model, &dest_file)?;
+        } else if !dry_run::skip_write(&dest_file) {
+            let mut encoder = GzEncoder::new(File::create(&dest_file)?, Compression::default());
+            encoder.write_all(&fs::read(model)?)?;
+            encoder.finish()?;
+        }
+        imported.push(ImportedModel { original: model.clone(), path: dest_file });
+    }
+    Ok(imported)
+}
+
+/// Names for the models after the footprints referring to them, by the
+/// footprint file and its new name. A model shared by several footprints is
+/// named after the first.
+pub(crate) fn names_after_footprints(
+    footprints: &[(PathBuf, String)],
+    models: &[PathBuf],
+) -> Result<BTreeMap<PathBuf, String>, anyhow::Error> {
+    let stem = |path: &Path| path.file_stem().map(|stem| stem.to_string_lossy().to_lowercase());
+    let mut stems: Vec<Option<String>> = models.iter().map(|model| stem(model)).collect();
+    stems.sort();
+    stems.dedup();
+
+    let mut names = BTreeMap::new();
+    for (footprint, name) in footprints {
+        let references = KiCadFootprint::from_path(footprint)?.models().iter().map(|model| model.path().to_string()).collect::<Vec<_>>();
+        let mut wanted: Vec<Option<String>> =
+            references.iter().map(|reference| stem(Path::new(&reference.replace('\\', "/")))).collect();
+        if references.is_empty() {
+            wanted.push(stem(footprint));
+        }
+        let mut matched: Vec<&PathBuf> = models.iter().filter(|model| wanted.contains(&stem(model))).collect();
+        if matched.is_empty() && references.len() == 1 && stems.len() == 1 {
+            matched = models.iter().collect();
+        }
+        for model in matched {
+            names.entry(model.clone()).or_insert_with(|| name.clone());
+        }
+    }
+    Ok(names)
+}
+
+/// Runs the `command` converting the imported STEP model to a `.wrl` next to
+/// it, unless there is one already. A gzipped model is given to the command
+/// decompressed. Returns the imported `.wrl`.
+pub(crate) fn convert_to_wrl(model: &ImportedModel, command: &[String]) -> Result<ImportedModel, anyhow::Error> {
+    let output = model.path.with_extension("wrl");
+    let imported = ImportedModel { original: model.original.with_extension("wrl"), path: output.clone() };
+    if output.exists() || dry_run::skip_write(&output) {
+        return Ok(imported);
+    }
+    let Some((program, arguments)) = command.split_first() else { bail!("step_to_wrl of the config is empty") };
+
+    let temp_dir = Temp::new_dir()?;
+    let input = if is_compressed(&model.path) {
+        let input = temp_dir.join(model.path.with_extension("step").file_name().unwrap_or_default());
+        fs::write(&input, read_model(&model.path)?)?;
+        input
+    } else {
+        model.path.clone()
+    };
+    let replace = |argument: &String| {
+        argument.replace("{input}", &input.to_string_lossy()).replace("{output}", &output.to_string_lossy())
+    };
+    let status = Command::new(program)
+        .args(arguments.iter().map(replace))
+        .status()
+        .map_err(|error| anyhow!("{program}: {error}"))?;
+    if !status.success() || !output.exists() {
+        bail!("{program} did not convert {} to {} ({status})", input.display(), output.display());
+    }
+    Ok(imported)
+}
+
+/// Fails unless the file is an ISO-10303-21 file that is complete, as
+/// downloads of models sometimes are HTML error pages or cut off.
+fn check_step_file(path: &Path) -> Result<(), anyhow::Error> {
+    let content = read_model(path)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let start = content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&content);
+    let start = &start[start.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(start.len())..];
+    if !start.starts_with(b"ISO-10303-21;") {
+        let looks_like_html = find(&start[..start.len().min(512)].to_ascii_lowercase(), b"<html").is_some()
+            || start.starts_with(b"<!");
+        let kind = if looks_like_html { "an HTML page" } else { "not a STEP file" };
+        bail!("{name} is {kind}, it does not start with ISO-10303-21; the download of the model probably failed");
+    }
+    if find(&content, b"ENDSEC;").is_none() || find(&content, b"END-ISO-10303-21;").is_none() {
+        bail!("{name} is truncated, it does not end with ENDSEC; and END-ISO-10303-21;");
+    }
+    Ok(())
+}
+
+/// The SHA-256 of the model. The header of STEP files is left out, as it
+/// holds the time and tool of the export, which differ between otherwise
+/// identical models.
+fn model_hash(path: &Path) -> Result<String, anyhow::Error> {
+    let content = read_model(path)?;
+    let mut data = content.as_slice();
+    if model_format(path) == Some(ModelFormat::Step) {
+        let header_end = find(data, b"HEADER;").and_then(|start| find(&data[start..], b"ENDSEC;").map(|end| start + end));
+        if let Some(header_end) = header_end {
+            data = &data[header_end..];
+        }
+    }
+    Ok(format!("{:x}", Sha256::digest(data)))
+}
+
+/// The content of the model, decompressed if it is a `.stpZ` file.
+fn read_model(path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+    let content = fs::read(path)?;
+    if !is_compressed(path) {
+        return Ok(content);
+    }
+    let mut decompressed = vec![];
+    GzDecoder::new(content.as_slice())
+        .read_to_end(&mut decompressed)
+        .map_err(|error| anyhow!("{}: {error}", path.display()))?;
+    Ok(decompressed)
+}
+
+fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case(COMPRESSED_STEP_EXTENSION))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// A model of the same format in `model_dir` with the given hash, `preferred`
+/// if it is one of them.
+fn find_identical_model(model_dir: &Path, preferred: &Path, hash: &str) -> Result<Option<PathBuf>, anyhow::Error> {
+    let mut candidates = vec![preferred.to_path_buf()];
+    // A dry run does not create the directory
+    if model_dir.is_dir() {
+        for entry in fs::read_dir(model_dir)? {
+            let path = entry?.path();
+            if path != preferred && model_format(&path).is_some() && model_format(&path) == model_format(preferred) {
+                candidates.push(path);
+            }
+        }
+    }
+    let identical = candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file() && model_hash(candidate).is_ok_and(|candidate_hash| candidate_hash == hash));
+    Ok(identical)
+}
+
+/// The format of a model file, by its extension.
+pub(crate) fn model_format(path: &Path) -> Option<ModelFormat> {
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "step" | "stp" | "stpz" => Some(ModelFormat::Step),
+        "wrl" => Some(ModelFormat::Wrl),
+        _ => None,
+    }
+}
+
+/// The model of the preferred format, or the first one.
+fn preferred<'a>(models: &[&'a ImportedModel], preference: ModelFormat) -> Option<&'a ImportedModel> {
+    models.iter().find(|model| model_format(&model.original) == Some(preference)).or(models.first()).copied()
+}
+
+/// Points the `(model ...)` references of a footprint file at the imported
+/// models and writes the file back if anything changed. A reference matches the
+/// models with the same file stem, so a `.wrl` reference can be pointed at the
+/// `.step` file, or all models if they are one part in several formats and the
+/// footprint has a single reference. Of a model in both formats, the reference
+/// points at the `preference`. A footprint without references gets one to the
+/// model named like it. Returns the number of rewritten or added references.
+pub(crate) fn rewrite_model_paths(
+    path: &Path,
+    models: &[ImportedModel],
+    references: &ModelReferences,
+    preference: ModelFormat,
+) -> Result<usize, anyhow::Error> {
+    let content = fs::read_to_string(path)?;
+    let tokens = footprint_tokens(&content, Some(path))?;
+
+    let mut found = vec![];
+    for window in tokens.windows(3) {
+        if let [Token::OpenParen(offset), Token::Word(name), Token::Word(reference)] = window {
+            if name == "model" {
+                let span = first_argument_span(&content, *offset).ok_or(anyhow!("{}: malformed model", path.display()))?;
+                found.push((span, reference.to_string()));
+            }
+        }
+    }
+
+    let stem = |path: &Path| path.file_stem().map(|stem| stem.to_string_lossy().to_lowercase());
+    let named = |name: Option<String>| -> Vec<&ImportedModel> { models.iter().filter(|model| stem(&model.original) == name).collect() };
+    let mut stems: Vec<Option<String>> = models.iter().map(|model| stem(&model.original)).collect();
+    stems.sort();
+    stems.dedup();
+
+    if found.is_empty() {
+        let Some(model) = preferred(&named(stem(path)), preference) else { return Ok(0) };
+        let Some(end) = content.rfind(')') else { bail!("{}: malformed footprint", path.display()) };
+        let mut rewritten = content.clone();
+        let model = format!(
+            "(model {} (offset (xyz 0 0 0)) (scale (xyz 1 1 1)) (rotate (xyz 0 0 0)))\n",
+            quote(&references.reference(&model.path)?)
+        );
+        rewritten.insert_str(end, &model);
+        write_footprint(path, &rewritten)?;
+        return Ok(1);
+    }
+
+    let mut rewritten = content.clone();
+    let mut count = 0;
+    // From the end, so the spans of the earlier references stay valid
+    for (span, reference) in found.iter().rev() {
+        let mut candidates = named(stem(Path::new(&reference.replace('\\', "/"))));
+        if candidates.is_empty() && stems.len() == 1 && found.len() == 1 {
+            candidates = models.iter().collect();
+        }
+        let Some(model) = preferred(&candidates, preference) else { continue };
+
+        let new_path = references.reference(&model.path)?;
+        if &new_path != reference {
+            rewritten.replace_range(span.clone(), &quote(&new_path));
+            count += 1;
+        }
+    }
+    if count > 0 {
+        write_footprint(path, &rewritten)?;
+    }
+    Ok(count)
+}
+
+/// The path a model reference stands for, with `${...}` variables expanded
+/// like [`ModelReferences::variable`] and relative paths taken from
+/// `project_dir`. `None` if a variable is unknown.
+pub(crate) fn resolve_reference(
+    reference: &str,
+    configured: &BTreeMap<String, PathBuf>,
+    project_dir: &Path,
+) -> Option<PathBuf> {
+    // Absolute, so a relative `${KIPRJMOD}` is not joined with it twice
+    let project_dir = std::path::absolute(project_dir).ok()?;
+    let mut path = String::new();
+    let mut rest = reference;
+    while let Some(start) = rest.find("${") {
+        let end = start + rest[start..].find('}')?;
+        path.push_str(&rest[..start]);
+        let ModelReferences::Variable { dir, .. } = ModelReferences::variable(&rest[start + 2..end], configured, &project_dir).ok()? else {
+            return None;
+        };
+        path.push_str(&dir.to_string_lossy());
+        rest = &rest[end + 1..];
+    }
+    path.push_str(rest);
+    Some(project_dir.join(path.replace('\\', "/")))
+}
+
+/// The model files in `model_dir` no footprint of the libraries refers to. A
+/// reference with an unknown variable keeps every model of its file name, so
+/// nothing in use is reported. Relative references are taken from the project
+/// and from the footprint library, as KiCad looks in both. The project
+/// defaults to the directory of each library.
+pub(crate) fn find_orphaned_models(
+    model_dir: &Path,
+    libraries: &[PathBuf],
+    configured: &BTreeMap<String, PathBuf>,
+    project_dir: Option<&Path>,
+) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut referenced = vec![];
+    let mut unresolved_names = vec![];
+    for library in libraries {
+        let project_dir =
+            project_dir.or(library.parent().filter(|dir| !dir.as_os_str().is_empty())).unwrap_or(Path::new("."));
+        for footprint in find_files_with_extension(library, "kicad_mod")? {
+            for model in KiCadFootprint::from_path(&footprint)?.models() {
+                let reference = model.path();
+                match resolve_reference(reference, configured, project_dir) {
+                    Some(path) => referenced.push(resolved(&path)?),
+                    None => unresolved_names.extend(reference.rsplit(['/', '\\']).next().map(str::to_lowercase)),
+                }
+                if !reference.contains("${") && Path::new(reference).is_relative() {
+                    referenced.extend(resolve_reference(reference, configured, library).map(|path| resolved(&path)).transpose()?);
+                }
+            }
+        }
+    }
+
+    let mut orphaned = vec![];
+    for entry in fs::read_dir(model_dir)? {
+        let path = entry?.path();
+        if model_format(&path).is_none() {
+            continue;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+        if !referenced.contains(&resolved(&path)?) && !unresolved_names.contains(&name) {
+            orphaned.push(path);
+        }
+    }
+    orphaned.sort();
+    Ok(orphaned)
+}
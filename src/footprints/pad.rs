@@ -0,0 +1,285 @@
+use crate::symbols::Token::Word;
+use crate::symbols::{expression_offset, locate_error, subdivide_expression, Expression, TryFromExpression};
+use anyhow::{anyhow, bail, Error};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum KiCadPadType {
+    Smd,
+    ThruHole,
+    /// A mounting hole without copper
+    NpThruHole,
+    /// A copper pad without paste or mask, e.g. for edge connectors
+    Connect,
+}
+
+impl FromStr for KiCadPadType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "smd" => Ok(Self::Smd),
+            "thru_hole" => Ok(Self::ThruHole),
+            "np_thru_hole" => Ok(Self::NpThruHole),
+            "connect" => Ok(Self::Connect),
+            _ => bail!("Not a valid KiCad pad type: {s}"),
+        }
+    }
+}
+
+impl Display for KiCadPadType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Smd => write!(f, "smd"),
+            Self::ThruHole => write!(f, "thru_hole"),
+            Self::NpThruHole => write!(f, "np_thru_hole"),
+            Self::Connect => write!(f, "connect"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum KiCadPadShape {
+    Circle,
+    Rect,
+    Oval,
+    Trapezoid,
+    RoundRect,
+    /// Made of graphic primitives on top of an anchor pad
+    Custom,
+}
+
+impl FromStr for KiCadPadShape {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "circle" => Ok(Self::Circle),
+            "rect" => Ok(Self::Rect),
+            "oval" => Ok(Self::Oval),
+            "trapezoid" => Ok(Self::Trapezoid),
+            "roundrect" => Ok(Self::RoundRect),
+            "custom" => Ok(Self::Custom),
+            _ => bail!("Not a valid KiCad pad shape: {s}"),
+        }
+    }
+}
+
+impl Display for KiCadPadShape {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Circle => write!(f, "circle"),
+            Self::Rect => write!(f, "rect"),
+            Self::Oval => write!(f, "oval"),
+            Self::Trapezoid => write!(f, "trapezoid"),
+            Self::RoundRect => write!(f, "roundrect"),
+            Self::Custom => write!(f, "custom"),
+        }
+    }
+}
+
+/// The hole of a pad, `(drill 0.8)` or `(drill oval 0.8 1.2 (offset 0 0.1))`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KiCadDrill {
+    oval: bool,
+    width: f32,
+    /// Only given for oval holes
+    height: Option<f32>,
+    offset: Option<(f32, f32)>,
+}
+
+impl KiCadDrill {
+    pub fn is_oval(&self) -> bool {
+        self.oval
+    }
+
+    /// Width and height of the hole, the same for round holes.
+    pub fn size(&self) -> (f32, f32) {
+        (self.width, self.height.unwrap_or(self.width))
+    }
+
+    pub fn offset(&self) -> Option<(f32, f32)> {
+        self.offset
+    }
+}
+
+impl TryFromExpression<KiCadDrill> for KiCadDrill {
+    fn try_from_expression(expression: &Expression) -> Result<KiCadDrill, Error> {
+        check_list(expression, "drill")?;
+
+        let mut oval = false;
+        let mut sizes = vec![];
+        let mut offset = None;
+        let mut index = 2;
+        while let Some(token) = expression.get(index) {
+            match token {
+                Word(word) if word == "oval" => oval = true,
+                Word(size) => sizes.push(size.parse::<f32>()?),
+                _ => break,
+            }
+            index += 1;
+        }
+        for subexpression in subdivide_expression(&expression[index..]) {
+            if subexpression.get(1) == Some(&Word("offset".into())) {
+                offset = Some(parse_pair(subexpression, "offset")?);
+            }
+        }
+
+        let Some(&width) = sizes.first() else { bail!("Drill does not contain a size") };
+        Ok(KiCadDrill { oval, width, height: sizes.get(1).copied(), offset })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KiCadPad {
+    /// Empty for pads that are not connected, like mounting holes
+    number: String,
+    pad_type: KiCadPadType,
+    shape: KiCadPadShape,
+    /// x, y and rotation in degrees
+    location: (f32, f32, f32),
+    size: (f32, f32),
+    drill: Option<KiCadDrill>,
+    layers: Vec<String>,
+    roundrect_rratio: Option<f32>,
+    solder_mask_margin: Option<f32>,
+    solder_paste_margin: Option<f32>,
+    solder_paste_margin_ratio: Option<f32>,
+}
+
+impl KiCadPad {
+    pub fn number(&self) -> &str {
+        &self.number
+    }
+
+    pub fn pad_type(&self) -> KiCadPadType {
+        self.pad_type
+    }
+
+    pub fn shape(&self) -> KiCadPadShape {
+        self.shape
+    }
+
+    pub fn location(&self) -> (f32, f32, f32) {
+        self.location
+    }
+
+    pub fn size(&self) -> (f32, f32) {
+        self.size
+    }
+
+    pub fn drill(&self) -> Option<&KiCadDrill> {
+        self.drill.as_ref()
+    }
+
+    /// Layer names as written, including wildcards like `*.Cu`.
+    pub fn layers(&self) -> &[String] {
+        &self.layers
+    }
+
+    pub fn roundrect_rratio(&self) -> Option<f32> {
+        self.roundrect_rratio
+    }
+
+    /// Margins overriding the ones of the footprint and board, in mm
+    pub fn solder_mask_margin(&self) -> Option<f32> {
+        self.solder_mask_margin
+    }
+
+    pub fn solder_paste_margin(&self) -> Option<f32> {
+        self.solder_paste_margin
+    }
+
+    pub fn solder_paste_margin_ratio(&self) -> Option<f32> {
+        self.solder_paste_margin_ratio
+    }
+}
+
+impl TryFromExpression<KiCadPad> for KiCadPad {
+    fn try_from_expression(expression: &Expression) -> Result<KiCadPad, Error> {
+        check_list(expression, "pad")?;
+
+        let Some(Word(number)) = expression.get(2) else { bail!("Pad does not contain a number") };
+        let Some(Word(pad_type)) = expression.get(3) else { bail!("Pad {number} does not contain a type") };
+        let Some(Word(shape)) = expression.get(4) else { bail!("Pad {number} does not contain a shape") };
+        let pad_type = KiCadPadType::from_str(pad_type)?;
+        let shape = KiCadPadShape::from_str(shape)?;
+
+        let mut location = None;
+        let mut size = None;
+        let mut drill = None;
+        let mut layers = vec![];
+        let mut roundrect_rratio = None;
+        let mut solder_mask_margin = None;
+        let mut solder_paste_margin = None;
+        let mut solder_paste_margin_ratio = None;
+
+        for subexpression in subdivide_expression(&expression[5..]) {
+            let Some(Word(property)) = subexpression.get(1) else { continue };
+            let located = |error| locate_error(error, expression_offset(subexpression));
+            match property.as_ref() {
+                "at" => {
+                    let (x, y) = parse_pair(subexpression, "at").map_err(located)?;
+                    let rotation = match subexpression.get(4) {
+                        Some(Word(rotation)) => rotation.parse::<f32>().map_err(|error| located(error.into()))?,
+                        _ => 0.0,
+                    };
+                    location = Some((x, y, rotation));
+                }
+                "size" => size = Some(parse_pair(subexpression, "size").map_err(located)?),
+                "drill" => drill = Some(KiCadDrill::parse_expression(subexpression)?),
+                "layers" => {
+                    layers = subexpression[2..]
+                        .iter()
+                        .filter_map(|token| match token {
+                            Word(layer) => Some(layer.to_string()),
+                            _ => None,
+                        })
+                        .collect();
+                }
+                "roundrect_rratio" => roundrect_rratio = Some(parse_number(subexpression).map_err(located)?),
+                "solder_mask_margin" => solder_mask_margin = Some(parse_number(subexpression).map_err(located)?),
+                "solder_paste_margin" => solder_paste_margin = Some(parse_number(subexpression).map_err(located)?),
+                "solder_paste_margin_ratio" => solder_paste_margin_ratio = Some(parse_number(subexpression).map_err(located)?),
+                // Nets, pin functions, thermal relief, primitives of custom pads, ...
+                _ => {}
+            }
+        }
+
+        Ok(KiCadPad {
+            number: number.to_string(),
+            pad_type,
+            shape,
+            location: location.ok_or(anyhow!("Pad {number} does not contain a location"))?,
+            size: size.ok_or(anyhow!("Pad {number} does not contain a size"))?,
+            drill,
+            layers,
+            roundrect_rratio,
+            solder_mask_margin,
+            solder_paste_margin,
+            solder_paste_margin_ratio,
+        })
+    }
+}
+
+fn check_list(expression: &Expression, name: &str) -> Result<(), Error> {
+    if expression.get(1) != Some(&Word(name.into())) {
+        bail!("Not a KiCad {name}: {expression:?}");
+    }
+    Ok(())
+}
+
+/// Parses lists of two numbers like `(size 1.5 0.6)`.
+fn parse_pair(expression: &Expression, name: &str) -> Result<(f32, f32), Error> {
+    let (Some(Word(x)), Some(Word(y))) = (expression.get(2), expression.get(3)) else {
+        bail!("{name} does not contain two numbers")
+    };
+    Ok((x.parse::<f32>()?, y.parse::<f32>()?))
+}
+
+fn parse_number(expression: &Expression) -> Result<f32, Error> {
+    let Some(Word(value)) = expression.get(2) else { bail!("{expression:?} does not contain a value") };
+    Ok(value.parse::<f32>()?)
+}
@@ -0,0 +1,73 @@
+//! Renaming footprints in a library, by name or by patterns like
+//! `PCBLIB_*=ACME_*`.
+
+use crate::dry_run;
+use crate::footprints::rename_footprint;
+use crate::footprints::writer::write_library_footprint;
+use anyhow::{anyhow, bail};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Renames footprints matching `from` to `to`. A `*` in `from` matches any
+/// text, which takes the place of the `*` in `to`, e.g. `*=ACME_*` adds a
+/// prefix and `PCBLIB_SOIC8=SOIC-8_3.9x4.9mm` renames a single footprint.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "String")]
+pub(crate) struct RenamePattern {
+    from: String,
+    to: String,
+}
+
+impl FromStr for RenamePattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((from, to)) = s.split_once('=') else { bail!("Rename pattern {s} is not of the form FROM=TO") };
+        if from.matches('*').count() > 1 || to.matches('*').count() > 1 {
+            bail!("Rename pattern {s} has more than one * on a side");
+        }
+        if to.contains('*') && !from.contains('*') {
+            bail!("Rename pattern {s} uses * in the new name only");
+        }
+        Ok(RenamePattern { from: from.to_string(), to: to.to_string() })
+    }
+}
+
+impl TryFrom<String> for RenamePattern {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl RenamePattern {
+    /// The new name, or `None` if the pattern does not match `name`.
+    pub(crate) fn apply(&self, name: &str) -> Option<String> {
+        let Some((prefix, suffix)) = self.from.split_once('*') else {
+            return (name == self.from).then(|| self.to.clone());
+        };
+        let matched = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+        Some(self.to.replacen('*', matched, 1))
+    }
+}
+
+/// The name given by the first matching pattern, or `name` itself.
+pub(crate) fn renamed(patterns: &[RenamePattern], name: &str) -> String {
+    patterns.iter().find_map(|pattern| pattern.apply(name)).unwrap_or_else(|| name.to_string())
+}
+
+/// Renames the footprint file `old` of the library and its name inside.
+pub(crate) fn rename_in_library(library: &Path, old: &str, new: &str) -> Result<PathBuf, anyhow::Error> {
+    let old_path = library.join(format!("{old}.kicad_mod"));
+    let new_path = library.join(format!("{new}.kicad_mod"));
+    if new_path.exists() {
+        bail!("{} already exists", new_path.display());
+    }
+    let content = fs::read_to_string(&old_path).map_err(|error| anyhow!("{}: {error}", old_path.display()))?;
+    write_library_footprint(&new_path, &rename_footprint(&content, new, Some(&old_path))?)?;
+    dry_run::remove_file(&old_path)?;
+    Ok(new_path)
+}
@@ -0,0 +1,187 @@
+//! Renders footprints as SVG images, for previews and reports.
+
+use crate::footprints::graphics::{KiCadFootprintGraphic, KiCadShape};
+use crate::footprints::pad::{KiCadPad, KiCadPadShape, KiCadPadType};
+use crate::footprints::KiCadFootprint;
+use std::f32::consts::TAU;
+use std::fmt::Write;
+
+/// Pixels per mm of the `width` and `height` of the image.
+const SCALE: f32 = 20.0;
+/// Space around the footprint in mm.
+const MARGIN: f32 = 0.5;
+const BACKGROUND: &str = "#001023";
+
+/// Layers drawn under and over the pads, from the bottom up, with the colours
+/// of KiCad's default theme.
+const LAYERS_UNDER_PADS: [(&str, &str); 4] =
+    [("B.Fab", "#585d84"), ("F.Fab", "#afafaf"), ("B.CrtYd", "#26e9ff"), ("F.CrtYd", "#ff26e2")];
+const LAYERS_OVER_PADS: [(&str, &str); 2] = [("B.SilkS", "#e8b2a7"), ("F.SilkS", "#f2eda1")];
+const FRONT_PAD: &str = "#c83434";
+const BACK_PAD: &str = "#4d7fc4";
+const THROUGH_HOLE_PAD: &str = "#c2c200";
+
+impl KiCadFootprint {
+    /// An SVG image of the pads, silkscreen, courtyard and fabrication
+    /// drawings, seen from the front.
+    pub fn to_svg(&self) -> String {
+        let (min_x, min_y, max_x, max_y) = self.extent();
+        let (width, height) = (max_x - min_x, max_y - min_y);
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{min_x} {min_y} {width} {height}">"#,
+            (width * SCALE).round(),
+            (height * SCALE).round()
+        );
+        let _ = writeln!(svg, "<title>{}</title>", escape(&self.name));
+        let _ = writeln!(svg, r#"<rect x="{min_x}" y="{min_y}" width="{width}" height="{height}" fill="{BACKGROUND}"/>"#);
+
+        for (layer, color) in LAYERS_UNDER_PADS {
+            self.write_layer(&mut svg, layer, color);
+        }
+        for pad in &self.pads {
+            write_pad(&mut svg, pad);
+        }
+        for (layer, color) in LAYERS_OVER_PADS {
+            self.write_layer(&mut svg, layer, color);
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    fn write_layer(&self, svg: &mut String, layer: &str, color: &str) {
+        for graphic in self.graphics.iter().filter(|graphic| graphic.layer() == layer) {
+            write_graphic(svg, graphic, color);
+        }
+    }
+
+    /// The box around everything drawn, with a margin that also covers the
+    /// width of lines.
+    fn extent(&self) -> (f32, f32, f32, f32) {
+        let mut points = vec![];
+        for graphic in &self.graphics {
+            match graphic.shape() {
+                KiCadShape::Line { start, end } | KiCadShape::Rect { start, end } => points.extend([*start, *end]),
+                KiCadShape::Circle { center, radius } => {
+                    points.extend([(center.0 - radius, center.1 - radius), (center.0 + radius, center.1 + radius)]);
+                }
+                KiCadShape::Arc { start, mid, end } => points.extend([*start, *mid, *end]),
+                KiCadShape::Polygon(polygon) => points.extend(polygon),
+            }
+        }
+        for pad in &self.pads {
+            let (x, y, _) = pad.location();
+            // Any rotation stays within the circle around the pad
+            let radius = pad.size().0.hypot(pad.size().1) / 2.0;
+            points.extend([(x - radius, y - radius), (x + radius, y + radius)]);
+        }
+        if points.is_empty() {
+            points.push((0.0, 0.0));
+        }
+        let min_x = points.iter().map(|point| point.0).fold(f32::MAX, f32::min) - MARGIN;
+        let min_y = points.iter().map(|point| point.1).fold(f32::MAX, f32::min) - MARGIN;
+        let max_x = points.iter().map(|point| point.0).fold(f32::MIN, f32::max) + MARGIN;
+        let max_y = points.iter().map(|point| point.1).fold(f32::MIN, f32::max) + MARGIN;
+        (min_x, min_y, max_x, max_y)
+    }
+}
+
+fn write_graphic(svg: &mut String, graphic: &KiCadFootprintGraphic, color: &str) {
+    let fill = if graphic.is_filled() { color } else { "none" };
+    let style = format!(r#"fill="{fill}" stroke="{color}" stroke-width="{}" stroke-linecap="round""#, graphic.width());
+    let _ = match graphic.shape() {
+        KiCadShape::Line { start, end } => {
+            writeln!(svg, r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {style}/>"#, start.0, start.1, end.0, end.1)
+        }
+        KiCadShape::Rect { start, end } => writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" {style}/>"#,
+            start.0.min(end.0),
+            start.1.min(end.1),
+            (end.0 - start.0).abs(),
+            (end.1 - start.1).abs()
+        ),
+        KiCadShape::Circle { center, radius } => {
+            writeln!(svg, r#"<circle cx="{}" cy="{}" r="{radius}" {style}/>"#, center.0, center.1)
+        }
+        KiCadShape::Arc { start, mid, end } => writeln!(svg, r#"<path d="{}" {style}/>"#, arc_path(*start, *mid, *end)),
+        KiCadShape::Polygon(points) => {
+            let points: Vec<String> = points.iter().map(|(x, y)| format!("{x},{y}")).collect();
+            writeln!(svg, r#"<polygon points="{}" {style}/>"#, points.join(" "))
+        }
+    };
+}
+
+/// The path of the arc from `start` through `mid` to `end`.
+fn arc_path(start: (f32, f32), mid: (f32, f32), end: (f32, f32)) -> String {
+    let (ax, ay) = (mid.0 - start.0, mid.1 - start.1);
+    let (bx, by) = (end.0 - mid.0, end.1 - mid.1);
+    let cross = ax * by - ay * bx;
+    if cross.abs() < 1e-9 {
+        return format!("M {} {} L {} {}", start.0, start.1, end.0, end.1);
+    }
+    // The center is where the perpendicular bisectors of the chords meet
+    let d = 2.0 * (start.0 * (mid.1 - end.1) + mid.0 * (end.1 - start.1) + end.0 * (start.1 - mid.1));
+    let square = |(x, y): (f32, f32)| x * x + y * y;
+    let cx = (square(start) * (mid.1 - end.1) + square(mid) * (end.1 - start.1) + square(end) * (start.1 - mid.1)) / d;
+    let cy = (square(start) * (end.0 - mid.0) + square(mid) * (start.0 - end.0) + square(end) * (mid.0 - start.0)) / d;
+    let radius = (start.0 - cx).hypot(start.1 - cy);
+
+    // With y pointing down, a positive cross product turns clockwise on screen
+    let clockwise = cross > 0.0;
+    let angle = |(x, y): (f32, f32)| (y - cy).atan2(x - cx);
+    let swept = if clockwise { angle(end) - angle(start) } else { angle(start) - angle(end) }.rem_euclid(TAU);
+    format!(
+        "M {} {} A {radius} {radius} 0 {} {} {} {}",
+        start.0,
+        start.1,
+        u8::from(swept > TAU / 2.0),
+        u8::from(clockwise),
+        end.0,
+        end.1
+    )
+}
+
+fn write_pad(svg: &mut String, pad: &KiCadPad) {
+    let (x, y, rotation) = pad.location();
+    let (width, height) = pad.size();
+    let on_front = pad.layers().iter().any(|layer| matches!(layer.as_str(), "F.Cu" | "*.Cu" | "F&B.Cu"));
+    let color = match pad.pad_type() {
+        KiCadPadType::ThruHole => THROUGH_HOLE_PAD,
+        _ if on_front => FRONT_PAD,
+        _ => BACK_PAD,
+    };
+    // KiCad turns pads counter-clockwise on screen
+    let _ = writeln!(svg, r#"<g transform="translate({x} {y}) rotate({})">"#, -rotation);
+    if pad.pad_type() != KiCadPadType::NpThruHole {
+        let corner = match pad.shape() {
+            KiCadPadShape::Circle => width / 2.0,
+            KiCadPadShape::Oval => width.min(height) / 2.0,
+            KiCadPadShape::RoundRect => width.min(height) * pad.roundrect_rratio().unwrap_or(0.25),
+            _ => 0.0,
+        };
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{width}" height="{height}" rx="{corner}" fill="{color}"/>"#,
+            -width / 2.0,
+            -height / 2.0
+        );
+    }
+    if let Some(drill) = pad.drill() {
+        let (drill_width, drill_height) = drill.size();
+        let (offset_x, offset_y) = drill.offset().unwrap_or_default();
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{drill_width}" height="{drill_height}" rx="{}" fill="{BACKGROUND}" stroke="{THROUGH_HOLE_PAD}" stroke-width="0.02"/>"#,
+            offset_x - drill_width / 2.0,
+            offset_y - drill_height / 2.0,
+            drill_width.min(drill_height) / 2.0
+        );
+    }
+    svg.push_str("</g>\n");
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,159 @@
+//! Upgrades footprints of KiCad 5 and 6 to the format of KiCad 7, so a
+//! library does not mix formats depending on where its footprints came from.
+
+use crate::footprints::graphics::GRAPHIC_ITEMS;
+use crate::footprints::layers::fix_layer_names;
+use crate::footprints::writer::write_footprint;
+use crate::footprints::{argument_spans, footprint_tokens};
+use crate::symbols::Token;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// The format version KiCad 7 writes, which KiCad 7 and later read without
+/// converting anything.
+pub(crate) const FOOTPRINT_VERSION: u32 = 20221018;
+
+/// The first version with arcs given by start, mid and end point.
+const THREE_POINT_ARCS: u32 = 20210925;
+
+/// Upgrades the footprint file in place if it is older than
+/// [`FOOTPRINT_VERSION`]. Returns the version it had, 0 if it had none.
+pub(crate) fn upgrade_footprint(path: &Path) -> Result<Option<u32>, anyhow::Error> {
+    let content = fs::read_to_string(path)?;
+    let tokens = footprint_tokens(&content, Some(path))?;
+    let version = file_version(&tokens);
+    if version >= FOOTPRINT_VERSION && tokens.get(1) == Some(&Token::Word("footprint".into())) {
+        return Ok(None);
+    }
+
+    let mut edits: Vec<(Range<usize>, String)> = vec![];
+    let Some(&Token::OpenParen(header)) = tokens.first() else { return Ok(None) };
+    edits.push((header + 1..header + 1 + keyword_length(&content, header), "footprint".to_string()));
+    let Some(name) = argument_spans(&content, header).into_iter().next() else { return Ok(None) };
+    let version_text = format!("(version {FOOTPRINT_VERSION})");
+    match list_offsets(&tokens, "version").first() {
+        Some(&offset) => edits.extend(list_span(&content, offset).map(|span| (span, version_text))),
+        None => edits.push((name.end..name.end, format!(" {version_text}"))),
+    }
+    // Only written by KiCad 5, KiCad 6 replaced it with the file modification time
+    for offset in list_offsets(&tokens, "tedit") {
+        edits.extend(list_span(&content, offset).map(|span| (leading_space(&content, span), String::new())));
+    }
+
+    for (index, token) in tokens.iter().enumerate() {
+        let (Token::OpenParen(_), Some(Token::Word(keyword))) = (token, tokens.get(index + 1)) else { continue };
+        if !GRAPHIC_ITEMS.contains(&keyword.as_ref()) {
+            continue;
+        }
+        let children = child_offsets(&tokens, index);
+        for (child, offset) in &children {
+            if child == "width" {
+                let Some(span) = list_span(&content, *offset) else { continue };
+                let width = content[span.clone()].trim_start_matches("(width").trim_end_matches(')').trim().to_string();
+                edits.push((span, format!("(stroke (width {width}) (type solid))")));
+            }
+        }
+        if keyword == "fp_arc" && version < THREE_POINT_ARCS {
+            edits.extend(upgrade_arc(&content, &children));
+        }
+    }
+
+    edits.sort_by_key(|(span, _)| span.start);
+    let mut upgraded = content.clone();
+    for (span, replacement) in edits.into_iter().rev() {
+        upgraded.replace_range(span, &replacement);
+    }
+    write_footprint(path, &upgraded)?;
+    fix_layer_names(path)?;
+    Ok(Some(version))
+}
+
+/// KiCad 5 wrote arcs as center, start point and angle, clockwise in degrees.
+fn upgrade_arc(content: &str, children: &[(String, usize)]) -> Vec<(Range<usize>, String)> {
+    let child = |name: &str| children.iter().find(|(child, _)| child == name).map(|(_, offset)| *offset);
+    let (Some(start), Some(end), Some(angle)) = (child("start"), child("end"), child("angle")) else { return vec![] };
+    let numbers = |offset: usize| -> Option<Vec<f64>> {
+        argument_spans(content, offset).into_iter().map(|span| content[span].trim_matches('"').parse().ok()).collect()
+    };
+    let (Some(center), Some(point), Some(degrees)) = (numbers(start), numbers(end), numbers(angle)) else { return vec![] };
+    let (&[cx, cy], &[px, py], &[degrees]) = (&center[..], &point[..], &degrees[..]) else { return vec![] };
+
+    // Clockwise on screen, with y pointing down
+    let rotate = |degrees: f64| {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (dx, dy) = (px - cx, py - cy);
+        (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos)
+    };
+    let (mx, my) = rotate(degrees / 2.0);
+    let (ex, ey) = rotate(degrees);
+    let format = |value: f64| format!("{}", (value * 1e6).round() / 1e6);
+
+    let [Some(start), Some(end), Some(angle)] = [start, end, angle].map(|offset| list_span(content, offset)) else { return vec![] };
+    vec![
+        (start, format!("(start {} {})", format(px), format(py))),
+        (end, format!("(mid {} {}) (end {} {})", format(mx), format(my), format(ex), format(ey))),
+        (leading_space(content, angle), String::new()),
+    ]
+}
+
+/// The version of the file, 0 for files of KiCad 5 and older, which have none.
+fn file_version(tokens: &[Token]) -> u32 {
+    tokens
+        .windows(3)
+        .find_map(|window| match window {
+            [Token::OpenParen(_), Token::Word(keyword), Token::Word(version)] if keyword == "version" => version.parse().ok(),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+/// Offsets of the lists named `keyword` directly inside the footprint.
+fn list_offsets(tokens: &[Token], keyword: &str) -> Vec<usize> {
+    child_offsets(tokens, 0).into_iter().filter(|(name, _)| name == keyword).map(|(_, offset)| offset).collect()
+}
+
+/// Names and offsets of the lists directly inside the list at token `index`.
+fn child_offsets(tokens: &[Token], index: usize) -> Vec<(String, usize)> {
+    let mut children = vec![];
+    let mut depth = 0;
+    for (position, token) in tokens[index..].iter().enumerate() {
+        match token {
+            Token::OpenParen(offset) => {
+                depth += 1;
+                if depth == 2 {
+                    if let Some(Token::Word(name)) = tokens.get(index + position + 1) {
+                        children.push((name.to_string(), *offset));
+                    }
+                }
+            }
+            Token::CloseParen => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            Token::Word(_) => {}
+        }
+    }
+    children
+}
+
+fn keyword_length(content: &str, offset: usize) -> usize {
+    content[offset + 1..].find(|c: char| c.is_whitespace() || c == '(' || c == ')').unwrap_or(0)
+}
+
+/// Byte range of a list of words only, like `(width 0.12)`, from its opening
+/// to its closing parenthesis.
+fn list_span(content: &str, offset: usize) -> Option<Range<usize>> {
+    let last = argument_spans(content, offset).last().map_or(offset + 1 + keyword_length(content, offset), |span| span.end);
+    let close = last + content[last..].find(|c: char| !c.is_whitespace())?;
+    (content.as_bytes()[close] == b')').then_some(offset..close + 1)
+}
+
+/// The span extended by the whitespace in front of it, to remove a list
+/// without leaving a gap.
+fn leading_space(content: &str, span: Range<usize>) -> Range<usize> {
+    let start = content[..span.start].trim_end().len();
+    start..span.end
+}
@@ -0,0 +1,118 @@
+//! Writes footprint files the way KiCad formats them, so footprints rewritten
+//! here diff cleanly against ones saved by KiCad.
+
+use crate::dry_run;
+use crate::footprints::footprint_tokens;
+use crate::symbols::unescape;
+use crate::symbols::writer::SExpr;
+use anyhow::bail;
+use std::fs;
+use std::path::Path;
+
+/// The footprint in `content` with KiCad's formatting. Only whitespace
+/// changes; which strings are quoted is kept as it was.
+pub(crate) fn format_footprint(content: &str, path: Option<&Path>) -> Result<String, anyhow::Error> {
+    Ok(format!("{}\n", parse_footprint(content, path)?))
+}
+
+/// The footprint in `content` as an expression tree to edit and write back.
+pub(crate) fn parse_footprint(content: &str, path: Option<&Path>) -> Result<SExpr, anyhow::Error> {
+    footprint_tokens(content, path)?;
+    let mut position = 0;
+    parse(content.as_bytes(), &mut position)
+}
+
+/// Formats the footprint and writes it to `path`.
+pub(crate) fn write_footprint(path: &Path, content: &str) -> Result<(), anyhow::Error> {
+    fs::write(path, format_footprint(content, Some(path))?)?;
+    Ok(())
+}
+
+/// Writes a footprint into a library, which a dry run only reports. Edits of
+/// extracted footprints use [`write_footprint`].
+pub(crate) fn write_library_footprint(path: &Path, content: &str) -> Result<(), anyhow::Error> {
+    if dry_run::skip_write(path) {
+        return Ok(());
+    }
+    write_footprint(path, content)
+}
+
+/// Reads the expression at `position`, which the tokenizer already checked
+/// to be well-formed.
+fn parse(bytes: &[u8], position: &mut usize) -> Result<SExpr, anyhow::Error> {
+    let is_delimiter = |byte: u8| byte.is_ascii_whitespace() || byte == b'(' || byte == b')';
+    while bytes.get(*position).is_some_and(u8::is_ascii_whitespace) {
+        *position += 1;
+    }
+    match bytes.get(*position) {
+        None | Some(b')') => bail!("Unexpected end of expression at byte {position}"),
+        Some(b'(') => {
+            *position += 1;
+            let mut children = vec![];
+            loop {
+                while bytes.get(*position).is_some_and(u8::is_ascii_whitespace) {
+                    *position += 1;
+                }
+                if bytes.get(*position) == Some(&b')') {
+                    *position += 1;
+                    return Ok(SExpr::List(children));
+                }
+                children.push(parse(bytes, position)?);
+            }
+        }
+        Some(b'"') => {
+            *position += 1;
+            let start = *position;
+            loop {
+                match bytes.get(*position) {
+                    None => bail!("Unterminated string"),
+                    Some(b'"') => break,
+                    Some(b'\\') => *position += 2,
+                    Some(_) => *position += 1,
+                }
+            }
+            let value = unescape(&bytes[start..*position]);
+            *position += 1;
+            Ok(SExpr::Quoted(String::from_utf8_lossy(&value).into_owned()))
+        }
+        Some(_) => {
+            let start = *position;
+            while bytes.get(*position).is_some_and(|&byte| !is_delimiter(byte)) {
+                *position += 1;
+            }
+            Ok(SExpr::Atom(String::from_utf8_lossy(&bytes[start..*position]).into_owned()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_keep_windows_paths_and_quotes() {
+        let footprint = r#"(footprint "FP"
+  (descr "See C:\docs\x and \"quoted\" text")
+  (model "C:\Users\vendor\FP.step")
+)"#;
+        let formatted = format_footprint(footprint, None).unwrap();
+        let SExpr::List(children) = parse_footprint(&formatted, None).unwrap() else {
+            panic!("footprint is not a list");
+        };
+        let values: Vec<_> = children
+            .iter()
+            .filter_map(|child| match child {
+                SExpr::List(list) => list.get(1).cloned(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            values,
+            [
+                SExpr::quoted(r#"See C:\docs\x and "quoted" text"#),
+                SExpr::quoted(r"C:\Users\vendor\FP.step"),
+            ]
+        );
+        assert_eq!(format_footprint(&formatted, None).unwrap(), formatted);
+    }
+}
@@ -0,0 +1,162 @@
+//! The KiCad installation: its configuration directory with the global
+//! library tables, the directory of the user's libraries and the stock
+//! libraries, on Windows, macOS and Linux, installed natively or as flatpak.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The application id of the KiCad flatpak.
+const FLATPAK_ID: &str = "org.kicad.KiCad";
+
+#[derive(Debug, Clone)]
+pub(crate) struct KiCadInstallation {
+    /// The version of the configuration, like `8.0`.
+    version: String,
+    /// The configuration directory of that version, like `~/.config/kicad/8.0`.
+    config_dir: PathBuf,
+    flatpak: bool,
+}
+
+/// A directory from the environment, unless it is empty.
+pub(crate) fn env_dir(name: &str) -> Option<PathBuf> {
+    env::var_os(name).filter(|dir| !dir.is_empty()).map(PathBuf::from)
+}
+
+pub(crate) fn home() -> Option<PathBuf> {
+    env_dir(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+}
+
+/// The directories KiCad may keep the configuration of each version in, e.g.
+/// `~/.config/kicad` with `8.0` and `9.0` in it, with whether it is the one of
+/// the flatpak.
+fn config_bases() -> Vec<(PathBuf, bool)> {
+    if let Some(dir) = env_dir("KICAD_CONFIG_HOME") {
+        return vec![(dir, false)];
+    }
+    let native = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Preferences"))
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| home().map(|home| home.join(".config")))
+    };
+    let flatpak = home()
+        .filter(|_| cfg!(target_os = "linux"))
+        .map(|home| home.join(".var").join("app").join(FLATPAK_ID).join("config"));
+    [(native, false), (flatpak, true)]
+        .into_iter()
+        .filter_map(|(base, flatpak)| Some((base?.join("kicad"), flatpak)))
+        .collect()
+}
+
+/// The `major.minor` version of a configuration directory.
+fn version(path: &Path) -> Option<(u32, u32)> {
+    let (major, minor) = path.file_name()?.to_str()?.split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+impl KiCadInstallation {
+    /// The installation with the newest configuration, the native one if
+    /// both it and the flatpak have that version.
+    pub(crate) fn detect() -> Option<&'static Self> {
+        static INSTALLATION: OnceLock<Option<KiCadInstallation>> = OnceLock::new();
+        INSTALLATION
+            .get_or_init(|| {
+                config_bases()
+                    .into_iter()
+                    .flat_map(|(base, flatpak)| {
+                        let entries = fs::read_dir(base).into_iter().flatten().filter_map(|entry| entry.ok());
+                        entries.map(move |entry| (entry.path(), flatpak))
+                    })
+                    .filter(|(path, _)| path.is_dir())
+                    .filter_map(|(path, flatpak)| Some(((version(&path)?, !flatpak), path, flatpak)))
+                    .max_by_key(|(key, _, _)| *key)
+                    .map(|(((major, minor), _), config_dir, flatpak)| KiCadInstallation {
+                        version: format!("{major}.{minor}"),
+                        config_dir,
+                        flatpak,
+                    })
+            })
+            .as_ref()
+    }
+
+    pub(crate) fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub(crate) fn major(&self) -> &str {
+        self.version.split('.').next().unwrap_or_default()
+    }
+
+    pub(crate) fn config_dir(&self) -> &Path {
+        &self.config_dir
+    }
+
+    pub(crate) fn is_flatpak(&self) -> bool {
+        self.flatpak
+    }
+
+    /// The global `sym-lib-table`.
+    pub(crate) fn sym_lib_table(&self) -> PathBuf {
+        self.config_dir.join("sym-lib-table")
+    }
+
+    /// The global `fp-lib-table`.
+    pub(crate) fn fp_lib_table(&self) -> PathBuf {
+        self.config_dir.join("fp-lib-table")
+    }
+
+    /// The directory of the user's files of this version, like
+    /// `~/Documents/KiCad/8.0`, which has the `3rdparty` libraries.
+    pub(crate) fn documents_dir(&self) -> Option<PathBuf> {
+        let base = if self.flatpak {
+            home()?.join(".var").join("app").join(FLATPAK_ID).join("data").join("kicad")
+        } else if cfg!(any(windows, target_os = "macos")) {
+            home()?.join("Documents").join("KiCad")
+        } else {
+            env_dir("XDG_DATA_HOME").or_else(|| home().map(|home| home.join(".local").join("share")))?.join("kicad")
+        };
+        Some(base.join(&self.version))
+    }
+
+    /// The `share/kicad` directory with the stock libraries.
+    pub(crate) fn share_dir(&self) -> Option<PathBuf> {
+        let mut candidates = vec![];
+        if cfg!(windows) {
+            let program_files = env_dir("ProgramFiles").unwrap_or(PathBuf::from(r"C:\Program Files"));
+            candidates.push(program_files.join("KiCad").join(&self.version).join("share").join("kicad"));
+        } else if cfg!(target_os = "macos") {
+            candidates.push(PathBuf::from("/Applications/KiCad/KiCad.app/Contents/SharedSupport"));
+        } else if self.flatpak {
+            let installations = [Some(PathBuf::from("/var/lib/flatpak")), home().map(|home| home.join(".local/share/flatpak"))];
+            let files = Path::new("app").join(FLATPAK_ID).join("current/active/files/share/kicad");
+            candidates.extend(installations.into_iter().flatten().map(|installation| installation.join(&files)));
+        } else {
+            candidates.extend(["/usr/share/kicad", "/usr/local/share/kicad"].map(PathBuf::from));
+        }
+        candidates.into_iter().find(|dir| dir.is_dir())
+    }
+
+    /// The value KiCad gives one of its own variables, like
+    /// `KICAD8_SYMBOL_DIR`, if it is of this version.
+    pub(crate) fn variable(&self, name: &str) -> Option<PathBuf> {
+        let suffix = name.strip_prefix("KICAD")?.strip_prefix(self.major())?.strip_prefix('_')?;
+        match suffix {
+            "SYMBOL_DIR" => Some(self.share_dir()?.join("symbols")),
+            "FOOTPRINT_DIR" => Some(self.share_dir()?.join("footprints")),
+            "3DMODEL_DIR" => Some(self.share_dir()?.join("3dmodels")),
+            "TEMPLATE_DIR" => Some(self.share_dir()?.join("template")),
+            "3RD_PARTY" => Some(self.documents_dir()?.join("3rdparty")),
+            _ => None,
+        }
+    }
+
+    /// The libraries parts are imported into when none are given, in the
+    /// user's KiCad directory.
+    pub(crate) fn default_libraries(&self) -> Option<(PathBuf, PathBuf)> {
+        let dir = self.documents_dir()?.join("libraries");
+        Some((dir.join("Imported.kicad_sym"), dir.join("Imported.pretty")))
+    }
+}
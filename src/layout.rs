@@ -0,0 +1,132 @@
+//! The layouts of the archives of part library vendors, which put the files
+//! of several EDA tools and versions in their own folders, and the vendors
+//! recognised by the generator strings in their files.
+
+use crate::pipeline::ClassifiedFiles;
+use std::fs;
+use std::path::{Path, PathBuf};
+use strum::Display;
+
+/// The vendor layout an archive was recognised as.
+#[derive(Display, Copy, Clone, Debug, Default, PartialEq)]
+pub enum ArchiveLayout {
+    /// Files are found by their extension wherever they are
+    #[default]
+    Generic,
+    /// `KiCADv6/footprints.pretty/`, `KiCADv6/<part>.kicad_sym` and legacy
+    /// libraries in `KiCAD/`, next to the folders of other tools
+    #[strum(serialize = "Ultra Librarian")]
+    UltraLibrarian,
+    /// Library Loader archives of SamacSys and Component Search Engine, with
+    /// the `.kicad_sym` and KiCad 5 files in `LIB_<part>/KiCad/` or symbols
+    /// referring to footprints of `SamacSys_Parts`
+    #[strum(serialize = "SamacSys")]
+    SamacSys,
+    /// SnapEDA archives, the symbol library and footprint of the part side
+    /// by side, their files mentioning SnapEDA
+    #[strum(serialize = "SnapEDA")]
+    SnapEda,
+    /// Parts of LCSC converted from EasyEDA, by this tool or by easyeda2kicad
+    #[strum(serialize = "EasyEDA")]
+    EasyEda,
+}
+
+/// The strings the tools of the vendors leave in the files they generate,
+/// matched without case.
+const GENERATOR_STRINGS: [(&str, ArchiveLayout); 4] = [
+    ("snapeda", ArchiveLayout::SnapEda),
+    ("ultra librarian", ArchiveLayout::UltraLibrarian),
+    ("easyeda", ArchiveLayout::EasyEda),
+    ("samacsys", ArchiveLayout::SamacSys),
+];
+
+/// The library nickname SamacSys symbols refer to their footprints with.
+const SAMACSYS_NICKNAME: &str = "SamacSys_Parts";
+
+/// The version of a KiCad folder of Ultra Librarian, 6 for `KiCADv6` and 5
+/// for the legacy `KiCAD`.
+fn kicad_folder_version(path: &Path) -> Option<u32> {
+    path.components().rev().skip(1).find_map(|component| {
+        let name = component.as_os_str().to_str()?.to_ascii_lowercase();
+        match name.strip_prefix("kicad")? {
+            "" => Some(5),
+            version => version.strip_prefix('v')?.parse().ok(),
+        }
+    })
+}
+
+/// Keeps the files of the newest KiCad folder that has any, so the symbols
+/// of `KiCADv6` are not imported a second time from the legacy library.
+fn keep_newest(files: &mut Vec<PathBuf>, newest: impl Fn(&Path) -> bool) {
+    if files.iter().any(|file| newest(file)) {
+        files.retain(|file| newest(file));
+    }
+}
+
+/// Keeps the symbols and footprints of the newest KiCad folder of an Ultra
+/// Librarian archive.
+fn select_newest_kicad_folder(files: &mut ClassifiedFiles) {
+    let symbol_files = files.symbol_libs.iter().chain(&files.legacy_symbol_libs);
+    let newest_symbols = symbol_files.filter_map(|file| kicad_folder_version(file)).max();
+    let is_newest = |file: &Path| newest_symbols.is_some() && kicad_folder_version(file) == newest_symbols;
+    keep_newest(&mut files.symbol_libs, is_newest);
+    keep_newest(&mut files.legacy_symbol_libs, is_newest);
+    if !files.symbol_libs.is_empty() {
+        files.legacy_symbol_libs.clear();
+    }
+    let newest_footprints = files.footprints.iter().filter_map(|file| kicad_folder_version(file)).max();
+    keep_newest(&mut files.footprints, |file| newest_footprints.is_some() && kicad_folder_version(file) == newest_footprints);
+}
+
+impl ArchiveLayout {
+    /// The layout of an archive by the paths of its files.
+    pub fn detect(files: &[PathBuf]) -> Self {
+        if files.iter().any(|file| kicad_folder_version(file).is_some_and(|version| version >= 6)) {
+            return ArchiveLayout::UltraLibrarian;
+        }
+        // Ultra Librarian spells the folder `KiCAD`, and a `KiCad` folder alone
+        // is common in archives of any origin
+        let in_samacsys_folder = |file: &&PathBuf| {
+            let mut folders = file.ancestors().skip(1).filter_map(Path::file_name).filter_map(|folder| folder.to_str());
+            folders.next() == Some("KiCad") && folders.next().is_some_and(|part| part.starts_with("LIB_"))
+        };
+        let is_symbol_lib = |file: &&PathBuf| file.extension().is_some_and(|extension| extension == "kicad_sym" || extension == "lib");
+        let refers_to_samacsys =
+            |file: &PathBuf| fs::read_to_string(file).is_ok_and(|content| content.contains(&format!("\"{SAMACSYS_NICKNAME}:")));
+        if files.iter().any(|file| in_samacsys_folder(&file)) || files.iter().filter(is_symbol_lib).any(refers_to_samacsys) {
+            return ArchiveLayout::SamacSys;
+        }
+        Self::detect_generator(files).unwrap_or_default()
+    }
+
+    /// The vendor named in the KiCad files, like `#(c) SnapEDA 2016
+    /// (snapeda.com)` in the libraries of SnapEDA.
+    fn detect_generator(files: &[PathBuf]) -> Option<Self> {
+        let is_kicad_file =
+            |file: &&PathBuf| file.extension().is_some_and(|extension| ["kicad_sym", "lib", "kicad_mod"].contains(&&*extension.to_string_lossy()));
+        files.iter().filter(is_kicad_file).find_map(|file| {
+            let content = fs::read_to_string(file).ok()?.to_lowercase();
+            GENERATOR_STRINGS.iter().find(|(generator, _)| content.contains(generator)).map(|(_, layout)| *layout)
+        })
+    }
+
+    /// Drops the files the import should not use, like the KiCad 5 files of
+    /// an archive that also has those of KiCad 6.
+    pub fn select(self, files: &mut ClassifiedFiles) {
+        match self {
+            ArchiveLayout::Generic => {}
+            ArchiveLayout::UltraLibrarian => select_newest_kicad_folder(files),
+            // The `.lib` next to the `.kicad_sym` has the same symbols for KiCad 5
+            ArchiveLayout::SamacSys if !files.symbol_libs.is_empty() => files.legacy_symbol_libs.clear(),
+            ArchiveLayout::SamacSys | ArchiveLayout::SnapEda | ArchiveLayout::EasyEda => {}
+        }
+    }
+
+    /// Whether the symbols refer to footprints by a nickname of the vendor
+    /// that is not in any library table, like `footprints` of
+    /// `footprints.pretty` or `SamacSys_Parts`, or by its name only, so they
+    /// are pointed at the imported ones.
+    pub fn rewrites_footprint_nicknames(self) -> bool {
+        self != ArchiveLayout::Generic
+    }
+}
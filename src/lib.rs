@@ -0,0 +1,32 @@
+//! Imports vendor KiCad symbol libraries, footprints and models into a
+//! personal library. The import is available to other programs as a
+//! [`pipeline::ImportPipeline`] of replaceable stages.
+
+mod bom;
+mod catalog;
+mod cli;
+pub mod config;
+mod dbl;
+mod dedupe;
+mod dry_run;
+mod dimensions;
+mod easyeda;
+mod fields;
+pub mod footprints;
+mod installation;
+pub mod layout;
+mod lib_table;
+mod lifecycle;
+mod overlay;
+pub mod pipeline;
+mod policy;
+mod project;
+mod routing;
+mod snapshot;
+pub mod symbols;
+mod update;
+pub mod validation;
+mod variables;
+mod watch;
+
+pub use cli::run;
@@ -0,0 +1,214 @@
+//! Reads KiCad library tables, `fp-lib-table` and `sym-lib-table`, which map
+//! the nicknames symbols and schematics refer to onto library paths, and adds
+//! libraries to them.
+
+use crate::dry_run;
+use crate::footprints::quote;
+use crate::symbols::{describe_error, subdivide_expression, tokenise, Token};
+use crate::variables::variable;
+use anyhow::{anyhow, bail};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LibTableEntry {
+    pub(crate) name: String,
+    /// Path of the library, may contain variables like `${KIPRJMOD}`
+    pub(crate) uri: String,
+    /// The plugin reading the library, `KiCad` for the current formats
+    pub(crate) kind: String,
+    pub(crate) options: String,
+    pub(crate) descr: String,
+}
+
+impl LibTableEntry {
+    pub(crate) fn new(name: &str, uri: &str, descr: &str) -> Self {
+        Self { name: name.to_string(), uri: uri.to_string(), kind: "KiCad".to_string(), options: String::new(), descr: descr.to_string() }
+    }
+
+    /// The entry as KiCad writes it, on one line.
+    fn to_line(&self) -> String {
+        format!(
+            "(lib (name {})(type {})(uri {})(options {})(descr {}))",
+            quote(&self.name),
+            quote(&self.kind),
+            quote(&self.uri),
+            quote(&self.options),
+            quote(&self.descr)
+        )
+    }
+}
+
+/// What [`register_library`] did to the table.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Registration {
+    Added,
+    Updated,
+    /// The table had the library already
+    Unchanged,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LibTable {
+    /// The directory of the table, which `${KIPRJMOD}` stands for
+    dir: PathBuf,
+    pub(crate) libraries: Vec<LibTableEntry>,
+}
+
+impl LibTable {
+    pub(crate) fn from_path(path: &Path) -> Result<Self, anyhow::Error> {
+        let content = fs::read_to_string(path).map_err(|error| anyhow!("{}: {error}", path.display()))?;
+        let tokens = tokenise(&content).map_err(|error| describe_error(error, &content, Some(path)))?;
+        let Some(Token::Word(kind)) = tokens.get(1) else { bail!("{} is not a KiCad library table", path.display()) };
+        if kind != "fp_lib_table" && kind != "sym_lib_table" {
+            bail!("{} is not a KiCad library table", path.display());
+        }
+
+        let mut libraries = vec![];
+        for library in subdivide_expression(&tokens[2..tokens.len() - 1]) {
+            if library.get(1) != Some(&Token::Word("lib".into())) {
+                continue;
+            }
+            let mut name = None;
+            let mut uri = None;
+            let mut entry = LibTableEntry::new("", "", "");
+            for field in subdivide_expression(&library[2..library.len() - 1]) {
+                let (Some(Token::Word(key)), Some(Token::Word(value))) = (field.get(1), field.get(2)) else { continue };
+                match key.as_ref() {
+                    "name" => name = Some(value.to_string()),
+                    "uri" => uri = Some(value.to_string()),
+                    "type" => entry.kind = value.to_string(),
+                    "options" => entry.options = value.to_string(),
+                    "descr" => entry.descr = value.to_string(),
+                    _ => {}
+                }
+            }
+            if let (Some(name), Some(uri)) = (name, uri) {
+                libraries.push(LibTableEntry { name, uri, ..entry });
+            }
+        }
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new(".")).to_path_buf();
+        Ok(LibTable { dir, libraries })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.libraries.is_empty()
+    }
+
+    pub(crate) fn find(&self, nickname: &str) -> Option<&LibTableEntry> {
+        self.libraries.iter().find(|library| library.name == nickname)
+    }
+
+    /// The path of the library, or `None` if its URI uses a variable that is
+    /// neither set in the environment nor in KiCad.
+    pub(crate) fn library_path(&self, library: &LibTableEntry) -> Option<PathBuf> {
+        let mut path = String::new();
+        let mut rest = library.uri.as_str();
+        while let Some(start) = rest.find("${") {
+            let end = start + rest[start..].find('}')?;
+            let name = &rest[start + 2..end];
+            path.push_str(&rest[..start]);
+            match name {
+                "KIPRJMOD" => path.push_str(&self.dir.to_string_lossy()),
+                _ => path.push_str(&variable(name)?),
+            }
+            rest = &rest[end + 1..];
+        }
+        path.push_str(rest);
+        Some(PathBuf::from(path))
+    }
+}
+
+/// Adds `entry` to the library table at `path`, or updates the URI of the
+/// library of the same nickname. Tables that do not exist are created as
+/// `kind`, `sym_lib_table` or `fp_lib_table`. The other libraries are left as
+/// they are written.
+pub(crate) fn register_library(path: &Path, kind: &str, entry: &LibTableEntry) -> Result<Registration, anyhow::Error> {
+    if !path.exists() {
+        dry_run::write(path, format!("({kind}\n  (version 7)\n  {}\n)\n", entry.to_line()))?;
+        return Ok(Registration::Added);
+    }
+    let table = LibTable::from_path(path)?;
+    let existing = table.find(&entry.name);
+    if existing.is_some_and(|existing| existing.uri == entry.uri) {
+        return Ok(Registration::Unchanged);
+    }
+
+    let content = fs::read_to_string(path)?;
+    let tokens = tokenise(&content).map_err(|error| describe_error(error, &content, Some(path)))?;
+    let Some(existing) = existing else {
+        let Some(end) = content.rfind(')') else { bail!("{} is not a KiCad library table", path.display()) };
+        let mut updated = content.clone();
+        updated.insert_str(end, &format!("  {}\n", entry.to_line()));
+        dry_run::write(path, updated)?;
+        return Ok(Registration::Added);
+    };
+
+    // The `(lib ...)` of the nickname, whose other fields are kept
+    let start = tokens
+        .windows(2)
+        .filter_map(|window| match window {
+            [Token::OpenParen(offset), Token::Word(keyword)] if keyword == "lib" => Some(*offset),
+            _ => None,
+        })
+        .find(|&offset| table_entry_name(&content[offset..]) == Some(existing.name.as_str()))
+        .ok_or(anyhow!("{}: malformed entry of {}", path.display(), existing.name))?;
+    let end = start + expression_length(&content[start..]).ok_or(anyhow!("{}: unterminated entry", path.display()))?;
+    let descr = if existing.descr.is_empty() { &entry.descr } else { &existing.descr };
+    let updated_entry = LibTableEntry { uri: entry.uri.clone(), descr: descr.clone(), ..existing.clone() };
+    let mut updated = content.clone();
+    updated.replace_range(start..end, &updated_entry.to_line());
+    dry_run::write(path, updated)?;
+    Ok(Registration::Updated)
+}
+
+/// The URI a table refers to the library at `path` with: relative to
+/// `${KIPRJMOD}` for a library inside `project_dir`, relative to one of the
+/// `variables` whose directory holds it, or the absolute path.
+pub(crate) fn library_uri(
+    path: &Path,
+    project_dir: Option<&Path>,
+    variables: &BTreeMap<String, PathBuf>,
+) -> Result<String, anyhow::Error> {
+    let path = std::path::absolute(path)?;
+    let project = project_dir.map(|dir| ("KIPRJMOD".to_string(), dir.to_path_buf()));
+    for (name, dir) in project.into_iter().chain(variables.iter().map(|(name, dir)| (name.clone(), dir.clone()))) {
+        if let Ok(relative) = path.strip_prefix(std::path::absolute(&dir)?) {
+            let parts: Vec<String> = relative.iter().map(|part| part.to_string_lossy().to_string()).collect();
+            return Ok(format!("${{{name}}}/{}", parts.join("/")));
+        }
+    }
+    Ok(path.display().to_string())
+}
+
+/// The nickname of the `(lib (name "...") ...)` at the start of `content`.
+fn table_entry_name(content: &str) -> Option<&str> {
+    let rest = content.split_once("(name")?.1.trim_start();
+    let rest = rest.strip_prefix('"').unwrap_or(rest);
+    rest.split(['"', ')']).next()
+}
+
+/// The length of the expression at the start of `content`, up to and with its
+/// closing parenthesis.
+fn expression_length(content: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, byte) in content.bytes().enumerate() {
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' if in_string => escaped = true,
+            b'"' => in_string = !in_string,
+            b'(' if !in_string => depth += 1,
+            b')' if !in_string => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
@@ -0,0 +1,63 @@
+use crate::fields::FieldNames;
+use crate::symbols::property::{KiCadProperty, KiCadSymbol};
+use anyhow::{anyhow, bail};
+use std::time::Duration;
+use strum::{Display, EnumString};
+
+#[derive(EnumString, Display, Copy, Clone, PartialEq, Debug)]
+#[strum(ascii_case_insensitive)]
+pub(crate) enum Lifecycle {
+    Active,
+    #[strum(serialize = "NRND")]
+    Nrnd,
+    Obsolete,
+}
+
+impl Lifecycle {
+    /// Maps the wording used by distributor APIs onto a lifecycle status.
+    fn from_api_status(status: &str) -> Option<Lifecycle> {
+        match status.trim().to_lowercase().as_str() {
+            "active" | "production" | "in production" | "new" | "new product" => Some(Lifecycle::Active),
+            "nrnd" | "not recommended for new designs" | "last time buy" | "ltb" => Some(Lifecycle::Nrnd),
+            "obsolete" | "eol" | "end of life" | "discontinued" => Some(Lifecycle::Obsolete),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn symbol_lifecycle(symbol: &KiCadSymbol, fields: &FieldNames) -> Option<Lifecycle> {
+    symbol.property_value(&fields.lifecycle_property())?.parse().ok()
+}
+
+pub(crate) fn set_symbol_lifecycle(symbol: &mut KiCadSymbol, lifecycle: Lifecycle, fields: &FieldNames) {
+    symbol.set_property(KiCadProperty::new_hidden(fields.lifecycle_property(), lifecycle.to_string()));
+}
+
+/// Looks up the lifecycle of a part from a JSON API. `{mpn}` in the URL
+/// template is replaced by the part number and `field` names the member of
+/// the returned object holding the status.
+pub(crate) fn fetch_lifecycle(
+    agent: &ureq::Agent,
+    url_template: &str,
+    field: &str,
+    mpn: &str,
+) -> Result<Lifecycle, anyhow::Error> {
+    if !url_template.contains("{mpn}") {
+        bail!("Lifecycle API URL does not contain {{mpn}}: {url_template}");
+    }
+    let url = url_template.replace("{mpn}", mpn);
+    let body = agent.get(&url).call()?.body_mut().read_to_string()?;
+    let response: serde_json::Value = serde_json::from_str(&body)?;
+    let status = response
+        .get(field)
+        .and_then(|value| value.as_str())
+        .ok_or(anyhow!("Lifecycle API response has no string field '{field}': {url}"))?;
+    Lifecycle::from_api_status(status).ok_or(anyhow!("Unknown lifecycle status '{status}' for {mpn}"))
+}
+
+pub(crate) fn lifecycle_agent(timeout: Duration) -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .build()
+        .into()
+}
@@ -0,0 +1,54 @@
+use crate::symbols::property::KiCadSymbol;
+use crate::symbols::KicadSymbolLib;
+use std::collections::BTreeSet;
+use std::fs::OpenOptions;
+use std::path::Path;
+
+/// A read-only base library (e.g. a team library on a network mount) combined
+/// with a writable overlay library holding the user's own additions. Symbols
+/// in the overlay shadow base symbols of the same name.
+pub(crate) struct OverlayLibrary {
+    pub(crate) base: KicadSymbolLib,
+    pub(crate) overlay: KicadSymbolLib,
+}
+
+impl OverlayLibrary {
+    /// Loads both libraries, starting with an empty overlay if it does not exist yet.
+    pub(crate) fn open(base_path: &Path, overlay_path: &Path) -> Result<Self, anyhow::Error> {
+        let base = KicadSymbolLib::from_path(base_path)?;
+        let overlay = if overlay_path.exists() {
+            KicadSymbolLib::from_path(overlay_path)?
+        } else {
+            KicadSymbolLib::new()
+        };
+        Ok(OverlayLibrary { base, overlay })
+    }
+
+    /// The union of both libraries as KiCad would resolve it.
+    pub(crate) fn symbols(&self) -> Vec<&KiCadSymbol> {
+        self.base_symbols().into_iter().chain(&self.overlay.symbols).collect()
+    }
+
+    /// The symbols of the base library the overlay does not shadow.
+    pub(crate) fn base_symbols(&self) -> Vec<&KiCadSymbol> {
+        let overlay_names: BTreeSet<&str> = self.overlay.symbols.iter().map(KiCadSymbol::name).collect();
+        self.base.symbols.iter().filter(|symbol| !overlay_names.contains(symbol.name())).collect()
+    }
+
+    /// Names defined in both libraries, the overlay version wins.
+    pub(crate) fn conflicts(&self) -> Vec<&str> {
+        let base_names: BTreeSet<&str> = self.base.symbols.iter().map(KiCadSymbol::name).collect();
+        self.overlay
+            .symbols
+            .iter()
+            .map(KiCadSymbol::name)
+            .filter(|name| base_names.contains(name))
+            .collect()
+    }
+}
+
+/// Network mounts and package-managed installs are often read-only even when
+/// the permission bits say otherwise, so this actually tries to open the file.
+pub(crate) fn is_writable(path: &Path) -> bool {
+    OpenOptions::new().append(true).open(path).is_ok()
+}
@@ -0,0 +1,819 @@
+//! The import of a vendor archive as separate stages:
+//! extract → classify → parse → transform → validate → merge → write.
+//!
+//! Every stage is a trait, so programs using this crate can replace a stage or
+//! add their own transforms and checks, e.g. to inject company specific
+//! properties into every imported symbol:
+//!
+//! ```no_run
+//! use kicad_library_manager::config::Config;
+//! use kicad_library_manager::pipeline::{ImportPipeline, Transform};
+//! use kicad_library_manager::symbols::property::{KiCadProperty, KiCadPropertyType, KiCadSymbol};
+//! use kicad_library_manager::symbols::KicadSymbolLib;
+//! use kicad_library_manager::validation::Finding;
+//! use std::path::Path;
+//!
+//! struct AddApprovalStatus;
+//!
+//! impl Transform for AddApprovalStatus {
+//!     fn transform(&self, symbol: &mut KiCadSymbol) -> Result<Vec<Finding>, anyhow::Error> {
+//!         let status = KiCadPropertyType::Custom("Approval".to_string());
+//!         symbol.set_property(KiCadProperty::new_hidden(status, "pending".to_string()));
+//!         Ok(vec![])
+//!     }
+//! }
+//!
+//! # fn main() -> Result<(), anyhow::Error> {
+//! let mut pipeline = ImportPipeline::new(&Config::default());
+//! pipeline.add_transform(AddApprovalStatus);
+//!
+//! let files = pipeline.extract(Path::new("LM358.zip"), Path::new("/tmp/LM358"))?;
+//! let mut library = KicadSymbolLib::from_path(Path::new("MyLib.kicad_sym"))?;
+//! let outcome = pipeline.import_symbols(&files, &mut library)?;
+//! pipeline.write(&library, Path::new("MyLib.kicad_sym"))?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::config::{Config, ConflictPolicy, ReferencePrefixRule};
+use crate::footprints::ask_conflict;
+use crate::easyeda;
+use crate::fields::{property_type, FieldNames};
+use crate::layout::ArchiveLayout;
+use crate::symbols::property::{KiCadProperty, KiCadPropertyType, KiCadSymbol};
+use crate::symbols::legacy::is_legacy_library;
+use crate::symbols::fidelity::FidelityReport;
+use crate::symbols::{fidelity, KicadSymbolLib, ParseLimits, SkippedSymbol};
+use crate::validation::reference::fix_reference_prefix;
+use crate::validation::{validate_symbol, Finding, Severity};
+use anyhow::bail;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::File;
+use flate2::read::GzDecoder;
+use std::io::{Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// The files of an extracted archive the import knows how to handle.
+#[derive(Default, Debug)]
+pub struct ClassifiedFiles {
+    pub symbol_libs: Vec<PathBuf>,
+    /// KiCad 5 `.lib` libraries, their `.dcm` files are read along with them
+    pub legacy_symbol_libs: Vec<PathBuf>,
+    pub footprints: Vec<PathBuf>,
+    pub models: Vec<PathBuf>,
+    pub ibis_models: Vec<PathBuf>,
+    pub layout: ArchiveLayout,
+    /// Files of no use to the import, like datasheets, instructions and
+    /// example projects
+    pub ignored: Vec<PathBuf>,
+}
+
+/// Unpacks an archive into `dest` and returns the files it contained.
+pub trait Extractor {
+    fn extract(&self, archive: &Path, dest: &Path) -> Result<Vec<PathBuf>, anyhow::Error>;
+}
+
+/// Sorts extracted files by kind, files that are not needed are dropped.
+pub trait Classifier {
+    fn classify(&self, files: Vec<PathBuf>) -> ClassifiedFiles;
+}
+
+/// The symbols read from an archive and the ones that had to be left out.
+#[derive(Default)]
+pub struct ParsedSymbols {
+    pub symbols: Vec<KiCadSymbol>,
+    pub skipped: Vec<SkippedSymbol>,
+}
+
+/// Reads the symbols out of the classified symbol library files.
+pub trait Parser {
+    fn parse(&self, files: &ClassifiedFiles) -> Result<ParsedSymbols, anyhow::Error>;
+}
+
+/// Modifies an imported symbol before it is validated and returns a finding
+/// for every change worth telling the user about.
+pub trait Transform {
+    fn transform(&self, symbol: &mut KiCadSymbol) -> Result<Vec<Finding>, anyhow::Error>;
+}
+
+pub trait Validator {
+    fn validate(&self, symbol: &KiCadSymbol) -> Vec<Finding>;
+}
+
+/// Adds the imported symbols to the target library.
+pub trait Merger {
+    fn merge(&self, library: &mut KicadSymbolLib, symbols: &[KiCadSymbol]) -> Result<MergeReport, anyhow::Error>;
+}
+
+pub trait LibraryWriter {
+    fn write(&self, library: &KicadSymbolLib, path: &Path) -> Result<(), anyhow::Error>;
+}
+
+/// The files in `dir` and its subdirectories, like the `KiCad/` and `3D/`
+/// folders of vendor archives, sorted by path. Hidden files and the
+/// `__MACOSX` resource forks of archives made on a Mac are left out.
+fn files_in(dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') || name == "__MACOSX" {
+            continue;
+        }
+        if path.is_dir() {
+            files.extend(files_in(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn matches_chars(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some(('*', rest)), _) => matches_chars(rest, name) || (!name.is_empty() && matches_chars(pattern, &name[1..])),
+        (Some(('?', rest)), Some((_, name_rest))) => matches_chars(rest, name_rest),
+        (Some((expected, rest)), Some((actual, name_rest))) => expected == actual && matches_chars(rest, name_rest),
+        (Some(_), None) => false,
+    }
+}
+
+/// Whether a name matches a pattern with `*` and `?` wildcards.
+pub(crate) fn matches_wildcards(pattern: &str, name: &str) -> bool {
+    matches_chars(&pattern.chars().collect::<Vec<_>>(), &name.chars().collect::<Vec<_>>())
+}
+
+/// How deep zips wrapped in zips are extracted.
+const MAX_NESTED_ARCHIVES: usize = 3;
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// Whether the file is a gzipped tarball, `.tar.gz` or `.tgz`.
+fn is_tar_gz(path: &Path) -> bool {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default().to_ascii_lowercase();
+    name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+/// Whether the file is an archive the import extracts: a zip, a 7-Zip
+/// archive or a gzipped tarball.
+fn is_archive(path: &Path) -> bool {
+    has_extension(path, &["zip", "7z"]) || is_tar_gz(path)
+}
+
+/// Whether the import takes the file: an archive or an EasyEDA `.json` export.
+pub(crate) fn is_importable(path: &Path) -> bool {
+    is_archive(path) || has_extension(path, &["json"])
+}
+
+/// Whether the path of an archive entry would be written outside of the
+/// folder it is extracted to.
+fn is_outside(name: &Path) -> bool {
+    name.is_absolute() || name.components().any(|component| component == Component::ParentDir)
+}
+
+/// The size of a header and of the blocks of a tar archive.
+const TAR_BLOCK: usize = 512;
+
+/// A NUL terminated field of a tar header.
+fn tar_field(field: &[u8]) -> &[u8] {
+    field.split(|&byte| byte == 0).next().unwrap_or_default()
+}
+
+/// Extracts the regular files of a gzipped tarball, in the ustar and GNU
+/// formats. Links and other special entries are skipped.
+fn extract_tar_gz(archive: &Path, dest: &Path) -> Result<(), anyhow::Error> {
+    let mut content = vec![];
+    GzDecoder::new(File::open(archive)?).read_to_end(&mut content)?;
+    let mut offset = 0;
+    let mut long_name = None;
+    while let Some(header) = content.get(offset..offset + TAR_BLOCK) {
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        let size_field = String::from_utf8_lossy(tar_field(&header[124..136])).trim().to_string();
+        let Ok(size) = usize::from_str_radix(&size_field, 8) else { bail!("{} is not a valid tar archive", archive.display()) };
+        let start = offset + TAR_BLOCK;
+        let Some(data) = content.get(start..start + size) else { bail!("{} is truncated", archive.display()) };
+        offset = start + size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+        let name = match long_name.take() {
+            Some(name) => name,
+            None if &header[257..262] == b"ustar" && header[345] != 0 => {
+                [tar_field(&header[345..500]), b"/", tar_field(&header[..100])].concat()
+            }
+            None => tar_field(&header[..100]).to_vec(),
+        };
+        match header[156] {
+            // GNU long name of the next entry
+            b'L' => long_name = Some(tar_field(data).to_vec()),
+            b'0' | 0 | b'5' => {
+                let name = PathBuf::from(String::from_utf8_lossy(&name).into_owned());
+                if is_outside(&name) {
+                    bail!("{} has a file outside of the archive: {}", archive.display(), name.display());
+                }
+                // Without the `./` tar puts in front of the names
+                let path = dest.join(name.components().filter(|component| *component != Component::CurDir).collect::<PathBuf>());
+                if header[156] == b'5' {
+                    fs::create_dir_all(&path)?;
+                } else {
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(&path, data)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Extracts a 7-Zip archive, refusing the ones with files outside of it.
+fn extract_7z(archive: &Path, dest: &Path) -> Result<(), anyhow::Error> {
+    let mut outside = None;
+    sevenz_rust::decompress_file_with_extract_fn(archive, dest, |entry, reader, path| {
+        if is_outside(Path::new(entry.name())) {
+            outside.get_or_insert(entry.name().to_string());
+            return Ok(true);
+        }
+        sevenz_rust::default_entry_extract_fn(entry, reader, path)
+    })?;
+    if let Some(name) = outside {
+        bail!("{} has a file outside of the archive: {name}", archive.display());
+    }
+    Ok(())
+}
+
+/// Extracts a zip, a gzipped tarball or a 7-Zip archive by its name.
+fn extract_archive(archive: &Path, dest: &Path) -> Result<(), anyhow::Error> {
+    if is_tar_gz(archive) {
+        extract_tar_gz(archive, dest)
+    } else if has_extension(archive, &["7z"]) {
+        extract_7z(archive, dest)
+    } else {
+        Ok(zip_extract::extract(Cursor::new(fs::read(archive)?), dest, true)?)
+    }
+}
+
+/// The files of an extracted folder, with the archives in it extracted if
+/// it has no KiCad files, like the downloads of some distributors that wrap
+/// the archive of the part.
+fn extract_nested_archives(dest: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut files = files_in(dest)?;
+    for _ in 0..MAX_NESTED_ARCHIVES {
+        let inner_archives: Vec<PathBuf> = files.iter().filter(|file| is_archive(file)).cloned().collect();
+        if inner_archives.is_empty() || files.iter().any(|file| has_extension(file, &["kicad_sym", "kicad_mod", "lib"])) {
+            break;
+        }
+        for inner_archive in inner_archives {
+            let name = inner_archive.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            let stem = [".tar.gz", ".tgz", ".zip", ".7z"].iter().find_map(|suffix| {
+                name.len().checked_sub(suffix.len()).filter(|&end| name[end..].eq_ignore_ascii_case(suffix)).map(|end| &name[..end])
+            });
+            let inner_dest = inner_archive.with_file_name(stem.unwrap_or(name));
+            extract_archive(&inner_archive, &inner_dest)?;
+            fs::remove_file(&inner_archive)?;
+        }
+        files = files_in(dest)?;
+    }
+    Ok(files)
+}
+
+/// Extracts zip and 7-Zip archives and gzipped tarballs, returning the files
+/// of all folders.
+pub struct ArchiveExtractor;
+
+impl Extractor for ArchiveExtractor {
+    fn extract(&self, archive: &Path, dest: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        extract_archive(archive, dest)?;
+        extract_nested_archives(dest)
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), anyhow::Error> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name() else { continue };
+        if path.is_dir() {
+            copy_dir(&path, &to.join(name))?;
+        } else {
+            fs::copy(&path, to.join(name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Imports a folder that was already extracted, or a staging folder of
+/// parts. It is copied, so the import does not change it.
+pub struct DirectoryExtractor;
+
+impl Extractor for DirectoryExtractor {
+    fn extract(&self, dir: &Path, dest: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        if !dir.is_dir() {
+            bail!("{} is not a directory", dir.display());
+        }
+        copy_dir(dir, dest)?;
+        extract_nested_archives(dest)
+    }
+}
+
+/// Converts the EasyEDA JSON of an LCSC part into a symbol library and a
+/// footprint.
+pub struct EasyEdaExtractor;
+
+impl Extractor for EasyEdaExtractor {
+    fn extract(&self, part: &Path, dest: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        easyeda::convert(part, dest)
+    }
+}
+
+/// Classifies files by their extension, choosing between the files of
+/// several KiCad versions by the [`ArchiveLayout`]. The libraries of example
+/// projects, in a folder with a `.kicad_pro`, are not imported.
+pub struct ExtensionClassifier;
+
+impl Classifier for ExtensionClassifier {
+    fn classify(&self, files: Vec<PathBuf>) -> ClassifiedFiles {
+        let mut classified = ClassifiedFiles { layout: ArchiveLayout::detect(&files), ..Default::default() };
+        let project_dirs: Vec<PathBuf> = files
+            .iter()
+            .filter(|file| has_extension(file, &["kicad_pro", "pro"]))
+            .filter_map(|file| Some(file.parent()?.to_path_buf()))
+            .collect();
+        for file in files {
+            let extension = file.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+            if project_dirs.iter().any(|dir| file.starts_with(dir)) {
+                classified.ignored.push(file);
+                continue;
+            }
+            match extension.to_ascii_lowercase().as_str() {
+                "kicad_sym" => classified.symbol_libs.push(file),
+                "lib" if is_legacy_library(&file) => classified.legacy_symbol_libs.push(file),
+                "kicad_mod" => classified.footprints.push(file),
+                "step" | "stp" | "stpz" | "wrl" => classified.models.push(file),
+                "ibs" => classified.ibis_models.push(file),
+                // Read along with the `.lib` of the same name
+                "dcm" => {}
+                _ => classified.ignored.push(file),
+            }
+        }
+        classified.layout.select(&mut classified);
+        classified
+    }
+}
+
+/// Parses `.kicad_sym` files within the given limits and converts legacy `.lib`
+/// files. A malformed symbol is skipped, the other symbols of the file are
+/// still imported.
+pub struct KicadSymbolParser {
+    limits: ParseLimits,
+}
+
+impl KicadSymbolParser {
+    pub fn new(limits: ParseLimits) -> Self {
+        Self { limits }
+    }
+}
+
+impl Parser for KicadSymbolParser {
+    fn parse(&self, files: &ClassifiedFiles) -> Result<ParsedSymbols, anyhow::Error> {
+        let mut parsed = ParsedSymbols::default();
+        for file in &files.symbol_libs {
+            let (library, skipped) = KicadSymbolLib::from_path_recovering(file, &self.limits)?;
+            parsed.symbols.extend(library.symbols);
+            parsed.skipped.extend(skipped);
+        }
+        for file in &files.legacy_symbol_libs {
+            let (library, skipped) = KicadSymbolLib::from_legacy_path(file)?;
+            parsed.symbols.extend(library.symbols);
+            parsed.skipped.extend(skipped);
+        }
+        Ok(parsed)
+    }
+}
+
+/// The properties every KiCad symbol has.
+const REQUIRED_PROPERTIES: [KiCadPropertyType; 4] =
+    [KiCadPropertyType::Reference, KiCadPropertyType::Value, KiCadPropertyType::Footprint, KiCadPropertyType::Datasheet];
+
+/// Removes the marketing and tracking properties of vendors, like the
+/// `Check_prices` link of SnapEDA, by the `strip_properties` of the config.
+/// The fields KiCad requires are kept.
+pub struct StripProperties {
+    patterns: Vec<String>,
+}
+
+impl StripProperties {
+    pub fn new(config: &Config) -> Self {
+        Self { patterns: config.strip_properties.iter().map(|pattern| pattern.to_lowercase()).collect() }
+    }
+}
+
+impl Transform for StripProperties {
+    fn transform(&self, symbol: &mut KiCadSymbol) -> Result<Vec<Finding>, anyhow::Error> {
+        let stripped: Vec<KiCadPropertyType> = symbol
+            .properties()
+            .iter()
+            .map(|property| property.property_type().clone())
+            .filter(|property_type| {
+                let name = property_type.to_string().to_lowercase();
+                !REQUIRED_PROPERTIES.contains(property_type) && self.patterns.iter().any(|pattern| matches_wildcards(pattern, &name))
+            })
+            .collect();
+        if stripped.is_empty() {
+            return Ok(vec![]);
+        }
+        for property_type in &stripped {
+            symbol.remove_property(property_type);
+        }
+        let names: Vec<String> = stripped.iter().map(ToString::to_string).collect();
+        Ok(vec![Finding::new(Severity::Info, symbol.name(), format!("removed {}", names.join(", ")))])
+    }
+}
+
+/// Renames the fields vendors spell their own way, like
+/// `MANUFACTURER_PART_NUMBER`, to the field names of the config. A field the
+/// symbol already has with another value is left as it is.
+pub struct NormalizeFields {
+    names: FieldNames,
+}
+
+impl NormalizeFields {
+    pub fn new(config: &Config) -> Self {
+        Self { names: config.fields.clone() }
+    }
+}
+
+impl Transform for NormalizeFields {
+    fn transform(&self, symbol: &mut KiCadSymbol) -> Result<Vec<Finding>, anyhow::Error> {
+        let renames: Vec<(KiCadPropertyType, String, String)> = symbol
+            .properties()
+            .iter()
+            .filter_map(|property| {
+                let name = property.property_type().to_string();
+                let normalized = self.names.normalized_name(&name)?;
+                (normalized != name).then(|| (property.property_type().clone(), normalized.to_string(), property.value().to_string()))
+            })
+            .collect();
+        let mut findings = vec![];
+        for (from, to, value) in renames {
+            let to_type = property_type(&to);
+            match symbol.property_value(&to_type) {
+                Some(existing) if !existing.is_empty() && existing != value => {
+                    let message = format!("field {from} differs from {to}, kept both");
+                    findings.push(Finding::new(Severity::Warning, symbol.name(), message));
+                    continue;
+                }
+                Some(_) => symbol.remove_property(&to_type),
+                None => {}
+            }
+            symbol.rename_property(&from, to_type);
+            findings.push(Finding::new(Severity::Info, symbol.name(), format!("field {from} -> {to}")));
+        }
+        Ok(findings)
+    }
+}
+
+/// Rewrites reference prefixes that do not match the part category where unambiguous.
+pub struct FixReferencePrefixes {
+    rules: Vec<ReferencePrefixRule>,
+}
+
+impl FixReferencePrefixes {
+    pub fn new(config: &Config) -> Self {
+        Self { rules: config.reference_prefixes.clone() }
+    }
+}
+
+impl Transform for FixReferencePrefixes {
+    fn transform(&self, symbol: &mut KiCadSymbol) -> Result<Vec<Finding>, anyhow::Error> {
+        let Some((old, new)) = fix_reference_prefix(symbol, &self.rules) else { return Ok(vec![]) };
+        Ok(vec![Finding::new(Severity::Info, symbol.name(), format!("reference {old} -> {new}"))])
+    }
+}
+
+/// Points the Footprint property of symbols at the imported footprints, e.g.
+/// `SamacSys_Parts:SOIC127P600X175-8N` at `MyFootprints:SOIC127P600X175-8N`.
+pub struct RewriteFootprintNicknames {
+    nickname: String,
+    /// The name of each imported footprint in the archive and in the library,
+    /// which differ if it was imported under a new name
+    footprints: BTreeMap<String, String>,
+}
+
+impl RewriteFootprintNicknames {
+    pub fn new(nickname: &str, footprints: BTreeMap<String, String>) -> Self {
+        Self { nickname: nickname.to_string(), footprints }
+    }
+}
+
+impl Transform for RewriteFootprintNicknames {
+    fn transform(&self, symbol: &mut KiCadSymbol) -> Result<Vec<Finding>, anyhow::Error> {
+        let Some(old) = symbol.property_value(&KiCadPropertyType::Footprint) else { return Ok(vec![]) };
+        let name = old.rsplit(':').next().unwrap_or(old);
+        let Some(imported_name) = self.footprints.get(name) else { return Ok(vec![]) };
+        let new = format!("{}:{imported_name}", self.nickname);
+        if new == old {
+            return Ok(vec![]);
+        }
+        let finding = Finding::new(Severity::Info, symbol.name(), format!("footprint {old} -> {new}"));
+        symbol.set_property(KiCadProperty::new_hidden(KiCadPropertyType::Footprint, new));
+        Ok(vec![finding])
+    }
+}
+
+/// Adds an `IBIS_Model` property, or the IBIS model field of the config,
+/// pointing at the archived IBIS files.
+pub struct LinkIbisModels {
+    property_type: KiCadPropertyType,
+    paths: Vec<PathBuf>,
+}
+
+impl LinkIbisModels {
+    pub fn new(config: &Config, paths: Vec<PathBuf>) -> Self {
+        Self { property_type: config.fields.ibis_model_property(), paths }
+    }
+}
+
+impl Transform for LinkIbisModels {
+    fn transform(&self, symbol: &mut KiCadSymbol) -> Result<Vec<Finding>, anyhow::Error> {
+        let ibis_paths: Vec<_> = self.paths.iter().map(|path| path.display().to_string()).collect();
+        symbol.set_property(KiCadProperty::new_hidden(self.property_type.clone(), ibis_paths.join(";")));
+        Ok(vec![])
+    }
+}
+
+/// The built-in symbol checks, configured by the config file.
+pub struct SymbolChecks {
+    config: Config,
+}
+
+impl SymbolChecks {
+    pub fn new(config: &Config) -> Self {
+        Self { config: config.clone() }
+    }
+}
+
+impl Validator for SymbolChecks {
+    fn validate(&self, symbol: &KiCadSymbol) -> Vec<Finding> {
+        validate_symbol(symbol, &self.config)
+    }
+}
+
+/// Appends the symbols to the end of the library.
+pub struct AppendMerger;
+
+impl Merger for AppendMerger {
+    fn merge(&self, library: &mut KicadSymbolLib, symbols: &[KiCadSymbol]) -> Result<MergeReport, anyhow::Error> {
+        library.symbols.extend_from_slice(symbols);
+        Ok(MergeReport { symbols: symbols.to_vec(), added: symbols.len(), ..MergeReport::default() })
+    }
+}
+
+/// What a [`Merger`] did with the symbols.
+#[derive(Default, Debug)]
+pub struct MergeReport {
+    /// The symbols that are in the library now, under their final names.
+    /// Skipped symbols are left out.
+    pub symbols: Vec<KiCadSymbol>,
+    pub added: usize,
+    pub unchanged: usize,
+    pub skipped: usize,
+    pub overwritten: usize,
+    pub renamed: usize,
+}
+
+impl MergeReport {
+    /// Adds the outcome of another merge, for a summary of several.
+    pub fn add(&mut self, other: MergeReport) {
+        self.symbols.extend(other.symbols);
+        self.added += other.added;
+        self.unchanged += other.unchanged;
+        self.skipped += other.skipped;
+        self.overwritten += other.overwritten;
+        self.renamed += other.renamed;
+    }
+}
+
+impl Display for MergeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} unchanged, {} skipped, {} overwritten, {} renamed",
+            self.added, self.unchanged, self.skipped, self.overwritten, self.renamed
+        )
+    }
+}
+
+/// Adds the symbols to the library, leaving out the ones it already has
+/// unchanged. A different symbol of the same name is handled by the
+/// [`ConflictPolicy`], like imported footprints.
+pub(crate) struct ConflictMerger {
+    policy: ConflictPolicy,
+}
+
+impl ConflictMerger {
+    pub(crate) fn new(policy: ConflictPolicy) -> Self {
+        Self { policy }
+    }
+
+}
+
+impl Merger for ConflictMerger {
+    fn merge(&self, library: &mut KicadSymbolLib, symbols: &[KiCadSymbol]) -> Result<MergeReport, anyhow::Error> {
+        let mut report = MergeReport::default();
+        // A lazily read library is only parsed to compare symbols of the same name
+        let names = library.symbol_names();
+        if symbols.iter().any(|symbol| names.contains(&symbol.name())) {
+            library.materialize()?;
+        }
+        // Symbols imported under a new name, their derived symbols follow them
+        let mut renames: Vec<(String, String)> = vec![];
+        for symbol in symbols {
+            let mut symbol = symbol.clone();
+            for (old, new) in &renames {
+                symbol.rename_extended(old, new);
+            }
+            let Some(index) = library.symbols.iter().position(|existing| existing.name() == symbol.name()) else {
+                println!("{}: added", symbol.name());
+                report.symbols.push(symbol.clone());
+                library.symbols.push(symbol);
+                report.added += 1;
+                continue;
+            };
+            if library.symbols[index] == symbol {
+                println!("{}: unchanged", symbol.name());
+                report.symbols.push(symbol);
+                report.unchanged += 1;
+                continue;
+            }
+            let policy = match self.policy {
+                ConflictPolicy::Ask => ask_conflict(&format!("Symbol {}", symbol.name()))?,
+                policy => policy,
+            };
+            match policy {
+                ConflictPolicy::Skip => {
+                    println!("{}: a different symbol of that name exists, skipped", symbol.name());
+                    report.skipped += 1;
+                }
+                ConflictPolicy::Overwrite => {
+                    println!("{}: overwritten", symbol.name());
+                    report.symbols.push(symbol.clone());
+                    library.symbols[index] = symbol;
+                    report.overwritten += 1;
+                }
+                ConflictPolicy::Ask => unreachable!("the answer is one of the other policies"),
+                ConflictPolicy::Rename => {
+                    let old = symbol.name().to_string();
+                    let new = (1..)
+                        .map(|suffix| format!("{old}_{suffix}"))
+                        .find(|new| !library.symbols.iter().any(|existing| existing.name() == new))
+                        .expect("the suffixes are unbounded");
+                    println!("{old}: a different symbol of that name exists, imported as {new}");
+                    symbol.rename(&new);
+                    report.symbols.push(symbol.clone());
+                    library.symbols.push(symbol);
+                    renames.push((old, new));
+                    report.renamed += 1;
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Writes the library in the KiCad 8 file format.
+pub struct KicadLibraryWriter;
+
+impl LibraryWriter for KicadLibraryWriter {
+    fn write(&self, library: &KicadSymbolLib, path: &Path) -> Result<(), anyhow::Error> {
+        library.write_to_path(path)
+    }
+}
+
+/// What [`ImportPipeline::import_symbols`] added to the library.
+pub struct ImportOutcome {
+    /// What the merger did, with the symbols as they are in the library now
+    pub merged: MergeReport,
+    /// Symbols of the archive that could not be parsed
+    pub skipped: Vec<SkippedSymbol>,
+    pub findings: Vec<Finding>,
+    /// What parsing the archive could not carry over
+    pub fidelity: FidelityReport,
+}
+
+pub struct ImportPipeline {
+    extractor: Box<dyn Extractor>,
+    classifier: Box<dyn Classifier>,
+    parser: Box<dyn Parser>,
+    transforms: Vec<Box<dyn Transform>>,
+    validators: Vec<Box<dyn Validator>>,
+    merger: Box<dyn Merger>,
+    writer: Box<dyn LibraryWriter>,
+}
+
+impl ImportPipeline {
+    /// The stages of the command line import, without any optional transforms.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            extractor: Box::new(ArchiveExtractor),
+            classifier: Box::new(ExtensionClassifier),
+            parser: Box::new(KicadSymbolParser::new(config.parse_limits)),
+            transforms: vec![],
+            validators: vec![Box::new(SymbolChecks::new(config))],
+            merger: Box::new(AppendMerger),
+            writer: Box::new(KicadLibraryWriter),
+        }
+    }
+
+    pub fn extractor(&mut self, extractor: impl Extractor + 'static) -> &mut Self {
+        self.extractor = Box::new(extractor);
+        self
+    }
+
+    pub fn classifier(&mut self, classifier: impl Classifier + 'static) -> &mut Self {
+        self.classifier = Box::new(classifier);
+        self
+    }
+
+    pub fn parser(&mut self, parser: impl Parser + 'static) -> &mut Self {
+        self.parser = Box::new(parser);
+        self
+    }
+
+    /// Transforms run in the order they were added.
+    pub fn add_transform(&mut self, transform: impl Transform + 'static) -> &mut Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    pub fn add_validator(&mut self, validator: impl Validator + 'static) -> &mut Self {
+        self.validators.push(Box::new(validator));
+        self
+    }
+
+    pub fn merger(&mut self, merger: impl Merger + 'static) -> &mut Self {
+        self.merger = Box::new(merger);
+        self
+    }
+
+    pub fn writer(&mut self, writer: impl LibraryWriter + 'static) -> &mut Self {
+        self.writer = Box::new(writer);
+        self
+    }
+
+    /// Extracts the archive into `dest` and classifies its files.
+    pub fn extract(&self, archive: &Path, dest: &Path) -> Result<ClassifiedFiles, anyhow::Error> {
+        let files = self.extractor.extract(archive, dest)?;
+        Ok(self.classifier.classify(files))
+    }
+
+    /// Parses the symbols of `files` as they are in the archive.
+    pub fn parse(&self, files: &ClassifiedFiles) -> Result<ParsedSymbols, anyhow::Error> {
+        self.parser.parse(files)
+    }
+
+    /// Parses and transforms the symbols of `files` without merging them, to
+    /// check them before anything is imported.
+    pub fn transformed_symbols(&self, files: &ClassifiedFiles) -> Result<Vec<KiCadSymbol>, anyhow::Error> {
+        let mut symbols = self.parser.parse(files)?.symbols;
+        for symbol in symbols.iter_mut() {
+            for transform in &self.transforms {
+                transform.transform(symbol)?;
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Parses, transforms and validates the symbols of `files` and merges them into `library`.
+    pub fn import_symbols(&self, files: &ClassifiedFiles, library: &mut KicadSymbolLib) -> Result<ImportOutcome, anyhow::Error> {
+        let (parsed, fidelity) = fidelity::collect(|| self.parser.parse(files));
+        let ParsedSymbols { mut symbols, skipped } = parsed?;
+        let mut findings = vec![];
+        for symbol in symbols.iter_mut() {
+            for transform in &self.transforms {
+                findings.extend(transform.transform(symbol)?);
+            }
+        }
+        for symbol in &symbols {
+            for validator in &self.validators {
+                findings.extend(validator.validate(symbol));
+            }
+        }
+        let merged = self.merger.merge(library, &symbols)?;
+        Ok(ImportOutcome { merged, skipped, findings, fidelity })
+    }
+
+    pub fn write(&self, library: &KicadSymbolLib, path: &Path) -> Result<(), anyhow::Error> {
+        self.writer.write(library, path)
+    }
+}
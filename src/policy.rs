@@ -0,0 +1,183 @@
+//! Library standards shared by an organisation. The config points at a policy
+//! file on a web server or in a git repository, which is fetched on every run
+//! and cached, so imports keep working offline with the last known policy.
+
+use crate::config::PolicyConfig;
+use crate::footprints::lint;
+use crate::pipeline::{Transform, Validator};
+use crate::symbols::property::{KiCadProperty, KiCadPropertyType, KiCadSymbol};
+use crate::validation::{Finding, Severity, SYMBOL_RULES};
+use anyhow::{anyhow, bail};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+use std::{env, fs};
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub(crate) struct Policy {
+    /// Properties every symbol must have with a non-empty value
+    pub(crate) required_properties: Vec<String>,
+    pub(crate) naming: NamingPolicy,
+    /// Properties added to imported symbols that do not have them
+    pub(crate) field_templates: Vec<FieldTemplate>,
+    /// Rules of `check` whose findings violate the policy: `check` runs them
+    /// even if disabled and imports stop on them. Imports run the symbol rules
+    /// that do not need the imported footprints in place and the footprint
+    /// rules on the footprints of the archive
+    pub(crate) mandatory_rules: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub(crate) struct NamingPolicy {
+    pub(crate) max_length: Option<usize>,
+    /// Characters symbol names must not contain, e.g. `" /"`
+    pub(crate) forbidden_characters: String,
+    /// Symbol names must not contain lowercase letters
+    pub(crate) uppercase: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct FieldTemplate {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) value: String,
+}
+
+/// Where the cached copy of a policy source is kept.
+fn cache_dir() -> PathBuf {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(env::temp_dir);
+    base.join("kicad-library-manager").join("policy")
+}
+
+fn cache_key(source: &str) -> String {
+    format!("{:x}", Sha256::digest(source.as_bytes()))[..16].to_string()
+}
+
+fn fetch_http(url: &str, timeout: Duration) -> Result<String, anyhow::Error> {
+    let agent: ureq::Agent = ureq::Agent::config_builder().timeout_global(Some(timeout)).build().into();
+    Ok(agent.get(url).call()?.body_mut().read_to_string()?)
+}
+
+/// Clones the repository on first use and pulls it afterwards.
+fn fetch_git(repository: &str, checkout: &Path, file: &str) -> Result<String, anyhow::Error> {
+    let status = if checkout.join(".git").is_dir() {
+        Command::new("git").arg("-C").arg(checkout).args(["pull", "--ff-only", "--quiet"]).status()?
+    } else {
+        Command::new("git").args(["clone", "--depth", "1", "--quiet", "--", repository]).arg(checkout).status()?
+    };
+    if !status.success() {
+        bail!("git could not fetch {repository}");
+    }
+    fs::read_to_string(checkout.join(file)).map_err(|error| anyhow!("{file} in {repository}: {error}"))
+}
+
+/// Fetches the policy and refreshes the cache. If the source cannot be reached
+/// the cached copy is used, with a warning once it is older than `max_age_hours`.
+pub(crate) fn load_policy(config: &PolicyConfig) -> Result<Policy, anyhow::Error> {
+    let dir = cache_dir();
+    let key = cache_key(&config.source);
+    let cache = dir.join(format!("{key}.toml"));
+
+    let fetched = match config.source.strip_prefix("git+") {
+        Some(repository) => {
+            let Some(file) = &config.file else { bail!("A git policy source needs `file`, the policy file in the repository") };
+            fs::create_dir_all(&dir)?;
+            fetch_git(repository, &dir.join(&key), file)
+        }
+        None => fetch_http(&config.source, Duration::from_secs(config.timeout)),
+    };
+
+    let content = match fetched {
+        Ok(content) => {
+            fs::create_dir_all(&dir)?;
+            fs::write(&cache, &content)?;
+            content
+        }
+        Err(error) => {
+            let content = fs::read_to_string(&cache)
+                .map_err(|_| anyhow!("Could not fetch policy {} and there is no cached copy: {error}", config.source))?;
+            let age = fs::metadata(&cache)?.modified()?.elapsed().unwrap_or_default();
+            println!("warning: could not fetch policy {}, using the cached copy: {error}", config.source);
+            if age > Duration::from_secs(config.max_age_hours * 3600) {
+                println!("warning: the cached policy is {} hours old", age.as_secs() / 3600);
+            }
+            content
+        }
+    };
+    let policy: Policy = toml::from_str(&content).map_err(|error| anyhow!("Invalid policy {}: {error}", config.source))?;
+    let rules = SYMBOL_RULES.iter().chain(&lint::RULES).map(|(rule, _)| *rule).collect::<Vec<_>>();
+    if let Some(rule) = policy.mandatory_rules.iter().find(|rule| !rules.contains(&rule.as_str())) {
+        bail!("Unknown mandatory rule {rule} in policy {}, the rules are {}", config.source, rules.join(", "));
+    }
+    Ok(policy)
+}
+
+/// Adds the fields of the policy templates that imported symbols are missing.
+pub(crate) struct ApplyFieldTemplates {
+    templates: Vec<FieldTemplate>,
+}
+
+impl ApplyFieldTemplates {
+    pub(crate) fn new(policy: &Policy) -> Self {
+        Self { templates: policy.field_templates.clone() }
+    }
+}
+
+impl Transform for ApplyFieldTemplates {
+    fn transform(&self, symbol: &mut KiCadSymbol) -> Result<Vec<Finding>, anyhow::Error> {
+        for template in &self.templates {
+            let property_type = KiCadPropertyType::from_str(&template.name)?;
+            if symbol.property_value(&property_type).is_none() {
+                symbol.set_property(KiCadProperty::new_hidden(property_type, template.value.clone()));
+            }
+        }
+        Ok(vec![])
+    }
+}
+
+/// The mandatory rules of the policy, violations are errors.
+pub(crate) struct PolicyChecks {
+    policy: Policy,
+}
+
+impl PolicyChecks {
+    pub(crate) fn new(policy: &Policy) -> Self {
+        Self { policy: policy.clone() }
+    }
+}
+
+impl Validator for PolicyChecks {
+    fn validate(&self, symbol: &KiCadSymbol) -> Vec<Finding> {
+        let mut findings = vec![];
+        let mut violation = |message: String| findings.push(Finding::new(Severity::Error, symbol.name(), message));
+
+        for name in &self.policy.required_properties {
+            let property_type = KiCadPropertyType::from_str(name).unwrap_or(KiCadPropertyType::Custom(name.clone()));
+            if symbol.property_value(&property_type).is_none_or(|value| value.trim().is_empty()) {
+                violation(format!("policy requires a {name} property"));
+            }
+        }
+
+        let naming = &self.policy.naming;
+        let name = symbol.name();
+        if let Some(max_length) = naming.max_length.filter(|max_length| name.chars().count() > *max_length) {
+            violation(format!("policy limits symbol names to {max_length} characters"));
+        }
+        if let Some(c) = name.chars().find(|c| naming.forbidden_characters.contains(*c)) {
+            violation(format!("policy forbids '{c}' in symbol names"));
+        }
+        if naming.uppercase && name.chars().any(char::is_lowercase) {
+            violation("policy requires uppercase symbol names".to_string());
+        }
+        findings
+    }
+}
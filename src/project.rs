@@ -0,0 +1,65 @@
+use crate::symbols::{describe_error, tokenise, Token};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Recursively collects the files below `dir` with the given extension.
+pub(crate) fn find_files_with_extension(dir: &Path, extension: &str) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(find_files_with_extension(&path, extension)?);
+        } else if path.extension() == Some(extension.as_ref()) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Scans the schematics of the given projects and maps every placed symbol
+/// name (the part of the `lib_id` after the library nickname) to the
+/// schematics using it.
+pub(crate) fn find_symbol_usages(project_dirs: &[PathBuf]) -> Result<BTreeMap<String, BTreeSet<PathBuf>>, anyhow::Error> {
+    let mut usages = BTreeMap::<String, BTreeSet<PathBuf>>::new();
+    for project_dir in project_dirs {
+        for schematic in find_files_with_extension(project_dir, "kicad_sch")? {
+            let content = fs::read_to_string(&schematic)?;
+            let tokens = tokenise(&content).map_err(|error| describe_error(error, &content, Some(&schematic)))?;
+            for window in tokens.windows(3) {
+                let [Token::OpenParen(_), Token::Word(keyword), Token::Word(lib_id)] = window else { continue };
+                if keyword != "lib_id" {
+                    continue;
+                }
+                let name = lib_id.split_once(':').map_or(lib_id.as_ref(), |(_, name)| name);
+                usages.entry(name.to_string()).or_default().insert(schematic.clone());
+            }
+        }
+    }
+    Ok(usages)
+}
+
+/// The schematics and boards of the given projects referring to the footprint
+/// `nickname:name`, by a Footprint property or a placed footprint.
+pub(crate) fn find_footprint_usages(project_dirs: &[PathBuf], footprint: &str) -> Result<BTreeSet<PathBuf>, anyhow::Error> {
+    let mut usages = BTreeSet::new();
+    for project_dir in project_dirs {
+        let mut files = find_files_with_extension(project_dir, "kicad_sch")?;
+        files.extend(find_files_with_extension(project_dir, "kicad_pcb")?);
+        for file in files {
+            let content = fs::read_to_string(&file)?;
+            let tokens = tokenise(&content).map_err(|error| describe_error(error, &content, Some(&file)))?;
+            let used = tokens.windows(4).any(|window| match window {
+                [Token::OpenParen(_), Token::Word(keyword), Token::Word(key), Token::Word(value)] if keyword == "property" => {
+                    key == "Footprint" && value == footprint
+                }
+                [Token::OpenParen(_), Token::Word(keyword), Token::Word(lib_id), _] => keyword == "footprint" && lib_id == footprint,
+                _ => false,
+            });
+            if used {
+                usages.insert(file);
+            }
+        }
+    }
+    Ok(usages)
+}
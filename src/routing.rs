@@ -0,0 +1,46 @@
+//! Picks the libraries an import goes to by the category of its parts, see
+//! [`LibraryRoute`].
+
+use crate::config::LibraryRoute;
+use crate::symbols::property::{KiCadPropertyType, KiCadSymbol};
+use crate::validation::reference::{category_words, reference_prefix};
+use anyhow::anyhow;
+
+/// Whether the symbol is of the category of the route.
+fn matches(route: &LibraryRoute, symbol: &KiCadSymbol) -> bool {
+    let words = category_words(symbol);
+    let prefix = symbol.property_value(&KiCadPropertyType::Reference).map(reference_prefix);
+    route.keywords.iter().any(|keyword| words.contains(&keyword.to_lowercase()))
+        || prefix.is_some_and(|prefix| route.reference_prefixes.iter().any(|route_prefix| route_prefix == prefix))
+}
+
+/// The route of `category`, or else the first route one of the symbols
+/// matches. Symbols matching a different route are imported along with the
+/// others, with a warning.
+pub(crate) fn route<'a>(
+    routes: &'a [LibraryRoute],
+    category: Option<&str>,
+    symbols: &[KiCadSymbol],
+) -> Result<Option<&'a LibraryRoute>, anyhow::Error> {
+    if let Some(category) = category {
+        let route = routes.iter().find(|route| route.category == category);
+        let categories: Vec<&str> = routes.iter().map(|route| route.category.as_str()).collect();
+        return route.map(Some).ok_or(anyhow!("No route for category {category}, the config has: {}", categories.join(", ")));
+    }
+    let symbol_routes: Vec<(&KiCadSymbol, &LibraryRoute)> = symbols
+        .iter()
+        .filter_map(|symbol| Some((symbol, routes.iter().find(|route| matches(route, symbol))?)))
+        .collect();
+    let Some((_, chosen)) = symbol_routes.first() else { return Ok(None) };
+    for (symbol, route) in &symbol_routes {
+        if route.category != chosen.category {
+            println!(
+                "warning: {} is of category {}, but is imported with the {} parts",
+                symbol.name(),
+                route.category,
+                chosen.category
+            );
+        }
+    }
+    Ok(Some(chosen))
+}
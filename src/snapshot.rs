@@ -0,0 +1,250 @@
+//! Records of the state of a library at points in time, so changes can be
+//! listed without version control, e.g. for libraries on a shared drive.
+
+use crate::dry_run;
+use crate::symbols::property::KiCadSymbol;
+use crate::symbols::{KicadSymbolLib, ToSExpr};
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A symbol as its hash and its properties, so changes to properties can be
+/// told apart from changes to the drawing.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct SymbolState {
+    hash: String,
+    properties: BTreeMap<String, String>,
+}
+
+impl SymbolState {
+    fn of(symbol: &KiCadSymbol) -> Self {
+        let properties = symbol
+            .properties()
+            .iter()
+            .map(|property| (property.property_type().to_string(), property.value().to_string()))
+            .collect();
+        // The written form, so reformatting the file is not a change
+        let hash = format!("{:x}", Sha256::digest(symbol.to_sexpr().to_string().as_bytes()));
+        SymbolState { hash, properties }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub(crate) struct LibraryState {
+    pub(crate) symbols: BTreeMap<String, SymbolState>,
+    /// Hashes of the `.kicad_mod` files by footprint name, if a footprint
+    /// directory was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) footprints: Option<BTreeMap<String, String>>,
+}
+
+impl LibraryState {
+    pub(crate) fn capture(library: &KicadSymbolLib, footprint_dir: Option<&Path>) -> Result<Self, anyhow::Error> {
+        let symbols = library.symbols.iter().map(|symbol| (symbol.name().to_string(), SymbolState::of(symbol))).collect();
+        let footprints = match footprint_dir {
+            Some(dir) => Some(footprint_hashes(dir)?),
+            None => None,
+        };
+        Ok(LibraryState { symbols, footprints })
+    }
+}
+
+fn footprint_hashes(dir: &Path) -> Result<BTreeMap<String, String>, anyhow::Error> {
+    let mut hashes = BTreeMap::new();
+    for entry in fs::read_dir(dir).map_err(|error| anyhow!("{}: {error}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "kicad_mod") {
+            continue;
+        }
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        hashes.insert(name, format!("{:x}", Sha256::digest(fs::read(&path)?)));
+    }
+    Ok(hashes)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Snapshot {
+    pub(crate) id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) label: Option<String>,
+    /// Seconds since the Unix epoch
+    pub(crate) created: u64,
+    /// The footprint directory the footprints were recorded from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) footprint_dir: Option<PathBuf>,
+    #[serde(flatten)]
+    pub(crate) state: LibraryState,
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.id, format_timestamp(self.created))?;
+        if let Some(label) = &self.label {
+            write!(f, " {label}")?;
+        }
+        write!(f, " ({} symbols", self.state.symbols.len())?;
+        if let Some(footprints) = &self.state.footprints {
+            write!(f, ", {} footprints", footprints.len())?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub(crate) struct SnapshotHistory {
+    pub(crate) snapshots: Vec<Snapshot>,
+}
+
+impl SnapshotHistory {
+    /// The snapshots live next to the symbol library, e.g. `MyLib.kicad_sym`
+    /// in `MyLib.snapshots.json`.
+    pub(crate) fn default_path(symbol_lib: &Path) -> PathBuf {
+        symbol_lib.with_extension("snapshots.json")
+    }
+
+    pub(crate) fn from_path(path: &Path) -> Result<Self, anyhow::Error> {
+        if !path.exists() {
+            return Ok(SnapshotHistory::default());
+        }
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub(crate) fn write_to_path(&self, path: &Path) -> Result<(), anyhow::Error> {
+        if dry_run::skip_write(path) {
+            return Ok(());
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub(crate) fn add(&mut self, label: Option<String>, footprint_dir: Option<PathBuf>, state: LibraryState) -> &Snapshot {
+        if let Some(label) = &label {
+            // Labels are also used to refer to snapshots, so they must be unique
+            self.snapshots.retain(|snapshot| snapshot.label.as_ref() != Some(label));
+        }
+        let id = self.snapshots.iter().map(|snapshot| snapshot.id).max().unwrap_or_default() + 1;
+        let created = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        self.snapshots.push(Snapshot { id, label, created, footprint_dir, state });
+        self.snapshots.last().expect("a snapshot was just added")
+    }
+
+    /// Finds a snapshot by its id or label.
+    pub(crate) fn find(&self, reference: &str) -> Result<&Snapshot, anyhow::Error> {
+        let found = self
+            .snapshots
+            .iter()
+            .find(|snapshot| snapshot.label.as_deref() == Some(reference))
+            .or_else(|| self.snapshots.iter().find(|snapshot| snapshot.id.to_string() == reference));
+        match found {
+            Some(snapshot) => Ok(snapshot),
+            None => bail!("No snapshot {reference}"),
+        }
+    }
+}
+
+/// A difference between two library states.
+#[derive(Debug)]
+pub(crate) enum Change<'a> {
+    Added(&'static str, &'a str),
+    Removed(&'static str, &'a str),
+    /// A symbol with the properties that changed, as name, old and new value
+    SymbolChanged(&'a str, Vec<(&'a str, Option<&'a str>, Option<&'a str>)>),
+    FootprintChanged(&'a str),
+}
+
+impl Display for Change<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added(kind, name) => write!(f, "+ {kind} {name}"),
+            Change::Removed(kind, name) => write!(f, "- {kind} {name}"),
+            Change::FootprintChanged(name) => write!(f, "~ footprint {name}"),
+            Change::SymbolChanged(name, properties) => {
+                write!(f, "~ symbol {name}")?;
+                if properties.is_empty() {
+                    return write!(f, ": drawing changed");
+                }
+                for (i, (property, old, new)) in properties.iter().enumerate() {
+                    let separator = if i == 0 { ": " } else { ", " };
+                    match (old, new) {
+                        (Some(old), Some(new)) => write!(f, "{separator}{property} {old:?} -> {new:?}")?,
+                        (None, Some(new)) => write!(f, "{separator}{property} added {new:?}")?,
+                        (Some(_), None) => write!(f, "{separator}{property} removed")?,
+                        (None, None) => {}
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The changes from `old` to `new`. Footprints are only compared if both
+/// states recorded them.
+pub(crate) fn diff<'a>(old: &'a LibraryState, new: &'a LibraryState) -> Vec<Change<'a>> {
+    let mut changes = vec![];
+    for (name, state) in &new.symbols {
+        match old.symbols.get(name) {
+            None => changes.push(Change::Added("symbol", name)),
+            Some(old_state) if old_state.hash != state.hash => {
+                changes.push(Change::SymbolChanged(name, changed_properties(&old_state.properties, &state.properties)));
+            }
+            Some(_) => {}
+        }
+    }
+    changes.extend(old.symbols.keys().filter(|name| !new.symbols.contains_key(*name)).map(|name| Change::Removed("symbol", name)));
+
+    if let (Some(old_footprints), Some(new_footprints)) = (&old.footprints, &new.footprints) {
+        for (name, hash) in new_footprints {
+            match old_footprints.get(name) {
+                None => changes.push(Change::Added("footprint", name)),
+                Some(old_hash) if old_hash != hash => changes.push(Change::FootprintChanged(name)),
+                Some(_) => {}
+            }
+        }
+        changes.extend(old_footprints.keys().filter(|name| !new_footprints.contains_key(*name)).map(|name| Change::Removed("footprint", name)));
+    }
+    changes
+}
+
+fn changed_properties<'a>(
+    old: &'a BTreeMap<String, String>,
+    new: &'a BTreeMap<String, String>,
+) -> Vec<(&'a str, Option<&'a str>, Option<&'a str>)> {
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .map(|name| (name.as_str(), old.get(name).map(String::as_str), new.get(name).map(String::as_str)))
+        .filter(|(_, old, new)| old != new)
+        .collect()
+}
+
+/// Formats seconds since the Unix epoch as a UTC date and time.
+fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+    // Civil from days, after Howard Hinnant's date algorithms
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02} {:02}:{:02} UTC", time / 3600, time % 3600 / 60)
+}
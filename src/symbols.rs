@@ -1,165 +1,792 @@
+use std::borrow::Cow;
 use std::cmp::PartialEq;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::Path;
 use std::str::FromStr;
 use anyhow::{anyhow, bail};
-use crate::symbols::property::{check_expression_validity, KiCadSymbol};
+use serde::Deserialize;
+use crate::dry_run;
+use crate::symbols::property::{try_parse_string_to_bool, KiCadSymbol};
+use crate::symbols::writer::{bool_to_kicad_string, SExpr};
 
-mod property;
+pub mod property;
+#[cfg(feature = "proptest")]
+mod arbitrary;
+pub mod fidelity;
+mod graphics;
+pub(crate) mod legacy;
 mod pin;
+pub(crate) mod writer;
 
-pub trait TryFromExpression<T> {
-    fn try_from_expression(expression: Expression) -> Result<T, anyhow::Error>;
+pub(crate) trait TryFromExpression<T> {
+    fn try_from_expression(expression: &Expression) -> Result<T, anyhow::Error>;
+
+    /// Like `try_from_expression`, but errors point at the start of the expression.
+    fn parse_expression(expression: &Expression) -> Result<T, anyhow::Error> {
+        let offset = expression_offset(expression);
+        Self::try_from_expression(expression).map_err(|error| locate_error(error, offset))
+    }
+}
+
+pub(crate) trait ToSExpr {
+    fn to_sexpr(&self) -> SExpr;
+}
+
+/// Upper bounds for parsing untrusted files, so a malformed or malicious file
+/// fails fast instead of exhausting memory or time.
+#[derive(Deserialize, Copy, Clone, Debug)]
+#[serde(default)]
+pub struct ParseLimits {
+    /// Deepest allowed nesting of parentheses
+    pub max_depth: usize,
+    /// Largest allowed number of tokens in one top level expression, like a
+    /// symbol, so large libraries of ordinary symbols stay readable
+    pub max_tokens: usize,
+}
+
+impl ParseLimits {
+    /// For the user's own libraries, which may be of any size. The nesting
+    /// stays bounded as the parser recurses into it.
+    pub(crate) fn trusted() -> Self {
+        ParseLimits { max_tokens: usize::MAX, ..Self::default() }
+    }
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        // Well above anything KiCad writes: symbol libraries nest less than ten
+        // levels deep and the largest official symbols have a few ten thousand tokens
+        ParseLimits { max_depth: 64, max_tokens: 1_000_000 }
+    }
 }
 
-pub(crate) struct KicadSymbolLib {
+#[derive(Clone, Debug, PartialEq)]
+pub struct KicadSymbolLib {
     version: Option<u64>,
     generator: Option<String>,
     generator_version: Option<f32>,
+    embedded_fonts: Option<bool>,
+    /// The parsed symbols. A lazily read library leaves the symbols of its
+    /// file out, see [`KicadSymbolLib::symbol_names`].
     pub symbols: Vec<KiCadSymbol>,
+    /// Symbols of a lazily read library that are not parsed yet, in file order
+    unparsed: Vec<UnparsedSymbol>,
+    /// The file `unparsed` points into
+    source: String,
 }
 
-type Expression = Vec<Token>;
+/// A symbol of a lazily read library, as its name and the byte range of its
+/// expression in the file.
+#[derive(Clone, Debug, PartialEq)]
+struct UnparsedSymbol {
+    name: String,
+    span: Range<usize>,
+}
+
+/// What `parse_tokens` does with the symbols of a library.
+#[derive(Copy, Clone, PartialEq)]
+enum SymbolParsing {
+    /// Fail on the first symbol that cannot be parsed
+    Strict,
+    /// Leave out symbols that cannot be parsed
+    Recovering,
+    /// Only read the names of the symbols and keep where they are in the file
+    Lazy,
+}
+
+/// A symbol that was left out of a library because it could not be parsed.
+#[derive(Debug, Clone)]
+pub struct SkippedSymbol {
+    pub name: String,
+    pub error: String,
+}
+
+/// A list and everything in it, as a slice of the tokens of the file.
+pub(crate) type Expression<'a> = [Token<'a>];
 
 #[derive(Debug, PartialEq, Clone)]
-pub(crate) enum Token {
-    OpenParen,
+pub(crate) enum Token<'a> {
+    /// Holds the byte offset of the parenthesis, so errors can point at the
+    /// expression it starts
+    OpenParen(usize),
     CloseParen,
-    Word(String)
+    /// Borrowed from the source where possible, owned when escapes had to be
+    /// resolved or the source is a reader
+    Word(Cow<'a, str>),
 }
 
 impl KicadSymbolLib {
-    pub(crate) fn from_file(file: File) -> Result<Self, anyhow::Error> {
-        let mut content = String::new();
+    /// An empty library, used when the target library does not exist yet.
+    pub fn new() -> Self {
+        KicadSymbolLib {
+            version: Some(20231120),
+            generator: Some("kicad_library_manager".to_string()),
+            generator_version: None,
+            embedded_fonts: None,
+            symbols: vec![],
+            unparsed: vec![],
+            source: String::new(),
+        }
+    }
+
+    pub fn from_file(file: File) -> Result<Self, anyhow::Error> {
+        Self::from_file_with_limits(file, &ParseLimits::trusted())
+    }
+
+    /// Parses the library while reading it, so only one symbol at a time is
+    /// held as tokens, not the whole file.
+    pub fn from_file_with_limits(file: File, limits: &ParseLimits) -> Result<Self, anyhow::Error> {
         let mut reader = BufReader::new(file);
-        reader.read_to_string(&mut content)?;
+        match Self::parse_stream(&mut reader, limits) {
+            Ok(library) => Ok(library),
+            Err(error) => {
+                let mut file = reader.into_inner();
+                let mut content = String::new();
+                let source = file.rewind().and_then(|_| file.read_to_string(&mut content)).map(|_| content);
+                Err(describe_streamed_error(error, source, None))
+            }
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self, anyhow::Error> {
+        Self::from_path_with_limits(path, &ParseLimits::trusted())
+    }
+
+    /// Like `from_file_with_limits`, with the path in error messages.
+    pub fn from_path_with_limits(path: &Path, limits: &ParseLimits) -> Result<Self, anyhow::Error> {
+        let file = File::open(path).map_err(|error| anyhow!("{}: {error}", path.display()))?;
+        match Self::parse_stream(BufReader::new(file), limits) {
+            Ok(library) => Ok(library),
+            Err(error) => Err(describe_streamed_error(error, fs::read_to_string(path), Some(path))),
+        }
+    }
+
+    /// Like `from_path_with_limits`, but symbols that cannot be parsed are left
+    /// out and returned with the reason instead of failing the whole library.
+    /// Symbols derived from a skipped symbol are skipped as well.
+    pub fn from_path_recovering(path: &Path, limits: &ParseLimits) -> Result<(Self, Vec<SkippedSymbol>), anyhow::Error> {
+        let content = fs::read_to_string(path).map_err(|error| anyhow!("{}: {error}", path.display()))?;
+        let (mut library, mut skipped) = Self::parse(&content, Some(path), limits, SymbolParsing::Recovering)?;
+
+        let mut skipped_names: Vec<String> = skipped.iter().map(|symbol| symbol.name.clone()).collect();
+        while let Some(index) = library
+            .symbols
+            .iter()
+            .position(|symbol| symbol.extends().is_some_and(|parent| skipped_names.iter().any(|name| name == parent)))
+        {
+            let symbol = library.symbols.remove(index);
+            let parent = symbol.extends().unwrap_or_default();
+            fidelity::record("skipped symbol");
+            skipped.push(SkippedSymbol { name: symbol.name().to_string(), error: format!("extends skipped symbol {parent}") });
+            skipped_names.push(symbol.name().to_string());
+        }
+        Ok((library, skipped))
+    }
+
+    /// Reads only the names of the symbols and keeps the rest of the file as it
+    /// is, for merging into a library without parsing all of it. The symbols
+    /// are parsed by [`KicadSymbolLib::materialize`] and otherwise written back
+    /// unchanged.
+    pub fn from_path_lazy(path: &Path, limits: &ParseLimits) -> Result<Self, anyhow::Error> {
+        let content = fs::read_to_string(path).map_err(|error| anyhow!("{}: {error}", path.display()))?;
+        let (mut library, _) = Self::parse(&content, Some(path), limits, SymbolParsing::Lazy)?;
+        library.source = content;
+        Ok(library)
+    }
+
+    /// Reads a KiCad 5 `.lib` library and the `.dcm` file next to it. Symbols
+    /// that cannot be converted are returned with the reason.
+    pub fn from_legacy_path(path: &Path) -> Result<(Self, Vec<SkippedSymbol>), anyhow::Error> {
+        legacy::read_library(path)
+    }
 
-        // println!("content: {content}");
-        let expression = tokenise(&content)?;
+    fn parse(content: &str, path: Option<&Path>, limits: &ParseLimits, mode: SymbolParsing) -> Result<(Self, Vec<SkippedSymbol>), anyhow::Error> {
+        let describe = |error| describe_error(error, content, path);
+        let mut tokens = Tokenizer::new(SliceSource::new(content.as_bytes()), limits);
+        Self::parse_tokens(&mut tokens, mode, &describe).map_err(describe)
+    }
+
+    fn parse_stream(reader: impl BufRead, limits: &ParseLimits) -> Result<Self, anyhow::Error> {
+        let mut tokens = Tokenizer::new(ReaderSource::new(reader), limits);
+        let (library, _) = Self::parse_tokens(&mut tokens, SymbolParsing::Strict, &|error| error)?;
+        Ok(library)
+    }
 
-        check_expression_validity(&expression, "kicad_symbol_lib".to_string())?;
-        
-        let subexpressions = subdivide_expression(expression[2..expression.len()].to_owned());
+    /// Reads the library one top level expression at a time. `describe` turns
+    /// the errors of skipped symbols into their message.
+    fn parse_tokens<'a>(
+        tokens: &mut Tokenizer<'a, impl ByteSource<'a>>,
+        mode: SymbolParsing,
+        describe: &dyn Fn(anyhow::Error) -> anyhow::Error,
+    ) -> Result<(Self, Vec<SkippedSymbol>), anyhow::Error> {
+        let start = tokens.next_token()?;
+        let offset = match start {
+            Some(Token::OpenParen(offset)) => Some(offset),
+            _ => None,
+        };
+        if offset.is_none() || tokens.next_token()? != Some(Token::Word("kicad_symbol_lib".into())) {
+            return Err(locate_error(anyhow!("Not a KiCad symbol library"), offset));
+        }
 
         let mut generator = None;
         let mut generator_version = None;
         let mut version = None;
+        let mut embedded_fonts = None;
         let mut symbols = Vec::<KiCadSymbol>::new();
+        let mut unparsed = Vec::<UnparsedSymbol>::new();
+        let mut skipped = Vec::<SkippedSymbol>::new();
 
-        for expression in subexpressions {
+        while let Some(expression) = tokens.next_expression()? {
+            let located = |error| locate_error(error, expression_offset(&expression));
             if let Some(Token::Word(property)) = expression.get(1) {
-                match property.as_str(){
+                match property.as_ref() {
                     "version" => {
-                        version = Some(parse_parameter_from_expression::<u64>(&expression, "version".to_string())?);
+                        version = Some(parse_parameter_from_expression::<u64>(&expression, "version".to_string()).map_err(located)?);
                     }
                     "generator" => {
-                        generator = Some(parse_parameter_from_expression::<String>(&expression, "generator".to_string())?);
+                        generator = Some(parse_parameter_from_expression::<String>(&expression, "generator".to_string()).map_err(located)?);
                     }
                     "generator_version" => {
-                        generator_version = Some(parse_parameter_from_expression::<f32>(&expression, "generator_version".to_string())?);
+                        generator_version = Some(parse_parameter_from_expression::<f32>(&expression, "generator_version".to_string()).map_err(located)?);
+                    }
+                    "embedded_fonts" => {
+                        let value = parse_parameter_from_expression::<String>(&expression, "embedded_fonts".to_string()).map_err(located)?;
+                        embedded_fonts = Some(try_parse_string_to_bool(&value).map_err(located)?);
+                    }
+                    "symbol" if mode == SymbolParsing::Lazy => {
+                        let Some(Token::Word(name)) = expression.get(2) else {
+                            return Err(located(anyhow!("Symbol has no name")));
+                        };
+                        let start = expression_offset(&expression).unwrap_or_default();
+                        unparsed.push(UnparsedSymbol { name: name.to_string(), span: start..tokens.offset });
                     }
                     "symbol" => {
-                        let kicad_symbol = KiCadSymbol::try_from_expression(expression.clone())?;
-                        symbols.push(kicad_symbol);
+                        let name = match expression.get(2) {
+                            Some(Token::Word(name)) => name.to_string(),
+                            _ => "<unnamed>".to_string(),
+                        };
+                        match KiCadSymbol::parse_expression(&expression) {
+                            Ok(kicad_symbol) => symbols.push(kicad_symbol),
+                            Err(error) if mode == SymbolParsing::Recovering => {
+                                fidelity::record("skipped symbol");
+                                skipped.push(SkippedSymbol { name, error: describe(error).to_string() });
+                            }
+                            Err(error) => return Err(error),
+                        }
                     }
                     _ => {
-                        bail!("Not a valid KiCad symbol library property: {property}");
+                        return Err(located(anyhow!("Not a valid KiCad symbol library property: {property}")));
                     }
                 }
             }
         }
+        // Anything after the library is ignored, but must still be well formed
+        let mut trailing = false;
+        while tokens.next_token()?.is_some() {
+            trailing = true;
+        }
+        if trailing {
+            fidelity::record("content after the library");
+        }
 
-        Ok(
+        Ok((
             KicadSymbolLib {
                 version,
                 generator,
                 generator_version,
-                symbols
+                embedded_fonts,
+                symbols,
+                unparsed,
+                source: String::new(),
+            },
+            skipped,
+        ))
+    }
+
+    /// The file format version, the date of the format like `20231120`.
+    pub fn version(&self) -> Option<u64> {
+        self.version
+    }
+
+    /// The program that wrote the library, with its version if recorded.
+    pub fn generator(&self) -> Option<String> {
+        let generator = self.generator.as_ref()?;
+        Some(match self.generator_version {
+            Some(version) => format!("{generator} {version}"),
+            None => generator.clone(),
+        })
+    }
+
+    /// The names of all symbols of the library, including the ones a lazily
+    /// read library has not parsed.
+    pub fn symbol_names(&self) -> Vec<&str> {
+        let unparsed = self.unparsed.iter().map(|symbol| symbol.name.as_str());
+        unparsed.chain(self.symbols.iter().map(KiCadSymbol::name)).collect()
+    }
+
+    /// Parses the symbols a lazily read library has left unparsed, so
+    /// `symbols` holds all symbols of the library in file order.
+    pub fn materialize(&mut self) -> Result<(), anyhow::Error> {
+        let mut symbols = Vec::with_capacity(self.unparsed.len() + self.symbols.len());
+        for symbol in &self.unparsed {
+            let mut tokens = Tokenizer::new(SliceSource::new(self.source[symbol.span.clone()].as_bytes()), &ParseLimits::trusted());
+            tokens.offset = symbol.span.start;
+            let parsed = tokens
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|expression| KiCadSymbol::parse_expression(&expression))
+                .map_err(|error| describe_error(error, &self.source, None))?;
+            symbols.push(parsed);
+        }
+        symbols.append(&mut self.symbols);
+        self.symbols = symbols;
+        self.unparsed.clear();
+        self.source.clear();
+        Ok(())
+    }
+
+    pub fn write_to_file(&self, file: File) -> Result<(), anyhow::Error> {
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{}", self.to_sexpr())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Renames a symbol, along with the `extends` of the symbols derived from it.
+    pub(crate) fn rename_symbol(&mut self, old: &str, new: &str) -> Result<(), anyhow::Error> {
+        if new.is_empty() || new.contains([':', '/']) {
+            bail!("{new:?} is not a valid symbol name");
+        }
+        if self.symbols.iter().any(|symbol| symbol.name() == new) {
+            bail!("There already is a symbol {new}");
+        }
+        let Some(symbol) = self.symbols.iter_mut().find(|symbol| symbol.name() == old) else { bail!("There is no symbol {old}") };
+        symbol.rename(new);
+        for symbol in &mut self.symbols {
+            symbol.rename_extended(old, new);
+        }
+        Ok(())
+    }
+
+    /// Writes the library to a file next to `path` first and then replaces
+    /// `path` with it, so a failed write does not leave a truncated library.
+    pub fn write_to_path(&self, path: &Path) -> Result<(), anyhow::Error> {
+        if dry_run::skip_write(path) {
+            return Ok(());
+        }
+        let temporary = path.with_extension("kicad_sym.tmp");
+        if let Err(error) = self.write_to_file(File::create(&temporary)?) {
+            fs::remove_file(&temporary)?;
+            return Err(error);
+        }
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+/// Parses a library in the KiCad format, the inverse of `to_string`.
+impl FromStr for KicadSymbolLib {
+    type Err = anyhow::Error;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        let (library, _) = Self::parse(content, None, &ParseLimits::trusted(), SymbolParsing::Strict)?;
+        Ok(library)
+    }
+}
+
+/// Formats the library the way KiCad writes it.
+impl Display for KicadSymbolLib {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_sexpr())
+    }
+}
+
+impl Default for KicadSymbolLib {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ToSExpr for KicadSymbolLib {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![];
+        if let Some(version) = self.version {
+            children.push(SExpr::list("version", vec![SExpr::atom(version)]));
+        }
+        if let Some(generator) = &self.generator {
+            children.push(SExpr::list("generator", vec![SExpr::quoted(generator)]));
+        }
+        if let Some(generator_version) = self.generator_version {
+            children.push(SExpr::list("generator_version", vec![SExpr::quoted(format!("{generator_version:?}"))]));
+        }
+        children.extend(self.unparsed.iter().map(|symbol| SExpr::Raw(self.source[symbol.span.clone()].to_string())));
+        children.extend(self.symbols.iter().map(ToSExpr::to_sexpr));
+        if let Some(embedded_fonts) = self.embedded_fonts {
+            children.push(SExpr::list("embedded_fonts", vec![SExpr::atom(bool_to_kicad_string(embedded_fonts))]));
+        }
+        SExpr::list("kicad_symbol_lib", children)
+    }
+}
+
+pub(crate) fn tokenise(input: &str) -> Result<Vec<Token<'_>>, anyhow::Error> {
+    tokenise_with_limits(input, &ParseLimits::default())
+}
+
+pub(crate) fn tokenise_with_limits<'a>(input: &'a str, limits: &ParseLimits) -> Result<Vec<Token<'a>>, anyhow::Error> {
+    Tokenizer::new(SliceSource::new(input.as_bytes()), limits).collect()
+}
+
+/// Where the tokenizer reads from. Words are captured between `begin` and
+/// `take`, which either borrows them from the source or hands out a copy.
+pub(crate) trait ByteSource<'a> {
+    fn peek(&mut self) -> Result<Option<u8>, anyhow::Error>;
+    /// Moves past the byte returned by `peek`.
+    fn advance(&mut self);
+    fn begin(&mut self);
+    fn take(&mut self) -> Cow<'a, [u8]>;
+}
+
+/// A source held in memory, which words borrow from.
+pub(crate) struct SliceSource<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    start: usize,
+}
+
+impl<'a> SliceSource<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        SliceSource { bytes, position: 0, start: 0 }
+    }
+}
+
+impl<'a> ByteSource<'a> for SliceSource<'a> {
+    fn peek(&mut self) -> Result<Option<u8>, anyhow::Error> {
+        Ok(self.bytes.get(self.position).copied())
+    }
+
+    fn advance(&mut self) {
+        self.position += 1;
+    }
+
+    fn begin(&mut self) {
+        self.start = self.position;
+    }
+
+    fn take(&mut self) -> Cow<'a, [u8]> {
+        Cow::Borrowed(&self.bytes[self.start..self.position])
+    }
+}
+
+/// A source read as the tokens are needed, so large files never have to be
+/// in memory as a whole.
+pub(crate) struct ReaderSource<R> {
+    reader: R,
+    word: Vec<u8>,
+    capturing: bool,
+}
+
+impl<R: BufRead> ReaderSource<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        ReaderSource { reader, word: vec![], capturing: false }
+    }
+}
+
+impl<R: BufRead> ByteSource<'static> for ReaderSource<R> {
+    fn peek(&mut self) -> Result<Option<u8>, anyhow::Error> {
+        Ok(self.reader.fill_buf()?.first().copied())
+    }
+
+    fn advance(&mut self) {
+        if self.capturing {
+            if let Ok([byte, ..]) = self.reader.fill_buf() {
+                self.word.push(*byte);
             }
-        )
+        }
+        self.reader.consume(1);
+    }
+
+    fn begin(&mut self) {
+        self.word.clear();
+        self.capturing = true;
+    }
+
+    fn take(&mut self) -> Cow<'static, [u8]> {
+        self.capturing = false;
+        Cow::Owned(std::mem::take(&mut self.word))
     }
 }
 
-fn tokenise(input: &str) -> Result<Vec<Token>, anyhow::Error> {
-    let mut tokens = Vec::<Token>::new();
-    let mut chars = input.chars().peekable();
+/// Resolves the escapes KiCad writes in quoted strings: quotes, backslashes
+/// and line breaks. Other backslashes are kept as they are.
+pub(crate) fn unescape(raw: &[u8]) -> Vec<u8> {
+    let mut bytes = raw.iter().copied();
+    let mut word = Vec::with_capacity(raw.len());
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            word.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'n') => word.push(b'\n'),
+            Some(b'r') => word.push(b'\r'),
+            Some(b't') => word.push(b'\t'),
+            Some(escaped @ (b'"' | b'\\')) => word.push(escaped),
+            Some(other) => word.extend([b'\\', other]),
+            None => word.push(b'\\'),
+        }
+    }
+    word
+}
 
-    while let Some(&c) = chars.peek() {
-        match c {
-            '(' => {
-                tokens.push(Token::OpenParen);
-                chars.next();
-            },
-            ')' => {
-                tokens.push(Token::CloseParen);
-                chars.next();
-            },
-            ' ' | '\t' | '\n' | '\r' => { chars.next(); },
-            '"' => {
-                chars.next();
-                let mut word = String::new();
-
-                while let Some(&c) = chars.peek() {
-                    chars.next();
-                    if c == '"' {
-                        break
+/// Splits a source into tokens as they are needed. Token offsets count bytes
+/// from the start of the source.
+pub(crate) struct Tokenizer<'a, S> {
+    source: S,
+    limits: ParseLimits,
+    offset: usize,
+    /// Tokens of the current top level expression
+    count: usize,
+    /// Offsets of the parentheses that are not closed yet
+    open: Vec<usize>,
+    failed: bool,
+    words: PhantomData<&'a str>,
+}
+
+impl<'a, S: ByteSource<'a>> Tokenizer<'a, S> {
+    pub(crate) fn new(source: S, limits: &ParseLimits) -> Self {
+        Tokenizer { source, limits: *limits, offset: 0, count: 0, open: vec![], failed: false, words: PhantomData }
+    }
+
+    fn advance(&mut self) {
+        self.source.advance();
+        self.offset += 1;
+    }
+
+    pub(crate) fn next_token(&mut self) -> Result<Option<Token<'a>>, anyhow::Error> {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.source.peek()? {
+            self.advance();
+        }
+        let offset = self.offset;
+        let Some(byte) = self.source.peek()? else {
+            if let Some(&offset) = self.open.last() {
+                return Err(locate_error(anyhow!("Unbalanced parentheses: parenthesis is never closed"), Some(offset)));
+            }
+            return Ok(None);
+        };
+        if self.open.len() <= 1 {
+            self.count = 0;
+        }
+        if self.count >= self.limits.max_tokens {
+            return Err(locate_error(anyhow!("KiCad expression has more than {} tokens", self.limits.max_tokens), Some(offset)));
+        }
+        self.count += 1;
+
+        match byte {
+            b'(' => {
+                self.advance();
+                self.open.push(offset);
+                if self.open.len() > self.limits.max_depth {
+                    return Err(locate_error(anyhow!("KiCad file is nested deeper than {} levels", self.limits.max_depth), Some(offset)));
+                }
+                Ok(Some(Token::OpenParen(offset)))
+            }
+            b')' => {
+                self.advance();
+                if self.open.pop().is_none() {
+                    return Err(locate_error(anyhow!("Unbalanced parentheses: closing parenthesis without opening one"), Some(offset)));
+                }
+                Ok(Some(Token::CloseParen))
+            }
+            b'"' => {
+                self.advance();
+                self.source.begin();
+                let mut escaped = false;
+                loop {
+                    match self.source.peek()? {
+                        Some(b'"') => break,
+                        Some(b'\\') => {
+                            escaped = true;
+                            self.advance();
+                            if self.source.peek()?.is_some() {
+                                self.advance();
+                            }
+                        }
+                        Some(_) => self.advance(),
+                        None => return Err(locate_error(anyhow!("Unterminated string"), Some(offset))),
                     }
-                    word.push(c);
                 }
-                tokens.push(Token::Word(word));
-            },
+                let raw = self.source.take();
+                self.advance();
+                let word = if escaped { Cow::Owned(unescape(&raw)) } else { raw };
+                Ok(Some(Token::Word(Self::utf8(word, offset)?)))
+            }
             _ => {
-                let mut word = String::new();
-
+                self.source.begin();
                 // Read until whitespace or special character
-                while let Some(&c) = chars.peek() {
-                    if c == ' ' || c == '\t' || c == '\n' || c == '\r' || c == '(' || c == ')' {
+                while let Some(byte) = self.source.peek()? {
+                    if matches!(byte, b' ' | b'\t' | b'\n' | b'\r' | b'(' | b')') {
                         break;
                     }
-                    word.push(c);
-                    chars.next();
+                    self.advance();
                 }
+                let word = self.source.take();
+                Ok(Some(Token::Word(Self::utf8(word, offset)?)))
+            }
+        }
+    }
+
+    fn utf8(word: Cow<'a, [u8]>, offset: usize) -> Result<Cow<'a, str>, anyhow::Error> {
+        let invalid = |_| locate_error(anyhow!("KiCad file is not valid UTF-8"), Some(offset));
+        match word {
+            Cow::Borrowed(bytes) => Ok(Cow::Borrowed(std::str::from_utf8(bytes).map_err(invalid)?)),
+            Cow::Owned(bytes) => Ok(Cow::Owned(String::from_utf8(bytes).map_err(|error| invalid(error.utf8_error()))?)),
+        }
+    }
 
-                tokens.push(Token::Word(word));
+    /// The next list inside the current one, or `None` once the current list
+    /// is closed. Bare words in between are skipped.
+    pub(crate) fn next_expression(&mut self) -> Result<Option<Vec<Token<'a>>>, anyhow::Error> {
+        loop {
+            match self.next_token()? {
+                Some(Token::OpenParen(offset)) => {
+                    let depth = self.open.len();
+                    let mut expression = vec![Token::OpenParen(offset)];
+                    while self.open.len() >= depth {
+                        let Some(token) = self.next_token()? else { break };
+                        expression.push(token);
+                    }
+                    return Ok(Some(expression));
+                }
+                Some(Token::CloseParen) | None => return Ok(None),
+                Some(Token::Word(_)) => fidelity::record("bare word in a list"),
             }
         }
     }
+}
 
-    Ok(tokens)
+impl<'a, S: ByteSource<'a>> Iterator for Tokenizer<'a, S> {
+    type Item = Result<Token<'a>, anyhow::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let token = self.next_token().transpose();
+        self.failed = matches!(token, Some(Err(_)));
+        token
+    }
 }
 
-pub(crate) fn subdivide_expression(expression: Expression) -> Vec<Expression> {
-    let mut tokens_peekable = expression.iter().peekable();
-    let mut symbols_vec = Vec::<Vec<Token>>::new();
-    let mut current_symbol = Vec::<Token>::new();
+/// Splits the contents of a list into its lists, without copying tokens.
+/// Bare words in front of a list are part of the list's slice.
+pub(crate) fn subdivide_expression<'t, 'a>(expression: &'t Expression<'a>) -> Vec<&'t Expression<'a>> {
+    let mut expressions = vec![];
+    let mut start = 0;
     let mut open_count = 0;
-    
-    while let Some(token) = tokens_peekable.peek() {
-        let token_clone = token.clone();
+
+    for (index, token) in expression.iter().enumerate() {
         match token {
-            Token::OpenParen => {
-                current_symbol.push(token_clone.clone());
-                open_count += 1;
-                tokens_peekable.next();
-            }
+            Token::OpenParen(_) => open_count += 1,
             Token::CloseParen => {
-                current_symbol.push(token_clone.clone());
                 if open_count == 1 {
-                    symbols_vec.push(current_symbol.clone());
-                    current_symbol.clear();
+                    expressions.push(&expression[start..=index]);
+                    start = index + 1;
                 }
                 open_count -= 1;
-                tokens_peekable.next();
-            }
-            Token::Word(_) => {
-                current_symbol.push(token_clone.clone());
-                tokens_peekable.next();
             }
+            Token::Word(_) => {}
+        }
+    }
+    expressions
+}
+
+/// The bare words directly inside `expression`, like the `hide` flag KiCad 6
+/// writes in `(pin_names (offset 0) hide)`. Words of nested lists are skipped.
+pub(crate) fn top_level_words<'t>(expression: &'t Expression) -> Vec<&'t str> {
+    let mut depth = 0;
+    let mut words = vec![];
+    for token in expression {
+        match token {
+            Token::OpenParen(_) => depth += 1,
+            Token::CloseParen => depth -= 1,
+            Token::Word(word) if depth == 1 => words.push(word.as_ref()),
+            Token::Word(_) => {}
         }
     }
-    
-    symbols_vec
-    
+    words
+}
+
+/// A parse error together with the byte offset in the source it was found at.
+#[derive(Debug)]
+pub(crate) struct LocatedError {
+    offset: usize,
+    error: anyhow::Error,
+}
+
+impl Display for LocatedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#} (at byte {})", self.error, self.offset)
+    }
+}
+
+impl std::error::Error for LocatedError {}
+
+pub(crate) fn expression_offset(expression: &[Token]) -> Option<usize> {
+    match expression.first() {
+        Some(Token::OpenParen(offset)) => Some(*offset),
+        _ => None,
+    }
+}
+
+/// Attaches the source offset to an error, unless the error was already
+/// located in a nested expression.
+pub(crate) fn locate_error(error: anyhow::Error, offset: Option<usize>) -> anyhow::Error {
+    match offset {
+        Some(offset) if !error.is::<LocatedError>() => anyhow::Error::new(LocatedError { offset, error }),
+        _ => error,
+    }
+}
+
+/// Like `describe_error` for libraries that were parsed while reading, which
+/// read the source again only when there is an error to show.
+fn describe_streamed_error(error: anyhow::Error, source: std::io::Result<String>, path: Option<&Path>) -> anyhow::Error {
+    match (source, path) {
+        (Ok(source), _) => describe_error(error, &source, path),
+        (Err(_), Some(path)) => anyhow!("{}: {error:#}", path.display()),
+        (Err(_), None) => error,
+    }
+}
+
+/// Turns a located error into `path:line:column: message` followed by the
+/// offending line of `source`.
+pub(crate) fn describe_error(error: anyhow::Error, source: &str, path: Option<&Path>) -> anyhow::Error {
+    let located = match error.downcast::<LocatedError>() {
+        Ok(located) => located,
+        Err(error) => return match path {
+            Some(path) => anyhow!("{}: {error:#}", path.display()),
+            None => error,
+        },
+    };
+    let offset = located.offset.min(source.len());
+    let line_start = source[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+    let line_end = source[offset..].find('\n').map_or(source.len(), |newline| offset + newline);
+    let line = source[..offset].matches('\n').count() + 1;
+    let column = source[line_start..offset].chars().count() + 1;
+    let text = source[line_start..line_end].trim_end_matches('\r');
+
+    let location = match path {
+        Some(path) => format!("{}:{line}:{column}", path.display()),
+        None => format!("line {line}, column {column}"),
+    };
+    let gutter = " ".repeat(line.to_string().len());
+    // Keep tabs so the marker lines up with the source line
+    let marker: String = text.chars().take(column - 1).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    anyhow!("{location}: {:#}\n{gutter} |\n{line} | {text}\n{gutter} | {marker}^", located.error)
 }
 
 fn parse_parameter_from_expression<T>(expression: &[Token], parameter: String) -> Result<T, anyhow::Error>
@@ -169,20 +796,60 @@ where
     if expression.len() < 4 {
         bail!("Version expression does not contain four entries");
     }
-    if expression[0] != Token::OpenParen {
+    if !matches!(expression[0], Token::OpenParen(_)) {
         bail!("Version expression does not start with opening parentheses");
     }
-    if expression[1] != Token::Word(parameter.clone()) {
+    if expression[1] != Token::Word(parameter.as_str().into()) {
         bail!("Expression does not contain '{}'", parameter);
     }
     match &expression[2] {
-        Token::OpenParen => bail!("No version found"),
+        Token::OpenParen(_) => bail!("No version found"),
         Token::CloseParen => bail!("No version found"),
         Token::Word(value) => value.parse::<T>().map_err(|err| anyhow!("Could not parse value: {err}"))
     }
 }
 
-fn check_token_vec_healthy(tokens: Vec<Token>) -> bool {
-    tokens.iter().filter(|token| **token == Token::OpenParen).count() == tokens.iter().filter(|token| **token == Token::CloseParen).count()
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbols::property::KiCadPropertyType;
 
+    #[test]
+    fn unescape_keeps_unknown_escapes() {
+        assert_eq!(unescape(br#"C:\Users\a \"b\" \\ \n"#), b"C:\\Users\\a \"b\" \\ \n");
+    }
+
+    #[test]
+    fn escaped_strings_round_trip() {
+        let library: KicadSymbolLib = r#"(kicad_symbol_lib (version 20231120) (generator "test")
+  (symbol "R"
+    (property "Datasheet" "C:\docs\spec.pdf" (at 0 0 0) (effects (font (size 1.27 1.27))))
+    (property "Description" "A \"quoted\" \\ line\nbreak" (at 0 0 0) (effects (font (size 1.27 1.27))))
+  )
+)"#
+        .parse()
+        .unwrap();
+        let symbol = &library.symbols[0];
+        assert_eq!(symbol.property_value(&KiCadPropertyType::Datasheet), Some(r"C:\docs\spec.pdf"));
+        assert_eq!(symbol.property_value(&KiCadPropertyType::Description), Some("A \"quoted\" \\ line\nbreak"));
+        let reparsed: KicadSymbolLib = library.to_string().parse().unwrap();
+        assert_eq!(reparsed, library);
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let limits = ParseLimits { max_depth: 3, max_tokens: 100 };
+        assert!(tokenise_with_limits("(a (b (c)))", &limits).is_ok());
+        let error = tokenise_with_limits("(a (b (c (d))))", &limits).unwrap_err();
+        assert!(error.to_string().contains("nested deeper than 3 levels"), "{error}");
+    }
+
+    #[test]
+    fn tokens_are_limited_per_expression() {
+        let limits = ParseLimits { max_depth: 8, max_tokens: 8 };
+        let symbols = "(symbol (a b))".repeat(100);
+        assert!(tokenise_with_limits(&format!("(lib {symbols})"), &limits).is_ok());
+        let error = tokenise_with_limits("(lib (symbol a b c d e f g))", &limits).unwrap_err();
+        assert!(error.to_string().contains("more than 8 tokens"), "{error}");
+    }
+}
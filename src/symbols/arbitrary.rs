@@ -0,0 +1,65 @@
+//! Proptest strategies for random but valid symbol libraries, enabled with the
+//! `proptest` feature. Generated libraries are written and read back without
+//! changes, so they can be used to hunt round-trip bugs:
+//!
+//! ```
+//! use kicad_library_manager::symbols::KicadSymbolLib;
+//! use proptest::prelude::*;
+//! use proptest::test_runner::TestRunner;
+//!
+//! TestRunner::default()
+//!     .run(&any::<KicadSymbolLib>(), |library| {
+//!         let parsed: KicadSymbolLib = library.to_string().parse().unwrap();
+//!         prop_assert_eq!(parsed, library);
+//!         Ok(())
+//!     })
+//!     .unwrap();
+//! ```
+
+use crate::symbols::property::KiCadSymbol;
+use crate::symbols::KicadSymbolLib;
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+
+/// Coordinates on a 0.01 grid, which are written and parsed back exactly.
+pub(crate) fn coordinate() -> impl Strategy<Value = f32> {
+    (-100_000..100_000).prop_map(|value| value as f32 / 100.0)
+}
+
+/// Any text, with extra weight on the characters the writer has to escape.
+pub(crate) fn text() -> impl Strategy<Value = String> {
+    prop_oneof![".{0,16}", "[a-z ()\"\\\\\n\r\t]{0,8}"]
+}
+
+impl Arbitrary for KicadSymbolLib {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (option::of(any::<u64>()), option::of(text()), option::of(coordinate()), option::of(any::<bool>()), vec(any::<KiCadSymbol>(), 0..4))
+            .prop_map(|(version, generator, generator_version, embedded_fonts, symbols)| KicadSymbolLib {
+                version,
+                generator,
+                generator_version,
+                embedded_fonts,
+                symbols,
+                unparsed: vec![],
+                source: String::new(),
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn libraries_round_trip(library in any::<KicadSymbolLib>()) {
+            let parsed: KicadSymbolLib = library.to_string().parse().unwrap();
+            prop_assert_eq!(parsed, library);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Counts what parsing and conversion could not carry over, e.g. pin
+//! alternates or records of legacy libraries the converter does not know, so
+//! a run can report which information was not preserved.
+//!
+//! Parsing functions are called deep inside each other without a context, so
+//! the counts are collected per thread while [`collect`] runs.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+thread_local! {
+    static DROPPED: RefCell<BTreeMap<String, usize>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// How often each kind of item was left out, by a short description like
+/// `pin alternate` or `legacy record Y`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FidelityReport {
+    pub dropped: BTreeMap<String, usize>,
+}
+
+impl FidelityReport {
+    pub fn is_empty(&self) -> bool {
+        self.dropped.is_empty()
+    }
+
+    pub fn merge(&mut self, other: FidelityReport) {
+        for (item, count) in other.dropped {
+            *self.dropped.entry(item).or_default() += count;
+        }
+    }
+}
+
+impl Display for FidelityReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let total: usize = self.dropped.values().sum();
+        write!(f, "{total} item(s) could not be represented and were left out")?;
+        for (item, count) in &self.dropped {
+            write!(f, "\n    {item}: {count}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Notes that an item described by `item` was left out.
+pub(crate) fn record(item: impl Into<String>) {
+    DROPPED.with(|dropped| *dropped.borrow_mut().entry(item.into()).or_default() += 1);
+}
+
+/// Runs `f` without counting what it leaves out, for parsing that is
+/// repeated later.
+pub(crate) fn uncounted<T>(f: impl FnOnce() -> T) -> T {
+    let outer = DROPPED.with(|dropped| dropped.take());
+    let result = f();
+    DROPPED.with(|dropped| dropped.replace(outer));
+    result
+}
+
+/// Runs `f` and returns what it left out. Items are also counted for an
+/// enclosing `collect`.
+pub fn collect<T>(f: impl FnOnce() -> T) -> (T, FidelityReport) {
+    let outer = DROPPED.with(|dropped| dropped.take());
+    let result = f();
+    let report = FidelityReport { dropped: DROPPED.with(|dropped| dropped.replace(outer)) };
+    DROPPED.with(|dropped| {
+        let mut dropped = dropped.borrow_mut();
+        for (item, count) in &report.dropped {
+            *dropped.entry(item.clone()).or_default() += count;
+        }
+    });
+    (result, report)
+}
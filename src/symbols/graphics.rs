@@ -0,0 +1,186 @@
+use crate::symbols::property::{check_expression_validity, KiCad2DPoint, KiCadFill, KiCadStroke};
+use crate::symbols::writer::SExpr;
+use crate::symbols::Token::Word;
+use crate::symbols::{expression_offset, locate_error, subdivide_expression, Expression, ToSExpr, TryFromExpression};
+use anyhow::{anyhow, bail, Error};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct KiCadRectangle {
+    start: KiCad2DPoint,
+    end: KiCad2DPoint,
+    stroke: Option<KiCadStroke>,
+    fill: Option<KiCadFill>,
+}
+
+impl TryFromExpression<KiCadRectangle> for KiCadRectangle {
+    fn try_from_expression(expression: &Expression) -> Result<KiCadRectangle, Error> {
+        check_expression_validity(expression, "rectangle".to_string())?;
+
+        let subexpressions = subdivide_expression(&expression[2..]);
+
+        let mut start = None;
+        let mut end = None;
+        let mut stroke = None;
+        let mut fill = None;
+
+        for expression in subexpressions {
+            if let Some(Word(property)) = expression.get(1) {
+                match property.as_ref() {
+                    "start" => start = Some(KiCad2DPoint::try_from_named_expression(expression, "start")?),
+                    "end" => end = Some(KiCad2DPoint::try_from_named_expression(expression, "end")?),
+                    "stroke" => stroke = Some(KiCadStroke::parse_expression(expression)?),
+                    "fill" => fill = Some(KiCadFill::parse_expression(expression)?),
+                    _ => return Err(locate_error(anyhow!("Not a valid KiCad rectangle property: {property}"), expression_offset(expression))),
+                }
+            }
+        }
+
+        Ok(Self {
+            start: start.ok_or(anyhow!("Rectangle does not contain start"))?,
+            end: end.ok_or(anyhow!("Rectangle does not contain end"))?,
+            stroke,
+            fill,
+        })
+    }
+}
+
+impl ToSExpr for KiCadRectangle {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![self.start.to_named_sexpr("start"), self.end.to_named_sexpr("end")];
+        children.extend(self.stroke.iter().map(ToSExpr::to_sexpr));
+        children.extend(self.fill.iter().map(ToSExpr::to_sexpr));
+        SExpr::list("rectangle", children)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct KiCadCircle {
+    center: KiCad2DPoint,
+    radius: f32,
+    stroke: Option<KiCadStroke>,
+    fill: Option<KiCadFill>,
+}
+
+impl TryFromExpression<KiCadCircle> for KiCadCircle {
+    fn try_from_expression(expression: &Expression) -> Result<KiCadCircle, Error> {
+        check_expression_validity(expression, "circle".to_string())?;
+
+        let subexpressions = subdivide_expression(&expression[2..]);
+
+        let mut center = None;
+        let mut radius = None;
+        let mut stroke = None;
+        let mut fill = None;
+
+        for expression in subexpressions {
+            if let Some(Word(property)) = expression.get(1) {
+                match property.as_ref() {
+                    "center" => center = Some(KiCad2DPoint::try_from_named_expression(expression, "center")?),
+                    "radius" => {
+                        let Some(Word(radius_value)) = expression.get(2) else { bail!("Circle radius does not contain value") };
+                        radius = Some(radius_value.parse::<f32>()?);
+                    }
+                    "stroke" => stroke = Some(KiCadStroke::parse_expression(expression)?),
+                    "fill" => fill = Some(KiCadFill::parse_expression(expression)?),
+                    _ => return Err(locate_error(anyhow!("Not a valid KiCad circle property: {property}"), expression_offset(expression))),
+                }
+            }
+        }
+
+        Ok(Self {
+            center: center.ok_or(anyhow!("Circle does not contain center"))?,
+            radius: radius.ok_or(anyhow!("Circle does not contain radius"))?,
+            stroke,
+            fill,
+        })
+    }
+}
+
+impl ToSExpr for KiCadCircle {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![self.center.to_named_sexpr("center"), SExpr::list("radius", vec![SExpr::atom(self.radius)])];
+        children.extend(self.stroke.iter().map(ToSExpr::to_sexpr));
+        children.extend(self.fill.iter().map(ToSExpr::to_sexpr));
+        SExpr::list("circle", children)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct KiCadArc {
+    start: KiCad2DPoint,
+    mid: KiCad2DPoint,
+    end: KiCad2DPoint,
+    stroke: Option<KiCadStroke>,
+    fill: Option<KiCadFill>,
+}
+
+impl TryFromExpression<KiCadArc> for KiCadArc {
+    fn try_from_expression(expression: &Expression) -> Result<KiCadArc, Error> {
+        check_expression_validity(expression, "arc".to_string())?;
+
+        let subexpressions = subdivide_expression(&expression[2..]);
+
+        let mut start = None;
+        let mut mid = None;
+        let mut end = None;
+        let mut stroke = None;
+        let mut fill = None;
+
+        for expression in subexpressions {
+            if let Some(Word(property)) = expression.get(1) {
+                match property.as_ref() {
+                    "start" => start = Some(KiCad2DPoint::try_from_named_expression(expression, "start")?),
+                    "mid" => mid = Some(KiCad2DPoint::try_from_named_expression(expression, "mid")?),
+                    "end" => end = Some(KiCad2DPoint::try_from_named_expression(expression, "end")?),
+                    "stroke" => stroke = Some(KiCadStroke::parse_expression(expression)?),
+                    "fill" => fill = Some(KiCadFill::parse_expression(expression)?),
+                    _ => return Err(locate_error(anyhow!("Not a valid KiCad arc property: {property}"), expression_offset(expression))),
+                }
+            }
+        }
+
+        Ok(Self {
+            start: start.ok_or(anyhow!("Arc does not contain start"))?,
+            mid: mid.ok_or(anyhow!("Arc does not contain mid"))?,
+            end: end.ok_or(anyhow!("Arc does not contain end"))?,
+            stroke,
+            fill,
+        })
+    }
+}
+
+impl ToSExpr for KiCadArc {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![
+            self.start.to_named_sexpr("start"),
+            self.mid.to_named_sexpr("mid"),
+            self.end.to_named_sexpr("end"),
+        ];
+        children.extend(self.stroke.iter().map(ToSExpr::to_sexpr));
+        children.extend(self.fill.iter().map(ToSExpr::to_sexpr));
+        SExpr::list("arc", children)
+    }
+}
+
+#[cfg(feature = "proptest")]
+pub(crate) mod strategy {
+    use super::*;
+    use crate::symbols::arbitrary::coordinate;
+    use crate::symbols::property::strategy::{fill, point, stroke};
+    use proptest::option;
+    use proptest::prelude::*;
+
+    pub(crate) fn rectangle() -> impl Strategy<Value = KiCadRectangle> {
+        (point(), point(), option::of(stroke()), option::of(fill())).prop_map(|(start, end, stroke, fill)| KiCadRectangle { start, end, stroke, fill })
+    }
+
+    pub(crate) fn circle() -> impl Strategy<Value = KiCadCircle> {
+        (point(), coordinate(), option::of(stroke()), option::of(fill()))
+            .prop_map(|(center, radius, stroke, fill)| KiCadCircle { center, radius, stroke, fill })
+    }
+
+    pub(crate) fn arc() -> impl Strategy<Value = KiCadArc> {
+        (point(), point(), point(), option::of(stroke()), option::of(fill()))
+            .prop_map(|(start, mid, end, stroke, fill)| KiCadArc { start, mid, end, stroke, fill })
+    }
+}
@@ -0,0 +1,512 @@
+//! Reader for the KiCad 5 `EESchema-LIBRARY` symbol libraries (`.lib`) and
+//! their documentation files (`.dcm`).
+//!
+//! Every legacy symbol is translated into the S-expression KiCad 6 would write
+//! for it and then read with the regular symbol parser, so legacy symbols end
+//! up as ordinary [`KiCadSymbol`]s.
+
+use crate::symbols::property::KiCadSymbol;
+use crate::symbols::writer::{bool_to_kicad_string, SExpr};
+use crate::symbols::{describe_error, fidelity, tokenise, KicadSymbolLib, SkippedSymbol, TryFromExpression};
+use anyhow::{anyhow, bail};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+const LIBRARY_HEADER: &str = "EESchema-LIBRARY";
+const DOC_HEADER: &str = "EESchema-DOCLIB";
+/// Legacy files store lengths in mils
+const MM_PER_MIL: f32 = 0.0254;
+
+/// Whether `path` starts with the legacy library header. Vendors also ship
+/// SPICE models as `.lib` files, which have to be left alone.
+pub(crate) fn is_legacy_library(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else { return false };
+    let mut first_line = String::new();
+    BufReader::new(file).read_line(&mut first_line).is_ok() && first_line.starts_with(LIBRARY_HEADER)
+}
+
+#[derive(Default)]
+struct SymbolDoc {
+    description: Option<String>,
+    keywords: Option<String>,
+    datasheet: Option<String>,
+}
+
+/// Reads the `.lib` file at `path` together with the `.dcm` file next to it, if
+/// there is one. Symbols that cannot be converted are returned with the reason.
+pub(crate) fn read_library(path: &Path) -> Result<(KicadSymbolLib, Vec<SkippedSymbol>), anyhow::Error> {
+    let content = fs::read_to_string(path).map_err(|error| anyhow!("{}: {error}", path.display()))?;
+    if !content.starts_with(LIBRARY_HEADER) {
+        bail!("{} is not a KiCad legacy symbol library", path.display());
+    }
+    let docs = match fs::read_to_string(path.with_extension("dcm")) {
+        Ok(dcm) if dcm.starts_with(DOC_HEADER) => parse_docs(&dcm),
+        _ => HashMap::new(),
+    };
+
+    let mut library = KicadSymbolLib::new();
+    let mut skipped = vec![];
+    let mut lines = content.lines().enumerate();
+    while let Some((number, line)) = lines.next() {
+        if !line.starts_with("DEF ") {
+            continue;
+        }
+        let mut definition = vec![(number + 1, line)];
+        for (number, line) in lines.by_ref() {
+            definition.push((number + 1, line));
+            if line.trim() == "ENDDEF" {
+                break;
+            }
+        }
+        let name = split_fields(line).get(1).map_or("<unnamed>".to_string(), |name| name.trim_start_matches('~').to_string());
+        match convert_symbol(&definition, &docs) {
+            Ok(symbols) => library.symbols.extend(symbols),
+            Err(error) => {
+                fidelity::record("skipped symbol");
+                skipped.push(SkippedSymbol { name, error: format!("{}:{error:#}", path.display()) });
+            }
+        }
+    }
+    Ok((library, skipped))
+}
+
+/// The `$CMP` entries of a `.dcm` file by symbol name.
+fn parse_docs(content: &str) -> HashMap<String, SymbolDoc> {
+    let mut docs = HashMap::new();
+    let mut current: Option<(String, SymbolDoc)> = None;
+    for line in content.lines() {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        let value = value.trim();
+        match (key, &mut current) {
+            ("$CMP", _) => current = Some((value.to_string(), SymbolDoc::default())),
+            ("$ENDCMP", _) => {
+                if let Some((name, doc)) = current.take() {
+                    docs.insert(name, doc);
+                }
+            }
+            ("D", Some((_, doc))) => doc.description = Some(value.to_string()),
+            ("K", Some((_, doc))) => doc.keywords = Some(value.to_string()),
+            ("F", Some((_, doc))) => doc.datasheet = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    docs
+}
+
+/// Splits a legacy line into its fields. Quoted fields may contain spaces and
+/// `\"` escapes, the quotes are removed.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut field = String::new();
+        if c == '"' {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => field.extend(chars.next()),
+                    _ => field.push(c),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                field.push(c);
+                chars.next();
+            }
+        }
+        fields.push(field);
+    }
+    fields
+}
+
+struct Fields<'a> {
+    kind: &'a str,
+    fields: Vec<String>,
+}
+
+impl Fields<'_> {
+    fn text(&self, index: usize) -> Result<&str, anyhow::Error> {
+        self.fields.get(index).map(String::as_str).ok_or_else(|| anyhow!("{} record has no field {index}", self.kind))
+    }
+
+    fn text_or<'a>(&'a self, index: usize, default: &'a str) -> &'a str {
+        self.fields.get(index).map_or(default, String::as_str)
+    }
+
+    fn number(&self, index: usize) -> Result<f32, anyhow::Error> {
+        let text = self.text(index)?;
+        text.parse().map_err(|_| anyhow!("{} record field {index} is not a number: {text}", self.kind))
+    }
+
+    /// A length or coordinate converted from mils to mm
+    fn length(&self, index: usize) -> Result<f32, anyhow::Error> {
+        Ok(mils_to_mm(self.number(index)?))
+    }
+
+    fn unit(&self, index: usize) -> Result<(u32, u32), anyhow::Error> {
+        Ok((self.number(index)? as u32, self.number(index + 1)? as u32))
+    }
+}
+
+pub(crate) fn mils_to_mm(mils: f32) -> f32 {
+    // Round away the float noise of the conversion, KiCad writes at most 4
+    // decimals. Adding zero turns a rounded -0 into 0.
+    (mils * MM_PER_MIL * 10_000.0).round() / 10_000.0 + 0.0
+}
+
+pub(crate) fn point(name: &str, x: f32, y: f32) -> SExpr {
+    SExpr::list(name, vec![SExpr::atom(x), SExpr::atom(y)])
+}
+
+pub(crate) fn stroke(width: f32) -> SExpr {
+    SExpr::list("stroke", vec![SExpr::list("width", vec![SExpr::atom(width)]), SExpr::list("type", vec![SExpr::atom("default")])])
+}
+
+fn fill(fill: &str) -> SExpr {
+    let fill_type = match fill {
+        "F" => "outline",
+        "f" => "background",
+        _ => "none",
+    };
+    SExpr::list("fill", vec![SExpr::list("type", vec![SExpr::atom(fill_type)])])
+}
+
+pub(crate) fn effects(size: f32, italic: bool, bold: bool, justify: Vec<&str>, hide: bool) -> SExpr {
+    let mut font = vec![SExpr::list("size", vec![SExpr::atom(size), SExpr::atom(size)])];
+    if italic {
+        font.push(SExpr::list("italic", vec![SExpr::atom(bool_to_kicad_string(true))]));
+    }
+    if bold {
+        font.push(SExpr::list("bold", vec![SExpr::atom(bool_to_kicad_string(true))]));
+    }
+    let mut children = vec![SExpr::list("font", font)];
+    if !justify.is_empty() {
+        children.push(SExpr::list("justify", justify.into_iter().map(SExpr::atom).collect()));
+    }
+    if hide {
+        children.push(SExpr::list("hide", vec![SExpr::atom(bool_to_kicad_string(true))]));
+    }
+    SExpr::list("effects", children)
+}
+
+fn justify(horizontal: &str, vertical: &str) -> Vec<&'static str> {
+    let mut justify = vec![];
+    match horizontal {
+        "L" => justify.push("left"),
+        "R" => justify.push("right"),
+        _ => {}
+    }
+    match vertical {
+        "T" => justify.push("top"),
+        "B" => justify.push("bottom"),
+        _ => {}
+    }
+    justify
+}
+
+pub(crate) fn property(name: &str, value: &str, at: (f32, f32, f32), effects: SExpr) -> SExpr {
+    let at = SExpr::list("at", vec![SExpr::atom(at.0), SExpr::atom(at.1), SExpr::atom(at.2)]);
+    SExpr::list("property", vec![SExpr::quoted(name), SExpr::quoted(value), at, effects])
+}
+
+pub(crate) fn hidden_property(name: &str, value: &str) -> SExpr {
+    property(name, value, (0.0, 0.0, 0.0), effects(1.27, false, false, vec![], true))
+}
+
+/// `F0 "U" -100 200 50 H V L CNN ["Name"]`
+fn convert_field(fields: &Fields) -> Result<(usize, String, SExpr), anyhow::Error> {
+    let index: usize = fields.kind[1..].parse().map_err(|_| anyhow!("Not a valid field record: {}", fields.kind))?;
+    let name = match index {
+        0 => "Reference".to_string(),
+        1 => "Value".to_string(),
+        2 => "Footprint".to_string(),
+        3 => "Datasheet".to_string(),
+        _ => fields.text(9).map_or_else(|_| format!("Field{index}"), str::to_string),
+    };
+    let value = fields.text(1)?.to_string();
+    let angle = if fields.text_or(5, "H") == "V" { 90.0 } else { 0.0 };
+    let style = fields.text_or(8, "CNN");
+    let effects = effects(
+        fields.length(4)?,
+        style.get(1..2) == Some("I"),
+        style.get(2..3) == Some("B"),
+        justify(fields.text_or(7, "C"), style.get(0..1).unwrap_or("C")),
+        fields.text_or(6, "V") == "I",
+    );
+    let property = property(&name, &value, (fields.length(2)?, fields.length(3)?, angle), effects);
+    Ok((index, value, property))
+}
+
+/// Legacy arcs store the center, the radius, both angles in tenths of a degree
+/// and the end points. KiCad 5 always drew the shorter way from start to end,
+/// which is what the mid point is taken from.
+fn convert_arc(fields: &Fields) -> Result<SExpr, anyhow::Error> {
+    let (x, y, radius) = (fields.number(1)?, fields.number(2)?, fields.number(3)?);
+    let start_angle = fields.number(4)?;
+    let end_angle = fields.number(5)?;
+    let at_angle = |angle: f32| {
+        let radians = (angle / 10.0).to_radians();
+        (x + radius * radians.cos(), y + radius * radians.sin())
+    };
+    let (start, end) = match (fields.number(10), fields.number(11), fields.number(12), fields.number(13)) {
+        (Ok(start_x), Ok(start_y), Ok(end_x), Ok(end_y)) => ((start_x, start_y), (end_x, end_y)),
+        _ => (at_angle(start_angle), at_angle(end_angle)),
+    };
+    let mut span = (end_angle - start_angle).rem_euclid(3600.0);
+    if span > 1800.0 {
+        span -= 3600.0;
+    }
+    let mid = at_angle(start_angle + span / 2.0);
+    Ok(SExpr::list("arc", vec![
+        point("start", mils_to_mm(start.0), mils_to_mm(start.1)),
+        point("mid", mils_to_mm(mid.0), mils_to_mm(mid.1)),
+        point("end", mils_to_mm(end.0), mils_to_mm(end.1)),
+        stroke(fields.length(8)?),
+        fill(fields.text_or(9, "N")),
+    ]))
+}
+
+fn convert_polyline(fields: &Fields) -> Result<SExpr, anyhow::Error> {
+    let count = fields.number(1)? as usize;
+    let points = (0..count)
+        .map(|i| Ok(point("xy", fields.length(5 + 2 * i)?, fields.length(6 + 2 * i)?)))
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    Ok(SExpr::list("polyline", vec![
+        SExpr::list("pts", points),
+        stroke(fields.length(4)?),
+        fill(fields.text_or(5 + 2 * count, "N")),
+    ]))
+}
+
+/// `T angle x y size hidden unit convert text [Italic|Normal] bold hjustify vjustify`
+fn convert_text(fields: &Fields) -> Result<SExpr, anyhow::Error> {
+    // Unquoted legacy texts use `~` for spaces
+    let text = fields.text(8)?.replace('~', " ");
+    // Text items are the one place where symbol libraries keep tenths of a degree
+    let at = SExpr::list("at", vec![SExpr::atom(fields.length(2)?), SExpr::atom(fields.length(3)?), SExpr::atom(fields.number(1)?)]);
+    let effects = effects(
+        fields.length(4)?,
+        fields.text_or(9, "Normal") == "Italic",
+        fields.text_or(10, "0") != "0",
+        justify(fields.text_or(11, "C"), fields.text_or(12, "C")),
+        fields.text_or(5, "0") != "0",
+    );
+    Ok(SExpr::list("text", vec![SExpr::quoted(text), at, effects]))
+}
+
+/// `X name number x y length orientation number_size name_size unit convert type [shape]`
+fn convert_pin(fields: &Fields) -> Result<SExpr, anyhow::Error> {
+    let pin_type = match fields.text(11)? {
+        "I" => "input",
+        "O" => "output",
+        "B" => "bidirectional",
+        "T" => "tri_state",
+        "P" => "passive",
+        "U" => "unspecified",
+        "W" => "power_in",
+        "w" => "power_out",
+        "C" => "open_collector",
+        "E" => "open_emitter",
+        "N" => "no_connect",
+        other => bail!("Not a valid legacy pin type: {other}"),
+    };
+    let shape = fields.text_or(12, "");
+    let hide = shape.contains('N');
+    let mut shape: Vec<char> = shape.chars().filter(|&c| c != 'N').collect();
+    shape.sort_unstable();
+    let polarity = match shape.into_iter().collect::<String>().as_str() {
+        "" => "line",
+        "I" => "inverted",
+        "C" => "clock",
+        "CI" => "inverted_clock",
+        "L" => "input_low",
+        "CL" => "clock_low",
+        "V" => "output_low",
+        "F" => "edge_clock_high",
+        "X" => "non_logic",
+        other => bail!("Not a valid legacy pin shape: {other}"),
+    };
+    let angle = match fields.text(6)? {
+        "R" => 0,
+        "U" => 90,
+        "L" => 180,
+        "D" => 270,
+        other => bail!("Not a valid legacy pin orientation: {other}"),
+    };
+    // A name or number of `~` means the pin has none
+    let name = match fields.text(1)? {
+        "~" => "",
+        name => name,
+    };
+    let number = match fields.text(2)? {
+        "~" => "",
+        number => number,
+    };
+
+    let mut children = vec![
+        SExpr::atom(pin_type),
+        SExpr::atom(polarity),
+        SExpr::list("at", vec![SExpr::atom(fields.length(3)?), SExpr::atom(fields.length(4)?), SExpr::atom(angle)]),
+        SExpr::list("length", vec![SExpr::atom(fields.length(5)?)]),
+    ];
+    if hide {
+        children.push(SExpr::list("hide", vec![SExpr::atom(bool_to_kicad_string(true))]));
+    }
+    children.push(SExpr::list("name", vec![SExpr::quoted(name), effects(fields.length(8)?, false, false, vec![], false)]));
+    children.push(SExpr::list("number", vec![SExpr::quoted(number), effects(fields.length(7)?, false, false, vec![], false)]));
+    Ok(SExpr::list("pin", children))
+}
+
+/// Converts one `DEF` ... `ENDDEF` block. Aliases become symbols derived from it.
+fn convert_symbol(definition: &[(usize, &str)], docs: &HashMap<String, SymbolDoc>) -> Result<Vec<KiCadSymbol>, anyhow::Error> {
+    let mut header = None;
+    let mut properties = BTreeMap::new();
+    let mut aliases = vec![];
+    let mut footprint_filters = vec![];
+    // Drawing items by (unit, body style), 0 meaning shared by all
+    let mut units = BTreeMap::<(u32, u32), Vec<SExpr>>::new();
+    let mut in_footprint_list = false;
+
+    for &(number, line) in definition {
+        let line = line.trim();
+        let located = |error: anyhow::Error| anyhow!("{number}: {error:#}");
+        if in_footprint_list {
+            match line {
+                "$ENDFPLIST" => in_footprint_list = false,
+                filter => footprint_filters.push(filter.to_string()),
+            }
+            continue;
+        }
+        let fields = Fields { kind: line.split_whitespace().next().unwrap_or_default(), fields: split_fields(line) };
+        match fields.kind {
+            "DEF" => header = Some(fields),
+            kind if kind.len() > 1 && kind.starts_with('F') && kind[1..].chars().all(|c| c.is_ascii_digit()) => {
+                let (index, value, property) = convert_field(&fields).map_err(located)?;
+                properties.insert(index, (value, property));
+            }
+            "ALIAS" => aliases.extend(fields.fields[1..].iter().cloned()),
+            "$FPLIST" => in_footprint_list = true,
+            "A" => units.entry(fields.unit(6).map_err(located)?).or_default().push(convert_arc(&fields).map_err(located)?),
+            "C" => {
+                let circle = SExpr::list("circle", vec![
+                    point("center", fields.length(1).map_err(located)?, fields.length(2).map_err(located)?),
+                    SExpr::list("radius", vec![SExpr::atom(fields.length(3).map_err(located)?)]),
+                    stroke(fields.length(6).map_err(located)?),
+                    fill(fields.text_or(7, "N")),
+                ]);
+                units.entry(fields.unit(4).map_err(located)?).or_default().push(circle);
+            }
+            "S" => {
+                let rectangle = SExpr::list("rectangle", vec![
+                    point("start", fields.length(1).map_err(located)?, fields.length(2).map_err(located)?),
+                    point("end", fields.length(3).map_err(located)?, fields.length(4).map_err(located)?),
+                    stroke(fields.length(7).map_err(located)?),
+                    fill(fields.text_or(8, "N")),
+                ]);
+                units.entry(fields.unit(5).map_err(located)?).or_default().push(rectangle);
+            }
+            "P" => units.entry(fields.unit(2).map_err(located)?).or_default().push(convert_polyline(&fields).map_err(located)?),
+            "T" => units.entry(fields.unit(6).map_err(located)?).or_default().push(convert_text(&fields).map_err(located)?),
+            "X" => units.entry(fields.unit(9).map_err(located)?).or_default().push(convert_pin(&fields).map_err(located)?),
+            "B" => return Err(located(anyhow!("Bezier curves are not supported"))),
+            "DRAW" | "ENDDRAW" | "ENDDEF" | "" => {}
+            kind if kind.starts_with('#') => {}
+            kind => fidelity::record(format!("legacy record {kind}")),
+        }
+    }
+
+    let Some(header) = header else { bail!("Symbol has no DEF record") };
+    // `DEF name reference unused text_offset draw_numbers draw_names unit_count locked power`
+    let name = header.text(1)?.trim_start_matches('~').to_string();
+    let doc = docs.get(&name);
+
+    let mut children = vec![SExpr::quoted(&name)];
+    if header.text_or(9, "N") == "P" {
+        children.push(SExpr::list("power", vec![]));
+    }
+    if header.text_or(4, "Y") == "N" {
+        children.push(SExpr::list("pin_numbers", vec![SExpr::list("hide", vec![SExpr::atom(bool_to_kicad_string(true))])]));
+    }
+    let mut pin_names = vec![SExpr::list("offset", vec![SExpr::atom(header.length(3)?)])];
+    if header.text_or(5, "Y") == "N" {
+        pin_names.push(SExpr::list("hide", vec![SExpr::atom(bool_to_kicad_string(true))]));
+    }
+    children.push(SExpr::list("pin_names", pin_names));
+    for flag in ["exclude_from_sim", "in_bom", "on_board"] {
+        let value = flag != "exclude_from_sim";
+        children.push(SExpr::list(flag, vec![SExpr::atom(bool_to_kicad_string(value))]));
+    }
+
+    children.extend(symbol_properties(&properties, doc));
+    if !footprint_filters.is_empty() {
+        children.push(hidden_property("ki_fp_filters", &footprint_filters.join(" ")));
+    }
+    for ((unit, body_style), items) in units {
+        let mut sub_symbol = vec![SExpr::quoted(format!("{name}_{unit}_{body_style}"))];
+        sub_symbol.extend(items);
+        children.push(SExpr::list("symbol", sub_symbol));
+    }
+
+    let mut symbols = vec![parse_symbol(SExpr::list("symbol", children))?];
+
+    // An alias shares everything with its root symbol but the value and docs
+    for alias in aliases {
+        let alias_doc = docs.get(&alias);
+        let mut children = vec![SExpr::quoted(&alias), SExpr::list("extends", vec![SExpr::quoted(&name)])];
+        let mut alias_properties = properties.clone();
+        if let Some((_, property)) = alias_properties.get_mut(&1) {
+            set_property_value(property, &alias);
+        }
+        children.extend(symbol_properties(&alias_properties, alias_doc));
+        symbols.push(parse_symbol(SExpr::list("symbol", children))?);
+    }
+    Ok(symbols)
+}
+
+fn set_property_value(property: &mut SExpr, value: &str) {
+    if let SExpr::List(items) = property {
+        items[2] = SExpr::quoted(value);
+    }
+}
+
+/// The fields of a symbol followed by the documentation from the `.dcm` file.
+/// A documented datasheet fills in an empty datasheet field.
+fn symbol_properties(fields: &BTreeMap<usize, (String, SExpr)>, doc: Option<&SymbolDoc>) -> Vec<SExpr> {
+    let mut properties: BTreeMap<usize, SExpr> = fields.iter().map(|(index, (_, property))| (*index, property.clone())).collect();
+    let Some(doc) = doc else { return properties.into_values().collect() };
+
+    let datasheet_missing = fields.get(&3).is_none_or(|(value, _)| value.is_empty() || value == "~");
+    if let (true, Some(datasheet)) = (datasheet_missing, &doc.datasheet) {
+        match properties.get_mut(&3) {
+            Some(property) => set_property_value(property, datasheet),
+            None => {
+                properties.insert(3, hidden_property("Datasheet", datasheet));
+            }
+        }
+    }
+    let mut properties: Vec<SExpr> = properties.into_values().collect();
+    if let Some(description) = &doc.description {
+        properties.push(hidden_property("Description", description));
+    }
+    if let Some(keywords) = &doc.keywords {
+        properties.push(hidden_property("ki_keywords", keywords));
+    }
+    properties
+}
+
+pub(crate) fn parse_symbol(symbol: SExpr) -> Result<KiCadSymbol, anyhow::Error> {
+    let text = symbol.to_string();
+    let tokens = tokenise(&text)?;
+    KiCadSymbol::parse_expression(&tokens).map_err(|error| describe_error(error, &text, None))
+}
@@ -1,33 +1,36 @@
 use crate::symbols::property::{
-    check_expression_validity, KiCadEffects, KiCadLocation,
+    check_expression_validity, effects_or_default, parse_flag, KiCadEffects, KiCadLocation,
 };
+use crate::symbols::writer::{bool_to_kicad_string, SExpr};
 use crate::symbols::Token::Word;
-use crate::symbols::{subdivide_expression, Expression, TryFromExpression};
-use anyhow::{bail, Error};
+use crate::symbols::{expression_offset, fidelity, locate_error, subdivide_expression, top_level_words, Expression, ToSExpr, TryFromExpression};
+use anyhow::{anyhow, bail, Error};
+use std::fmt;
+use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct KiCadPinName {
     name: String,
     effects: Option<KiCadEffects>,
 }
 
 impl TryFromExpression<KiCadPinName> for KiCadPinName {
-    fn try_from_expression(expression: Expression) -> Result<KiCadPinName, Error> {
-        check_expression_validity(&expression, "name".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadPinName, Error> {
+        check_expression_validity(expression, "name".to_string())?;
         
         let Some(Word(name)) = expression.get(2) else {
             bail!("No pin name found")
         };
-        let subexpressions = subdivide_expression(expression[3..expression.len()].to_owned());
+        let subexpressions = subdivide_expression(&expression[3..]);
 
         let mut effects = None;
 
         for subexpression in subexpressions {
             if let Some(Word(property_name)) = subexpression.get(1) {
-                match property_name.as_str() {
-                    "effects" => effects = Some(KiCadEffects::try_from_expression(subexpression)?),
-                    _ => bail!("Not a valid KiCad pin name property: {property_name}"),
+                match property_name.as_ref() {
+                    "effects" => effects = Some(KiCadEffects::parse_expression(subexpression)?),
+                    _ => return Err(locate_error(anyhow!("Not a valid KiCad pin name property: {property_name}"), expression_offset(subexpression))),
                 }
             }
         }
@@ -39,29 +42,37 @@ impl TryFromExpression<KiCadPinName> for KiCadPinName {
     }
 }
 
-#[derive(Clone)]
+impl ToSExpr for KiCadPinName {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![SExpr::quoted(&self.name)];
+        children.push(effects_or_default(&self.effects));
+        SExpr::list("name", children)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct KiCadPinNumber {
     number: String,
     effects: Option<KiCadEffects>,
 }
 
 impl TryFromExpression<KiCadPinNumber> for KiCadPinNumber {
-    fn try_from_expression(expression: Expression) -> Result<KiCadPinNumber, Error> {
-        check_expression_validity(&expression, "number".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadPinNumber, Error> {
+        check_expression_validity(expression, "number".to_string())?;
 
-        let Some(Word(number)) = expression.get(1) else {
+        let Some(Word(number)) = expression.get(2) else {
             bail!("No pin number found")
         };
-        let subexpressions = subdivide_expression(expression[2..expression.len()].to_owned());
+        let subexpressions = subdivide_expression(&expression[3..]);
 
         let mut effects = None;
 
         for subexpression in subexpressions {
             if let Some(Word(property_name)) = subexpression.get(1) {
-                match property_name.as_str() {
-                    "effects" => effects = Some(KiCadEffects::try_from_expression(subexpression)?),
+                match property_name.as_ref() {
+                    "effects" => effects = Some(KiCadEffects::parse_expression(subexpression)?),
                     _ => {
-                        bail!("Not a valid KiCad pin number property: {property_name}")
+                        return Err(locate_error(anyhow!("Not a valid KiCad pin number property: {property_name}"), expression_offset(subexpression)))
                     }
                 }
             }
@@ -74,13 +85,28 @@ impl TryFromExpression<KiCadPinNumber> for KiCadPinNumber {
     }
 }
 
-#[derive(Copy, Clone)]
+impl ToSExpr for KiCadPinNumber {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![SExpr::quoted(&self.number)];
+        children.push(effects_or_default(&self.effects));
+        SExpr::list("number", children)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) enum KiCadPinType {
+    Input,
+    Output,
+    Bidirectional,
+    TriState,
     Passive,
+    Free,
+    Unspecified,
     PowerIn,
     PowerOut,
-    Input,
-    Unspecified,
+    OpenCollector,
+    OpenEmitter,
+    NoConnect,
 }
 
 impl FromStr for KiCadPinType {
@@ -88,20 +114,53 @@ impl FromStr for KiCadPinType {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
+            "input" => Ok(Self::Input),
+            "output" => Ok(Self::Output),
+            "bidirectional" => Ok(Self::Bidirectional),
+            "tri_state" => Ok(Self::TriState),
             "passive" => Ok(Self::Passive),
+            "free" => Ok(Self::Free),
+            "unspecified" => Ok(Self::Unspecified),
             "power_in" => Ok(Self::PowerIn),
             "power_out" => Ok(Self::PowerOut),
-            "input" => Ok(Self::Input),
-            "unspecified" => Ok(Self::Unspecified),
+            "open_collector" => Ok(Self::OpenCollector),
+            "open_emitter" => Ok(Self::OpenEmitter),
+            "no_connect" => Ok(Self::NoConnect),
             _ => bail!("Not a valid KiCad pin type: {s}"),
         }
     }
 }
 
-#[derive(Copy, Clone)]
+impl Display for KiCadPinType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Input => write!(f, "input"),
+            Self::Output => write!(f, "output"),
+            Self::Bidirectional => write!(f, "bidirectional"),
+            Self::TriState => write!(f, "tri_state"),
+            Self::Passive => write!(f, "passive"),
+            Self::Free => write!(f, "free"),
+            Self::Unspecified => write!(f, "unspecified"),
+            Self::PowerIn => write!(f, "power_in"),
+            Self::PowerOut => write!(f, "power_out"),
+            Self::OpenCollector => write!(f, "open_collector"),
+            Self::OpenEmitter => write!(f, "open_emitter"),
+            Self::NoConnect => write!(f, "no_connect"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) enum KiCadPinPolarity {
     Line,
     Inverted,
+    Clock,
+    InvertedClock,
+    InputLow,
+    ClockLow,
+    OutputLow,
+    EdgeClockHigh,
+    NonLogic,
 }
 
 impl FromStr for KiCadPinPolarity {
@@ -111,17 +170,40 @@ impl FromStr for KiCadPinPolarity {
         match s {
             "line" => Ok(Self::Line),
             "inverted" => Ok(Self::Inverted),
+            "clock" => Ok(Self::Clock),
+            "inverted_clock" => Ok(Self::InvertedClock),
+            "input_low" => Ok(Self::InputLow),
+            "clock_low" => Ok(Self::ClockLow),
+            "output_low" => Ok(Self::OutputLow),
+            "edge_clock_high" => Ok(Self::EdgeClockHigh),
+            "non_logic" => Ok(Self::NonLogic),
             _ => bail!("Not a valid KiCad pin polarity"),
         }
     }
 }
 
-#[derive(Copy, Clone)]
+impl Display for KiCadPinPolarity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Line => write!(f, "line"),
+            Self::Inverted => write!(f, "inverted"),
+            Self::Clock => write!(f, "clock"),
+            Self::InvertedClock => write!(f, "inverted_clock"),
+            Self::InputLow => write!(f, "input_low"),
+            Self::ClockLow => write!(f, "clock_low"),
+            Self::OutputLow => write!(f, "output_low"),
+            Self::EdgeClockHigh => write!(f, "edge_clock_high"),
+            Self::NonLogic => write!(f, "non_logic"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) struct KiCadPinLength(f32);
 
 impl TryFromExpression<KiCadPinLength> for KiCadPinLength {
-    fn try_from_expression(expression: Expression) -> Result<KiCadPinLength, Error> {
-        check_expression_validity(&expression, "length".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadPinLength, Error> {
+        check_expression_validity(expression, "length".to_string())?;
         
         let Some(Word(length)) = expression.get(2) else {
             bail!("No pin length found")
@@ -131,7 +213,13 @@ impl TryFromExpression<KiCadPinLength> for KiCadPinLength {
     }
 }
 
-#[derive(Clone)]
+impl ToSExpr for KiCadPinLength {
+    fn to_sexpr(&self) -> SExpr {
+        SExpr::list("length", vec![SExpr::atom(self.0)])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct KiCadPin {
     pin_type: KiCadPinType,
     pin_polarity: KiCadPinPolarity,
@@ -139,11 +227,12 @@ pub(crate) struct KiCadPin {
     length: Option<KiCadPinLength>,
     name: Option<KiCadPinName>,
     number: Option<KiCadPinNumber>,
+    hide: bool,
 }
 
 impl TryFromExpression<KiCadPin> for KiCadPin {
-    fn try_from_expression(expression: Expression) -> Result<KiCadPin, Error> {
-        check_expression_validity(&expression, "pin".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadPin, Error> {
+        check_expression_validity(expression, "pin".to_string())?;
 
         let Some(Word(pin_type)) = expression.get(2) else {
             bail!("No pin type found")
@@ -154,21 +243,24 @@ impl TryFromExpression<KiCadPin> for KiCadPin {
         let pin_type = KiCadPinType::from_str(pin_type)?;
         let pin_polarity = KiCadPinPolarity::from_str(pin_polarity)?;
 
-        let subexpressions = subdivide_expression(expression[4..expression.len()].to_owned());
+        let subexpressions = subdivide_expression(&expression[4..]);
 
         let mut pin_name = None;
         let mut pin_number = None;
         let mut pin_location = None;
         let mut pin_length = None;
+        // KiCad 6 and 7 write a bare `hide` flag
+        let mut hide = top_level_words(expression).contains(&"hide");
 
         for subexpression in subexpressions {
             if let Some(Word(property_name)) = subexpression.get(1) {
-                match property_name.as_str() {
-                    "name" => pin_name = Some(KiCadPinName::try_from_expression(subexpression)?),
-                    "number" => pin_number = Some(KiCadPinNumber::try_from_expression(subexpression)?),
-                    "at" => pin_location = Some(KiCadLocation::try_from_expression(subexpression)?),
-                    "length" => pin_length = Some(KiCadPinLength::try_from_expression(subexpression)?),
-                    _ => {}
+                match property_name.as_ref() {
+                    "name" => pin_name = Some(KiCadPinName::parse_expression(subexpression)?),
+                    "number" => pin_number = Some(KiCadPinNumber::parse_expression(subexpression)?),
+                    "at" => pin_location = Some(KiCadLocation::parse_expression(subexpression)?),
+                    "length" => pin_length = Some(KiCadPinLength::parse_expression(subexpression)?),
+                    "hide" => hide = parse_flag(subexpression)?,
+                    _ => fidelity::record(format!("pin {property_name}")),
                 }
             }
         }
@@ -179,6 +271,125 @@ impl TryFromExpression<KiCadPin> for KiCadPin {
             length: pin_length,
             name: pin_name,
             number: pin_number,
+            hide,
         })
     }
 }
+
+impl KiCadPin {
+    pub(crate) fn number(&self) -> Option<&str> {
+        self.number.as_ref().map(|number| number.number.as_str())
+    }
+
+    pub(crate) fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|name| name.name.as_str())
+    }
+
+    pub(crate) fn pin_type(&self) -> KiCadPinType {
+        self.pin_type
+    }
+
+    /// What changed from `old` to this pin, like `name "IN+" -> "IN-"`.
+    pub(crate) fn changes_from(&self, old: &KiCadPin) -> Vec<String> {
+        let mut changes = vec![];
+        if self.name() != old.name() {
+            changes.push(format!("name {:?} -> {:?}", old.name().unwrap_or_default(), self.name().unwrap_or_default()));
+        }
+        if self.pin_type != old.pin_type {
+            changes.push(format!("type {} -> {}", old.pin_type, self.pin_type));
+        }
+        if self.pin_polarity != old.pin_polarity {
+            changes.push(format!("shape {} -> {}", old.pin_polarity, self.pin_polarity));
+        }
+        if self.location != old.location {
+            changes.push("moved".to_string());
+        }
+        if self.length != old.length {
+            changes.push("length changed".to_string());
+        }
+        if self.hide != old.hide {
+            changes.push(if self.hide { "hidden" } else { "shown" }.to_string());
+        }
+        changes
+    }
+}
+
+impl ToSExpr for KiCadPin {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![SExpr::atom(self.pin_type), SExpr::atom(self.pin_polarity)];
+        if let Some(location) = &self.location {
+            children.push(location.to_sexpr());
+        }
+        if let Some(length) = &self.length {
+            children.push(length.to_sexpr());
+        }
+        if self.hide {
+            children.push(SExpr::list("hide", vec![SExpr::atom(bool_to_kicad_string(true))]));
+        }
+        if let Some(name) = &self.name {
+            children.push(name.to_sexpr());
+        }
+        if let Some(number) = &self.number {
+            children.push(number.to_sexpr());
+        }
+        SExpr::list("pin", children)
+    }
+}
+
+#[cfg(feature = "proptest")]
+pub(crate) mod strategy {
+    use super::*;
+    use crate::symbols::arbitrary::{coordinate, text};
+    use crate::symbols::property::strategy::{effects, location};
+    use proptest::option;
+    use proptest::prelude::*;
+    use proptest::sample::select;
+
+    pub(crate) fn pin() -> impl Strategy<Value = KiCadPin> {
+        let pin_type = select(vec![
+            KiCadPinType::Input,
+            KiCadPinType::Output,
+            KiCadPinType::Bidirectional,
+            KiCadPinType::TriState,
+            KiCadPinType::Passive,
+            KiCadPinType::Free,
+            KiCadPinType::Unspecified,
+            KiCadPinType::PowerIn,
+            KiCadPinType::PowerOut,
+            KiCadPinType::OpenCollector,
+            KiCadPinType::OpenEmitter,
+            KiCadPinType::NoConnect,
+        ]);
+        let pin_polarity = select(vec![
+            KiCadPinPolarity::Line,
+            KiCadPinPolarity::Inverted,
+            KiCadPinPolarity::Clock,
+            KiCadPinPolarity::InvertedClock,
+            KiCadPinPolarity::InputLow,
+            KiCadPinPolarity::ClockLow,
+            KiCadPinPolarity::OutputLow,
+            KiCadPinPolarity::EdgeClockHigh,
+            KiCadPinPolarity::NonLogic,
+        ]);
+        let name = (text(), effects()).prop_map(|(name, effects)| KiCadPinName { name, effects: Some(effects) });
+        let number = (text(), effects()).prop_map(|(number, effects)| KiCadPinNumber { number, effects: Some(effects) });
+        (
+            pin_type,
+            pin_polarity,
+            option::of(location()),
+            option::of(coordinate().prop_map(KiCadPinLength)),
+            option::of(name),
+            option::of(number),
+            any::<bool>(),
+        )
+            .prop_map(|(pin_type, pin_polarity, location, length, name, number, hide)| KiCadPin {
+                pin_type,
+                pin_polarity,
+                location,
+                length,
+                name,
+                number,
+                hide,
+            })
+    }
+}
@@ -1,13 +1,15 @@
+use crate::symbols::graphics::{KiCadArc, KiCadCircle, KiCadRectangle};
 use crate::symbols::pin::KiCadPin;
+use crate::symbols::writer::{bool_to_kicad_string, SExpr};
 use crate::symbols::Token::Word;
-use crate::symbols::{subdivide_expression, Expression, Token, TryFromExpression};
+use crate::symbols::{expression_offset, locate_error, subdivide_expression, top_level_words, Expression, ToSExpr, Token, TryFromExpression};
 use anyhow::{anyhow, bail, Error};
 use std::str::FromStr;
 use strum::{Display, EnumString};
 
-#[derive(EnumString, Display, Copy, Clone)]
+#[derive(EnumString, Display, Clone, Debug, PartialEq)]
 #[strum(serialize_all = "PascalCase")]
-pub(crate) enum KiCadPropertyType {
+pub enum KiCadPropertyType {
     Reference,
     Value,
     Footprint,
@@ -27,14 +29,17 @@ pub(crate) enum KiCadPropertyType {
     MaximumPackageHeight,
     #[strum(serialize = "MANUFACTURER")]
     Manufacturer,
+    /// Any vendor or user specific field, e.g. "MPN" or "MOUSER PART NUMBER".
+    #[strum(default)]
+    Custom(String),
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 struct KiCadPropertyId(u32);
 
 impl TryFromExpression<KiCadPropertyId> for KiCadPropertyId {
-    fn try_from_expression(expression: Expression) -> Result<KiCadPropertyId, Error> {
-        check_expression_validity(&expression, "id".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadPropertyId, Error> {
+        check_expression_validity(expression, "id".to_string())?;
 
         if expression.len() < 4 {
             bail!("Property ID expression should have four entries: {expression:?}");
@@ -46,43 +51,76 @@ impl TryFromExpression<KiCadPropertyId> for KiCadPropertyId {
     }
 }
 
-#[derive(Clone)]
-pub(crate) struct KiCadProperty {
+impl ToSExpr for KiCadPropertyId {
+    fn to_sexpr(&self) -> SExpr {
+        SExpr::list("id", vec![SExpr::atom(self.0)])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KiCadProperty {
     property_type: KiCadPropertyType,
     value: String,
     id: Option<KiCadPropertyId>,
     location: Option<KiCadLocation>,
+    show_name: bool,
+    do_not_autoplace: bool,
     effects: Option<KiCadEffects>
 }
 
+impl KiCadProperty {
+    pub fn property_type(&self) -> &KiCadPropertyType {
+        &self.property_type
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// A property that is not shown on the schematic, like the fields KiCad
+    /// adds through the symbol properties dialog.
+    pub fn new_hidden(property_type: KiCadPropertyType, value: String) -> Self {
+        let effects = KiCadEffects { hide: true, ..KiCadEffects::default() };
+        let mut kicad_property_builder = KiCadPropertyBuilder::new(property_type, value);
+        kicad_property_builder.location((0.0, 0.0, 0.0)).effects(effects);
+        kicad_property_builder.build()
+    }
+}
+
 impl TryFromExpression<KiCadProperty> for KiCadProperty {
-    fn try_from_expression(expression: Expression) -> Result<KiCadProperty, Error> {
-        check_expression_validity(&expression, "property".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadProperty, Error> {
+        check_expression_validity(expression, "property".to_string())?;
 
         let Some(Word(property_type)) = expression.get(2) else { bail!("Property does not contain type") };
         let Some(Word(value)) = expression.get(3) else { bail!("Property does not contain value") };
 
-        let property_type = KiCadPropertyType::from_str(property_type.as_str())?;
+        let property_type = KiCadPropertyType::from_str(property_type)?;
 
         let mut kicad_property_builder = KiCadPropertyBuilder::new(property_type, value.to_string());
 
-        let subexpressions = subdivide_expression(expression[4..expression.len()].to_owned());
+        let subexpressions = subdivide_expression(&expression[4..]);
 
         for expression in subexpressions {
             if let Some(Word(property)) = expression.get(1) {
-                let property = property.as_str();
+                let property: &str = property;
                 match property {
                     "id" => {
-                        kicad_property_builder.id(KiCadPropertyId::try_from_expression(expression)?);
+                        kicad_property_builder.id(KiCadPropertyId::parse_expression(expression)?);
                     },
                     "at" => {
-                        kicad_property_builder.location(KiCadLocation::try_from_expression(expression)?);
+                        kicad_property_builder.location(KiCadLocation::parse_expression(expression)?);
+                    }
+                    "show_name" => {
+                        kicad_property_builder.show_name(parse_flag(expression)?);
+                    }
+                    "do_not_autoplace" => {
+                        kicad_property_builder.do_not_autoplace(parse_flag(expression)?);
                     }
                     "effects" => {
-                        kicad_property_builder.effects(KiCadEffects::try_from_expression(expression)?);
+                        kicad_property_builder.effects(KiCadEffects::parse_expression(expression)?);
                     },
                     _ => {
-                        bail!("Not a valid KiCad property: {property}");
+                        return Err(locate_error(anyhow!("Not a valid KiCad property: {property}"), expression_offset(expression)));
                     }
                 }
             }
@@ -91,17 +129,39 @@ impl TryFromExpression<KiCadProperty> for KiCadProperty {
     }
 }
 
+impl ToSExpr for KiCadProperty {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![SExpr::quoted(&self.property_type), SExpr::quoted(&self.value)];
+        if let Some(id) = &self.id {
+            children.push(id.to_sexpr());
+        }
+        if let Some(location) = &self.location {
+            children.push(location.to_sexpr());
+        }
+        if self.show_name {
+            children.push(SExpr::list("show_name", vec![SExpr::atom(bool_to_kicad_string(true))]));
+        }
+        if self.do_not_autoplace {
+            children.push(SExpr::list("do_not_autoplace", vec![SExpr::atom(bool_to_kicad_string(true))]));
+        }
+        children.push(effects_or_default(&self.effects));
+        SExpr::list("property", children)
+    }
+}
+
 struct KiCadPropertyBuilder {
     property_type: KiCadPropertyType,
     value: String,
     id: Option<KiCadPropertyId>,
     location: Option<KiCadLocation>,
+    show_name: bool,
+    do_not_autoplace: bool,
     effects: Option<KiCadEffects>
 }
 
 impl KiCadPropertyBuilder {
     fn new(property_type: KiCadPropertyType, value: String) -> Self {
-        Self { property_type, value, id: None, location: None, effects: None }
+        Self { property_type, value, id: None, location: None, show_name: false, do_not_autoplace: false, effects: None }
     }
     fn id(&mut self, id: KiCadPropertyId) -> &mut KiCadPropertyBuilder {
         self.id = Some(id);
@@ -111,20 +171,36 @@ impl KiCadPropertyBuilder {
         self.location = Some(location);
         self
     }
+    fn show_name(&mut self, show_name: bool) -> &mut KiCadPropertyBuilder {
+        self.show_name = show_name;
+        self
+    }
+    fn do_not_autoplace(&mut self, do_not_autoplace: bool) -> &mut KiCadPropertyBuilder {
+        self.do_not_autoplace = do_not_autoplace;
+        self
+    }
     fn effects(&mut self, effects: KiCadEffects) -> &mut KiCadPropertyBuilder {
         self.effects = Some(effects);
         self
     }
     fn build(self) -> KiCadProperty {
-        KiCadProperty { property_type: self.property_type, value: self.value, id: self.id, location: self.location, effects: self.effects }
+        KiCadProperty {
+            property_type: self.property_type,
+            value: self.value,
+            id: self.id,
+            location: self.location,
+            show_name: self.show_name,
+            do_not_autoplace: self.do_not_autoplace,
+            effects: self.effects,
+        }
     }
 }
 
 pub(crate) type KiCadLocation = (f32, f32, f32);
 
 impl TryFromExpression<KiCadLocation> for KiCadLocation {
-    fn try_from_expression(expression: Expression) -> Result<KiCadLocation, Error> {
-        check_expression_validity(&expression, "at".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadLocation, Error> {
+        check_expression_validity(expression, "at".to_string())?;
 
         if expression.len() < 5 {
             bail!("Location expression should have five entries: {expression:?}");
@@ -141,15 +217,21 @@ impl TryFromExpression<KiCadLocation> for KiCadLocation {
     }
 }
 
-#[derive(Copy, Clone)]
+impl ToSExpr for KiCadLocation {
+    fn to_sexpr(&self) -> SExpr {
+        SExpr::list("at", vec![SExpr::atom(self.0), SExpr::atom(self.1), SExpr::atom(self.2)])
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) struct KiCadFontSize {
     width: f32,
     height: f32,
 }
 
 impl TryFromExpression<KiCadFontSize> for KiCadFontSize {
-    fn try_from_expression(expression: Expression) -> Result<KiCadFontSize, Error> {
-        check_expression_validity(&expression, "size".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadFontSize, Error> {
+        check_expression_validity(expression, "size".to_string())?;
 
         if expression.len() != 5 {
             bail!("Font size expression should have four entries: {expression:?}");
@@ -164,97 +246,215 @@ impl TryFromExpression<KiCadFontSize> for KiCadFontSize {
     }
 }
 
-#[derive(Copy, Clone)]
+impl ToSExpr for KiCadFontSize {
+    fn to_sexpr(&self) -> SExpr {
+        SExpr::list("size", vec![SExpr::atom(self.width), SExpr::atom(self.height)])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct KiCadFont {
+    /// Font name, KiCad's built-in stroke font if not given
+    face: Option<String>,
     font_size: Option<KiCadFontSize>,
+    thickness: Option<f32>,
     bold: bool,
     italic: bool,
     subscript: bool,
     superscript: bool,
     overbar: bool,
     underline: bool,
+    color: Option<KiCadColor>,
+    line_spacing: Option<f32>,
+}
+
+impl Default for KiCadFont {
+    fn default() -> Self {
+        Self {
+            face: None,
+            font_size: Some(KiCadFontSize { width: 1.27, height: 1.27 }),
+            thickness: None,
+            bold: false,
+            italic: false,
+            subscript: false,
+            superscript: false,
+            overbar: false,
+            underline: false,
+            color: None,
+            line_spacing: None,
+        }
+    }
 }
 
 impl TryFromExpression<KiCadFont> for KiCadFont {
-    fn try_from_expression(expression: Expression) -> Result<KiCadFont, Error> {
-        check_expression_validity(&expression, "font".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadFont, Error> {
+        check_expression_validity(expression, "font".to_string())?;
 
-        let subexpressions = subdivide_expression(expression[2..expression.len()].to_owned());
+        let subexpressions = subdivide_expression(&expression[2..]);
 
+        // KiCad 6 writes the style flags as bare words
+        let words = top_level_words(expression);
+        let mut face = None;
         let mut font_size = None;
-        let mut bold = false;
-        let mut italic = false;
+        let mut thickness = None;
+        let mut bold = words.contains(&"bold");
+        let mut italic = words.contains(&"italic");
         let mut subscript = false;
         let mut superscript = false;
         let mut overbar = false;
         let mut underline = false;
+        let mut color = None;
+        let mut line_spacing = None;
 
         for expression in subexpressions {
             if let Some(Word(property)) = expression.get(1) {
-                let property = property.as_str();
+                let property: &str = property;
                 match property {
+                    "face" => {
+                        let Some(Word(face_value)) = expression.get(2) else { bail!("Font face does not contain a name") };
+                        face = Some(face_value.to_string());
+                    },
                     "size" => {
-                        font_size = Some(KiCadFontSize::try_from_expression(expression)?);
+                        font_size = Some(KiCadFontSize::parse_expression(expression)?);
+                    },
+                    "thickness" => {
+                        let Some(Word(thickness_value)) = expression.get(2) else { bail!("Font thickness does not contain value") };
+                        thickness = Some(thickness_value.parse::<f32>()?);
                     },
                     "bold" => {
-                        bold = true;
+                        bold = parse_flag(expression)?;
                     },
                     "italic" => {
-                        italic = true;
+                        italic = parse_flag(expression)?;
                     },
                     "subscript" => {
-                        subscript = true;
+                        subscript = parse_flag(expression)?;
                     },
                     "superscript" => {
-                        superscript = true;
+                        superscript = parse_flag(expression)?;
                     },
                     "overbar" => {
-                        overbar = true;
+                        overbar = parse_flag(expression)?;
                     },
                     "underline" => {
-                        underline = true;
+                        underline = parse_flag(expression)?;
+                    },
+                    "color" => {
+                        color = Some(KiCadColor::parse_expression(expression)?);
+                    },
+                    "line_spacing" => {
+                        let Some(Word(line_spacing_value)) = expression.get(2) else { bail!("Font line spacing does not contain value") };
+                        line_spacing = Some(line_spacing_value.parse::<f32>()?);
                     }
                     _ => {
-                        bail!("Not a valid KiCad font property: {property}");
+                        return Err(locate_error(anyhow!("Not a valid KiCad font property: {property}"), expression_offset(expression)));
                     }
                 }
             }
         }
 
-        Ok(Self { font_size, bold, italic, subscript, superscript, overbar, underline })
+        Ok(Self { face, font_size, thickness, bold, italic, subscript, superscript, overbar, underline, color, line_spacing })
     }
 }
 
-#[derive(Copy, Clone)]
+impl ToSExpr for KiCadFont {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![];
+        if let Some(face) = &self.face {
+            children.push(SExpr::list("face", vec![SExpr::quoted(face)]));
+        }
+        if let Some(font_size) = &self.font_size {
+            children.push(font_size.to_sexpr());
+        }
+        if let Some(thickness) = self.thickness {
+            children.push(SExpr::list("thickness", vec![SExpr::atom(thickness)]));
+        }
+        let flags = [
+            ("bold", self.bold),
+            ("italic", self.italic),
+            ("subscript", self.subscript),
+            ("superscript", self.superscript),
+            ("overbar", self.overbar),
+            ("underline", self.underline),
+        ];
+        for (flag, value) in flags {
+            if value {
+                children.push(SExpr::list(flag, vec![SExpr::atom(bool_to_kicad_string(value))]));
+            }
+        }
+        if let Some(color) = &self.color {
+            children.push(color.to_sexpr());
+        }
+        if let Some(line_spacing) = self.line_spacing {
+            children.push(SExpr::list("line_spacing", vec![SExpr::atom(line_spacing)]));
+        }
+        SExpr::list("font", children)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) enum KiCadEffectsJustify {
     Bottom,
     Top,
     Left,
     Right,
+    /// Text drawn mirrored, written by KiCad after the alignment, e.g. `(justify left mirror)`
+    Mirror,
 }
 
-#[derive(Clone)]
+impl KiCadEffectsJustify {
+    fn as_str(&self) -> &'static str {
+        match self {
+            KiCadEffectsJustify::Bottom => "bottom",
+            KiCadEffectsJustify::Top => "top",
+            KiCadEffectsJustify::Left => "left",
+            KiCadEffectsJustify::Right => "right",
+            KiCadEffectsJustify::Mirror => "mirror",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct KiCadEffects {
     font: Option<KiCadFont>,
     hide: bool,
     justify: Vec<KiCadEffectsJustify>,
+    /// Hyperlink opened when the text is clicked in the schematic
+    href: Option<String>,
+}
+
+impl Default for KiCadEffects {
+    fn default() -> Self {
+        Self { font: Some(KiCadFont::default()), hide: false, justify: vec![], href: None }
+    }
+}
+
+/// KiCad writes an effects block for every text item, even when it only holds
+/// the default font, so items parsed without one get the default on write.
+pub(crate) fn effects_or_default(effects: &Option<KiCadEffects>) -> SExpr {
+    match effects {
+        Some(effects) => effects.to_sexpr(),
+        None => KiCadEffects::default().to_sexpr(),
+    }
 }
 
 impl TryFromExpression<KiCadEffects> for KiCadEffects {
-    fn try_from_expression(expression: Expression) -> Result<KiCadEffects, Error> {
-        check_expression_validity(&expression, "effects".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadEffects, Error> {
+        check_expression_validity(expression, "effects".to_string())?;
 
-        let subexpressions = subdivide_expression(expression[2..expression.len()].to_owned());
+        let subexpressions = subdivide_expression(&expression[2..]);
 
         let mut font = None;
         let mut justify = vec![];
-        let mut hide = false;
+        let mut href = None;
+        // KiCad 6 writes a bare `hide` flag instead of `(hide yes)`
+        let mut hide = top_level_words(expression).contains(&"hide");
         for expression in subexpressions {
             if let Some(Word(property)) = expression.get(1) {
-                let property = property.as_str();
+                let property: &str = property;
                 match property {
                     "font" => {
-                        font = Some(KiCadFont::try_from_expression(expression)?);
+                        font = Some(KiCadFont::parse_expression(expression)?);
                     },
                     "justify" => {
                         if expression.len() < 3 {
@@ -262,31 +462,52 @@ impl TryFromExpression<KiCadEffects> for KiCadEffects {
                         }
                         for i in 2..(expression.len() - 1) {
                             let Some(Word(justify_value)) = expression.get(i) else { bail!("Justify does not contain value") };
-                            let justify_value = justify_value.as_str();
+                            let justify_value: &str = justify_value;
                             match justify_value {
                                 "bottom" => justify.push(KiCadEffectsJustify::Bottom),
                                 "top" => justify.push(KiCadEffectsJustify::Top),
                                 "left" => justify.push(KiCadEffectsJustify::Left),
                                 "right" => justify.push(KiCadEffectsJustify::Right),
+                                "mirror" => justify.push(KiCadEffectsJustify::Mirror),
                                 _ => bail!("Not a valid KiCad effects justify value: {justify_value}"),
                             }
                         }
                     },
                     "hide" => {
-                        hide = true;
+                        hide = parse_flag(expression)?;
+                    }
+                    "href" => {
+                        let Some(Word(href_value)) = expression.get(2) else { bail!("Href does not contain a link") };
+                        href = Some(href_value.to_string());
                     }
                     _ => {
-                        bail!("Not a valid KiCad effects property: {property}");
+                        return Err(locate_error(anyhow!("Not a valid KiCad effects property: {property}"), expression_offset(expression)));
                     }
                 }
             }
         }
 
-        Ok(Self { font, hide, justify })
+        Ok(Self { font, hide, justify, href })
     }
 }
 
-#[derive(Clone)]
+impl ToSExpr for KiCadEffects {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![self.font.clone().unwrap_or_default().to_sexpr()];
+        if !self.justify.is_empty() {
+            children.push(SExpr::list("justify", self.justify.iter().map(|justify| SExpr::atom(justify.as_str())).collect()));
+        }
+        if let Some(href) = &self.href {
+            children.push(SExpr::list("href", vec![SExpr::quoted(href)]));
+        }
+        if self.hide {
+            children.push(SExpr::list("hide", vec![SExpr::atom(bool_to_kicad_string(true))]));
+        }
+        SExpr::list("effects", children)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 enum KiCadSingleValueProperty {
     Offset(f32),
     InBom(bool),
@@ -294,7 +515,7 @@ enum KiCadSingleValueProperty {
     ExcludeFromSim(bool),
 }
 
-fn try_parse_string_to_bool(value: &str) -> Result<bool, anyhow::Error> {
+pub(crate) fn try_parse_string_to_bool(value: &str) -> Result<bool, anyhow::Error> {
     match value {
         "yes" => Ok(true),
         "no" => Ok(false),
@@ -302,30 +523,49 @@ fn try_parse_string_to_bool(value: &str) -> Result<bool, anyhow::Error> {
     }
 }
 
+/// Reads a flag written as `(hide yes)`, or as `(hide)` without a value.
+pub(crate) fn parse_flag(expression: &Expression) -> Result<bool, anyhow::Error> {
+    match expression.get(2) {
+        Some(Word(value)) => try_parse_string_to_bool(value),
+        _ => Ok(true),
+    }
+}
+
 impl TryFromExpression<KiCadSingleValueProperty> for KiCadSingleValueProperty {
-    fn try_from_expression(expression: Expression) -> Result<KiCadSingleValueProperty, Error> {
-        let Token::Word(prop) = get_expression_first_value(&expression)? else {
+    fn try_from_expression(expression: &Expression) -> Result<KiCadSingleValueProperty, Error> {
+        let Token::Word(prop) = get_expression_first_value(expression)? else {
             bail!("Expression's second Token is not a word: {expression:?}")
         };
         let Word(value) = expression.get(2).ok_or(anyhow!("Could not get expression second value"))? else { bail!("Expression's second value not a word") };
         
-        Ok(match prop.as_str() { 
+        Ok(match prop.as_ref() {
             "offset" => Self::Offset(value.parse::<f32>()?),
-            "in_bom" => Self::InBom(try_parse_string_to_bool(&value)?),
-            "on_board" => Self::OnBoard(try_parse_string_to_bool(&value)?),
-            "exclude_from_sim" => Self::ExcludeFromSim(try_parse_string_to_bool(&value)?),
+            "in_bom" => Self::InBom(try_parse_string_to_bool(value)?),
+            "on_board" => Self::OnBoard(try_parse_string_to_bool(value)?),
+            "exclude_from_sim" => Self::ExcludeFromSim(try_parse_string_to_bool(value)?),
             _ => bail!("Not a valid option for KiCadSingleValueProperty: {prop}, {value}"),
         })
         
     }
 }
 
-#[derive(Clone)]
+impl ToSExpr for KiCadSingleValueProperty {
+    fn to_sexpr(&self) -> SExpr {
+        match self {
+            Self::Offset(offset) => SExpr::list("offset", vec![SExpr::atom(offset)]),
+            Self::InBom(value) => SExpr::list("in_bom", vec![SExpr::atom(bool_to_kicad_string(*value))]),
+            Self::OnBoard(value) => SExpr::list("on_board", vec![SExpr::atom(bool_to_kicad_string(*value))]),
+            Self::ExcludeFromSim(value) => SExpr::list("exclude_from_sim", vec![SExpr::atom(bool_to_kicad_string(*value))]),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct Offset(f32);
 
 impl TryFromExpression<Offset> for Offset {
-    fn try_from_expression(expression: Expression) -> Result<Offset, Error> {
-        check_expression_validity(&expression, "offset".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<Offset, Error> {
+        check_expression_validity(expression, "offset".to_string())?;
         let Some(Word(offset)) = expression.get(2) else {
             bail!("Offset does not contain value")
         };
@@ -333,29 +573,127 @@ impl TryFromExpression<Offset> for Offset {
     }
 }
 
-#[derive(Clone)]
+impl ToSExpr for Offset {
+    fn to_sexpr(&self) -> SExpr {
+        SExpr::list("offset", vec![SExpr::atom(self.0)])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct KiCadPinNames {
-    offset: Offset,
+    offset: Option<Offset>,
+    hide: bool,
 }
 
 impl TryFromExpression<KiCadPinNames> for KiCadPinNames {
-    fn try_from_expression(expression: Expression) -> Result<KiCadPinNames, Error> {
-        check_expression_validity(&expression, "pin_names".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadPinNames, Error> {
+        check_expression_validity(expression, "pin_names".to_string())?;
+
+        let subexpressions = subdivide_expression(&expression[2..]);
+
+        let mut offset = None;
+        let mut hide = top_level_words(expression).contains(&"hide");
+
+        for expression in subexpressions {
+            if let Some(Word(property)) = expression.get(1) {
+                match property.as_ref() {
+                    "offset" => offset = Some(Offset::parse_expression(expression)?),
+                    "hide" => hide = parse_flag(expression)?,
+                    _ => return Err(locate_error(anyhow!("Not a valid KiCad pin names property: {property}"), expression_offset(expression))),
+                }
+            }
+        }
+
+        Ok(Self { offset, hide })
+    }
+}
 
-        let subexpression = subdivide_expression(expression[2..expression.len()].to_owned());
+impl ToSExpr for KiCadPinNames {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![];
+        if let Some(offset) = &self.offset {
+            children.push(offset.to_sexpr());
+        }
+        if self.hide {
+            children.push(SExpr::list("hide", vec![SExpr::atom(bool_to_kicad_string(true))]));
+        }
+        SExpr::list("pin_names", children)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct KiCadPinNumbers {
+    hide: bool,
+}
+
+impl TryFromExpression<KiCadPinNumbers> for KiCadPinNumbers {
+    fn try_from_expression(expression: &Expression) -> Result<KiCadPinNumbers, Error> {
+        check_expression_validity(expression, "pin_numbers".to_string())?;
+
+        let subexpressions = subdivide_expression(&expression[2..]);
+        let mut hide = top_level_words(expression).contains(&"hide");
+
+        for expression in subexpressions {
+            if let Some(Word(property)) = expression.get(1) {
+                match property.as_ref() {
+                    "hide" => hide = parse_flag(expression)?,
+                    _ => return Err(locate_error(anyhow!("Not a valid KiCad pin numbers property: {property}"), expression_offset(expression))),
+                }
+            }
+        }
+
+        Ok(Self { hide })
+    }
+}
+
+impl ToSExpr for KiCadPinNumbers {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![];
+        if self.hide {
+            children.push(SExpr::list("hide", vec![SExpr::atom(bool_to_kicad_string(true))]));
+        }
+        SExpr::list("pin_numbers", children)
+    }
+}
 
-        if subexpression.len() != 1 {
-            unimplemented!()
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct KiCadColor {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: f32,
+}
+
+impl TryFromExpression<KiCadColor> for KiCadColor {
+    fn try_from_expression(expression: &Expression) -> Result<KiCadColor, Error> {
+        check_expression_validity(expression, "color".to_string())?;
+
+        if expression.len() != 7 {
+            bail!("Color expression should have four values: {expression:?}");
         }
-        let offset = Offset::try_from_expression(subexpression[0].to_owned())?;
+        let Some(Word(r)) = expression.get(2) else { bail!("Color does not contain red") };
+        let Some(Word(g)) = expression.get(3) else { bail!("Color does not contain green") };
+        let Some(Word(b)) = expression.get(4) else { bail!("Color does not contain blue") };
+        let Some(Word(a)) = expression.get(5) else { bail!("Color does not contain alpha") };
+
+        Ok(Self { r: r.parse::<u8>()?, g: g.parse::<u8>()?, b: b.parse::<u8>()?, a: a.parse::<f32>()? })
+    }
+}
 
-        Ok(Self { offset })
+impl ToSExpr for KiCadColor {
+    fn to_sexpr(&self) -> SExpr {
+        SExpr::list("color", vec![SExpr::atom(self.r), SExpr::atom(self.g), SExpr::atom(self.b), SExpr::atom(self.a)])
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) enum KiCadStrokeType {
     Default,
+    Solid,
+    Dash,
+    Dot,
+    DashDot,
+    DashDotDot,
 }
 
 impl FromStr for KiCadStrokeType {
@@ -364,28 +702,48 @@ impl FromStr for KiCadStrokeType {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
             "default" => Ok(KiCadStrokeType::Default),
+            "solid" => Ok(KiCadStrokeType::Solid),
+            "dash" => Ok(KiCadStrokeType::Dash),
+            "dot" => Ok(KiCadStrokeType::Dot),
+            "dash_dot" => Ok(KiCadStrokeType::DashDot),
+            "dash_dot_dot" => Ok(KiCadStrokeType::DashDotDot),
             _ => bail!("Not a valid KiCad stroke type: {s}")
         }
     }
 }
 
-#[derive(Copy, Clone)]
+impl std::fmt::Display for KiCadStrokeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KiCadStrokeType::Default => write!(f, "default"),
+            KiCadStrokeType::Solid => write!(f, "solid"),
+            KiCadStrokeType::Dash => write!(f, "dash"),
+            KiCadStrokeType::Dot => write!(f, "dot"),
+            KiCadStrokeType::DashDot => write!(f, "dash_dot"),
+            KiCadStrokeType::DashDotDot => write!(f, "dash_dot_dot"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) struct KiCadStroke {
     width: Option<f32>,
     stroke_type: Option<KiCadStrokeType>,
+    color: Option<KiCadColor>,
 }
 
 impl TryFromExpression<KiCadStroke> for KiCadStroke {
-    fn try_from_expression(expression: Expression) -> Result<KiCadStroke, Error> {
-        check_expression_validity(&expression, "stroke".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadStroke, Error> {
+        check_expression_validity(expression, "stroke".to_string())?;
 
-        let subexpressions = subdivide_expression(expression[2..expression.len()].to_owned());
+        let subexpressions = subdivide_expression(&expression[2..]);
         let mut width = None;
         let mut stroke_type = None;
+        let mut color = None;
         
         for expression in subexpressions {
             if let Some(Word(property)) = expression.get(1) {
-                let property = property.as_str();
+                let property: &str = property;
                 match property {
                     "width" => {
                         let Some(Word(width_value)) = expression.get(2) else { bail!("Stroke does not contain width") };
@@ -393,22 +751,42 @@ impl TryFromExpression<KiCadStroke> for KiCadStroke {
                     },
                     "type" => {
                         let Some(Word(stroke_type_value)) = expression.get(2) else { bail!("Stroke does not contain type") };
-                        stroke_type = Some(KiCadStrokeType::from_str(stroke_type_value.as_str())?);
+                        stroke_type = Some(KiCadStrokeType::from_str(stroke_type_value)?);
+                    },
+                    "color" => {
+                        color = Some(KiCadColor::parse_expression(expression)?);
                     },
                     _ => {
-                        bail!("Not a valid KiCad stroke property: {property}");
+                        return Err(locate_error(anyhow!("Not a valid KiCad stroke property: {property}"), expression_offset(expression)));
                     }
                 }
             }
         }
-        Ok(Self { width, stroke_type })
+        Ok(Self { width, stroke_type, color })
     }
 }
 
-#[derive(Copy, Clone)]
+impl ToSExpr for KiCadStroke {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![];
+        if let Some(width) = self.width {
+            children.push(SExpr::list("width", vec![SExpr::atom(width)]));
+        }
+        if let Some(stroke_type) = self.stroke_type {
+            children.push(SExpr::list("type", vec![SExpr::atom(stroke_type)]));
+        }
+        if let Some(color) = &self.color {
+            children.push(color.to_sexpr());
+        }
+        SExpr::list("stroke", children)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) enum KiCadFillType {
     Background,
     Outline,
+    Color,
     None,
 }
 
@@ -419,65 +797,111 @@ impl FromStr for KiCadFillType {
         match s.to_lowercase().as_str() {
             "background" => Ok(KiCadFillType::Background),
             "outline" => Ok(KiCadFillType::Outline),
+            "color" => Ok(KiCadFillType::Color),
             "none" => Ok(KiCadFillType::None),
             _ => bail!("Not a valid KiCad fill type: {s}")
         }
     }
 }
 
-#[derive(Copy, Clone)]
+impl std::fmt::Display for KiCadFillType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KiCadFillType::Background => write!(f, "background"),
+            KiCadFillType::Outline => write!(f, "outline"),
+            KiCadFillType::Color => write!(f, "color"),
+            KiCadFillType::None => write!(f, "none"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) struct KiCadFill {
     fill_type: Option<KiCadFillType>,
+    color: Option<KiCadColor>,
 }
 
 impl TryFromExpression<KiCadFill> for KiCadFill {
-    fn try_from_expression(expression: Expression) -> Result<KiCadFill, Error> {
-        check_expression_validity(&expression, "fill".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadFill, Error> {
+        check_expression_validity(expression, "fill".to_string())?;
         
-        let subexpressions = subdivide_expression(expression[2..expression.len()].to_owned());
+        let subexpressions = subdivide_expression(&expression[2..]);
         let mut fill_type = None;
+        let mut color = None;
         
         for expression in subexpressions {
             if let Some(Word(property)) = expression.get(1) {
-                let property = property.as_str();
+                let property: &str = property;
                 match property {
                     "type" => {
                         let Some(Word(fill_type_value)) = expression.get(2) else { bail!("Fill does not contain type") };
-                        fill_type = Some(KiCadFillType::from_str(fill_type_value.as_str())?);
+                        fill_type = Some(KiCadFillType::from_str(fill_type_value)?);
+                    },
+                    "color" => {
+                        color = Some(KiCadColor::parse_expression(expression)?);
                     },
                     _ => {
-                        bail!("Not a valid KiCad fill property: {property}");
+                        return Err(locate_error(anyhow!("Not a valid KiCad fill property: {property}"), expression_offset(expression)));
                     }
                 }
             }
         }
 
-        Ok(Self { fill_type })
+        Ok(Self { fill_type, color })
     }
 }
 
-#[derive(Copy, Clone)]
+impl ToSExpr for KiCadFill {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![];
+        if let Some(fill_type) = self.fill_type {
+            children.push(SExpr::list("type", vec![SExpr::atom(fill_type)]));
+        }
+        if let Some(color) = &self.color {
+            children.push(color.to_sexpr());
+        }
+        SExpr::list("fill", children)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) struct KiCad2DPoint {
     x: f32,
     y: f32,
 }
 
-#[derive(Copy, Clone)]
+impl KiCad2DPoint {
+    /// Parses points like `(start x y)` or `(center x y)`.
+    pub(crate) fn try_from_named_expression(expression: &Expression, name: &str) -> Result<KiCad2DPoint, Error> {
+        check_expression_validity(expression, name.to_string())?;
+
+        let Some(Word(x)) = expression.get(2) else { bail!("Point {name} does not contain x") };
+        let Some(Word(y)) = expression.get(3) else { bail!("Point {name} does not contain y") };
+
+        Ok(Self { x: x.parse::<f32>()?, y: y.parse::<f32>()? })
+    }
+
+    pub(crate) fn to_named_sexpr(self, name: &str) -> SExpr {
+        SExpr::list(name, vec![SExpr::atom(self.x), SExpr::atom(self.y)])
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) struct KiCadXY(KiCad2DPoint);
 
 type KiCadPolylinePts = Vec<KiCadXY>;
 
 impl TryFromExpression<KiCadPolylinePts> for KiCadPolylinePts {
-    fn try_from_expression(expression: Expression) -> Result<KiCadPolylinePts, Error> {
-        check_expression_validity(&expression, "pts".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadPolylinePts, Error> {
+        check_expression_validity(expression, "pts".to_string())?;
 
-        let subexpressions = subdivide_expression(expression[2..expression.len()].to_owned());
+        let subexpressions = subdivide_expression(&expression[2..]);
 
         let mut pts = vec![];
 
         for expression in subexpressions {
             if let Some(Word(property)) = expression.get(1) {
-                let property = property.as_str();
+                let property: &str = property;
                 match property {
                     "xy" => {
                         let Some(Word(x)) = expression.get(2) else {
@@ -489,7 +913,7 @@ impl TryFromExpression<KiCadPolylinePts> for KiCadPolylinePts {
                         pts.push(KiCadXY(KiCad2DPoint { x: x.parse::<f32>()?, y: y.parse::<f32>()? }));
                     },
                     _ => {
-                        bail!("Not a valid KiCad polyline pts property: {property}");
+                        return Err(locate_error(anyhow!("Not a valid KiCad polyline pts property: {property}"), expression_offset(expression)));
                     }
                 }
             }
@@ -499,7 +923,13 @@ impl TryFromExpression<KiCadPolylinePts> for KiCadPolylinePts {
     }
 }
 
-#[derive(Clone)]
+impl ToSExpr for KiCadPolylinePts {
+    fn to_sexpr(&self) -> SExpr {
+        SExpr::list("pts", self.iter().map(|KiCadXY(point)| SExpr::list("xy", vec![SExpr::atom(point.x), SExpr::atom(point.y)])).collect())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct KiCadPolyline {
     pts: Vec<KiCadXY>,
     stroke: Option<KiCadStroke>,
@@ -507,10 +937,10 @@ pub(crate) struct KiCadPolyline {
 }
 
 impl TryFromExpression<KiCadPolyline> for KiCadPolyline {
-    fn try_from_expression(expression: Expression) -> Result<KiCadPolyline, Error> {
-        check_expression_validity(&expression, "polyline".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadPolyline, Error> {
+        check_expression_validity(expression, "polyline".to_string())?;
 
-        let subexpressions = subdivide_expression(expression[2..expression.len()].to_owned());
+        let subexpressions = subdivide_expression(&expression[2..]);
 
         let mut pts = vec![];
         let mut stroke = None;
@@ -518,19 +948,19 @@ impl TryFromExpression<KiCadPolyline> for KiCadPolyline {
 
         for expression in subexpressions {
             if let Some(Word(property)) = expression.get(1) {
-                let property = property.as_str();
+                let property: &str = property;
                 match property {
                     "pts" => {
-                        pts = KiCadPolylinePts::try_from_expression(expression)?
+                        pts = KiCadPolylinePts::parse_expression(expression)?
                     },
                     "stroke" => {
-                        stroke = Some(KiCadStroke::try_from_expression(expression)?);
+                        stroke = Some(KiCadStroke::parse_expression(expression)?);
                     },
                     "fill" => {
-                        fill = Some(KiCadFill::try_from_expression(expression)?);
+                        fill = Some(KiCadFill::parse_expression(expression)?);
                     },
                     _ => {
-                        bail!("Not a valid KiCad polyline property: {property}");
+                        return Err(locate_error(anyhow!("Not a valid KiCad polyline property: {property}"), expression_offset(expression)));
                     }
                 }
             }
@@ -540,7 +970,20 @@ impl TryFromExpression<KiCadPolyline> for KiCadPolyline {
     }
 }
 
-#[derive(Clone)]
+impl ToSExpr for KiCadPolyline {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![self.pts.to_sexpr()];
+        if let Some(stroke) = &self.stroke {
+            children.push(stroke.to_sexpr());
+        }
+        if let Some(fill) = &self.fill {
+            children.push(fill.to_sexpr());
+        }
+        SExpr::list("polyline", children)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct KiCadText {
     text: String,
     location: KiCadLocation,
@@ -548,28 +991,28 @@ pub(crate) struct KiCadText {
 }
 
 impl TryFromExpression<KiCadText> for KiCadText {
-    fn try_from_expression(expression: Expression) -> Result<KiCadText, Error> {
-        check_expression_validity(&expression, "text".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadText, Error> {
+        check_expression_validity(expression, "text".to_string())?;
 
         let Some(Word(text)) = expression.get(2) else { bail!("Text does not contain text") };
 
-        let subexpressions = subdivide_expression(expression[3..expression.len()].to_owned());
+        let subexpressions = subdivide_expression(&expression[3..]);
 
         let mut location = None;
         let mut effects = None;
 
         for expression in subexpressions {
             if let Some(Word(property)) = expression.get(1) {
-                let property = property.as_str();
+                let property: &str = property;
                 match property {
                     "effects" => {
-                        effects = Some(KiCadEffects::try_from_expression(expression)?);
+                        effects = Some(KiCadEffects::parse_expression(expression)?);
                     },
                     "at" => {
-                        location = Some(KiCadLocation::try_from_expression(expression)?);
+                        location = Some(KiCadLocation::parse_expression(expression)?);
                     },
                     _ => {
-                        bail!("Not a valid KiCad text property: {property}");
+                        return Err(locate_error(anyhow!("Not a valid KiCad text property: {property}"), expression_offset(expression)));
                     }
                 }
             }
@@ -579,15 +1022,285 @@ impl TryFromExpression<KiCadText> for KiCadText {
     }
 }
 
-#[derive(Clone)]
-pub(crate) struct KiCadSymbol {
+impl ToSExpr for KiCadText {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![SExpr::quoted(&self.text), self.location.to_sexpr()];
+        children.push(effects_or_default(&self.effects));
+        SExpr::list("text", children)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct KiCadSize {
+    width: f32,
+    height: f32,
+}
+
+impl TryFromExpression<KiCadSize> for KiCadSize {
+    fn try_from_expression(expression: &Expression) -> Result<KiCadSize, Error> {
+        check_expression_validity(expression, "size".to_string())?;
+
+        let Some(Word(width)) = expression.get(2) else { bail!("Size does not contain width") };
+        let Some(Word(height)) = expression.get(3) else { bail!("Size does not contain height") };
+
+        Ok(Self { width: width.parse::<f32>()?, height: height.parse::<f32>()? })
+    }
+}
+
+impl ToSExpr for KiCadSize {
+    fn to_sexpr(&self) -> SExpr {
+        SExpr::list("size", vec![SExpr::atom(self.width), SExpr::atom(self.height)])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct KiCadTextBox {
+    text: String,
+    location: KiCadLocation,
+    size: KiCadSize,
+    stroke: Option<KiCadStroke>,
+    fill: Option<KiCadFill>,
+    effects: Option<KiCadEffects>,
+}
+
+impl TryFromExpression<KiCadTextBox> for KiCadTextBox {
+    fn try_from_expression(expression: &Expression) -> Result<KiCadTextBox, Error> {
+        check_expression_validity(expression, "text_box".to_string())?;
+
+        let Some(Word(text)) = expression.get(2) else { bail!("Text box does not contain text") };
+
+        let subexpressions = subdivide_expression(&expression[3..]);
+
+        let mut location = None;
+        let mut size = None;
+        let mut stroke = None;
+        let mut fill = None;
+        let mut effects = None;
+
+        for expression in subexpressions {
+            if let Some(Word(property)) = expression.get(1) {
+                let property: &str = property;
+                match property {
+                    "at" => {
+                        location = Some(KiCadLocation::parse_expression(expression)?);
+                    },
+                    "size" => {
+                        size = Some(KiCadSize::parse_expression(expression)?);
+                    },
+                    "stroke" => {
+                        stroke = Some(KiCadStroke::parse_expression(expression)?);
+                    },
+                    "fill" => {
+                        fill = Some(KiCadFill::parse_expression(expression)?);
+                    },
+                    "effects" => {
+                        effects = Some(KiCadEffects::parse_expression(expression)?);
+                    },
+                    _ => {
+                        return Err(locate_error(anyhow!("Not a valid KiCad text box property: {property}"), expression_offset(expression)));
+                    }
+                }
+            }
+        }
+        let location = location.ok_or(anyhow!("Text box does not contain location"))?;
+        let size = size.ok_or(anyhow!("Text box does not contain size"))?;
+        Ok(Self { text: text.to_string(), location, size, stroke, fill, effects })
+    }
+}
+
+impl ToSExpr for KiCadTextBox {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![SExpr::quoted(&self.text), self.location.to_sexpr(), self.size.to_sexpr()];
+        if let Some(stroke) = &self.stroke {
+            children.push(stroke.to_sexpr());
+        }
+        if let Some(fill) = &self.fill {
+            children.push(fill.to_sexpr());
+        }
+        children.push(effects_or_default(&self.effects));
+        SExpr::list("text_box", children)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KiCadSymbol {
     name: String,
+    extends: Option<String>,
+    power: bool,
+    pin_numbers: Option<KiCadPinNumbers>,
     pin_names: Option<KiCadPinNames>,
     exclude_from_sim: Option<KiCadSingleValueProperty>,
     in_bom: Option<KiCadSingleValueProperty>,
     on_board: Option<KiCadSingleValueProperty>,
     properties: Vec<KiCadProperty>,
     sub_symbols: Vec<KiCadSubSymbol>,
+    embedded_fonts: Option<bool>,
+}
+
+impl KiCadSymbol {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The symbol this one is derived from in the same library.
+    pub(crate) fn extends(&self) -> Option<&str> {
+        self.extends.as_deref()
+    }
+
+    pub fn properties(&self) -> &[KiCadProperty] {
+        &self.properties
+    }
+
+    pub(crate) fn sub_symbols(&self) -> &[KiCadSubSymbol] {
+        &self.sub_symbols
+    }
+
+    pub fn property_value(&self, property_type: &KiCadPropertyType) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|property| &property.property_type == property_type)
+            .map(|property| property.value.as_str())
+    }
+
+    /// The hyperlinks of the text items drawn in the symbol.
+    pub(crate) fn text_hyperlinks(&self) -> Vec<&str> {
+        self.sub_symbols
+            .iter()
+            .flat_map(KiCadSubSymbol::text_effects)
+            .filter_map(|effects| effects.href.as_deref())
+            .collect()
+    }
+
+    /// Points the Datasheet and every text hyperlink that link to `old` at `new`.
+    pub(crate) fn replace_link(&mut self, old: &str, new: &str) {
+        for property in self.properties.iter_mut() {
+            if property.property_type == KiCadPropertyType::Datasheet && property.value == old {
+                property.value = new.to_string();
+            }
+        }
+        for effects in self.sub_symbols.iter_mut().flat_map(KiCadSubSymbol::text_effects_mut) {
+            if effects.href.as_deref() == Some(old) {
+                effects.href = Some(new.to_string());
+            }
+        }
+    }
+
+    /// Renames the symbol along with its units, `<name>_<unit>_<body style>`,
+    /// and its Value if that was the name.
+    pub(crate) fn rename(&mut self, new: &str) {
+        for sub_symbol in &mut self.sub_symbols {
+            if let Some(suffix) = sub_symbol.name.strip_prefix(&self.name) {
+                sub_symbol.name = format!("{new}{suffix}");
+            }
+        }
+        for property in &mut self.properties {
+            if property.property_type == KiCadPropertyType::Value && property.value == self.name {
+                property.value = new.to_string();
+            }
+        }
+        self.name = new.to_string();
+    }
+
+    /// Points a derived symbol at the new name of the symbol it extends.
+    pub(crate) fn rename_extended(&mut self, old: &str, new: &str) {
+        if self.extends.as_deref() == Some(old) {
+            self.extends = Some(new.to_string());
+        }
+    }
+
+    /// Gives a property another name, keeping its value and placement.
+    pub(crate) fn rename_property(&mut self, from: &KiCadPropertyType, to: KiCadPropertyType) {
+        if let Some(property) = self.properties.iter_mut().find(|property| &property.property_type == from) {
+            property.property_type = to;
+        }
+    }
+
+    /// Whether the symbol draws the same units as `other`, whatever their
+    /// names and properties.
+    pub(crate) fn has_same_body(&self, other: &KiCadSymbol) -> bool {
+        let units = |symbol: &KiCadSymbol| -> Vec<KiCadSubSymbol> {
+            let unit_name = |sub_symbol: &KiCadSubSymbol| sub_symbol.name.strip_prefix(&symbol.name).unwrap_or(&sub_symbol.name).to_string();
+            symbol.sub_symbols.iter().map(|sub_symbol| KiCadSubSymbol { name: unit_name(sub_symbol), ..sub_symbol.clone() }).collect()
+        };
+        self.power == other.power && self.pin_numbers == other.pin_numbers && self.pin_names == other.pin_names && units(self) == units(other)
+    }
+
+    /// The pins added, removed and changed from `old` to this symbol, matched
+    /// by unit, body style and number.
+    pub(crate) fn pin_changes_from(&self, old: &KiCadSymbol) -> Vec<String> {
+        let (old_pins, new_pins) = (old.pins_by_unit(), self.pins_by_unit());
+        let multi_unit = old_pins.iter().chain(&new_pins).any(|((unit, _, _), _)| *unit > 1);
+        let label = |(unit, body_style, number): &(u32, u32, &str)| {
+            let mut label = format!("pin {number}");
+            if multi_unit && *unit > 0 {
+                label += &format!(" of unit {unit}");
+            }
+            if *body_style == 2 {
+                label += " (De Morgan)";
+            }
+            label
+        };
+        let describe = |pin: &&KiCadPin| match pin.name().filter(|name| !name.is_empty() && *name != "~") {
+            Some(name) => format!("{name} ({})", pin.pin_type()),
+            None => format!("({})", pin.pin_type()),
+        };
+
+        let mut changes = vec![];
+        for (key, pin) in &new_pins {
+            match old_pins.iter().find(|(old_key, _)| old_key == key) {
+                None => changes.push(format!("+ {} {}", label(key), describe(pin))),
+                Some((_, old_pin)) => {
+                    let pin_changes = pin.changes_from(old_pin);
+                    if !pin_changes.is_empty() {
+                        changes.push(format!("~ {}: {}", label(key), pin_changes.join(", ")));
+                    }
+                }
+            }
+        }
+        for (key, pin) in old_pins.iter().filter(|(key, _)| !new_pins.iter().any(|(new_key, _)| new_key == key)) {
+            changes.push(format!("- {} {}", label(key), describe(pin)));
+        }
+        changes
+    }
+
+    /// The number of units, 0 for derived symbols which have the units of
+    /// their parent.
+    pub(crate) fn unit_count(&self) -> u32 {
+        self.sub_symbols.iter().filter_map(KiCadSubSymbol::unit).max().map_or(0, |units| units.max(1))
+    }
+
+    /// The pins of all units with their unit, body style and number.
+    fn pins_by_unit(&self) -> Vec<((u32, u32, &str), &KiCadPin)> {
+        let mut pins = vec![];
+        for sub_symbol in &self.sub_symbols {
+            let (unit, body_style) = (sub_symbol.unit().unwrap_or_default(), sub_symbol.body_style().unwrap_or(1));
+            pins.extend(sub_symbol.pins().iter().map(|pin| ((unit, body_style, pin.number().unwrap_or_default()), pin)));
+        }
+        pins
+    }
+
+    /// Turns the symbol into one derived from `parent`, keeping its name and
+    /// properties but drawing the units of the parent.
+    pub(crate) fn derive_from(&mut self, parent: &str) {
+        self.extends = Some(parent.to_string());
+        self.power = false;
+        self.pin_numbers = None;
+        self.pin_names = None;
+        self.sub_symbols.clear();
+    }
+
+    pub(crate) fn remove_property(&mut self, property_type: &KiCadPropertyType) {
+        self.properties.retain(|property| &property.property_type != property_type);
+    }
+
+    /// Replaces the value of an existing property of the same type, or adds
+    /// the property if the symbol does not have it yet.
+    pub fn set_property(&mut self, property: KiCadProperty) {
+        match self.properties.iter_mut().find(|existing| existing.property_type == property.property_type) {
+            Some(existing) => existing.value = property.value,
+            None => self.properties.push(property),
+        }
+    }
 }
 
 pub(crate) fn check_expression_validity(
@@ -597,62 +1310,74 @@ pub(crate) fn check_expression_validity(
     if expression.len() < 2 {
         bail!("Expression smaller than two: {expression:?}");
     }
-    if !(expression.first() == Some(&Token::OpenParen)
-        && expression.get(1) == Some(&Word(property)))
+    if !(matches!(expression.first(), Some(Token::OpenParen(_)))
+        && expression.get(1) == Some(&Word(property.into())))
     {
         bail!("Not a valid KiCad symbol: {expression:?}")
     }
     Ok(())
 }
 
-fn get_expression_first_value(expression: &Expression) -> Result<Token, anyhow::Error> {
+fn get_expression_first_value<'a>(expression: &Expression<'a>) -> Result<Token<'a>, anyhow::Error> {
     if expression.len() < 2 {
         bail!("Expression smaller than two: {expression:?}");
     }
-    if expression.first() != Some(&Token::OpenParen) {
+    if !matches!(expression.first(), Some(Token::OpenParen(_))) {
         bail!("Expression does not start with opening parenthesis")
     }
     Ok(expression[1].to_owned())
 }
 
 impl TryFromExpression<KiCadSymbol> for KiCadSymbol {
-    fn try_from_expression(expression: Expression) -> Result<KiCadSymbol, Error> {
-        check_expression_validity(&expression, "symbol".to_string())?;
+    fn try_from_expression(expression: &Expression) -> Result<KiCadSymbol, Error> {
+        check_expression_validity(expression, "symbol".to_string())?;
 
         let Word(name) = &expression[2] else {
             bail!("Symbol has no name")
         };
 
-        let new_expression = Expression::from(&expression[3..expression.len()]);
-
-        let subexpressions = subdivide_expression(new_expression);
+        let subexpressions = subdivide_expression(&expression[3..]);
         let mut kicad_symbol_builder = KiCadSymbolBuilder::new(name.to_string());
 
         for expression in subexpressions {
             
             if let Some(Word(value)) = expression.get(1) {
-                let value = value.as_str();
+                let value: &str = value;
                 match value {
+                    "extends" => {
+                        let Some(Word(parent)) = expression.get(2) else { bail!("Extends does not contain parent symbol") };
+                        kicad_symbol_builder.extends(parent.to_string());
+                    },
+                    "power" => {
+                        kicad_symbol_builder.power();
+                    },
+                    "pin_numbers" => {
+                        kicad_symbol_builder.pin_numbers(KiCadPinNumbers::parse_expression(expression)?);
+                    },
                     "pin_names" => {
-                        kicad_symbol_builder.pin_names(KiCadPinNames::try_from_expression(expression)?);
+                        kicad_symbol_builder.pin_names(KiCadPinNames::parse_expression(expression)?);
                     },
                     "exclude_from_sim" => {
-                        kicad_symbol_builder.exclude_from_sim(KiCadSingleValueProperty::try_from_expression(expression)?);
+                        kicad_symbol_builder.exclude_from_sim(KiCadSingleValueProperty::parse_expression(expression)?);
                     },
                     "in_bom" => {
-                        kicad_symbol_builder.in_bom(KiCadSingleValueProperty::try_from_expression(expression)?);
+                        kicad_symbol_builder.in_bom(KiCadSingleValueProperty::parse_expression(expression)?);
                     },
                     "on_board" => {
-                        kicad_symbol_builder.on_board(KiCadSingleValueProperty::try_from_expression(expression)?);
+                        kicad_symbol_builder.on_board(KiCadSingleValueProperty::parse_expression(expression)?);
                     },
                     "property" => {
-                        kicad_symbol_builder.add_property(KiCadProperty::try_from_expression(expression)?);
+                        kicad_symbol_builder.add_property(KiCadProperty::parse_expression(expression)?);
                     },
                     "symbol" => {
-                        kicad_symbol_builder.add_sub_symbol(KiCadSubSymbol::try_from_expression(expression)?);
+                        kicad_symbol_builder.add_sub_symbol(KiCadSubSymbol::parse_expression(expression)?);
+                    },
+                    "embedded_fonts" => {
+                        let Some(Word(embedded_fonts)) = expression.get(2) else { bail!("Embedded fonts does not contain value") };
+                        kicad_symbol_builder.embedded_fonts(try_parse_string_to_bool(embedded_fonts)?);
                     },
                     _ => {
-                        bail!("Not a valid KiCad symbol property: {value}");
+                        return Err(locate_error(anyhow!("Not a valid KiCad symbol property: {value}"), expression_offset(expression)));
                     }
                 }
             }
@@ -662,19 +1387,74 @@ impl TryFromExpression<KiCadSymbol> for KiCadSymbol {
     }
 }
 
+impl ToSExpr for KiCadSymbol {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![SExpr::quoted(&self.name)];
+        if let Some(extends) = &self.extends {
+            children.push(SExpr::list("extends", vec![SExpr::quoted(extends)]));
+        }
+        if self.power {
+            children.push(SExpr::list("power", vec![]));
+        }
+        if let Some(pin_numbers) = &self.pin_numbers {
+            children.push(pin_numbers.to_sexpr());
+        }
+        if let Some(pin_names) = &self.pin_names {
+            children.push(pin_names.to_sexpr());
+        }
+        for property in [&self.exclude_from_sim, &self.in_bom, &self.on_board].into_iter().flatten() {
+            children.push(property.to_sexpr());
+        }
+        children.extend(self.properties.iter().map(ToSExpr::to_sexpr));
+        children.extend(self.sub_symbols.iter().map(ToSExpr::to_sexpr));
+        if let Some(embedded_fonts) = self.embedded_fonts {
+            children.push(SExpr::list("embedded_fonts", vec![SExpr::atom(bool_to_kicad_string(embedded_fonts))]));
+        }
+        SExpr::list("symbol", children)
+    }
+}
+
 struct KiCadSymbolBuilder {
     name: String,
+    extends: Option<String>,
+    power: bool,
+    pin_numbers: Option<KiCadPinNumbers>,
     pin_names: Option<KiCadPinNames>,
     exclude_from_sim: Option<KiCadSingleValueProperty>,
     in_bom: Option<KiCadSingleValueProperty>,
     on_board: Option<KiCadSingleValueProperty>,
     properties: Vec<KiCadProperty>,
     sub_symbols: Vec<KiCadSubSymbol>,
+    embedded_fonts: Option<bool>,
 }
 
 impl KiCadSymbolBuilder {
     fn new(name: String) -> Self {
-        Self {name, pin_names: None, exclude_from_sim: None, in_bom: None, on_board: None, properties: vec![], sub_symbols: vec![] }
+        Self {
+            name,
+            extends: None,
+            power: false,
+            pin_numbers: None,
+            pin_names: None,
+            exclude_from_sim: None,
+            in_bom: None,
+            on_board: None,
+            properties: vec![],
+            sub_symbols: vec![],
+            embedded_fonts: None,
+        }
+    }
+    fn extends(&mut self, extends: String) -> &mut KiCadSymbolBuilder {
+        self.extends = Some(extends);
+        self
+    }
+    fn power(&mut self) -> &mut KiCadSymbolBuilder {
+        self.power = true;
+        self
+    }
+    fn pin_numbers(&mut self, pin_numbers: KiCadPinNumbers) -> &mut KiCadSymbolBuilder {
+        self.pin_numbers = Some(pin_numbers);
+        self
     }
     fn pin_names(&mut self, pin_names: KiCadPinNames) -> &mut KiCadSymbolBuilder {
         self.pin_names = Some(pin_names);
@@ -700,54 +1480,340 @@ impl KiCadSymbolBuilder {
         self.sub_symbols.push(sub_symbol);
         self
     }
+    fn embedded_fonts(&mut self, embedded_fonts: bool) -> &mut KiCadSymbolBuilder {
+        self.embedded_fonts = Some(embedded_fonts);
+        self
+    }
     fn build(self) -> KiCadSymbol {
         KiCadSymbol {
             name: self.name,
+            extends: self.extends,
+            power: self.power,
+            pin_numbers: self.pin_numbers,
             pin_names: self.pin_names,
             exclude_from_sim: self.exclude_from_sim,
             in_bom: self.in_bom,
             on_board: self.on_board,
             properties: self.properties,
-            sub_symbols: self.sub_symbols
+            sub_symbols: self.sub_symbols,
+            embedded_fonts: self.embedded_fonts,
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct KiCadSubSymbol {
+    name: String,
+    unit_name: Option<String>,
+    arcs: Vec<KiCadArc>,
+    circles: Vec<KiCadCircle>,
+    rectangles: Vec<KiCadRectangle>,
     polylines: Vec<KiCadPolyline>,
     texts: Vec<KiCadText>,
+    text_boxes: Vec<KiCadTextBox>,
     pins: Vec<KiCadPin>,
 }
 
+impl KiCadSubSymbol {
+    pub(crate) fn pins(&self) -> &[KiCadPin] {
+        &self.pins
+    }
+
+    /// Sub symbols are named `<symbol>_<unit>_<body style>`, where unit 0 holds
+    /// the items shared by all units.
+    pub(crate) fn unit(&self) -> Option<u32> {
+        self.name.rsplit('_').nth(1)?.parse().ok()
+    }
+
+    /// Body style 1 is the normal body, 2 the De Morgan alternate and 0 is
+    /// shared by both.
+    pub(crate) fn body_style(&self) -> Option<u32> {
+        self.name.rsplit('_').next()?.parse().ok()
+    }
+
+    fn text_effects(&self) -> impl Iterator<Item = &KiCadEffects> {
+        let texts = self.texts.iter().filter_map(|text| text.effects.as_ref());
+        texts.chain(self.text_boxes.iter().filter_map(|text_box| text_box.effects.as_ref()))
+    }
+
+    fn text_effects_mut(&mut self) -> impl Iterator<Item = &mut KiCadEffects> {
+        let texts = self.texts.iter_mut().filter_map(|text| text.effects.as_mut());
+        texts.chain(self.text_boxes.iter_mut().filter_map(|text_box| text_box.effects.as_mut()))
+    }
+}
+
 impl TryFromExpression<KiCadSubSymbol> for KiCadSubSymbol {
-    fn try_from_expression(expression: Expression) -> Result<KiCadSubSymbol, Error> {
-        check_expression_validity(&expression, "symbol".to_string())?;
-        let subexpressions = subdivide_expression(expression[2..expression.len()].to_owned());
+    fn try_from_expression(expression: &Expression) -> Result<KiCadSubSymbol, Error> {
+        check_expression_validity(expression, "symbol".to_string())?;
 
+        let Some(Word(name)) = expression.get(2) else {
+            bail!("Sub symbol has no name")
+        };
+        let subexpressions = subdivide_expression(&expression[3..]);
+
+        let mut unit_name = None;
+        let mut arcs = vec![];
+        let mut circles = vec![];
+        let mut rectangles = vec![];
         let mut polylines = vec![];
         let mut texts = vec![];
+        let mut text_boxes = vec![];
         let mut pins = vec![];
 
         for expression in subexpressions {
             if let Some(Word(value)) = expression.get(1) {
-                let value = value.as_str();
+                let value: &str = value;
                 match value {
+                    "unit_name" => {
+                        let Some(Word(unit_name_value)) = expression.get(2) else { bail!("Unit name does not contain value") };
+                        unit_name = Some(unit_name_value.to_string());
+                    },
+                    "arc" => {
+                        arcs.push(KiCadArc::parse_expression(expression)?);
+                    },
+                    "circle" => {
+                        circles.push(KiCadCircle::parse_expression(expression)?);
+                    },
+                    "rectangle" => {
+                        rectangles.push(KiCadRectangle::parse_expression(expression)?);
+                    },
                     "polyline" => {
-                        polylines.push(KiCadPolyline::try_from_expression(expression)?);
+                        polylines.push(KiCadPolyline::parse_expression(expression)?);
                     },
                     "text" => {
-                        texts.push(KiCadText::try_from_expression(expression)?);
+                        texts.push(KiCadText::parse_expression(expression)?);
+                    },
+                    "text_box" => {
+                        text_boxes.push(KiCadTextBox::parse_expression(expression)?);
                     },
                     "pin" => {
-                        pins.push(KiCadPin::try_from_expression(expression)?);
+                        pins.push(KiCadPin::parse_expression(expression)?);
                     },
                     _ => {
-                        bail!("Not a valid KiCad sub symbol property: {value}");
+                        return Err(locate_error(anyhow!("Not a valid KiCad sub symbol property: {value}"), expression_offset(expression)));
                     }
                 }
             }
         }
-        Ok(Self { polylines, texts, pins })
+        Ok(Self { name: name.to_string(), unit_name, arcs, circles, rectangles, polylines, texts, text_boxes, pins })
+    }
+}
+
+impl ToSExpr for KiCadSubSymbol {
+    fn to_sexpr(&self) -> SExpr {
+        let mut children = vec![SExpr::quoted(&self.name)];
+        if let Some(unit_name) = &self.unit_name {
+            children.push(SExpr::list("unit_name", vec![SExpr::quoted(unit_name)]));
+        }
+        children.extend(self.arcs.iter().map(ToSExpr::to_sexpr));
+        children.extend(self.circles.iter().map(ToSExpr::to_sexpr));
+        children.extend(self.rectangles.iter().map(ToSExpr::to_sexpr));
+        children.extend(self.polylines.iter().map(ToSExpr::to_sexpr));
+        children.extend(self.texts.iter().map(ToSExpr::to_sexpr));
+        children.extend(self.text_boxes.iter().map(ToSExpr::to_sexpr));
+        children.extend(self.pins.iter().map(ToSExpr::to_sexpr));
+        SExpr::list("symbol", children)
+    }
+}
+
+/// Strategies for the `proptest` feature. Only values the writer puts back as
+/// they are get generated, e.g. effects always have a font because the writer
+/// adds the default font to effects without one.
+#[cfg(feature = "proptest")]
+pub(crate) mod strategy {
+    use super::*;
+    use crate::symbols::arbitrary::{coordinate, text};
+    use crate::symbols::graphics::strategy::{arc, circle, rectangle};
+    use crate::symbols::pin::strategy::pin;
+    use proptest::collection::vec;
+    use proptest::option;
+    use proptest::prelude::*;
+    use proptest::sample::select;
+
+    impl Arbitrary for KiCadPropertyType {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            let known = select(vec![
+                KiCadPropertyType::Reference,
+                KiCadPropertyType::Value,
+                KiCadPropertyType::Footprint,
+                KiCadPropertyType::Datasheet,
+                KiCadPropertyType::Description,
+                KiCadPropertyType::KiLocked,
+                KiCadPropertyType::KiKeywords,
+                KiCadPropertyType::KiFpFilters,
+                KiCadPropertyType::PartRev,
+                KiCadPropertyType::Standard,
+                KiCadPropertyType::MaximumPackageHeight,
+                KiCadPropertyType::Manufacturer,
+            ]);
+            // Names of known properties parse as the known property, not as `Custom`
+            let custom = text().prop_map(|name| KiCadPropertyType::from_str(&name).unwrap_or(KiCadPropertyType::Custom(name)));
+            prop_oneof![known, custom].boxed()
+        }
+    }
+
+    impl Arbitrary for KiCadProperty {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            let id = option::of(any::<u32>().prop_map(KiCadPropertyId));
+            (any::<KiCadPropertyType>(), text(), id, option::of(location()), any::<bool>(), any::<bool>(), effects())
+                .prop_map(|(property_type, value, id, location, show_name, do_not_autoplace, effects)| KiCadProperty {
+                    property_type,
+                    value,
+                    id,
+                    location,
+                    show_name,
+                    do_not_autoplace,
+                    effects: Some(effects),
+                })
+                .boxed()
+        }
+    }
+
+    impl Arbitrary for KiCadSymbol {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            let pin_numbers = any::<bool>().prop_map(|hide| KiCadPinNumbers { hide });
+            let pin_names = (option::of(coordinate().prop_map(Offset)), any::<bool>()).prop_map(|(offset, hide)| KiCadPinNames { offset, hide });
+            (
+                text(),
+                option::of(text()),
+                any::<bool>(),
+                option::of(pin_numbers),
+                option::of(pin_names),
+                option::of(any::<bool>().prop_map(KiCadSingleValueProperty::ExcludeFromSim)),
+                option::of(any::<bool>().prop_map(KiCadSingleValueProperty::InBom)),
+                option::of(any::<bool>().prop_map(KiCadSingleValueProperty::OnBoard)),
+                vec(any::<KiCadProperty>(), 0..6),
+                vec(sub_symbol(), 0..3),
+                option::of(any::<bool>()),
+            )
+                .prop_map(
+                    |(name, extends, power, pin_numbers, pin_names, exclude_from_sim, in_bom, on_board, properties, sub_symbols, embedded_fonts)| {
+                        KiCadSymbol {
+                            name,
+                            extends,
+                            power,
+                            pin_numbers,
+                            pin_names,
+                            exclude_from_sim,
+                            in_bom,
+                            on_board,
+                            properties,
+                            sub_symbols,
+                            embedded_fonts,
+                        }
+                    },
+                )
+                .boxed()
+        }
+    }
+
+    pub(crate) fn location() -> impl Strategy<Value = KiCadLocation> {
+        (coordinate(), coordinate(), coordinate())
     }
-}
\ No newline at end of file
+
+    pub(crate) fn point() -> impl Strategy<Value = KiCad2DPoint> {
+        (coordinate(), coordinate()).prop_map(|(x, y)| KiCad2DPoint { x, y })
+    }
+
+    fn color() -> impl Strategy<Value = KiCadColor> {
+        (any::<u8>(), any::<u8>(), any::<u8>(), 0..=100u8).prop_map(|(r, g, b, a)| KiCadColor { r, g, b, a: f32::from(a) / 100.0 })
+    }
+
+    fn font() -> impl Strategy<Value = KiCadFont> {
+        let font_size = (coordinate(), coordinate()).prop_map(|(width, height)| KiCadFontSize { width, height });
+        (option::of(text()), option::of(font_size), option::of(coordinate()), any::<[bool; 6]>(), option::of(color()), option::of(coordinate()))
+            .prop_map(|(face, font_size, thickness, [bold, italic, subscript, superscript, overbar, underline], color, line_spacing)| KiCadFont {
+                face,
+                font_size,
+                thickness,
+                bold,
+                italic,
+                subscript,
+                superscript,
+                overbar,
+                underline,
+                color,
+                line_spacing,
+            })
+    }
+
+    pub(crate) fn effects() -> impl Strategy<Value = KiCadEffects> {
+        let justify = select(vec![
+            KiCadEffectsJustify::Bottom,
+            KiCadEffectsJustify::Top,
+            KiCadEffectsJustify::Left,
+            KiCadEffectsJustify::Right,
+            KiCadEffectsJustify::Mirror,
+        ]);
+        (font(), any::<bool>(), vec(justify, 0..3), option::of(text()))
+            .prop_map(|(font, hide, justify, href)| KiCadEffects { font: Some(font), hide, justify, href })
+    }
+
+    pub(crate) fn stroke() -> impl Strategy<Value = KiCadStroke> {
+        let stroke_type = select(vec![
+            KiCadStrokeType::Default,
+            KiCadStrokeType::Solid,
+            KiCadStrokeType::Dash,
+            KiCadStrokeType::Dot,
+            KiCadStrokeType::DashDot,
+            KiCadStrokeType::DashDotDot,
+        ]);
+        (option::of(coordinate()), option::of(stroke_type), option::of(color()))
+            .prop_map(|(width, stroke_type, color)| KiCadStroke { width, stroke_type, color })
+    }
+
+    pub(crate) fn fill() -> impl Strategy<Value = KiCadFill> {
+        let fill_type = select(vec![KiCadFillType::Background, KiCadFillType::Outline, KiCadFillType::Color, KiCadFillType::None]);
+        (option::of(fill_type), option::of(color())).prop_map(|(fill_type, color)| KiCadFill { fill_type, color })
+    }
+
+    fn polyline() -> impl Strategy<Value = KiCadPolyline> {
+        (vec(point().prop_map(KiCadXY), 0..6), option::of(stroke()), option::of(fill()))
+            .prop_map(|(pts, stroke, fill)| KiCadPolyline { pts, stroke, fill })
+    }
+
+    fn text_item() -> impl Strategy<Value = KiCadText> {
+        (text(), location(), effects()).prop_map(|(text, location, effects)| KiCadText { text, location, effects: Some(effects) })
+    }
+
+    fn text_box() -> impl Strategy<Value = KiCadTextBox> {
+        let size = (coordinate(), coordinate()).prop_map(|(width, height)| KiCadSize { width, height });
+        (text(), location(), size, option::of(stroke()), option::of(fill()), effects()).prop_map(
+            |(text, location, size, stroke, fill, effects)| KiCadTextBox { text, location, size, stroke, fill, effects: Some(effects) },
+        )
+    }
+
+    fn sub_symbol() -> impl Strategy<Value = KiCadSubSymbol> {
+        (
+            text(),
+            option::of(text()),
+            vec(arc(), 0..3),
+            vec(circle(), 0..3),
+            vec(rectangle(), 0..3),
+            vec(polyline(), 0..3),
+            vec(text_item(), 0..3),
+            vec(text_box(), 0..2),
+            vec(pin(), 0..4),
+        )
+            .prop_map(|(name, unit_name, arcs, circles, rectangles, polylines, texts, text_boxes, pins)| KiCadSubSymbol {
+                name,
+                unit_name,
+                arcs,
+                circles,
+                rectangles,
+                polylines,
+                texts,
+                text_boxes,
+                pins,
+            })
+    }
+}
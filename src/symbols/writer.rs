@@ -0,0 +1,143 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+const MAX_LINE_WIDTH: usize = 99;
+
+/// A tree of S-expression nodes as KiCad writes them. Unlike the flat
+/// [`Token`](crate::symbols::Token) stream used for parsing, the writer needs
+/// to know which strings KiCad quotes, so quoted and bare atoms are separate.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum SExpr {
+    List(Vec<SExpr>),
+    Atom(String),
+    Quoted(String),
+    /// An expression copied from a file as it was written there
+    Raw(String),
+}
+
+impl SExpr {
+    pub(crate) fn list(name: &str, children: Vec<SExpr>) -> SExpr {
+        let mut list = vec![SExpr::atom(name)];
+        list.extend(children);
+        SExpr::List(list)
+    }
+
+    pub(crate) fn atom<T: ToString>(value: T) -> SExpr {
+        SExpr::Atom(value.to_string())
+    }
+
+    pub(crate) fn quoted<T: ToString>(value: T) -> SExpr {
+        SExpr::Quoted(value.to_string())
+    }
+
+    fn is_list(&self) -> bool {
+        matches!(self, SExpr::List(_) | SExpr::Raw(_))
+    }
+
+    fn is_xy(&self) -> bool {
+        match self {
+            SExpr::List(children) => children.first() == Some(&SExpr::atom("xy")),
+            _ => false,
+        }
+    }
+
+    fn write_inline(&self, out: &mut String) {
+        match self {
+            SExpr::List(children) => {
+                out.push('(');
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    child.write_inline(out);
+                }
+                out.push(')');
+            }
+            SExpr::Atom(value) | SExpr::Raw(value) => out.push_str(value),
+            SExpr::Quoted(value) => {
+                out.push('"');
+                for c in value.chars() {
+                    match c {
+                        '"' => out.push_str("\\\""),
+                        '\\' => out.push_str("\\\\"),
+                        '\n' => out.push_str("\\n"),
+                        '\r' => out.push_str("\\r"),
+                        '\t' => out.push_str("\\t"),
+                        _ => out.push(c),
+                    }
+                }
+                out.push('"');
+            }
+        }
+    }
+
+    /// Writes the expression the way KiCad 8 formats its files: lists that only
+    /// contain atoms stay on one line, every nested list goes on its own line
+    /// indented by a tab, and runs of `xy` points are packed onto shared lines.
+    fn write_pretty(&self, out: &mut String, indent: usize) {
+        let SExpr::List(children) = self else {
+            self.write_inline(out);
+            return;
+        };
+        if !children.iter().any(SExpr::is_list) {
+            self.write_inline(out);
+            return;
+        }
+
+        out.push('(');
+        let first_list = children.iter().position(SExpr::is_list).unwrap_or(children.len());
+        for (i, child) in children[..first_list].iter().enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            child.write_inline(out);
+        }
+
+        let mut line_start = None;
+        for child in &children[first_list..] {
+            if child.is_xy() {
+                let mut point = String::new();
+                child.write_inline(&mut point);
+                if let Some(start) = line_start {
+                    if out.len() - start + point.len() < MAX_LINE_WIDTH {
+                        out.push(' ');
+                        out.push_str(&point);
+                        continue;
+                    }
+                }
+                new_line(out, indent + 1);
+                line_start = Some(out.len() - (indent + 1));
+                out.push_str(&point);
+                continue;
+            }
+            line_start = None;
+            new_line(out, indent + 1);
+            child.write_pretty(out, indent + 1);
+        }
+        new_line(out, indent);
+        out.push(')');
+    }
+}
+
+fn new_line(out: &mut String, indent: usize) {
+    out.push('\n');
+    for _ in 0..indent {
+        out.push('\t');
+    }
+}
+
+impl Display for SExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        f.write_str(&out)
+    }
+}
+
+pub(crate) fn bool_to_kicad_string(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
@@ -0,0 +1,163 @@
+use crate::config::UpdateConfig;
+use crate::dry_run;
+use crate::installation::{env_dir, home};
+use anyhow::{anyhow, bail};
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::env::consts::{ARCH, EXE_SUFFIX, OS};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Public key the release binaries are signed with, embedded by the release build
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("KLM_RELEASE_PUBLIC_KEY");
+/// File in the user config directory with the `public_key` of in-house builds
+const USER_KEY_FILE: &str = "update.toml";
+/// Release binaries can be large, the default download limit of ureq is 10 MB
+const MAX_DOWNLOAD_BYTES: u64 = 200 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset_url(&self, name: &str) -> Result<&str, anyhow::Error> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .map(|asset| asset.browser_download_url.as_str())
+            .ok_or_else(|| anyhow!("Release {} has no {name}", self.tag_name))
+    }
+}
+
+/// The release binary for this platform, e.g. `kicad-library-manager-x86_64-windows.exe`.
+/// Its SHA-256 hash and minisign signature are published as `<binary>.sha256`
+/// and `<binary>.minisig`, signed with `version:<tag>` in the trusted comment.
+fn binary_name() -> String {
+    format!("{}-{ARCH}-{OS}{EXE_SUFFIX}", env!("CARGO_PKG_NAME"))
+}
+
+/// Version numbers of a tag like `v1.2.3`, compared component by component.
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split(['.', '-', '+'])
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+/// The directory of this program in the user config directory, e.g.
+/// `~/.config/kicad-library-manager`.
+fn user_config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| home().map(|home| home.join(".config")))
+    };
+    Some(base?.join(env!("CARGO_PKG_NAME")))
+}
+
+#[derive(Deserialize)]
+struct UserKey {
+    public_key: String,
+}
+
+/// The signing key of in-house builds from the user config directory. It is
+/// never read from the working directory, which may be someone else's
+/// repository with a `klm.toml` of its own.
+fn user_public_key() -> Result<String, anyhow::Error> {
+    let path = user_config_dir().ok_or_else(|| anyhow!("No user config directory"))?.join(USER_KEY_FILE);
+    let content = fs::read_to_string(&path).map_err(|error| anyhow!("{}: {error}", path.display()))?;
+    let key: UserKey = toml::from_str(&content).map_err(|error| anyhow!("{}: {error}", path.display()))?;
+    Ok(key.public_key)
+}
+
+fn download(agent: &ureq::Agent, url: &str) -> Result<Vec<u8>, anyhow::Error> {
+    Ok(agent.get(url).call()?.body_mut().with_config().limit(MAX_DOWNLOAD_BYTES).read_to_vec()?)
+}
+
+/// Checks the release feed and, unless `check_only`, replaces the running
+/// executable with the latest release for this platform once its hash and
+/// signature are verified. The signature must be made with the embedded
+/// release key, or with the key of the user config directory if `user_key`.
+pub(crate) fn self_update(config: &UpdateConfig, check_only: bool, user_key: bool) -> Result<(), anyhow::Error> {
+    let current = env!("CARGO_PKG_VERSION");
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(config.timeout)))
+        .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .into();
+
+    let body = agent.get(&config.feed).call()?.body_mut().read_to_string()?;
+    let release: Release = serde_json::from_str(&body).map_err(|error| anyhow!("Invalid release feed {}: {error}", config.feed))?;
+    if parse_version(&release.tag_name) <= parse_version(current) {
+        println!("{current} is the latest version");
+        return Ok(());
+    }
+    println!("Version {} is available, this is {current}", release.tag_name);
+    if check_only {
+        return Ok(());
+    }
+
+    // Check everything before downloading the binary
+    let public_key = match RELEASE_PUBLIC_KEY {
+        _ if user_key => user_public_key()?,
+        Some(key) => key.to_string(),
+        None => bail!("This build has no release signing key, pass --user-key to use `public_key` of {USER_KEY_FILE} in the user config directory"),
+    };
+    let public_key = PublicKey::from_base64(&public_key).map_err(|error| anyhow!("Invalid release signing key: {error}"))?;
+    let name = binary_name();
+    let binary_url = release.asset_url(&name)?;
+    let hash_url = release.asset_url(&format!("{name}.sha256"))?;
+    let signature_url = release.asset_url(&format!("{name}.minisig"))?;
+
+    println!("Downloading {binary_url}");
+    let binary = download(&agent, binary_url)?;
+
+    // `sha256sum` output: the hash followed by the file name
+    let hash_file = String::from_utf8(download(&agent, hash_url)?)?;
+    let expected_hash = hash_file.split_whitespace().next().unwrap_or_default().to_lowercase();
+    let hash = format!("{:x}", Sha256::digest(&binary));
+    if hash != expected_hash {
+        bail!("SHA-256 of {name} is {hash}, the release lists {expected_hash}");
+    }
+    let signature = Signature::decode(&String::from_utf8(download(&agent, signature_url)?)?)
+        .map_err(|error| anyhow!("Invalid signature for {name}: {error}"))?;
+    public_key
+        .verify(&binary, &signature, false)
+        .map_err(|error| anyhow!("Signature of {name} does not match the release signing key: {error}"))?;
+    // The trusted comment is signed as well, so an older release cannot be
+    // passed off as this one
+    let version = format!("version:{}", release.tag_name);
+    if !signature.trusted_comment().split_whitespace().any(|field| field == version) {
+        bail!("Signature of {name} is not for release {}, its trusted comment is {:?}", release.tag_name, signature.trusted_comment());
+    }
+
+    if dry_run::is_enabled() {
+        println!("Would update to {}", release.tag_name);
+        return Ok(());
+    }
+    let download_dir = mktemp::Temp::new_dir()?;
+    let new_binary = download_dir.join(&name);
+    fs::write(&new_binary, &binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&new_binary, fs::Permissions::from_mode(0o755))?;
+    }
+    // Also works on Windows, where a running executable cannot be overwritten
+    self_replace::self_replace(&new_binary)?;
+    println!("Updated to {}", release.tag_name);
+    Ok(())
+}
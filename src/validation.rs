@@ -0,0 +1,186 @@
+use crate::config::Config;
+use crate::fields::property_type;
+use crate::pipeline::Validator;
+use crate::symbols::property::{KiCadPropertyType, KiCadSymbol};
+use anyhow::bail;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+pub(crate) mod datasheet;
+pub(crate) mod footprint;
+pub(crate) mod reference;
+
+/// Units whose pin count differs from the median unit by more than this factor
+/// are reported, as that usually means gates were mangled during conversion.
+const UNBALANCED_UNIT_RATIO: usize = 4;
+
+/// The rules the `check` command runs on symbol libraries, and what they check.
+pub(crate) const SYMBOL_RULES: [(&str, &str); 7] = [
+    ("parse", "every symbol of the library can be read"),
+    ("unit-balance", "every unit of a multi-unit symbol has about as many pins as the others"),
+    ("reference-prefix", "the reference prefix is the one `reference_prefixes` gives the category of the part"),
+    ("footprint-reference", "the Footprint names a footprint that exists, in a library of a footprint library table"),
+    ("pin-pad", "the pin numbers match the pad numbers of the footprint"),
+    ("required-fields", "Reference, Value and the fields of `required_fields` are set"),
+    ("policy", "the symbol follows the organisation policy, if one is configured"),
+];
+
+/// Fails for names of rules that do not exist, e.g. misspelled in the config.
+pub(crate) fn check_symbol_rule_names(names: &[String]) -> Result<(), anyhow::Error> {
+    for name in names {
+        if !SYMBOL_RULES.iter().any(|(rule, _)| rule == name) {
+            bail!("Unknown symbol rule {name}, the rules are {}", SYMBOL_RULES.map(|(rule, _)| rule).join(", "));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Severity {
+    Info,
+    Warning,
+    /// Violations of the organisation policy, which stop the import
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    pub symbol: String,
+    pub message: String,
+}
+
+impl Finding {
+    pub fn new(severity: Severity, symbol: &str, message: String) -> Self {
+        Self { severity, symbol: symbol.to_string(), message }
+    }
+
+    /// Prefixes the message with the rule that found it, like `[pin-pad]`.
+    pub(crate) fn with_rule(mut self, rule: &str) -> Self {
+        self.message = format!("[{rule}] {}", self.message);
+        self
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.symbol, self.message)
+    }
+}
+
+pub(crate) fn validate_symbol(symbol: &KiCadSymbol, config: &Config) -> Vec<Finding> {
+    let mut findings = vec![];
+    findings.extend(check_unit_pin_balance(symbol));
+    findings.extend(reference::check_reference_prefix(symbol, &config.reference_prefixes));
+    findings
+}
+
+/// Reports the pins per unit of multi-unit symbols and flags units without
+/// pins or with a pin count far away from the other units.
+pub(crate) fn check_unit_pin_balance(symbol: &KiCadSymbol) -> Vec<Finding> {
+    let mut pins_per_unit = BTreeMap::<u32, usize>::new();
+    for sub_symbol in symbol.sub_symbols() {
+        // De Morgan bodies repeat the pins of the normal body
+        if sub_symbol.body_style() == Some(2) {
+            continue;
+        }
+        let Some(unit) = sub_symbol.unit() else { continue };
+        *pins_per_unit.entry(unit).or_default() += sub_symbol.pins().len();
+    }
+
+    let common_pins = pins_per_unit.remove(&0).unwrap_or_default();
+    let unit_count = pins_per_unit.keys().max().copied().unwrap_or_default();
+    if unit_count < 2 {
+        return vec![];
+    }
+    for unit in 1..=unit_count {
+        pins_per_unit.entry(unit).or_default();
+    }
+
+    let mut findings = vec![];
+    let mut summary: Vec<String> = pins_per_unit.iter().map(|(unit, pins)| format!("{unit}={pins}")).collect();
+    if common_pins > 0 {
+        summary.push(format!("common={common_pins}"));
+    }
+    findings.push(Finding::new(Severity::Info, symbol.name(), format!("pins per unit: {}", summary.join(", "))));
+
+    let mut counts: Vec<usize> = pins_per_unit.values().copied().collect();
+    counts.sort();
+    let median = counts[counts.len() / 2];
+
+    for (unit, pins) in &pins_per_unit {
+        if *pins == 0 {
+            findings.push(Finding::new(Severity::Warning, symbol.name(), format!("unit {unit} has no pins")));
+        } else if pins * UNBALANCED_UNIT_RATIO < median || *pins > median * UNBALANCED_UNIT_RATIO {
+            findings.push(Finding::new(
+                Severity::Warning,
+                symbol.name(),
+                format!("unit {unit} has {pins} pins while the median unit has {median}"),
+            ));
+        }
+    }
+    findings
+}
+
+/// Checks the pin counts of the units of symbols, for the `check` command.
+pub(crate) struct UnitPinBalance;
+
+impl Validator for UnitPinBalance {
+    fn validate(&self, symbol: &KiCadSymbol) -> Vec<Finding> {
+        check_unit_pin_balance(symbol)
+    }
+}
+
+/// Checks the reference prefixes of symbols, for the `check` command.
+pub(crate) struct ReferencePrefixes {
+    config: Config,
+}
+
+impl ReferencePrefixes {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self { config: config.clone() }
+    }
+}
+
+impl Validator for ReferencePrefixes {
+    fn validate(&self, symbol: &KiCadSymbol) -> Vec<Finding> {
+        reference::check_reference_prefix(symbol, &self.config.reference_prefixes)
+    }
+}
+
+/// Checks that symbols have a value for Reference, Value and the configured
+/// required fields. Derived symbols inherit the fields they do not set.
+pub(crate) struct RequiredFields {
+    fields: Vec<(String, KiCadPropertyType)>,
+}
+
+impl RequiredFields {
+    pub(crate) fn new(config: &Config) -> Self {
+        let names = ["Reference", "Value"].map(String::from).into_iter().chain(config.required_fields.iter().cloned());
+        Self { fields: names.map(|name| (name.clone(), property_type(&name))).collect() }
+    }
+}
+
+impl Validator for RequiredFields {
+    fn validate(&self, symbol: &KiCadSymbol) -> Vec<Finding> {
+        if symbol.extends().is_some() {
+            return vec![];
+        }
+        self.fields
+            .iter()
+            .filter(|(_, field)| symbol.property_value(field).is_none_or(|value| value.trim().is_empty() || value == "~"))
+            .map(|(name, _)| Finding::new(Severity::Warning, symbol.name(), format!("{name} is not set")))
+            .collect()
+    }
+}
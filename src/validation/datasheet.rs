@@ -0,0 +1,155 @@
+use crate::symbols::property::{KiCadPropertyType, KiCadSymbol};
+use crate::validation::{Finding, Severity};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+pub(crate) struct DatasheetCheckOptions {
+    /// Send HEAD requests to check that the datasheets are reachable
+    pub(crate) online: bool,
+    /// Maximum number of concurrent requests
+    pub(crate) jobs: usize,
+    pub(crate) timeout: Duration,
+}
+
+#[derive(Debug)]
+enum UrlStatus {
+    Reachable,
+    NotFound,
+    HttpError(u16),
+    Unreachable(String),
+}
+
+#[derive(Default)]
+pub(crate) struct DatasheetReport {
+    pub(crate) findings: Vec<Finding>,
+    /// Plain http links that are also served over https, with their https URL
+    pub(crate) https_upgrades: BTreeMap<String, String>,
+}
+
+/// Checks the Datasheet property of every symbol: the value has to be an
+/// http(s) URL and, when `online` is set, must not return an error. Web links
+/// of text items are checked online too. Plain http links that are also served
+/// over https are reported so they can be upgraded.
+pub(crate) fn check_datasheets(symbols: &[KiCadSymbol], options: &DatasheetCheckOptions) -> DatasheetReport {
+    let mut report = DatasheetReport::default();
+    let mut datasheets = BTreeMap::<String, Vec<&str>>::new();
+    let mut text_links = BTreeMap::<String, Vec<&str>>::new();
+
+    for symbol in symbols {
+        for link in symbol.text_hyperlinks() {
+            // Text links may also point at schematic pages or local files
+            if link.starts_with("http://") || link.starts_with("https://") {
+                text_links.entry(link.to_string()).or_default().push(symbol.name());
+            }
+        }
+        let Some(datasheet) = symbol.property_value(&KiCadPropertyType::Datasheet) else { continue };
+        if datasheet.is_empty() || datasheet == "~" {
+            continue;
+        }
+        match check_url_format(datasheet) {
+            Ok(()) => datasheets.entry(datasheet.to_string()).or_default().push(symbol.name()),
+            Err(message) => report.findings.push(Finding::new(Severity::Warning, symbol.name(), message)),
+        }
+    }
+
+    if !options.online {
+        return report;
+    }
+
+    let mut probes: Vec<String> = datasheets.keys().chain(text_links.keys()).cloned().collect();
+    probes.extend(datasheets.keys().chain(text_links.keys()).filter_map(|url| https_upgrade(url)));
+    probes.sort();
+    probes.dedup();
+    let statuses = probe_urls(probes, options);
+
+    for (label, urls) in [("datasheet", &datasheets), ("text link", &text_links)] {
+        for (url, symbol_names) in urls {
+            let message = match &statuses[url] {
+                UrlStatus::Reachable => None,
+                UrlStatus::NotFound => Some((Severity::Warning, format!("{label} not found (404): {url}"))),
+                UrlStatus::HttpError(status) => Some((Severity::Warning, format!("{label} returned HTTP {status}: {url}"))),
+                UrlStatus::Unreachable(error) => Some((Severity::Warning, format!("{label} unreachable: {url}: {error}"))),
+            };
+            let upgrade = https_upgrade(url).filter(|https_url| matches!(statuses[https_url], UrlStatus::Reachable));
+            if let Some(https_url) = &upgrade {
+                report.https_upgrades.insert(url.clone(), https_url.clone());
+            }
+            let upgrade = upgrade.map(|https_url| (Severity::Info, format!("{label} is available over https: {https_url}")));
+
+            for (severity, message) in message.into_iter().chain(upgrade) {
+                for symbol_name in symbol_names {
+                    report.findings.push(Finding::new(severity, symbol_name, message.clone()));
+                }
+            }
+        }
+    }
+
+    report
+}
+
+fn check_url_format(url: &str) -> Result<(), String> {
+    if url.chars().any(char::is_whitespace) {
+        return Err(format!("datasheet URL contains whitespace: {url}"));
+    }
+    let Some((scheme, rest)) = url.split_once("://") else {
+        if url.starts_with("www.") {
+            return Err(format!("datasheet URL has no scheme: {url}"));
+        }
+        return Err(format!("datasheet is not a URL: {url}"));
+    };
+    if scheme != "http" && scheme != "https" {
+        return Err(format!("datasheet URL has unsupported scheme {scheme}: {url}"));
+    }
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    if host.is_empty() || !host.contains('.') && !host.starts_with("localhost") {
+        return Err(format!("datasheet URL has no valid host: {url}"));
+    }
+    Ok(())
+}
+
+fn https_upgrade(url: &str) -> Option<String> {
+    url.strip_prefix("http://").map(|rest| format!("https://{rest}"))
+}
+
+/// Requests every URL with at most `options.jobs` requests in flight.
+fn probe_urls(urls: Vec<String>, options: &DatasheetCheckOptions) -> BTreeMap<String, UrlStatus> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(options.timeout))
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let queue = Mutex::new(urls);
+    let statuses = Mutex::new(BTreeMap::new());
+
+    thread::scope(|scope| {
+        for _ in 0..options.jobs.max(1) {
+            scope.spawn(|| loop {
+                let Some(url) = queue.lock().unwrap().pop() else { break };
+                let status = probe_url(&agent, &url);
+                statuses.lock().unwrap().insert(url, status);
+            });
+        }
+    });
+
+    statuses.into_inner().unwrap()
+}
+
+fn probe_url(agent: &ureq::Agent, url: &str) -> UrlStatus {
+    let mut response = agent.head(url).call();
+    // Some servers do not implement HEAD
+    if let Ok(head_response) = &response {
+        if head_response.status() == 405 {
+            response = agent.get(url).call();
+        }
+    }
+    match response {
+        Ok(response) => match response.status().as_u16() {
+            404 | 410 => UrlStatus::NotFound,
+            status if status >= 400 => UrlStatus::HttpError(status),
+            _ => UrlStatus::Reachable,
+        },
+        Err(error) => UrlStatus::Unreachable(error.to_string()),
+    }
+}
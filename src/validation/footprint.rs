@@ -0,0 +1,172 @@
+use crate::footprints::pad::{KiCadPad, KiCadPadType};
+use crate::footprints::KiCadFootprint;
+use crate::lib_table::LibTable;
+use crate::pipeline::Validator;
+use crate::symbols::property::{KiCadPropertyType, KiCadSymbol};
+use crate::validation::{Finding, Severity};
+use std::path::{Path, PathBuf};
+
+/// Pad numbers vendors give exposed and thermal pads.
+const THERMAL_PAD_NUMBERS: [&str; 5] = ["EP", "PAD", "TAB", "TP", "THERMAL"];
+
+/// Finds footprint files by `nickname:name`, in the footprint library of the
+/// import, known by `nickname`, or in a library of one of the footprint
+/// library tables.
+#[derive(Clone)]
+pub(crate) struct FootprintResolver {
+    library: PathBuf,
+    nickname: String,
+    tables: Vec<LibTable>,
+}
+
+impl FootprintResolver {
+    pub(crate) fn new(library: &Path, nickname: &str, tables: Vec<LibTable>) -> Self {
+        Self { library: library.to_path_buf(), nickname: nickname.to_string(), tables }
+    }
+
+    /// Whether there is any footprint library to look footprints up in.
+    pub(crate) fn has_libraries(&self) -> bool {
+        !self.library.as_os_str().is_empty() || self.tables.iter().any(|table| !table.is_empty())
+    }
+
+    fn knows(&self, nickname: &str) -> bool {
+        nickname == self.nickname || self.tables.iter().any(|table| table.find(nickname).is_some())
+    }
+
+    /// The path the footprint would be at, `None` if the path of its library
+    /// is unknown.
+    fn footprint_path(&self, nickname: &str, name: &str) -> Option<PathBuf> {
+        let file_name = format!("{name}.kicad_mod");
+        if nickname == self.nickname {
+            return Some(self.library.join(file_name));
+        }
+        let (table, library) = self.tables.iter().find_map(|table| table.find(nickname).map(|library| (table, library)))?;
+        table.library_path(library).map(|path| path.join(file_name))
+    }
+}
+
+/// Checks that the Footprint property of imported symbols names a footprint
+/// that exists.
+pub(crate) struct FootprintReferences {
+    resolver: FootprintResolver,
+}
+
+impl FootprintReferences {
+    pub(crate) fn new(resolver: FootprintResolver) -> Self {
+        Self { resolver }
+    }
+}
+
+impl Validator for FootprintReferences {
+    fn validate(&self, symbol: &KiCadSymbol) -> Vec<Finding> {
+        let Some(footprint) = symbol.property_value(&KiCadPropertyType::Footprint).map(str::trim) else { return vec![] };
+        if footprint.is_empty() {
+            return vec![];
+        }
+        let warning = |message: String| vec![Finding::new(Severity::Warning, symbol.name(), message)];
+
+        let Some((nickname, name)) = footprint.split_once(':') else {
+            return warning(format!("footprint {footprint} has no library nickname, KiCad will not find it"));
+        };
+        if !self.resolver.knows(nickname) {
+            return warning(format!("footprint {footprint} refers to library {nickname}, which is in no footprint library table"));
+        }
+        match self.resolver.footprint_path(nickname, name) {
+            Some(path) if !path.exists() => warning(format!("footprint {footprint} does not exist")),
+            _ => vec![],
+        }
+    }
+}
+
+/// Compares the pin numbers of imported symbols with the pad numbers of their
+/// footprints, as vendors often ship a footprint that does not match the
+/// symbol. Exposed and thermal pads without a pin are only reported as info.
+pub(crate) struct PinPadConsistency {
+    resolver: FootprintResolver,
+}
+
+impl PinPadConsistency {
+    pub(crate) fn new(resolver: FootprintResolver) -> Self {
+        Self { resolver }
+    }
+}
+
+impl Validator for PinPadConsistency {
+    fn validate(&self, symbol: &KiCadSymbol) -> Vec<Finding> {
+        // Derived symbols have the pins of the symbol they extend
+        if symbol.extends().is_some() {
+            return vec![];
+        }
+        let Some(footprint) = symbol.property_value(&KiCadPropertyType::Footprint).map(str::trim) else { return vec![] };
+        let Some((nickname, name)) = footprint.split_once(':') else { return vec![] };
+        let Some(path) = self.resolver.footprint_path(nickname, name).filter(|path| path.exists()) else { return vec![] };
+        let finding = |severity: Severity, message: String| Finding::new(severity, symbol.name(), message);
+        let footprint = match KiCadFootprint::from_path(&path) {
+            Ok(footprint) => footprint,
+            Err(error) => return vec![finding(Severity::Warning, format!("footprint {footprint} cannot be read: {error}"))],
+        };
+
+        let mut pins: Vec<&str> = symbol
+            .sub_symbols()
+            .iter()
+            // De Morgan bodies repeat the pins of the normal body
+            .filter(|sub_symbol| sub_symbol.body_style() != Some(2))
+            .flat_map(|sub_symbol| sub_symbol.pins())
+            .filter_map(|pin| pin.number())
+            .collect();
+        sort_numbers(&mut pins);
+        let pads: Vec<&KiCadPad> = footprint
+            .pads()
+            .iter()
+            .filter(|pad| pad.pad_type() != KiCadPadType::NpThruHole && !pad.number().is_empty())
+            .collect();
+        let mut pad_numbers: Vec<&str> = pads.iter().map(|pad| pad.number()).collect();
+        sort_numbers(&mut pad_numbers);
+
+        let missing_pads: Vec<&str> = pins.iter().copied().filter(|pin| !pad_numbers.contains(pin)).collect();
+        let (thermal_pads, extra_pads): (Vec<&str>, Vec<&str>) = pad_numbers
+            .iter()
+            .copied()
+            .filter(|pad| !pins.contains(pad))
+            .partition(|number| is_thermal_pad(number, &pads));
+
+        let mut findings = vec![];
+        if !missing_pads.is_empty() {
+            let message = format!("pin(s) {} have no pad in footprint {}", missing_pads.join(", "), footprint.name());
+            findings.push(finding(Severity::Warning, message));
+        }
+        if !extra_pads.is_empty() {
+            let message = format!("pad(s) {} of footprint {} have no pin", extra_pads.join(", "), footprint.name());
+            findings.push(finding(Severity::Warning, message));
+        }
+        if !thermal_pads.is_empty() {
+            let message = format!(
+                "exposed/thermal pad(s) {} of footprint {} have no pin and stay unconnected",
+                thermal_pads.join(", "),
+                footprint.name()
+            );
+            findings.push(finding(Severity::Info, message));
+        }
+        findings
+    }
+}
+
+/// Sorts pad and pin numbers numerically where they are numbers and removes
+/// duplicates, as pins and pads may share a number.
+fn sort_numbers(numbers: &mut Vec<&str>) {
+    numbers.sort_by_key(|number| (number.parse::<u32>().unwrap_or(u32::MAX), number.to_string()));
+    numbers.dedup();
+}
+
+/// Whether the pad is named like an exposed pad or is much larger than the
+/// other pads.
+fn is_thermal_pad(number: &str, pads: &[&KiCadPad]) -> bool {
+    if THERMAL_PAD_NUMBERS.iter().any(|name| name.eq_ignore_ascii_case(number)) {
+        return true;
+    }
+    let area = |pad: &KiCadPad| pad.size().0 * pad.size().1;
+    let mut areas: Vec<f32> = pads.iter().map(|pad| area(pad)).collect();
+    areas.sort_by(f32::total_cmp);
+    let median = areas.get(areas.len() / 2).copied().unwrap_or_default();
+    pads.iter().filter(|pad| pad.number() == number).any(|pad| area(pad) > 4.0 * median)
+}
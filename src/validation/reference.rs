@@ -0,0 +1,82 @@
+use crate::config::ReferencePrefixRule;
+use crate::symbols::property::{KiCadProperty, KiCadPropertyType, KiCadSymbol};
+use crate::validation::{Finding, Severity};
+
+/// The text a part category is derived from, split into lowercase words.
+pub(crate) fn category_words(symbol: &KiCadSymbol) -> Vec<String> {
+    let mut text = symbol.name().to_string();
+    for property_type in [
+        KiCadPropertyType::KiKeywords,
+        KiCadPropertyType::Description,
+        KiCadPropertyType::Value,
+        KiCadPropertyType::Footprint,
+    ] {
+        if let Some(value) = symbol.property_value(&property_type) {
+            text.push(' ');
+            text.push_str(value);
+        }
+    }
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The prefix of a reference like `U?` or `R1`.
+pub(crate) fn reference_prefix(reference: &str) -> &str {
+    reference.trim_end_matches(|c: char| c == '?' || c.is_ascii_digit())
+}
+
+/// All rules whose keywords match the symbol, without duplicate prefixes.
+fn matching_rules<'a>(symbol: &KiCadSymbol, rules: &'a [ReferencePrefixRule]) -> Vec<&'a ReferencePrefixRule> {
+    let words = category_words(symbol);
+    let mut matches: Vec<&ReferencePrefixRule> = vec![];
+    for rule in rules {
+        let matched = rule.keywords.iter().any(|keyword| words.contains(&keyword.to_lowercase()));
+        if matched && !matches.iter().any(|existing| existing.prefix == rule.prefix) {
+            matches.push(rule);
+        }
+    }
+    matches
+}
+
+pub(crate) fn check_reference_prefix(symbol: &KiCadSymbol, rules: &[ReferencePrefixRule]) -> Vec<Finding> {
+    let Some(reference) = symbol.property_value(&KiCadPropertyType::Reference) else {
+        return vec![Finding::new(Severity::Warning, symbol.name(), "symbol has no Reference".to_string())];
+    };
+    let prefix = reference_prefix(reference);
+    let matches = matching_rules(symbol, rules);
+    if matches.iter().any(|rule| rule.prefix == prefix) {
+        return vec![];
+    }
+    match matches.as_slice() {
+        [] => vec![],
+        [rule] => vec![Finding::new(
+            Severity::Warning,
+            symbol.name(),
+            format!("reference prefix {prefix} should be {}", rule.prefix),
+        )],
+        _ => {
+            let prefixes: Vec<&str> = matches.iter().map(|rule| rule.prefix.as_str()).collect();
+            vec![Finding::new(
+                Severity::Warning,
+                symbol.name(),
+                format!("reference prefix {prefix} does not match any of the candidate prefixes {}", prefixes.join(", ")),
+            )]
+        }
+    }
+}
+
+/// Rewrites the reference prefix when exactly one category matches the
+/// symbol, returning the old and new reference.
+pub(crate) fn fix_reference_prefix(symbol: &mut KiCadSymbol, rules: &[ReferencePrefixRule]) -> Option<(String, String)> {
+    let reference = symbol.property_value(&KiCadPropertyType::Reference)?.to_string();
+    let prefix = reference_prefix(&reference);
+    let [rule] = matching_rules(symbol, rules)[..] else { return None };
+    if rule.prefix == prefix {
+        return None;
+    }
+    let fixed = format!("{}{}", rule.prefix, &reference[prefix.len()..]);
+    symbol.set_property(KiCadProperty::new_hidden(KiCadPropertyType::Reference, fixed.clone()));
+    Some((reference, fixed))
+}
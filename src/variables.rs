@@ -0,0 +1,74 @@
+//! The path variables of KiCad, like `${KICAD8_SYMBOL_DIR}`, set in the
+//! environment or in the Configure Paths dialog of KiCad, which keeps them in
+//! `kicad_common.json`.
+
+use crate::installation::KiCadInstallation;
+use anyhow::bail;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The variables of `kicad_common.json` of the newest KiCad version.
+fn kicad_common_variables() -> &'static BTreeMap<String, String> {
+    static VARIABLES: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+    VARIABLES.get_or_init(|| {
+        let Some(content) = KiCadInstallation::detect()
+            .and_then(|installation| fs::read_to_string(installation.config_dir().join("kicad_common.json")).ok())
+        else {
+            return BTreeMap::new();
+        };
+        let Ok(common) = serde_json::from_str::<serde_json::Value>(&content) else { return BTreeMap::new() };
+        let Some(variables) = common.pointer("/environment/vars").and_then(|vars| vars.as_object()) else {
+            return BTreeMap::new();
+        };
+        variables
+            .iter()
+            .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+            .collect()
+    })
+}
+
+/// The value of a variable, from the environment like KiCad, or else from
+/// `kicad_common.json`, or else the one KiCad gives its own variables like
+/// `KICAD8_FOOTPRINT_DIR`.
+pub(crate) fn variable(name: &str) -> Option<String> {
+    env::var(name).ok().or_else(|| kicad_common_variables().get(name).cloned()).or_else(|| {
+        let path = KiCadInstallation::detect()?.variable(name)?;
+        Some(path.to_string_lossy().to_string())
+    })
+}
+
+/// `value` with every `${NAME}` replaced, by one of `extra` or else by
+/// [`variable`]. `${KIPRJMOD}` stands for the working directory unless it is
+/// set otherwise.
+pub(crate) fn expand(value: &str, extra: &BTreeMap<String, PathBuf>) -> Result<String, anyhow::Error> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else { bail!("Unterminated variable in {value}") };
+        let name = &rest[start + 2..end];
+        let replacement = match extra.get(name) {
+            Some(dir) => dir.to_string_lossy().to_string(),
+            None => match variable(name) {
+                Some(replacement) => replacement,
+                None if name == "KIPRJMOD" => env::current_dir()?.to_string_lossy().to_string(),
+                None => bail!("{name} in {value} is neither set nor a variable of KiCad"),
+            },
+        };
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&replacement);
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// The path with its variables expanded, see [`expand`].
+pub(crate) fn expand_path(path: &Path, extra: &BTreeMap<String, PathBuf>) -> Result<PathBuf, anyhow::Error> {
+    match path.to_str() {
+        Some(value) if value.contains("${") => Ok(PathBuf::from(expand(value, extra)?)),
+        _ => Ok(path.to_path_buf()),
+    }
+}
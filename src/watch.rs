@@ -0,0 +1,155 @@
+use crate::config::WatchTarget;
+use crate::pipeline::is_importable;
+use anyhow::bail;
+use std::collections::HashMap;
+use std::{fs, io};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+pub(crate) struct WatchOptions {
+    /// How often the watched directories are scanned
+    pub(crate) poll_interval: Duration,
+    /// How long an archive has to stay unchanged before it is imported, so a
+    /// download that is still being written is not picked up half-way
+    pub(crate) settle_time: Duration,
+    /// Import attempts per archive while it is locked or incomplete
+    pub(crate) retries: u32,
+}
+
+/// Size and modification time of a file, a new download or a rewrite changes either.
+type FileVersion = (u64, SystemTime);
+
+struct PendingArchive {
+    version: FileVersion,
+    since: Instant,
+}
+
+/// Watches the target directories for new archives and hands them to
+/// `import`. Archives for the same symbol library are imported one after the
+/// other, archives for different libraries concurrently. Runs until killed.
+pub(crate) fn watch<F>(targets: &[WatchTarget], options: &WatchOptions, import: F) -> Result<(), anyhow::Error>
+where
+    F: Fn(&Path, &WatchTarget) -> Result<(), anyhow::Error> + Sync,
+{
+    if targets.is_empty() {
+        bail!("Nothing to watch, pass --dir or add [[watch]] entries to the config");
+    }
+    for target in targets {
+        if !target.dir.is_dir() {
+            bail!("Watch directory {} does not exist", target.dir.display());
+        }
+    }
+
+    // Archives that are already there when the watch starts are left alone
+    let mut seen = HashMap::<(usize, PathBuf), FileVersion>::new();
+    for (index, target) in targets.iter().enumerate() {
+        for (path, version) in scan_archives(&target.dir) {
+            seen.insert((index, path), version);
+        }
+    }
+    let mut pending = HashMap::<(usize, PathBuf), PendingArchive>::new();
+
+    thread::scope(|scope| {
+        let mut queues = HashMap::<PathBuf, mpsc::Sender<(PathBuf, &WatchTarget)>>::new();
+        for target in targets {
+            queues.entry(target.symbol_lib.clone()).or_insert_with(|| {
+                let (sender, receiver) = mpsc::channel::<(PathBuf, &WatchTarget)>();
+                let import = &import;
+                scope.spawn(move || {
+                    for (archive, target) in receiver {
+                        import_with_retries(&archive, target, options, import);
+                    }
+                });
+                sender
+            });
+        }
+
+        loop {
+            for (index, target) in targets.iter().enumerate() {
+                for (path, version) in scan_archives(&target.dir) {
+                    let key = (index, path);
+                    if seen.get(&key) == Some(&version) {
+                        continue;
+                    }
+                    match pending.get(&key) {
+                        Some(archive) if archive.version == version => {
+                            if archive.since.elapsed() < options.settle_time {
+                                continue;
+                            }
+                            pending.remove(&key);
+                            println!("Queueing {} for {}", key.1.display(), target.symbol_lib.display());
+                            queues[&target.symbol_lib]
+                                .send((key.1.clone(), target))
+                                .expect("import worker stopped");
+                            seen.insert(key, version);
+                        }
+                        // A new archive, or one that is still being written: every
+                        // change restarts the settle timer, so bursts of events for
+                        // the same file end up as a single import
+                        _ => {
+                            pending.insert(key, PendingArchive { version, since: Instant::now() });
+                        }
+                    }
+                }
+            }
+            thread::sleep(options.poll_interval);
+        }
+    })
+}
+
+fn file_version(path: &Path) -> Option<FileVersion> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// The files in `dir` the import takes, like archives and EasyEDA exports. A
+/// directory that is temporarily unavailable (e.g. a network share
+/// reconnecting) is treated as empty.
+fn scan_archives(dir: &Path) -> Vec<(PathBuf, FileVersion)> {
+    let Ok(entries) = fs::read_dir(dir) else { return vec![] };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_importable(path))
+        .filter_map(|path| {
+            let version = file_version(&path)?;
+            Some((path, version))
+        })
+        .collect()
+}
+
+/// Whether an import failed because the archive was still being written or
+/// was locked, which a later attempt gets past. Other failures, like parse
+/// errors or policy violations, would fail the same way again.
+fn is_transient(error: &anyhow::Error, archive: &Path, version: Option<FileVersion>) -> bool {
+    file_version(archive) != version
+        || error
+            .chain()
+            .filter_map(|cause| cause.downcast_ref::<io::Error>())
+            .any(|error| matches!(error.kind(), io::ErrorKind::PermissionDenied | io::ErrorKind::UnexpectedEof))
+}
+
+/// Browsers can keep a finished download locked for a while, so imports that
+/// fail on that are retried with a growing delay before giving up on the archive.
+fn import_with_retries<F>(archive: &Path, target: &WatchTarget, options: &WatchOptions, import: &F)
+where
+    F: Fn(&Path, &WatchTarget) -> Result<(), anyhow::Error>,
+{
+    let attempts = options.retries.max(1);
+    for attempt in 1..=attempts {
+        let version = file_version(archive);
+        match import(archive, target) {
+            Ok(()) => return,
+            Err(error) if attempt < attempts && is_transient(&error, archive, version) => {
+                println!("Could not import {} (attempt {attempt}/{attempts}): {error}", archive.display());
+                thread::sleep(options.settle_time * attempt);
+            }
+            Err(error) => {
+                println!("Giving up on {}: {error}", archive.display());
+                return;
+            }
+        }
+    }
+}